@@ -1,7 +1,7 @@
 use clap::{Parser, Subcommand, Args};
 
 use crate::shared_options::SharedOptions;
-use crate::{s3, transfer, cli};
+use crate::{s3, transfer, cli, manifest, shell, prune, clean, checksum, config, policy};
 
 pub(crate) fn clap3_help_style() -> clap::builder::Styles {
     use clap::builder::styling::AnsiColor;
@@ -21,16 +21,125 @@ pub(crate) struct Arguments {
     #[clap(long, short='R', global=true)]
     pub region: Option<String>,
 
-    #[clap(long, short='e', global=true)]
-    /// Use custom endpoint URL for other S3 implementations
-    pub endpoint: Option<http::uri::Uri>,
+    #[clap(long, short='e', global=true, value_delimiter=',')]
+    /// Use custom endpoint URL for other S3 implementations.
+    /// Reads fail over between several equivalent endpoints, given comma separated or repeated
+    pub endpoint: Vec<http::uri::Uri>,
 
     #[clap(long, global=true)]
     /// Override config profile name
     pub profile: Option<String>,
 
+    #[clap(long, global=true, value_name="COMMAND")]
+    /// Get credentials from the JSON this shell command prints, as for the AWS config file's
+    /// credential_process: {"Version": 1, "AccessKeyId": .., "SecretAccessKey": .., "SessionToken": ..}.
+    /// Also read from `credential_helper = "COMMAND"` in the config file, unless --profile is given
+    pub credential_helper: Option<String>,
+
+    #[clap(long, global=true, env="SUP3_ACCESS_KEY_ID", value_name="ID", requires="secret_key")]
+    /// Access key ID to use instead of the profile, credential helper or AWS_* variables, e.g. for
+    /// temporary credentials handed over for one transfer
+    pub access_key: Option<String>,
+
+    #[clap(long, global=true, env="SUP3_SECRET_ACCESS_KEY", hide_env_values=true, value_name="KEY", requires="access_key")]
+    /// Secret access key for --access-key. Prefer the variable, as command lines are visible to other users
+    pub secret_key: Option<String>,
+
+    #[clap(long, global=true, env="SUP3_SESSION_TOKEN", hide_env_values=true, value_name="TOKEN", requires="access_key")]
+    /// Session token of temporary --access-key credentials
+    pub session_token: Option<String>,
+
+    #[clap(long, global=true)]
+    /// Use S3 Transfer Acceleration endpoints
+    pub accelerate: bool,
+
+    #[clap(long, global=true, value_enum, value_name="4|6|auto")]
+    /// Connect to endpoints over IPv4 or IPv6 only, e.g. where IPv6 is broken, instead of both
+    /// raced with happy eyeballs. Also read from `ip_version = 4` in the config file, within
+    /// `[profile.NAME]` for --profile NAME
+    pub ip_version: Option<s3::IpVersion>,
+
+    #[clap(long, global=true, value_hint=clap::ValueHint::FilePath)]
+    /// Read settings from this file instead of $SUP3_CONFIG or ~/.config/sup3/config.toml
+    pub config: Option<std::path::PathBuf>,
+
+    #[clap(long, global=true, value_name="FILE", value_hint=clap::ValueHint::FilePath)]
+    /// Append a JSON line to FILE for each object written, read or removed, with the time,
+    /// access key, operation, URI, bytes, ETag, request ID and a SHA-256 chaining it to the line before
+    pub audit_log: Option<std::path::PathBuf>,
+
     #[clap(flatten)]
     pub shared: SharedOptions,
+
+    #[clap(flatten)]
+    pub provider: s3::OptionsProvider,
+
+    /// Long names of the options `apply_config` filled in from the config file
+    #[clap(skip)]
+    pub configured: Vec<&'static str>,
+}
+
+impl Arguments {
+    /// Percent-decode and normalize the keys of the S3 URIs among the arguments, parsed as
+    /// written, unless --raw-keys
+    pub(crate) fn normalize_keys(&mut self) -> Result<(), String> {
+        if self.shared.raw_keys {
+            return Ok(());
+        }
+        for uri in self.command.uris_mut() {
+            *uri = uri.normalized().map_err(|e| format!("invalid S3 URI '{uri}': {e}"))?;
+        }
+        Ok(())
+    }
+
+    /// Fill in settings not given on the command line from the config file, returning its
+    /// operations policy
+    pub(crate) fn apply_config(&mut self) -> Result<policy::Policy, String> {
+        let config = config::Config::load(self.config.as_deref())?;
+        // An endpoint chosen on the command line replaces the configured provider and region
+        if self.endpoint.is_empty() && !self.provider.is_enabled() {
+            self.provider.provider = config.parse_with("", "provider", str::parse)?;
+            self.configure("provider", self.provider.provider.is_some());
+            if self.region.is_none() {
+                self.region = config.parse_with("", "region", s3::parse_region)?;
+                self.configure("region", self.region.is_some());
+            }
+        }
+        if self.credential_helper.is_none() && self.profile.is_none() && self.access_key.is_none() {
+            self.credential_helper = config.get("", "credential_helper").map(str::to_owned);
+            self.configure("credential-helper", self.credential_helper.is_some());
+        }
+        if self.ip_version.is_none() {
+            let profile = self.profile.as_ref().map(|profile| format!("profile.{profile}"));
+            self.ip_version = match profile.filter(|profile| config.get(profile, "ip_version").is_some()) {
+                Some(profile) => config.parse_with(&profile, "ip_version", str::parse)?,
+                None => config.parse_with("", "ip_version", str::parse)?,
+            };
+            self.configure("ip-version", self.ip_version.is_some());
+        }
+        if let (Some(_), Some(region)) = (self.provider.provider, &self.region) {
+            s3::parse_region(region)?;
+        }
+        use clap::CommandFactory;
+        policy::Policy::from_config(&config, &Arguments::command())
+    }
+
+    fn configure(&mut self, flag: &'static str, configured: bool) {
+        if configured {
+            self.configured.push(flag);
+        }
+    }
+
+    /// Long names of the options given on the command line or filled in from the config file
+    pub(crate) fn merged_flags<'a>(&'a self, invocation: &'a policy::Invocation) -> impl Iterator<Item = &'a str> {
+        invocation.flags().chain(self.configured.iter().copied())
+    }
+
+    /// Credentials given with --access-key and --secret-key, if any
+    pub(crate) fn static_credentials(&self) -> Option<aws_sdk_s3::config::Credentials> {
+        let (access_key, secret_key) = (self.access_key.as_ref()?, self.secret_key.as_ref()?);
+        Some(aws_sdk_s3::config::Credentials::new(access_key, secret_key, self.session_token.clone(), None, "sup3 --access-key"))
+    }
 }
 
 #[derive(Subcommand, Debug)]
@@ -43,29 +152,191 @@ pub(crate) enum Commands {
     Download(Download),
     /// Remove from S3
     ///
-    /// Note: will succeed if remote file doesn't exist
+    /// Note: will succeed if remote file doesn't exist. With -r, removes every object below each prefix
     Rm(Remove),
-    /// List S3 path
+    /// List S3 paths, or buckets without any
     Ls(ListFiles),
     /// List S3 buckets
     #[clap(alias="lb")]
     ListBuckets(ListBuckets),
     /// Copy to/from S3, depending on arguments
+    ///
+    /// Copies between S3 URIs are made server-side, within or across buckets
     Cp(Copy),
+    /// Move S3 objects server-side, within or across buckets
+    ///
+    /// With -r, moves every object below each prefix: into the destination if it ends
+    /// with '/', otherwise renaming the prefix to the destination
+    Mv(Move),
+    /// Synchronise a local directory and an S3 prefix, or two S3 prefixes, in the direction of
+    /// the arguments
+    ///
+    /// Only files missing or changed at the destination are transferred, copied server-side
+    /// between S3 prefixes
+    Sync(Sync),
+    /// Print which files are only in a local directory, only below an S3 prefix, or differ
+    /// between them, changing nothing
+    ///
+    /// Lines are tab separated: 'only-local' or 'only-remote' and the path, or 'differs', the
+    /// path and how. Files differ as sync compares them when uploading, e.g. to preview a sync
+    Diff(Diff),
     /// Print contents of S3 files
     Cat(Cat),
+    /// Print presigned URLs giving temporary access to S3 objects without credentials, one per line
+    Presign(Presign),
     /// Create S3 buckets
     #[clap(alias="mb")]
     MakeBuckets(MakeBuckets),
+    /// Get, replace or remove the tags of S3 objects
+    Tag(Tag),
+    /// Print, replace or remove the policies of S3 buckets
+    Policy(Policy),
+    /// Print, enable or suspend versioning of S3 buckets
+    ///
+    /// Suspending keeps existing versions, new writes replacing the null version
+    Versioning(Versioning),
+    /// Print, set or remove the default encryption of S3 buckets
+    ///
+    /// Applies to new objects uploaded without encryption of their own
+    Encryption(Encryption),
+    /// Write inventories of S3 objects back to S3, for providers lacking S3 Inventory
+    Inventory(Inventory),
+    /// Remove S3 buckets, which must be empty unless --force
+    #[clap(alias="rb")]
+    RemoveBuckets(RemoveBuckets),
+    /// Append a local file or stdin to an S3 object
+    ///
+    /// Composes the object server-side where possible, creating it if missing, for providers
+    /// lacking a native append. Starts over if another writer changes the object meanwhile
+    Append(Append),
+    /// Join S3 objects into a new object server-side, in the order given
+    ///
+    /// Copies with UploadPartCopy, only downloading objects and ranges under the 5MiB minimum
+    /// part size. A source ending in '/' stands for the objects below it, in key order
+    Concat(Concat),
+    /// List the parts of multipart S3 objects, with sizes and checksums
+    Parts(Parts),
+    /// List or abort multipart uploads left in progress, whose parts are stored and billed until
+    /// completed or aborted
+    Multipart(Multipart),
+    /// Print S3 object details, one 'field: value' line each, from HeadObject
+    Stat(Stat),
+    /// Print the owner and access control grants of S3 buckets or objects
+    ///
+    /// A URI without a key is the bucket's ACL. Grantees are named as for the --grant-* flags
+    GetAcl(GetAcl),
+    /// Replace the ACL of existing S3 objects with a canned ACL or grants, without re-uploading
+    SetAcl(SetAcl),
+    /// Make a single read-only S3 API request and print its parsed response as JSON
+    ///
+    /// Only the listed bucket and object queries are supported, not arbitrary operations. Parameters
+    /// are named as in the S3 API reference, e.g. 'sup3 api HeadObject --param Bucket=my-bucket --param Key=a.txt'
+    Api(Api),
+    /// Wait until S3 objects or buckets exist, polling with backoff
+    ///
+    /// Fails if they don't all exist before --timeout, e.g. for deployment scripts waiting for artifacts
+    Wait(Wait),
+    /// Apply grandfather-father-son retention to timestamped backups below an S3 prefix
+    ///
+    /// Lists what would be kept and deleted, only deleting with --delete
+    Prune(Prune),
+    /// Find files left behind in a local directory by killed downloads, e.g. '*.sup3.partial'
+    ///
+    /// Lists their age and size, only deleting with --delete
+    Clean(Clean),
+    /// Compare local files to S3 objects without downloading them, from their checksums or MD5 ETags
+    ///
+    /// Each LOCAL is compared to the S3_URI after it, a directory file by file to the objects of
+    /// the same relative paths below the prefix. Prints 'match', 'differs', 'missing' or
+    /// 'unknown', then the file and object, failing unless all match
+    Checksum(Checksum),
+    /// Mirror a manifest of HTTP(S) URLs into an S3 prefix, verifying checksums
+    ///
+    /// Each manifest line is '<url> [checksum]', where checksum is 'md5:', 'sha1:' or
+    /// 'sha256:' followed by the hex digest. Files failing verification are not written
+    Mirror(Mirror),
+    /// Interactive shell with a current bucket and prefix
+    ///
+    /// Supports cd, pwd, ls, get, put, rm and cat, with paths relative to the current location
+    Shell(Shell),
+    /// Mount an S3 prefix as a read-only filesystem via FUSE
+    ///
+    /// Runs in the foreground until interrupted or unmounted
+    #[cfg(feature = "mount")]
+    Mount(Mount),
     #[cfg(feature = "gen-completion")]
     /// Generate CLI completion
     GenerateCompletion(GenerateCompletion),
 }
 
+impl Commands {
+    /// The S3 URIs among the arguments, e.g. to normalize their keys
+    fn uris_mut(&mut self) -> Vec<&mut s3::Uri> {
+        fn copy_uris<'a>(args: impl IntoIterator<Item=&'a mut CopyArgument>) -> impl Iterator<Item=&'a mut s3::Uri> {
+            args.into_iter().filter_map(|arg| match arg {
+                CopyArgument::Uri(uri) => Some(uri),
+                CopyArgument::Url(_) | CopyArgument::LocalFile(_) => None,
+            })
+        }
+        match self {
+            Commands::Upload(upload) => std::iter::once(&mut upload.to).chain(upload.transfer.summary_to_mut()).collect(),
+            Commands::Download(download) => download.uris.iter_mut().chain(download.transfer.summary_to_mut()).collect(),
+            Commands::Rm(remove) => remove.remote_paths.iter_mut().collect(),
+            Commands::Ls(list) => list.remote_paths.iter_mut().collect(),
+            Commands::ListBuckets(_) | Commands::Api(_) | Commands::Clean(_) | Commands::Checksum(_) => vec![],
+            Commands::Cp(copy) => copy_uris(&mut copy.args).chain(copy.transfer.summary_to_mut()).collect(),
+            Commands::Mv(mv) => mv.from.iter_mut().chain(std::iter::once(&mut mv.to)).collect(),
+            Commands::Sync(sync) => copy_uris([&mut sync.from, &mut sync.to]).chain(sync.transfer.summary_to_mut()).collect(),
+            Commands::Diff(diff) => vec![&mut diff.remote],
+            Commands::Cat(cat) => cat.uris.iter_mut().collect(),
+            Commands::Presign(presign) => presign.uris.iter_mut().collect(),
+            Commands::MakeBuckets(make) => make.buckets.iter_mut().collect(),
+            Commands::Tag(tag) => match &mut tag.action {
+                TagAction::Get { uris, .. } | TagAction::Set { uris, .. } | TagAction::Delete { uris, .. } => uris.iter_mut().collect(),
+            },
+            Commands::Policy(policy) => match &mut policy.action {
+                PolicyAction::Get { buckets, .. } | PolicyAction::Delete { buckets, .. } => buckets.iter_mut().collect(),
+                PolicyAction::Set { bucket, .. } => vec![bucket],
+            },
+            Commands::Versioning(versioning) => match &mut versioning.action {
+                VersioningAction::Status { buckets, .. } | VersioningAction::Enable { buckets, .. } | VersioningAction::Suspend { buckets, .. } => buckets.iter_mut().collect(),
+            },
+            Commands::Encryption(encryption) => match &mut encryption.action {
+                EncryptionAction::Status { buckets, .. } | EncryptionAction::Set { buckets, .. } | EncryptionAction::Delete { buckets, .. } => buckets.iter_mut().collect(),
+            },
+            Commands::Inventory(inventory) => match &mut inventory.action {
+                InventoryAction::Create { source, to, .. } => vec![source, to],
+            },
+            Commands::RemoveBuckets(remove) => remove.buckets.iter_mut().collect(),
+            Commands::Append(append) => copy_uris(&mut append.args).collect(),
+            Commands::Concat(concat) => concat.from.iter_mut().chain(std::iter::once(&mut concat.to)).collect(),
+            Commands::Parts(parts) => parts.uris.iter_mut().collect(),
+            Commands::Multipart(multipart) => match &mut multipart.action {
+                MultipartAction::List { uris, .. } | MultipartAction::Abort { uris, .. } => uris.iter_mut().collect(),
+            },
+            Commands::Stat(stat) => stat.uris.iter_mut().collect(),
+            Commands::GetAcl(get_acl) => get_acl.uris.iter_mut().collect(),
+            Commands::SetAcl(set_acl) => set_acl.uris.iter_mut().collect(),
+            Commands::Wait(wait) => match &mut wait.condition {
+                WaitFor::ObjectExists { uris, .. } => uris.iter_mut().collect(),
+                WaitFor::BucketExists { buckets, .. } => buckets.iter_mut().collect(),
+            },
+            Commands::Prune(prune) => vec![&mut prune.uri],
+            Commands::Mirror(mirror) => std::iter::once(&mut mirror.to).chain(mirror.transfer.summary_to_mut()).collect(),
+            Commands::Shell(shell) => shell.uri.iter_mut().collect(),
+            #[cfg(feature = "mount")]
+            Commands::Mount(mount) => vec![&mut mount.uri],
+            #[cfg(feature = "gen-completion")]
+            Commands::GenerateCompletion(_) => vec![],
+        }
+    }
+}
+
 #[derive(Args, Debug)]
 pub(crate) struct Upload {
-    #[clap(required = true, value_parser, value_hint=clap::ValueHint::AnyPath)]
-    local_paths: Vec<std::path::PathBuf>,
+    /// Local paths, or HTTP(S) URLs streamed into S3 without touching local disk
+    #[clap(required = true, value_parser=clap::builder::OsStringValueParser::new().try_map(CopyArgument::try_from), value_hint=clap::ValueHint::AnyPath)]
+    sources: Vec<CopyArgument>,
     /// S3 URI in s3://bucket/path/components format
     #[clap(value_hint=clap::ValueHint::AnyPath)]
     to: s3::Uri,
@@ -76,21 +347,66 @@ pub(crate) struct Upload {
     #[clap(long, short = 'r')]
     recursive: bool,
 
+    /// Upload below a path rendered from this strftime format in UTC, e.g. '%Y/%m/%d/',
+    /// inserted between the destination prefix and the uploaded files
+    #[clap(long, value_name = "FORMAT", value_parser = parse_date_format)]
+    dated_prefix: Option<String>,
+
+    /// Upload each file below Hive-style partition directories rendered from its modification
+    /// time in UTC and the environment, e.g. 'date={mtime:%Y-%m-%d}/host={env:HOSTNAME}',
+    /// inserted between its destination directory and its name
+    #[clap(long, value_name = "TEMPLATE")]
+    partition_by: Option<transfer::Partition>,
+
+    /// Afterwards upload an index.html listing each directory below the destination that files
+    /// were uploaded into, and its parents up to the destination, for static website hosting.
+    /// An index.html not generated this way is left alone
+    #[clap(long)]
+    generate_index: bool,
+    /// Once everything is uploaded, write an empty _SUCCESS object into the destination prefix,
+    /// or with --partition-by into each partition written, as Hadoop and Spark jobs do for their output
+    #[clap(long)]
+    success_marker: bool,
+
     #[clap(flatten)]
     upload: s3::OptionsUpload,
 }
 
+fn parse_date_format(s: &str) -> Result<String, String> {
+    use chrono::format::{Item, StrftimeItems};
+    if StrftimeItems::new(s).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("invalid strftime format '{s}'"));
+    }
+    Ok(s.to_owned())
+}
+
 #[derive(Args, Debug)]
 pub(crate) struct Remove {
     /// S3 URI in s3://bucket/path/components format
     #[clap(required = true, value_hint=clap::ValueHint::Url)]
     remote_paths: Vec<s3::Uri>,
+    /// Remove every object below each path, treating it as a prefix
+    #[clap(long, short='r')]
+    recursive: bool,
+    /// Permanently remove every version and delete marker, instead of adding a delete marker in
+    /// versioned buckets. Size and age filters apply to each version
+    #[clap(long)]
+    all_versions: bool,
+    /// Continue to next object on error
+    #[clap(long, short='y')]
+    continue_on_error: bool,
+    #[clap(flatten)]
+    size: s3::OptionsSize,
+    #[clap(flatten)]
+    age: s3::OptionsAge,
+    #[clap(flatten)]
+    progress: cli::ArgProgress,
 }
 
 #[derive(Args, Debug)]
 pub(crate) struct ListFiles {
-    /// S3 URIs in s3://bucket/path/components format
-    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    /// S3 URIs in s3://bucket/path/components format. Without any, lists buckets as list-buckets
+    #[clap(value_hint=clap::ValueHint::Url)]
     remote_paths: Vec<s3::Uri>,
     #[clap(flatten)]
     command_args: s3::ListArguments,
@@ -109,17 +425,39 @@ pub(crate) struct Download {
 
     #[clap(long, short = 'r')]
     recursive: bool,
+
+    #[clap(flatten)]
+    glob_options: s3::GlobOptions,
+
+    #[clap(flatten)]
+    download: s3::OptionsDownload,
 }
 
 #[derive(Args, Debug)]
 pub(crate) struct ListBuckets {
 }
 
-use clap::builder::TypedValueParser;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+
+#[derive(Args, Debug)]
+pub(crate) struct RemoveBuckets {
+    /// S3 URIs in s3://bucket format
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    buckets: Vec<s3::Uri>,
+    /// Continue to next bucket on error
+    #[clap(long, short='y')]
+    continue_on_error: bool,
+    /// Remove every object in each bucket first. Previous versions of objects in versioned buckets are kept
+    #[clap(long)]
+    force: bool,
+
+    #[clap(flatten)]
+    progress: cli::ArgProgress,
+}
 
 #[derive(Args, Debug)]
 pub(crate) struct Copy {
-    /// Either <S3 URI..> <local path> or <local path..> <S3 URI>
+    /// Either <S3 URI..> <local path>, <local path..> <S3 URI>, <URL..> <S3 URI> or <S3 URI..> <S3 URI>
     #[clap(required = true, value_parser=clap::builder::OsStringValueParser::new().try_map(CopyArgument::try_from), value_hint=clap::ValueHint::AnyPath)]
     args: Vec<CopyArgument>,
 
@@ -129,8 +467,75 @@ pub(crate) struct Copy {
     #[clap(long, short = 'r')]
     recursive: bool,
 
+    #[clap(flatten)]
+    glob_options: s3::GlobOptions,
+
+    #[clap(flatten)]
+    rewrite: s3::OptionsRewrite,
+
+    #[clap(flatten)]
+    upload: s3::OptionsUpload,
+
+    #[clap(flatten)]
+    download: s3::OptionsDownload,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Move {
+    /// S3 URIs in s3://bucket/path/components format
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    from: Vec<s3::Uri>,
+    /// S3 URI, a directory ending in '/' when moving several objects
+    #[clap(value_hint=clap::ValueHint::Url)]
+    to: s3::Uri,
+    #[clap(long, short = 'r')]
+    recursive: bool,
+    /// Continue to next object on error
+    #[clap(long, short='y')]
+    continue_on_error: bool,
+    #[clap(flatten)]
+    rewrite: s3::OptionsRewrite,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Sync {
+    /// Either <local directory> <S3 URI>, <S3 URI> <local directory> or <S3 URI> <S3 URI>
+    #[clap(value_parser=clap::builder::OsStringValueParser::new().try_map(CopyArgument::try_from), value_hint=clap::ValueHint::AnyPath)]
+    from: CopyArgument,
+    #[clap(value_parser=clap::builder::OsStringValueParser::new().try_map(CopyArgument::try_from), value_hint=clap::ValueHint::AnyPath)]
+    to: CopyArgument,
+
+    #[clap(flatten)]
+    sync: transfer::OptionsSync,
+    /// When uploading, once the destination is in sync write an empty _SUCCESS object into it,
+    /// as Hadoop and Spark jobs do for their output
+    #[clap(long)]
+    success_marker: bool,
+
+    #[clap(flatten)]
+    transfer: transfer::OptionsTransfer,
+
     #[clap(flatten)]
     upload: s3::OptionsUpload,
+
+    #[clap(flatten)]
+    download: s3::OptionsDownload,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Diff {
+    /// Local directory
+    #[clap(value_parser, value_hint=clap::ValueHint::DirPath)]
+    local: std::path::PathBuf,
+    /// S3 prefix in s3://bucket/path/components format
+    #[clap(value_hint=clap::ValueHint::Url)]
+    remote: s3::Uri,
+    /// How to detect changed files
+    #[clap(long, value_enum, default_value = "size-mtime")]
+    compare: transfer::Compare,
+
+    #[clap(flatten)]
+    filter: s3::OptionsFilter,
 }
 
 #[derive(Args, Debug)]
@@ -138,6 +543,33 @@ pub(crate) struct Cat {
     /// S3 URIs in s3://bucket/path/components format
     #[clap(required = true, value_hint=clap::ValueHint::Url)]
     uris: Vec<s3::Uri>,
+    /// Continue to next object on error
+    #[clap(long, short='y')]
+    continue_on_error: bool,
+
+    #[clap(flatten)]
+    cache: s3::OptionsCache,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Presign {
+    /// S3 URIs in s3://bucket/path/components format
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    uris: Vec<s3::Uri>,
+    /// How long the URLs are valid, e.g. 900, 15m, 12h or 7d (the maximum)
+    #[clap(long, default_value = "1h", value_name = "DURATION", value_parser = parse_presign_expiry)]
+    expires_in: std::time::Duration,
+    /// Presign uploads (PUT) to the URIs, rather than downloads
+    #[clap(long)]
+    put: bool,
+}
+
+fn parse_presign_expiry(s: &str) -> Result<std::time::Duration, String> {
+    match cli::parse_duration(s)? {
+        expiry if expiry.is_zero() => Err("expiry must be greater than zero".into()),
+        expiry if expiry > s3::MAX_PRESIGN_EXPIRY => Err("expiry must be at most 7 days".into()),
+        expiry => Ok(expiry),
+    }
 }
 
 #[derive(Args, Debug)]
@@ -153,6 +585,365 @@ pub(crate) struct MakeBuckets {
     s3_options: s3::OptionsMakeBucket,
 }
 
+#[derive(Args, Debug)]
+pub(crate) struct Append {
+    /// Local file to append, or '-' or omitted for stdin, then the S3 URI in
+    /// s3://bucket/path/components format
+    #[clap(required = true, num_args = 1..=2, value_names = ["LOCAL", "S3_URI"], value_parser=clap::builder::OsStringValueParser::new().try_map(CopyArgument::try_from), value_hint=clap::ValueHint::AnyPath)]
+    args: Vec<CopyArgument>,
+    /// Use the provider's native append (e.g. S3 Express One Zone) instead of composing
+    #[clap(long)]
+    native: bool,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Concat {
+    /// S3 URIs in s3://bucket/path/components format, or prefixes ending in '/'
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    from: Vec<s3::Uri>,
+    /// S3 URI of the new object
+    #[clap(value_hint=clap::ValueHint::Url)]
+    to: s3::Uri,
+    #[clap(flatten)]
+    options: s3::OptionsConcat,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Parts {
+    /// S3 URIs in s3://bucket/path/components format
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    uris: Vec<s3::Uri>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Tag {
+    #[clap(subcommand)]
+    action: TagAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum TagAction {
+    /// Print each object's tags as KEY=VALUE lines
+    Get {
+        /// S3 URIs in s3://bucket/path/components format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        uris: Vec<s3::Uri>,
+    },
+    /// Replace each object's tags
+    Set {
+        /// S3 URIs in s3://bucket/path/components format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        uris: Vec<s3::Uri>,
+        /// Tag to set, e.g. 'team=data'. Repeatable, tags not given are removed
+        #[clap(long = "tag", value_name="KEY=VALUE", required = true, value_parser=s3::parse_tag)]
+        tags: Vec<(String, String)>,
+    },
+    /// Remove every tag of each object
+    Delete {
+        /// S3 URIs in s3://bucket/path/components format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        uris: Vec<s3::Uri>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Policy {
+    #[clap(subcommand)]
+    action: PolicyAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum PolicyAction {
+    /// Print each bucket's policy JSON
+    Get {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+    /// Replace a bucket's policy
+    Set {
+        /// S3 URI in s3://bucket format
+        #[clap(value_hint=clap::ValueHint::Url)]
+        bucket: s3::Uri,
+        /// Policy JSON file, or '-' for stdin
+        #[clap(value_parser, value_hint=clap::ValueHint::FilePath)]
+        from: std::path::PathBuf,
+    },
+    /// Remove each bucket's policy
+    Delete {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Versioning {
+    #[clap(subcommand)]
+    action: VersioningAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum VersioningAction {
+    /// Print each bucket's versioning state: enabled, suspended or unversioned
+    Status {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+    /// Keep every version of each object
+    Enable {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+    /// Stop keeping new versions
+    Suspend {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Encryption {
+    #[clap(subcommand)]
+    action: EncryptionAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum EncryptionAction {
+    /// Print each bucket's default encryption as 'set' takes it, noting an S3 Bucket Key, or 'none'
+    Status {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+    /// Encrypt new objects with SSE-S3 or SSE-KMS, KMS using an S3 Bucket Key
+    Set {
+        /// 'AES256', 'aws:kms' with the AWS managed key, or 'aws:kms:KEY' with a KMS key ID or ARN
+        #[clap(value_name = "ALGORITHM[:KEY]", value_parser = s3::parse_default_encryption)]
+        encryption: s3::DefaultEncryption,
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+    /// Remove each bucket's default encryption, which AWS reverts to AES256
+    Delete {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Inventory {
+    #[clap(subcommand)]
+    action: InventoryAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum InventoryAction {
+    /// List every object below a prefix into one file of key, size, ETag, last modified time and
+    /// storage class, printing its URI
+    ///
+    /// Each run writes to a new directory named by the time, e.g. DEST/2024-01-31T12-00Z/, so it
+    /// can run on a schedule. Earlier inventories below the source are left out
+    Create {
+        /// S3 URI of the prefix to list, in s3://bucket/path/components format
+        #[clap(value_hint=clap::ValueHint::Url)]
+        source: s3::Uri,
+        /// S3 URI of the directory inventories are written below
+        #[clap(long, value_name="DEST", value_hint=clap::ValueHint::Url)]
+        to: s3::Uri,
+        #[clap(long, value_enum, default_value="csv")]
+        format: s3::InventoryFormat,
+        #[clap(flatten)]
+        upload: s3::OptionsUpload,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Multipart {
+    #[clap(subcommand)]
+    action: MultipartAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum MultipartAction {
+    /// Print the age, bytes stored so far, destination and upload ID of each upload in progress
+    /// below the prefixes, oldest first
+    List {
+        /// S3 URIs in s3://bucket/path/components format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        uris: Vec<s3::Uri>,
+    },
+    /// Abort uploads in progress below the prefixes, deleting their parts
+    Abort {
+        /// S3 URIs in s3://bucket/path/components format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        uris: Vec<s3::Uri>,
+        /// Only the upload with this ID
+        #[clap(long, value_name="ID")]
+        upload_id: Option<String>,
+        /// Only uploads started at least this long ago, e.g. 7d, so running uploads are left alone
+        #[clap(long, value_name="DURATION", value_parser=cli::parse_duration)]
+        older_than: Option<std::time::Duration>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Wait {
+    #[clap(subcommand)]
+    condition: WaitFor,
+    /// Give up after this long in total, e.g. 90s or 10m
+    #[clap(long, global = true, value_name="DURATION", value_parser=cli::parse_duration, default_value="5m")]
+    timeout: std::time::Duration,
+    /// Pause after the first poll, doubling each poll up to 30s
+    #[clap(long, global = true, value_name="DURATION", value_parser=cli::parse_duration, default_value="1s")]
+    interval: std::time::Duration,
+}
+
+#[derive(Subcommand, Debug)]
+pub(crate) enum WaitFor {
+    /// Until each object exists, with HeadObject
+    ObjectExists {
+        /// S3 URIs in s3://bucket/path/components format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        uris: Vec<s3::Uri>,
+    },
+    /// Until each bucket exists, with HeadBucket
+    BucketExists {
+        /// S3 URIs in s3://bucket format
+        #[clap(required = true, value_hint=clap::ValueHint::Url)]
+        buckets: Vec<s3::Uri>,
+    },
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Stat {
+    /// S3 URIs in s3://bucket/path/components format
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    uris: Vec<s3::Uri>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct GetAcl {
+    /// S3 URIs in s3://bucket/path/components format, or s3://bucket/ for the bucket
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    uris: Vec<s3::Uri>,
+    /// Print one JSON object per URI instead of a table
+    #[clap(long)]
+    json: bool,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct SetAcl {
+    /// S3 URIs in s3://bucket/path/components format
+    #[clap(required = true, value_hint=clap::ValueHint::Url)]
+    uris: Vec<s3::Uri>,
+    /// Set the ACL of every object below each prefix
+    #[clap(long, short='r')]
+    recursive: bool,
+    /// Continue to next object on error
+    #[clap(long, short='y')]
+    continue_on_error: bool,
+    /// Canned access control list, e.g. private or public-read
+    #[clap(long, value_parser=PossibleValuesParser::new(aws_sdk_s3::types::ObjectCannedAcl::values()).map(|s| aws_sdk_s3::types::ObjectCannedAcl::from(s.as_str())),
+        help_heading="Access Control", conflicts_with_all=["grant_read", "grant_full", "grant_read_acp", "grant_write_acp"],
+        required_unless_present_any=["grant_read", "grant_full", "grant_read_acp", "grant_write_acp"])]
+    canned_acl: Option<aws_sdk_s3::types::ObjectCannedAcl>,
+    #[clap(flatten)]
+    access_control: s3::OptionsAccessControl,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Api {
+    /// S3 API operation, one of a fixed set of read-only queries
+    #[clap(value_enum)]
+    operation: s3::ApiOperation,
+    /// Request parameter, e.g. 'Bucket=my-bucket'. Repeatable
+    #[clap(long = "param", value_name="NAME=VALUE", value_parser=s3::parse_api_parameter)]
+    params: Vec<(String, String)>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Shell {
+    /// Starting location in s3://bucket/prefix format
+    #[clap(value_hint=clap::ValueHint::Url)]
+    uri: Option<s3::Uri>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Prune {
+    /// S3 prefix in s3://bucket/path/ format
+    #[clap(value_hint=clap::ValueHint::Url)]
+    uri: s3::Uri,
+    /// Layout of backup keys below the prefix, e.g. '{name}-{date}.tar.zst'. {date} is
+    /// YYYY-MM-DD, YYYY/MM/DD or YYYYMMDD with an optional time, backups are pruned
+    /// separately for each {name}. Keys not matching are left alone. Without a pattern,
+    /// every object but the chunks and manifests of chunked syncs is a backup dated by its
+    /// modification time
+    #[clap(long = "match", value_name = "PATTERN")]
+    pattern: Option<prune::Pattern>,
+    /// Delete backups not kept, rather than only listing them
+    #[clap(long)]
+    delete: bool,
+
+    #[clap(flatten)]
+    retention: prune::Retention,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Clean {
+    /// Local directory, searched recursively
+    #[clap(value_parser, value_hint=clap::ValueHint::DirPath, default_value=".")]
+    dir: std::path::PathBuf,
+    /// Only files unmodified for at least this long, so running transfers are left alone
+    #[clap(long, value_name="DURATION", value_parser=cli::parse_duration, default_value="1h")]
+    min_age: std::time::Duration,
+    /// Delete the files found, rather than only listing them
+    #[clap(long)]
+    delete: bool,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Checksum {
+    /// Pairs of a local file or directory and the S3 URI to compare it to
+    #[clap(required = true, num_args = 2.., value_names = ["LOCAL", "S3_URI"])]
+    pairs: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub(crate) struct Mirror {
+    /// Manifest file, or '-' for stdin
+    #[clap(value_parser, value_hint=clap::ValueHint::FilePath)]
+    manifest: std::path::PathBuf,
+    /// S3 prefix in s3://bucket/path/ format, files are stored under their URL filename
+    #[clap(value_hint=clap::ValueHint::Url)]
+    to: s3::Uri,
+
+    #[clap(flatten)]
+    transfer: transfer::OptionsTransfer,
+
+    #[clap(flatten)]
+    upload: s3::OptionsUpload,
+}
+
+#[cfg(feature = "mount")]
+#[derive(Args, Debug)]
+pub(crate) struct Mount {
+    /// S3 prefix in s3://bucket/path/ format
+    #[clap(value_hint=clap::ValueHint::Url)]
+    uri: s3::Uri,
+    /// Existing empty directory to mount on
+    #[clap(value_parser, value_hint=clap::ValueHint::DirPath)]
+    mountpoint: std::path::PathBuf,
+    /// Mount read-only, currently the only supported mode
+    #[clap(long, required = true)]
+    read_only: bool,
+}
+
 #[cfg(feature = "gen-completion")]
 #[derive(Args, Debug)]
 pub(crate) struct GenerateCompletion {
@@ -165,6 +956,10 @@ pub enum MainResult {
     ErrorArguments,
     ErrorSomeOperationsFailed,
     Cancelled,
+    /// The provider refused writes for lack of storage quota, so the rest weren't attempted
+    ErrorQuotaExceeded,
+    /// A policy rule in the config file denied the command, so nothing was attempted
+    ErrorDenied,
 }
 
 impl MainResult {
@@ -183,51 +978,141 @@ impl std::process::Termination for MainResult {
             Self::ErrorArguments => std::process::ExitCode::from(1),
             Self::ErrorSomeOperationsFailed => std::process::ExitCode::from(2),
             Self::Cancelled => std::process::ExitCode::from(3),
+            Self::ErrorQuotaExceeded => std::process::ExitCode::from(4),
+            Self::ErrorDenied => std::process::ExitCode::from(5),
         }
     }
 }
 
 impl Upload {
     pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
-        transfer::upload(&self.local_paths, &self.to, client, opts, &self.transfer, &self.upload, self.recursive).await
+        let invalid_args = |message: &str| {
+            use clap::CommandFactory;
+            let _ = Arguments::command()
+                .error(clap::error::ErrorKind::ArgumentConflict, message)
+                .print();
+            MainResult::ErrorArguments
+        };
+        let mut paths = vec![];
+        let mut urls = vec![];
+        for source in &self.sources {
+            match source {
+                CopyArgument::LocalFile(path) => paths.push(path.clone()),
+                CopyArgument::Url(url) => urls.push(manifest::Entry { url: url.clone(), checksum: None }),
+                CopyArgument::Uri(uri) => return invalid_args(&format!("upload requires local paths or URLs, '{uri}' is an S3 URI, for which see cp")),
+            }
+        }
+        if !paths.is_empty() && !urls.is_empty() {
+            return invalid_args("upload requires either <local path..> or <URL..>, not both");
+        }
+        if !urls.is_empty() && self.partition_by.is_some() {
+            return invalid_args("--partition-by requires local paths, having no modification time for URLs");
+        }
+        let to = match &self.dated_prefix {
+            Some(format) => transfer::dated_destination(&self.to, format, &chrono::Utc::now()),
+            None => self.to.clone(),
+        };
+        let (result, written) = match urls.is_empty() {
+            true => transfer::upload_written(&paths, &to, client, opts, &self.transfer, &self.upload, self.recursive, self.partition_by.as_ref()).await,
+            false => transfer::upload_urls_written(&urls, &to, client, opts, &self.transfer, &self.upload).await,
+        };
+        if !matches!(result, MainResult::Success) {
+            return result;
+        }
+        if self.generate_index {
+            let written: Vec<&s3::Key> = written.iter().filter(|uri| uri.bucket == to.bucket).map(|uri| &uri.key).collect();
+            match client.put_indexes(opts.verbose, &self.upload, &to, &written).await {
+                Ok(count) => if opts.verbose {
+                    println!("🏁 generated {count} index(es) below {to}");
+                },
+                Err(e) => {
+                    cli::println_error(format_args!("failed to generate indexes below {to}: {e}"));
+                    return MainResult::ErrorSomeOperationsFailed;
+                },
+            }
+        }
+        if self.success_marker {
+            for prefix in transfer::success_marker_prefixes(&to, &written, self.partition_by.is_some()) {
+                let result = transfer::write_success_marker(client, opts.verbose, &prefix).await;
+                if !matches!(result, MainResult::Success) {
+                    return result;
+                }
+            }
+        }
+        MainResult::Success
     }
 }
 
 impl Download {
     pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
-        transfer::download(&self.uris, &self.to, client, opts, &self.transfer, self.recursive).await
+        transfer::download(&self.uris, &self.to, client, opts, &self.transfer, &self.download, &self.glob_options, self.recursive).await
     }
 }
 
 impl Remove {
     pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
-        for uri in &self.remote_paths {
-            if let Err(e) = client.remove(opts, uri).await {
-                eprintln!("❌: failed to remove {}: {e}", uri);
-                return MainResult::ErrorSomeOperationsFailed;
-            }
+        let confirmed: Vec<s3::Uri> = self.remote_paths.iter()
+            .filter(|uri| opts.confirm(format_args!("remove {uri}{}{}", if self.recursive { " recursively" } else { "" }, if self.all_versions { " with all versions" } else { "" })))
+            .cloned()
+            .collect();
+        if confirmed.is_empty() {
+            return MainResult::Cancelled;
         }
-        MainResult::Success
+        transfer::remove(&confirmed, client, opts, &self.progress, &self.size, &self.age, self.recursive, self.all_versions, self.continue_on_error).await
     }
 }
 
 impl ListFiles {
     pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
-        if let Err(val_err) = self.command_args.validate() {
-                use clap::CommandFactory;
-                let _ = Arguments::command()
-                    .error(val_err.0, val_err.1)
-                    .print();
-            return MainResult::ErrorArguments;
-        };
-        for uri in &self.remote_paths {
-            if let Err(e) = client.ls(opts, &self.command_args, uri).await {
-                eprintln!("❌: failed to list {uri}: {e}");
-                return MainResult::ErrorSomeOperationsFailed;
+        if self.remote_paths.is_empty() {
+            return ListBuckets {}.run(client, opts).await;
+        }
+        list(client, opts, &self.command_args, &self.remote_paths).await
+    }
+}
+
+pub(crate) async fn list(client: &s3::Client, opts: &SharedOptions, command_args: &s3::ListArguments, remote_paths: &[s3::Uri]) -> MainResult {
+    if let Err(val_err) = command_args.validate() {
+            use clap::CommandFactory;
+            let _ = Arguments::command()
+                .error(val_err.0, val_err.1)
+                .print();
+        return MainResult::ErrorArguments;
+    };
+    let mut output = match command_args.open_output() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("❌: failed to open output: {e}");
+            return MainResult::ErrorSomeOperationsFailed;
+        },
+    };
+    let headers = command_args.group_headers(remote_paths.len());
+    let mut count = 0;
+    let mut error_count = 0;
+    for (i, uri) in remote_paths.iter().enumerate() {
+        if headers {
+            if i > 0 {
+                println!();
             }
+            println!("{uri}:");
+        }
+        match client.ls(opts, command_args, &mut output, uri).await {
+            Ok(listed) => count += listed,
+            Err(e) => {
+                eprintln!("❌: failed to list {uri}: {e}");
+                error_count += 1;
+            },
         }
-        MainResult::Success
     }
+    if let Err(e) = output.finish() {
+        eprintln!("❌: failed to write output: {e}");
+        return MainResult::ErrorSomeOperationsFailed;
+    }
+    if let Some(unmet) = command_args.unmet_expectation(count) {
+        cli::println_error(format_args!("{unmet}"));
+        return MainResult::ErrorSomeOperationsFailed;
+    }
+    MainResult::from_error_count(error_count)
 }
 
 impl ListBuckets {
@@ -240,10 +1125,11 @@ impl ListBuckets {
     }
 }
 
-/// Either an S3 URI or a local path
+/// Either an S3 URI, an HTTP(S) URL or a local path
 #[derive (Debug, Clone)]
 pub enum CopyArgument {
     Uri(s3::Uri),
+    Url(url::Url),
     LocalFile(std::path::PathBuf),
 }
 
@@ -251,6 +1137,11 @@ impl TryFrom<&std::ffi::OsStr> for CopyArgument {
     type Error = String;
     fn try_from(arg: &std::ffi::OsStr) -> Result<Self, String> {
         if let Some(unicode) = arg.to_str() {
+            if unicode.starts_with("http://") || unicode.starts_with("https://") {
+                return url::Url::parse(unicode)
+                    .map(CopyArgument::Url)
+                    .map_err(|e| format!("invalid URL: {e}"));
+            }
             match unicode.parse() {
                 Ok(uri) => return Ok(CopyArgument::Uri(uri)),
                 Err(s3::UriError::ParseError{..}) => {},
@@ -267,12 +1158,80 @@ impl TryFrom<std::ffi::OsString> for CopyArgument {
     }
 }
 
+impl Move {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        if self.from.len() > 1 && !self.to.key.is_explicitly_directory() {
+            use clap::CommandFactory;
+            let _ = Arguments::command()
+                .error(clap::error::ErrorKind::ArgumentConflict, "moving several sources requires a destination ending in '/'")
+                .print();
+            return MainResult::ErrorArguments;
+        }
+        let mut error_count = 0;
+        for from in &self.from {
+            let result = match self.recursive {
+                false => match self.rewrite.object_destination(from, &self.to) {
+                    Ok(to) => client.move_object(opts.verbose, from, &to).await.map(|_| 0),
+                    Err(e) => Err(e),
+                },
+                true => client.move_recursive(opts.verbose, from, &self.to, self.continue_on_error, &self.rewrite).await,
+            };
+            match result {
+                Ok(failed) => error_count += failed,
+                Err(e) => {
+                    cli::println_error(format_args!("failed to move {from}: {e}"));
+                    error_count += 1;
+                },
+            }
+            if error_count > 0 && !self.continue_on_error {
+                break;
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Sync {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let mut marker = None;
+        let direction = match (&self.from, &self.to) {
+            (CopyArgument::LocalFile(local), CopyArgument::Uri(remote)) => {
+                marker = Some(s3::Uri::new(remote.bucket.clone(), remote.key.to_explicit_directory()));
+                transfer::Direction::Upload { local, remote, options: &self.upload }
+            },
+            (CopyArgument::Uri(remote), CopyArgument::LocalFile(local)) => transfer::Direction::Download { remote, local, options: &self.download },
+            (CopyArgument::Uri(from), CopyArgument::Uri(to)) => {
+                marker = Some(s3::Uri::new(to.bucket.clone(), to.key.to_explicit_directory()));
+                transfer::Direction::Copy { from, to, options: &self.upload }
+            },
+            _ => {
+                use clap::CommandFactory;
+                let _ = Arguments::command()
+                    .error(clap::error::ErrorKind::ArgumentConflict, "sync requires either <local directory> <S3 URI>, <S3 URI> <local directory> or <S3 URI> <S3 URI>")
+                    .print();
+                return MainResult::ErrorArguments;
+            },
+        };
+        let result = transfer::sync(direction, client, opts, &self.transfer, &self.sync).await;
+        match (self.success_marker, marker) {
+            (true, Some(prefix)) if matches!(result, MainResult::Success) => transfer::write_success_marker(client, opts.verbose, &prefix).await,
+            _ => result,
+        }
+    }
+}
+
+impl Diff {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        transfer::diff(&self.local, &self.remote, client, opts, self.compare, &self.filter).await
+    }
+}
+
 impl Copy {
     pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
         let invalid_args = || {
             use clap::CommandFactory;
             let _ = Arguments::command()
-                .error(clap::error::ErrorKind::ArgumentConflict, "cp requires either <S3 URI..> <local path> or <local path..> <S3 URI>")
+                .error(clap::error::ErrorKind::ArgumentConflict, "cp requires either <S3 URI..> <local path>, <local path..> <S3 URI>, <URL..> <S3 URI> or <S3 URI..> <S3 URI>")
                 .print();
             MainResult::ErrorArguments
         };
@@ -282,20 +1241,29 @@ impl Copy {
                 for uri in from {
                     match uri {
                         CopyArgument::Uri(uri) => uris.push(uri.clone()),
-                        CopyArgument::LocalFile(_) => return invalid_args(),
+                        CopyArgument::LocalFile(_) | CopyArgument::Url(_) => return invalid_args(),
                     }
                 }
-                transfer::download(&uris, to, client, opts, &self.transfer, self.recursive).await
+                transfer::download(&uris, to, client, opts, &self.transfer, &self.download, &self.glob_options, self.recursive).await
             },
             [from @ .., CopyArgument::Uri(to)] => {
                 let mut paths = vec![];
+                let mut urls = vec![];
+                let mut uris = vec![];
                 for path in from {
                     match path {
                         CopyArgument::LocalFile(path) => paths.push(path.clone()),
-                        CopyArgument::Uri(_) => return invalid_args(),
+                        CopyArgument::Url(url) => urls.push(manifest::Entry { url: url.clone(), checksum: None }),
+                        CopyArgument::Uri(uri) => uris.push(uri.clone()),
                     }
                 }
-                transfer::upload(&paths, to, client, opts, &self.transfer, &self.upload, self.recursive).await
+                match (paths.is_empty(), urls.is_empty(), uris.is_empty()) {
+                    (_, true, true) => transfer::upload(&paths, to, client, opts, &self.transfer, &self.upload, self.recursive, None).await,
+                    (true, false, true) => transfer::upload_urls(&urls, to, client, opts, &self.transfer, &self.upload).await,
+                    (true, true, false) if uris.len() > 1 && !to.key.is_explicitly_directory() => invalid_args(),
+                    (true, true, false) => transfer::copy(&uris, to, client, opts, &self.transfer, &self.upload, &self.glob_options, &self.rewrite, self.recursive).await,
+                    _ => invalid_args(),
+                }
             },
             _ => invalid_args(),
         }
@@ -304,13 +1272,32 @@ impl Copy {
 
 impl Cat {
     pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let mut error_count = 0;
         for uri in &self.uris {
             if opts.verbose {
                 eprintln!("🏁 cat '{uri}'");
             }
-            if let Err(e) = client.cat(uri).await {
+            if let Err(e) = client.cat(&self.cache, uri).await {
                 cli::println_error(format_args!("failed to cat {uri}: {e}"));
-                return MainResult::ErrorSomeOperationsFailed;
+                error_count += 1;
+                if !self.continue_on_error {
+                    break;
+                }
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Presign {
+    pub(crate) async fn run(&self, client: &s3::Client, _opts: &SharedOptions) -> MainResult {
+        for uri in &self.uris {
+            match client.presign(uri, self.put, self.expires_in).await {
+                Ok(url) => println!("{url}"),
+                Err(e) => {
+                    cli::println_error(format_args!("failed to presign {uri}: {e}"));
+                    return MainResult::ErrorSomeOperationsFailed;
+                },
             }
         }
         MainResult::Success
@@ -355,3 +1342,716 @@ impl MakeBuckets {
     }
 }
 
+impl RemoveBuckets {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        for uri in &self.buckets {
+            if !uri.key.is_empty() {
+                use clap::CommandFactory;
+                let _ = Arguments::command()
+                    .error(clap::error::ErrorKind::InvalidValue, "remove_buckets requires pure bucket arguments without a key, e.g. 's3://bucketname/'")
+                    .print();
+                return MainResult::ErrorArguments;
+            }
+        }
+        let mut error_count = 0;
+        for uri in &self.buckets {
+            if !opts.confirm(format_args!("remove bucket {uri}{}", if self.force { " and everything in it" } else { "" })) {
+                continue;
+            }
+            if self.force {
+                match transfer::remove(std::slice::from_ref(uri), client, opts, &self.progress, &Default::default(), &Default::default(), true, false, false).await {
+                    MainResult::Success => {},
+                    MainResult::Cancelled => return MainResult::Cancelled,
+                    _ => {
+                        cli::println_error(format_args!("not removing bucket {uri} after failing to empty it"));
+                        error_count += 1;
+                        if !self.continue_on_error {
+                            break;
+                        }
+                        continue;
+                    },
+                }
+            }
+            if opts.verbose {
+                eprintln!("🏁 rb '{uri}'");
+            }
+            if let Err(e) = client.remove_bucket(uri).await {
+                cli::println_error(format_args!("failed to remove bucket {uri}: {e}"));
+                error_count += 1;
+                if !self.continue_on_error {
+                    break;
+                }
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+
+impl Append {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let stdin = std::path::Path::new("-");
+        let (from, to) = match self.args.as_slice() {
+            [CopyArgument::Uri(to)] => (stdin, to),
+            [CopyArgument::LocalFile(from), CopyArgument::Uri(to)] => (from.as_path(), to),
+            _ => {
+                use clap::CommandFactory;
+                let _ = Arguments::command()
+                    .error(clap::error::ErrorKind::ValueValidation, "expected an optional local file followed by an S3 URI")
+                    .print();
+                return MainResult::ErrorArguments;
+            },
+        };
+        let data = if from == stdin {
+            use tokio::io::AsyncReadExt;
+            let mut data = Vec::new();
+            tokio::io::stdin().read_to_end(&mut data).await.map(|_| data)
+        } else {
+            tokio::fs::read(from).await
+        };
+        let data = match data {
+            Ok(d) => d,
+            Err(e) => {
+                cli::println_error(format_args!("failed to read {from:?}: {e}"));
+                return MainResult::ErrorSomeOperationsFailed;
+            },
+        };
+        if let Err(e) = client.append(opts.verbose, &data, to, self.native).await {
+            cli::println_error(format_args!("failed to append to {to}: {e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        }
+        MainResult::Success
+    }
+}
+
+impl Concat {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        if let Err(e) = client.concat(opts.verbose, &self.options, &self.from, &self.to).await {
+            cli::println_error(format_args!("failed to join into {}: {e}", self.to));
+            return MainResult::ErrorSomeOperationsFailed;
+        }
+        MainResult::Success
+    }
+}
+
+impl Shell {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions, policy: &policy::Policy) -> MainResult {
+        shell::run(client, opts, policy, self.uri.clone()).await
+    }
+}
+
+impl Prune {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let root = s3::Uri::new(self.uri.bucket.clone(), self.uri.key.to_explicit_directory());
+        let objects = match client.list_objects(&root).await {
+            Ok(objects) => objects,
+            Err(e) => {
+                cli::println_error(format_args!("failed to list {root}: {e}"));
+                return MainResult::ErrorSomeOperationsFailed;
+            },
+        };
+        let mut backups = Vec::new();
+        for object in &objects {
+            let Some(key) = object.key() else { continue };
+            let relative = &key[root.key.len()..];
+            if relative.is_empty() || relative.ends_with('/') || (root.key.is_empty() && relative.starts_with(s3::LOCK_PREFIX)) {
+                continue;
+            }
+            // Chunks and manifests of chunked syncs aren't backups, and other files still reference them
+            let in_chunks = relative.starts_with(s3::CHUNK_DIRECTORY) || relative.contains(&format!("/{}", s3::CHUNK_DIRECTORY));
+            if in_chunks || relative.ends_with(s3::MANIFEST_SUFFIX) {
+                continue;
+            }
+            let modified = object.last_modified()
+                .and_then(|t| chrono::DateTime::from_timestamp(t.secs(), 0))
+                .map(|t| t.naive_utc())
+                .unwrap_or_default();
+            let (name, time) = match &self.pattern {
+                None => (String::new(), modified),
+                Some(pattern) => match pattern.matches(relative) {
+                    Some((name, time)) => (name, time.unwrap_or(modified)),
+                    None => {
+                        if opts.verbose {
+                            println!("🏁 ignoring {key}, not matching pattern");
+                        }
+                        continue;
+                    },
+                },
+            };
+            backups.push(prune::Backup { key: key.to_owned(), name, time });
+        }
+
+        let kept = prune::keep(&backups, &self.retention);
+        let mut removals = Vec::new();
+        for backup in &backups {
+            let uri = s3::Uri::new(root.bucket.clone(), s3::Key::new(backup.key.clone()));
+            if let Some(reasons) = kept.get(&backup.key) {
+                println!("keep\t{uri}\t{}", reasons.join(","));
+                continue;
+            }
+            println!("delete\t{uri}");
+            removals.push(uri);
+        }
+        let to_delete = removals.len();
+        if self.delete && to_delete > 0 && !opts.confirm(format_args!("delete {to_delete} backup(s) below {root}")) {
+            return MainResult::Cancelled;
+        }
+        let mut error_count = 0;
+        let mut deleted = 0;
+        for uri in removals.iter().filter(|_| self.delete) {
+            match client.remove(opts, uri).await {
+                Ok(()) => deleted += 1,
+                Err(e) => {
+                    cli::println_error(format_args!("failed to remove {uri}: {e}"));
+                    error_count += 1;
+                },
+            }
+        }
+        match self.delete {
+            true if opts.verbose => println!("🏁 deleted {deleted} of {} backup(s), kept {}", backups.len(), kept.len()),
+            true => {},
+            false if to_delete > 0 => eprintln!("⚠️: dry run, pass --delete to remove {to_delete} backup(s)"),
+            false => {},
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Clean {
+    pub(crate) async fn run(&self, _client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let orphans = match clean::find(&self.dir, true, self.min_age) {
+            Ok(orphans) => orphans,
+            Err(e) => {
+                cli::println_error(format_args!("failed to search {:?}: {e}", self.dir));
+                return MainResult::ErrorArguments;
+            },
+        };
+        for orphan in &orphans {
+            println!("{}\t{}\t{}", clean::describe_age(orphan.age), cli::format_size(orphan.size), orphan.path.display());
+        }
+        if orphans.is_empty() || !self.delete {
+            if !orphans.is_empty() {
+                eprintln!("⚠️: dry run, pass --delete to remove {} file(s)", orphans.len());
+            }
+            return MainResult::Success;
+        }
+        if !opts.confirm(format_args!("delete {} file(s) below {:?}", orphans.len(), self.dir)) {
+            return MainResult::Cancelled;
+        }
+        let mut error_count = 0;
+        for orphan in &orphans {
+            match std::fs::remove_file(&orphan.path) {
+                Ok(()) if opts.verbose => println!("🏁 deleted {:?}", orphan.path),
+                Ok(()) => {},
+                Err(e) => {
+                    cli::println_error(format_args!("failed to remove {:?}: {e}", orphan.path));
+                    error_count += 1;
+                },
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Checksum {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        if !self.pairs.len().is_multiple_of(2) {
+            cli::println_error(format_args!("expected pairs of LOCAL S3_URI, '{}' has no S3 URI", self.pairs[self.pairs.len() - 1]));
+            return MainResult::ErrorArguments;
+        }
+        let mut pairs = Vec::new();
+        for pair in self.pairs.chunks(2) {
+            let parsed = pair[1].parse::<s3::Uri>()
+                .and_then(|uri| match opts.raw_keys {
+                    true => Ok(uri),
+                    false => uri.normalized(),
+                });
+            match parsed {
+                Ok(uri) => pairs.push((std::path::PathBuf::from(&pair[0]), uri)),
+                Err(e) => {
+                    cli::println_error(format_args!("invalid S3 URI '{}': {e}", pair[1]));
+                    return MainResult::ErrorArguments;
+                },
+            }
+        }
+        let mut mismatches = 0;
+        for (local, uri) in &pairs {
+            mismatches += checksum::check(client, opts.verbose, local, uri).await;
+        }
+        MainResult::from_error_count(mismatches)
+    }
+}
+
+impl Mirror {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let text = if self.manifest.as_os_str() == "-" {
+            use tokio::io::AsyncReadExt;
+            let mut text = String::new();
+            tokio::io::stdin().read_to_string(&mut text).await.map(|_| text)
+        } else {
+            tokio::fs::read_to_string(&self.manifest).await
+        };
+        let text = match text {
+            Ok(t) => t,
+            Err(e) => {
+                cli::println_error(format_args!("failed to read manifest {:?}: {e}", self.manifest));
+                return MainResult::ErrorArguments;
+            },
+        };
+        let entries = match manifest::parse(&text) {
+            Ok(entries) => entries,
+            Err(e) => {
+                cli::println_error(format_args!("invalid manifest {:?}: {e}", self.manifest));
+                return MainResult::ErrorArguments;
+            },
+        };
+        let to = s3::Uri::new(self.to.bucket.clone(), self.to.key.to_explicit_directory());
+        transfer::upload_urls(&entries, &to, client, opts, &self.transfer, &self.upload).await
+    }
+}
+
+#[cfg(feature = "mount")]
+impl Mount {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        if let Err(e) = client.mount(opts.verbose, &self.uri, &self.mountpoint).await {
+            cli::println_error(format_args!("failed to mount {} at {:?}: {e}", self.uri, self.mountpoint));
+            return MainResult::ErrorSomeOperationsFailed;
+        }
+        MainResult::Success
+    }
+}
+
+impl Parts {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        for uri in &self.uris {
+            if opts.verbose {
+                eprintln!("🏁 parts '{uri}'");
+            }
+            let object = match client.object_parts(uri).await {
+                Ok(o) => o,
+                Err(e) => {
+                    cli::println_error(format_args!("failed to get parts of {uri}: {e}"));
+                    return MainResult::ErrorSomeOperationsFailed;
+                },
+            };
+            let etag = object.etag.as_deref().unwrap_or("-").trim_matches('"');
+            println!("{uri}: {} parts, {} bytes, ETag {etag}", object.parts.len(), object.size);
+            let number_width = cli::digit_count(object.parts.len() as u64).max("part".len());
+            let size_width = cli::digit_count(object.parts.iter().map(|p| p.size as u64).max().unwrap_or(0)).max("size".len());
+            if !object.parts.is_empty() {
+                println!("{:>number_width$} {:>size_width$} checksum", "part", "size");
+            }
+            for part in &object.parts {
+                println!("{:>number_width$} {:>size_width$} {}", part.number, part.size, part.checksum.as_deref().unwrap_or("-"));
+            }
+        }
+        MainResult::Success
+    }
+}
+
+impl Policy {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let (verb, buckets) = match &self.action {
+            PolicyAction::Get { buckets } => ("get policy of", buckets.as_slice()),
+            PolicyAction::Set { bucket, .. } => ("set policy of", std::slice::from_ref(bucket)),
+            PolicyAction::Delete { buckets } => ("delete policy of", buckets.as_slice()),
+        };
+        let policy = match &self.action {
+            PolicyAction::Set { from, .. } => match read_policy(from).await {
+                Ok(policy) => Some(policy),
+                Err(e) => {
+                    cli::println_error(format_args!("failed to read {from:?}: {e}"));
+                    return MainResult::ErrorSomeOperationsFailed;
+                },
+            },
+            _ => None,
+        };
+        let mut error_count = 0;
+        for uri in buckets {
+            if opts.verbose {
+                eprintln!("🏁 {verb} '{uri}'");
+            }
+            let result = match &self.action {
+                PolicyAction::Get { .. } => client.policy(&uri.bucket).await.map(|policy| {
+                    if buckets.len() > 1 {
+                        println!("s3://{}/", uri.bucket);
+                    }
+                    println!("{}", policy.trim_end());
+                }),
+                PolicyAction::Set { .. } => client.put_policy(&uri.bucket, policy.clone().unwrap_or_default()).await,
+                PolicyAction::Delete { .. } => client.delete_policy(&uri.bucket).await,
+            };
+            if let Err(e) = result {
+                cli::println_error(format_args!("failed to {verb} {uri}: {e}"));
+                error_count += 1;
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+/// Policy JSON from a file, or stdin for '-'
+async fn read_policy(from: &std::path::Path) -> std::io::Result<String> {
+    if from.as_os_str() != "-" {
+        return tokio::fs::read_to_string(from).await;
+    }
+    use tokio::io::AsyncReadExt;
+    let mut policy = String::new();
+    tokio::io::stdin().read_to_string(&mut policy).await?;
+    Ok(policy)
+}
+
+impl Versioning {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let (verb, buckets) = match &self.action {
+            VersioningAction::Status { buckets } => ("get versioning of", buckets),
+            VersioningAction::Enable { buckets } => ("enable versioning of", buckets),
+            VersioningAction::Suspend { buckets } => ("suspend versioning of", buckets),
+        };
+        let mut error_count = 0;
+        for uri in buckets {
+            if opts.verbose {
+                eprintln!("🏁 {verb} '{uri}'");
+            }
+            let result = match &self.action {
+                VersioningAction::Status { .. } => client.versioning(&uri.bucket).await.map(|status| match buckets.len() {
+                    1 => println!("{status}"),
+                    _ => println!("s3://{}/: {status}", uri.bucket),
+                }),
+                VersioningAction::Enable { .. } => client.set_versioning(&uri.bucket, true).await,
+                VersioningAction::Suspend { .. } => client.set_versioning(&uri.bucket, false).await,
+            };
+            if let Err(e) = result {
+                cli::println_error(format_args!("failed to {verb} {uri}: {e}"));
+                error_count += 1;
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Encryption {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let (verb, buckets) = match &self.action {
+            EncryptionAction::Status { buckets } => ("get encryption of", buckets),
+            EncryptionAction::Set { buckets, .. } => ("set encryption of", buckets),
+            EncryptionAction::Delete { buckets } => ("delete encryption of", buckets),
+        };
+        let mut error_count = 0;
+        for uri in buckets {
+            if opts.verbose {
+                eprintln!("🏁 {verb} '{uri}'");
+            }
+            let result = match &self.action {
+                EncryptionAction::Status { .. } => client.encryption(&uri.bucket).await.map(|encryption| {
+                    let encryption = encryption.unwrap_or_else(|| "none".into());
+                    match buckets.len() {
+                        1 => println!("{encryption}"),
+                        _ => println!("s3://{}/: {encryption}", uri.bucket),
+                    }
+                }),
+                EncryptionAction::Set { encryption, .. } => client.set_encryption(&uri.bucket, encryption).await,
+                EncryptionAction::Delete { .. } => client.delete_encryption(&uri.bucket).await,
+            };
+            if let Err(e) = result {
+                cli::println_error(format_args!("failed to {verb} {uri}: {e}"));
+                error_count += 1;
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Inventory {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let InventoryAction::Create { source, to, format, upload } = &self.action;
+        if opts.verbose {
+            eprintln!("🏁 listing '{source}'");
+        }
+        match client.create_inventory(opts.verbose, upload, source, to, *format, &chrono::Utc::now()).await {
+            Ok(uri) => {
+                println!("{uri}");
+                MainResult::Success
+            },
+            Err(e) => {
+                cli::println_error(format_args!("failed to create inventory of {source}: {e}"));
+                MainResult::ErrorSomeOperationsFailed
+            },
+        }
+    }
+}
+
+impl Multipart {
+    /// Uploads below each of `uris` for which `keep` holds, counting failed listings in `error_count`
+    async fn find(client: &s3::Client, opts: &SharedOptions, uris: &[s3::Uri], keep: impl Fn(&s3::IncompleteUpload) -> bool, error_count: &mut u32) -> Vec<s3::IncompleteUpload> {
+        let mut found = Vec::new();
+        for uri in uris {
+            if opts.verbose {
+                eprintln!("🏁 listing uploads below '{uri}'");
+            }
+            match client.incomplete_uploads(uri).await {
+                Ok(uploads) => found.extend(uploads.into_iter().filter(&keep)),
+                Err(e) => {
+                    cli::println_error(format_args!("failed to list uploads below {uri}: {e}"));
+                    *error_count += 1;
+                },
+            }
+        }
+        found
+    }
+
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let now = std::time::SystemTime::now();
+        let mut error_count = 0;
+        match &self.action {
+            MultipartAction::List { uris } => {
+                for upload in Self::find(client, opts, uris, |_| true, &mut error_count).await {
+                    match client.incomplete_upload_bytes(&upload).await {
+                        Ok(bytes) => println!("{}\t{}\t{}\t{}", clean::describe_age(upload.age(now)), cli::format_size(bytes), upload.uri, upload.upload_id),
+                        Err(e) => {
+                            cli::println_error(format_args!("failed to list parts of {} upload {}: {e}", upload.uri, upload.upload_id));
+                            error_count += 1;
+                        },
+                    }
+                }
+            },
+            MultipartAction::Abort { uris, upload_id, older_than } => {
+                let keep = |upload: &s3::IncompleteUpload| {
+                    upload_id.as_ref().is_none_or(|id| *id == upload.upload_id)
+                        && older_than.is_none_or(|older_than| upload.age(now) >= older_than)
+                };
+                let uploads = Self::find(client, opts, uris, keep, &mut error_count).await;
+                if uploads.is_empty() {
+                    return MainResult::from_error_count(error_count);
+                }
+                if !opts.confirm(format_args!("abort {} upload(s)", uploads.len())) {
+                    return MainResult::Cancelled;
+                }
+                for upload in &uploads {
+                    match client.abort_incomplete_upload(upload).await {
+                        Ok(()) if opts.verbose => println!("🏁 aborted {} upload {}", upload.uri, upload.upload_id),
+                        Ok(()) => {},
+                        Err(e) => {
+                            cli::println_error(format_args!("failed to abort {} upload {}: {e}", upload.uri, upload.upload_id));
+                            error_count += 1;
+                        },
+                    }
+                }
+            },
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Wait {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let conditions: Vec<s3::WaitCondition> = match &self.condition {
+            WaitFor::ObjectExists { uris } => uris.iter().cloned().map(s3::WaitCondition::ObjectExists).collect(),
+            WaitFor::BucketExists { buckets } => buckets.iter().map(|uri| s3::WaitCondition::BucketExists(uri.bucket.clone())).collect(),
+        };
+        let deadline = tokio::time::Instant::now() + self.timeout;
+        for condition in &conditions {
+            match client.wait_for(opts.verbose, condition, self.interval, deadline).await {
+                Ok(true) => {},
+                Ok(false) => {
+                    cli::println_error(format_args!("timed out after {}s waiting for {condition}", self.timeout.as_secs()));
+                    return MainResult::ErrorSomeOperationsFailed;
+                },
+                Err(e) => {
+                    cli::println_error(format_args!("failed waiting for {condition}: {e}"));
+                    return MainResult::ErrorSomeOperationsFailed;
+                },
+            }
+        }
+        MainResult::Success
+    }
+}
+
+impl Tag {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let (verb, uris) = match &self.action {
+            TagAction::Get { uris } => ("get tags of", uris),
+            TagAction::Set { uris, .. } => ("set tags of", uris),
+            TagAction::Delete { uris } => ("delete tags of", uris),
+        };
+        let mut error_count = 0;
+        for uri in uris {
+            if opts.verbose {
+                eprintln!("🏁 {verb} '{uri}'");
+            }
+            let result = match &self.action {
+                TagAction::Get { .. } => client.get_tags(uri).await.map(|tags| {
+                    if uris.len() > 1 {
+                        println!("{uri}");
+                    }
+                    let indent = if uris.len() > 1 { "  " } else { "" };
+                    for (key, value) in tags {
+                        println!("{indent}{key}={value}");
+                    }
+                }),
+                TagAction::Set { tags, .. } => client.put_tags(uri, tags).await,
+                TagAction::Delete { .. } => client.delete_tags(uri).await,
+            };
+            if let Err(e) = result {
+                cli::println_error(format_args!("failed to {verb} {uri}: {e}"));
+                error_count += 1;
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl GetAcl {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let mut error_count = 0;
+        for uri in &self.uris {
+            if opts.verbose {
+                eprintln!("🏁 get ACL of '{uri}'");
+            }
+            let acl = match client.acl(uri).await {
+                Ok(acl) => acl,
+                Err(e) => {
+                    cli::println_error(format_args!("failed to get ACL of {uri}: {e}"));
+                    error_count += 1;
+                    continue;
+                },
+            };
+            if self.json {
+                println!("{}", acl.to_json(uri));
+                continue;
+            }
+            let owner = match (&acl.owner_id, &acl.owner_name) {
+                (Some(id), Some(name)) => format!("id={id} ({name})"),
+                (Some(id), None) => format!("id={id}"),
+                (None, _) => "unknown".to_owned(),
+            };
+            println!("{uri}");
+            println!("  owner: {owner}");
+            let grantees: Vec<String> = acl.grants.iter().map(|grant| format!("{}={}", grant.kind, grant.grantee)).collect();
+            let permission_width = acl.grants.iter().map(|grant| grant.permission.len()).max().unwrap_or(0);
+            let grantee_width = grantees.iter().map(String::len).max().unwrap_or(0);
+            for (grant, grantee) in acl.grants.iter().zip(&grantees) {
+                let name = grant.display_name.as_deref().unwrap_or_default();
+                let line = format!("  {:permission_width$} {grantee:grantee_width$} {name}", grant.permission);
+                println!("{}", line.trim_end());
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl SetAcl {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let mut error_count = 0;
+        for uri in &self.uris {
+            let uris = match self.recursive {
+                false => vec![uri.clone()],
+                true => {
+                    let root = s3::Uri::new(uri.bucket.clone(), uri.key.to_explicit_directory());
+                    match client.list_objects(&root).await {
+                        Ok(objects) => objects.iter()
+                            .filter_map(|object| object.key())
+                            .map(|key| s3::Uri::new(root.bucket.clone(), s3::Key::new(key.to_owned())))
+                            .collect(),
+                        Err(e) => {
+                            cli::println_error(format_args!("failed to list {root}: {e}"));
+                            error_count += 1;
+                            if !self.continue_on_error {
+                                break;
+                            }
+                            continue;
+                        },
+                    }
+                },
+            };
+            for uri in uris {
+                if opts.verbose {
+                    eprintln!("🏁 set ACL of '{uri}'");
+                }
+                if let Err(e) = client.put_acl(&uri, self.canned_acl.as_ref(), &self.access_control).await {
+                    cli::println_error(format_args!("failed to set ACL of {uri}: {e}"));
+                    error_count += 1;
+                    if !self.continue_on_error {
+                        return MainResult::from_error_count(error_count);
+                    }
+                }
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
+
+impl Api {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let parameters = match self.operation.parameters(&self.params) {
+            Ok(parameters) => parameters,
+            Err(e) => {
+                cli::println_error(format_args!("{e}"));
+                return MainResult::ErrorArguments;
+            },
+        };
+        if opts.verbose {
+            eprintln!("🏁 {:?}", self.operation);
+        }
+        match client.api(self.operation, &parameters).await {
+            Ok(json) => {
+                println!("{json}");
+                MainResult::Success
+            },
+            Err(e) => {
+                cli::println_error(format_args!("{:?} failed: {e}", self.operation));
+                MainResult::ErrorSomeOperationsFailed
+            },
+        }
+    }
+}
+
+impl Stat {
+    pub(crate) async fn run(&self, client: &s3::Client, opts: &SharedOptions) -> MainResult {
+        let mut error_count = 0;
+        for uri in &self.uris {
+            if opts.verbose {
+                eprintln!("🏁 stat '{uri}'");
+            }
+            let head = match client.head(uri).await {
+                Ok(head) => head,
+                Err(e) => {
+                    cli::println_error(format_args!("failed to stat {uri}: {e}"));
+                    error_count += 1;
+                    continue;
+                },
+            };
+            let last_modified = head.last_modified()
+                .and_then(|d| d.fmt(aws_sdk_s3::primitives::DateTimeFormat::DateTime).ok());
+            let encryption = head.server_side_encryption().map(|sse| match head.ssekms_key_id() {
+                Some(key) => format!("{} {key}", sse.as_str()),
+                None => sse.as_str().to_owned(),
+            });
+            let fields = [
+                ("size", head.content_length().map(|size| size.to_string())),
+                ("last-modified", last_modified),
+                ("etag", head.e_tag().map(|etag| etag.trim_matches('"').to_owned())),
+                ("storage-class", Some(head.storage_class().map(|class| class.as_str()).unwrap_or("STANDARD").to_owned())),
+                ("content-type", head.content_type().map(str::to_owned)),
+                ("content-encoding", head.content_encoding().map(str::to_owned)),
+                ("cache-control", head.cache_control().map(str::to_owned)),
+                ("content-disposition", head.content_disposition().map(str::to_owned)),
+                ("expires", head.expires_string().map(str::to_owned)),
+                ("server-side-encryption", encryption),
+                ("version-id", head.version_id().map(str::to_owned)),
+            ];
+            println!("{uri}");
+            for (name, value) in fields {
+                if let Some(value) = value {
+                    println!("  {name}: {value}");
+                }
+            }
+            let mut metadata: Vec<_> = head.metadata().into_iter().flatten().collect();
+            metadata.sort();
+            for (name, value) in metadata {
+                println!("  metadata.{name}: {value}");
+            }
+        }
+        MainResult::from_error_count(error_count)
+    }
+}
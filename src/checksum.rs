@@ -0,0 +1,249 @@
+use std::path::{Path, PathBuf};
+
+use aws_smithy_checksums::ChecksumAlgorithm;
+use md5::Digest;
+use tokio::io::AsyncReadExt;
+
+use crate::{cli, s3};
+
+/// How a local file compares to the object it should match
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Match,
+    Differs,
+    /// No object at the URI
+    Missing,
+    /// Can't be told from the object's checksums or ETag, e.g. if encrypted with SSE-KMS and
+    /// uploaded without a checksum, or with SSE-C
+    Unknown,
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Outcome::Match => "match",
+            Outcome::Differs => "differs",
+            Outcome::Missing => "missing",
+            Outcome::Unknown => "unknown",
+        })
+    }
+}
+
+/// Size and unquoted ETag of an object
+pub struct Remote {
+    pub size: u64,
+    pub etag: String,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Digest of each consecutive part of the file at `path`, of the given sizes, each part fed to a
+/// hasher of its own from `new`
+async fn part_digests<H>(path: &Path, sizes: &[u64], new: impl Fn() -> H, update: impl Fn(&mut H, &[u8]), finalize: impl Fn(H) -> Vec<u8>) -> std::io::Result<Vec<Vec<u8>>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buffer = vec![0; 1 << 16];
+    let mut digests = Vec::with_capacity(sizes.len());
+    for &size in sizes {
+        let mut hasher = new();
+        let mut remaining = size;
+        while remaining > 0 {
+            let read = file.read(&mut buffer[..remaining.min(1 << 16) as usize]).await?;
+            if read == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            update(&mut hasher, &buffer[..read]);
+            remaining -= read as u64;
+        }
+        digests.push(finalize(hasher));
+    }
+    Ok(digests)
+}
+
+/// MD5 of each consecutive part of the file at `path`, of the given sizes
+async fn part_md5s(path: &Path, sizes: &[u64]) -> std::io::Result<Vec<Vec<u8>>> {
+    part_digests(path, sizes, md5::Md5::new, |hasher, data| hasher.update(data), |hasher| hasher.finalize().to_vec()).await
+}
+
+/// Base64 checksums, as S3 reports them, of each consecutive part of the file at `path`
+async fn part_checksums(path: &Path, sizes: &[u64], algorithm: ChecksumAlgorithm) -> std::io::Result<Vec<String>> {
+    let digests = part_digests(path, sizes, || algorithm.into_impl(), |hasher, data| hasher.update(data), |hasher| hasher.finalize().to_vec()).await?;
+    Ok(digests.iter().map(aws_smithy_types::base64::encode).collect())
+}
+
+/// Algorithm and value of a checksum named like "CRC32C:yZRlqg==", if one sup3 can compute
+fn parse_checksum(checksum: &str) -> Option<(ChecksumAlgorithm, &str)> {
+    let (name, value) = checksum.split_once(':')?;
+    Some((name.parse().ok()?, value))
+}
+
+/// Compare the file at `path` to the checksums S3 holds of the whole object or each of its parts,
+/// if it has any sup3 can compute
+async fn compare_checksums(path: &Path, size: u64, object: &s3::ObjectParts) -> std::io::Result<Option<Outcome>> {
+    let outcome = |matched| Some(if matched { Outcome::Match } else { Outcome::Differs });
+    if let Some((algorithm, value)) = object.checksum.as_deref().and_then(parse_checksum) {
+        return Ok(outcome(part_checksums(path, &[size], algorithm).await?[0] == value));
+    }
+    let Some(checksums) = object.parts.iter().map(|part| part.checksum.as_deref().and_then(parse_checksum)).collect::<Option<Vec<_>>>() else {
+        return Ok(None);
+    };
+    let Some(&(algorithm, _)) = checksums.first() else {
+        return Ok(None);
+    };
+    if checksums.iter().any(|(other, _)| *other != algorithm) {
+        return Ok(None);
+    }
+    let sizes: Vec<u64> = object.parts.iter().map(|part| part.size as u64).collect();
+    if sizes.iter().sum::<u64>() != size {
+        return Ok(outcome(false));
+    }
+    let local = part_checksums(path, &sizes, algorithm).await?;
+    Ok(outcome(local.iter().zip(&checksums).all(|(local, (_, value))| local == value)))
+}
+
+/// S3's ETag for content uploaded in parts with these MD5s: the MD5 of the MD5s, and the part count
+fn multipart_etag(part_md5s: &[Vec<u8>]) -> String {
+    format!("{}-{}", hex(&md5::Md5::digest(part_md5s.concat())), part_md5s.len())
+}
+
+/// Compare the file at `path` of `size` bytes to `remote`, from the checksums GetObjectAttributes
+/// reports if uploaded with one, else its ETag. Only the object's attributes are fetched, never
+/// its content
+pub async fn compare(client: &s3::Client, path: &Path, size: u64, uri: &s3::Uri, remote: &Remote) -> Result<Outcome, s3::Error> {
+    if size != remote.size {
+        return Ok(Outcome::Differs);
+    }
+    let encryption = client.opaque_encryption(uri).await?;
+    if encryption == Some(s3::OpaqueEncryption::CustomerKey) {
+        return Ok(Outcome::Unknown);
+    }
+    let object = client.object_parts(uri).await?;
+    if let Some(outcome) = compare_checksums(path, size, &object).await? {
+        return Ok(outcome);
+    }
+    if encryption.is_some() {
+        return Ok(Outcome::Unknown);
+    }
+    let etag = remote.etag.to_ascii_lowercase();
+    let Some((_, count)) = etag.split_once('-') else {
+        if etag.len() != 32 || !etag.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Ok(Outcome::Unknown);
+        }
+        let digests = part_md5s(path, &[size]).await?;
+        return Ok(if hex(&digests[0]) == etag { Outcome::Match } else { Outcome::Differs });
+    };
+    let parts = object.parts;
+    let sizes: Vec<u64> = parts.iter().map(|part| part.size as u64).collect();
+    if count.parse() != Ok(parts.len()) || sizes.iter().sum::<u64>() != size {
+        return Ok(Outcome::Unknown);
+    }
+    let digests = part_md5s(path, &sizes).await?;
+    Ok(if multipart_etag(&digests) == etag { Outcome::Match } else { Outcome::Differs })
+}
+
+/// Regular files below `dir`, following symlinks as uploads do, with their '/' separated path
+/// relative to it and size, sorted by path
+pub fn local_files(dir: &Path) -> std::io::Result<Vec<(PathBuf, String, u64)>> {
+    let mut files = Vec::new();
+    let mut directories = vec![(dir.to_owned(), String::new())];
+    while let Some((directory, relative)) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = std::fs::metadata(entry.path())?;
+            if metadata.is_dir() {
+                directories.push((entry.path(), format!("{relative}{name}/")));
+            } else if metadata.is_file() {
+                files.push((entry.path(), format!("{relative}{name}"), metadata.len()));
+            }
+        }
+    }
+    files.sort_by(|a, b| a.1.cmp(&b.1));
+    Ok(files)
+}
+
+fn report(outcome: Result<Outcome, s3::Error>, path: &Path, uri: &s3::Uri) -> u32 {
+    match outcome {
+        Ok(outcome) => {
+            println!("{outcome}\t{}\t{uri}", path.display());
+            (outcome != Outcome::Match).into()
+        },
+        Err(e) => {
+            cli::println_error(format_args!("failed to compare {path:?} to {uri}: {e}"));
+            1
+        },
+    }
+}
+
+/// Compare `local` to `uri`, or each file below the directory `local` to the object of the same
+/// relative path below the prefix `uri`, printing the outcome of each. Returns how many didn't match
+pub async fn check(client: &s3::Client, verbose: bool, local: &Path, uri: &s3::Uri) -> u32 {
+    if !local.is_dir() {
+        let mut uri = uri.clone();
+        if uri.key.is_explicitly_directory() {
+            uri.key.push(&local.file_name().unwrap_or_default().to_string_lossy());
+        }
+        let outcome = async {
+            let size = tokio::fs::metadata(local).await?.len();
+            let remote = match client.head(&uri).await {
+                Ok(head) => Remote { size: head.content_length().unwrap_or(0) as u64, etag: head.e_tag().unwrap_or_default().trim_matches('"').to_owned() },
+                Err(s3::Error::NoSuchKey(_)) => return Ok(Outcome::Missing),
+                Err(e) => return Err(e),
+            };
+            compare(client, local, size, &uri, &remote).await
+        }.await;
+        return report(outcome, local, &uri);
+    }
+
+    let prefix = s3::Uri::new(uri.bucket.clone(), uri.key.to_explicit_directory());
+    if verbose {
+        eprintln!("🏁 comparing {local:?} to {prefix}");
+    }
+    let files = match local_files(local) {
+        Ok(files) => files,
+        Err(e) => {
+            cli::println_error(format_args!("failed to list {local:?}: {e}"));
+            return 1;
+        },
+    };
+    let objects = match client.list_objects(&prefix).await {
+        Ok(objects) => objects,
+        Err(e) => {
+            cli::println_error(format_args!("failed to list {prefix}: {e}"));
+            return 1;
+        },
+    };
+    let remotes: std::collections::HashMap<&str, Remote> = objects.iter()
+        .filter_map(|object| {
+            let relative = object.key()?.strip_prefix(prefix.key.as_str())?;
+            Some((relative, Remote { size: object.size().unwrap_or(0) as u64, etag: object.e_tag().unwrap_or_default().trim_matches('"').to_owned() }))
+        })
+        .collect();
+    let mut mismatches = 0;
+    for (path, relative, size) in files {
+        let mut object = prefix.clone();
+        object.key.push(&relative);
+        let outcome = match remotes.get(relative.as_str()) {
+            Some(remote) => compare(client, &path, size, &object, remote).await,
+            None => Ok(Outcome::Missing),
+        };
+        mismatches += report(outcome, &path, &object);
+    }
+    mismatches
+}
+
+#[test]
+fn test_multipart_etag() {
+    let md5 = |data: &[u8]| md5::Md5::digest(data).to_vec();
+    assert_eq!(hex(&md5(b"abcdef")), "e80b5017098950fc58aad83c8c14978e");
+    assert_eq!(multipart_etag(&[md5(b"abc"), md5(b"def")]), "4c8e93283780e078db9e0c6b9b3f8043-2");
+}
+
+#[test]
+fn test_parse_checksum() {
+    assert_eq!(parse_checksum("CRC32C:yZRlqg=="), Some((ChecksumAlgorithm::Crc32c, "yZRlqg==")));
+    assert_eq!(parse_checksum("CRC64NVME:jC6QSBPnqY8="), Some((ChecksumAlgorithm::Crc64Nvme, "jC6QSBPnqY8=")));
+    assert_eq!(parse_checksum("SHA512:abc"), None);
+    assert_eq!(parse_checksum("yZRlqg=="), None);
+}
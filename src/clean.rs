@@ -0,0 +1,106 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::{cli, s3};
+
+/// How long a partial file must go unmodified before a download warns about it
+pub const STALE_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A file left behind by a transfer that was killed before it could clean up
+#[derive(Debug)]
+pub struct Orphan {
+    pub path: PathBuf,
+    pub size: u64,
+    /// Time since last modified
+    pub age: Duration,
+}
+
+/// Partial downloads, and cache entries that were never committed
+fn is_orphan_name(file_name: &str) -> bool {
+    file_name.ends_with(s3::PARTIAL_SUFFIX) || s3::is_cache_temporary(file_name)
+}
+
+/// Orphans in `dir`, and directories below it if `recursive`, unmodified for at least `min_age`
+pub fn find(dir: &Path, recursive: bool, min_age: Duration) -> std::io::Result<Vec<Orphan>> {
+    let mut orphans = Vec::new();
+    let mut directories = vec![dir.to_owned()];
+    let now = SystemTime::now();
+    while let Some(directory) = directories.pop() {
+        // Only `dir` itself must be readable, a subdirectory that isn't is passed over
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(e) if directory != dir => {
+                eprintln!("⚠️: skipping {directory:?}: {e}");
+                continue;
+            },
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            // Doesn't follow symlinks, so never leaves `dir`
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() && recursive {
+                directories.push(entry.path());
+                continue;
+            }
+            if !file_type.is_file() || !is_orphan_name(&entry.file_name().to_string_lossy()) {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            let age = metadata.modified().ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .unwrap_or_default();
+            if age >= min_age {
+                orphans.push(Orphan { path: entry.path(), size: metadata.len(), age });
+            }
+        }
+    }
+    orphans.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(orphans)
+}
+
+/// Largest whole unit of `age`, e.g. '3d' or '45m'
+pub fn describe_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        s if s >= 24 * 60 * 60 => format!("{}d", s / (24 * 60 * 60)),
+        s if s >= 60 * 60 => format!("{}h", s / (60 * 60)),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+/// Point out stale partial files in a download destination, as found by `sup3 clean`
+pub fn warn_stale(dir: &Path) {
+    let Ok(orphans) = find(dir, false, STALE_AGE) else {
+        return;
+    };
+    if orphans.is_empty() {
+        return;
+    }
+    let size: u64 = orphans.iter().map(|orphan| orphan.size).sum();
+    eprintln!("⚠️: {} stale partial file(s) in {dir:?} using {}, from interrupted transfers. Remove with 'sup3 clean {} --delete'",
+        orphans.len(), cli::format_size(size), dir.display());
+}
+
+#[test]
+fn test_find_orphans() {
+    let dir = std::env::temp_dir().join(format!("sup3-test-clean-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    let cache_temporary = format!("{}.123.tmp", "a".repeat(64));
+    for name in ["a.txt.sup3.partial", "a.txt", "other.tmp", &cache_temporary, "sub/b.sup3.partial"] {
+        std::fs::write(dir.join(name), b"abc").unwrap();
+    }
+    let names = |orphans: Vec<Orphan>| -> Vec<String> {
+        orphans.iter().map(|orphan| orphan.path.strip_prefix(&dir).unwrap().to_string_lossy().into_owned()).collect()
+    };
+    assert_eq!(names(find(&dir, false, Duration::ZERO).unwrap()), ["a.txt.sup3.partial", cache_temporary.as_str()]);
+    assert_eq!(names(find(&dir, true, Duration::ZERO).unwrap()), ["a.txt.sup3.partial", cache_temporary.as_str(), "sub/b.sup3.partial"]);
+    assert!(find(&dir, true, Duration::from_secs(60)).unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(describe_age(Duration::from_secs(59)), "59s");
+    assert_eq!(describe_age(Duration::from_secs(3 * 60 * 60 + 5)), "3h");
+    assert_eq!(describe_age(Duration::from_secs(2 * 24 * 60 * 60)), "2d");
+}
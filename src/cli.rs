@@ -4,6 +4,8 @@ pub enum ProgressOption {
     Off,
     /// Enable if stdout/stderr are a termimal
     Auto,
+    /// A single line totalling all files, for many concurrent small transfers
+    Compact,
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -12,13 +14,70 @@ pub struct ArgProgress {
     #[cfg(feature = "progress")]
     #[clap(long, short='p', value_enum, default_value="auto")]
     progress: ProgressOption,
+    /// Progress bar layout as an indicatif template, e.g. '{prefix} {msg} {percent}% [{bar:20}]' for narrow terminals
+    #[cfg(feature = "progress")]
+    #[clap(long, value_name="TEMPLATE", value_parser=parse_progress_template)]
+    progress_template: Option<String>,
+    /// Characters drawing the filled, current and remaining parts of progress bars
+    #[cfg(feature = "progress")]
+    #[clap(long, default_value="#>-", value_name="CHARS", value_parser=parse_progress_chars)]
+    progress_chars: String,
+    /// Most progress bars drawn at once, further tasks waiting for a finished one to be
+    /// collapsed into a line counting them
+    #[cfg(feature = "progress")]
+    #[clap(long, default_value="20", value_name="N", value_parser=clap::value_parser!(u16).range(1..))]
+    progress_max_bars: u16,
+    /// Also show overall progress in the terminal title and taskbar (OSC 9;4), for terminals
+    /// that support it
+    #[cfg(feature = "progress")]
+    #[clap(long)]
+    terminal_status: bool,
+    /// Disable colours in progress output, also disabled by a non-empty NO_COLOR environment variable
+    #[cfg(feature = "progress")]
+    #[clap(long)]
+    no_color: bool,
+}
+
+#[cfg(feature = "progress")]
+fn parse_progress_template(s: &str) -> Result<String, String> {
+    indicatif::ProgressStyle::with_template(s).map_err(|e| e.to_string())?;
+    Ok(s.to_owned())
+}
+
+/// indicatif requires at least two characters, all of the same width
+#[cfg(feature = "progress")]
+fn parse_progress_chars(s: &str) -> Result<String, String> {
+    let widths: Vec<usize> = s.chars().map(|c| console::measure_text_width(&c.to_string())).collect();
+    if widths.len() < 2 {
+        return Err("expected at least two characters".into());
+    }
+    if widths.iter().any(|width| *width != widths[0]) {
+        return Err("characters must all be the same width".into());
+    }
+    Ok(s.to_owned())
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Human readable, shell escaped names
+    Text,
+    /// Comma separated values with a header row
+    Csv,
+    /// Apache Parquet file of objects (requires --to)
+    #[cfg(feature = "parquet")]
+    Parquet,
 }
 
 #[derive(Debug)]
 pub enum Update {
     State(&'static str),
     StateLength(usize),
+    /// Length only known once finished, e.g. streams without a Content-Length
+    StateLengthUnknown,
+    /// Bytes done: transferred, or for uploads acknowledged as sent
     StateProgress(usize),
+    /// Bytes read for upload, awaiting their StateProgress once sent
+    StateBuffered(usize),
     StateRetried,
     Finished(),
     FinishedHide(),
@@ -47,6 +106,10 @@ mod progress_enabled {
     use std::sync::Arc;
     use super::*;
     pub type ProgressFn = Arc<dyn Fn(Update) + Send + Sync + 'static>;
+    /// For operations shown without progress
+    pub fn no_progress() -> ProgressFn {
+        Arc::new(|_| {})
+    }
 
     pub(super) const PREFIX_ERROR: console::Emoji = console::Emoji("❌ ", "");
     pub(super) const PREFIX_DONE: console::Emoji = console::Emoji("✅ ", "");
@@ -62,11 +125,132 @@ mod progress_enabled {
         bars: Vec<Bar>,
         incoming_task_count: usize,
     }
+    /// Totals across every task for --progress compact
+    #[derive (Default)]
+    struct Totals {
+        task_count: usize,
+        done_count: usize,
+        failed_count: usize,
+    }
+    struct Compact {
+        bar: indicatif::ProgressBar,
+        totals: std::sync::Mutex<Totals>,
+    }
+    impl Compact {
+        fn update(&self, change: impl FnOnce(&mut Totals)) {
+            let mut totals = self.totals.lock().unwrap();
+            change(&mut totals);
+            let failed = match totals.failed_count {
+                0 => String::new(),
+                count => format!(", {count} failed"),
+            };
+            self.bar.set_message(format!("{}/{} files{failed}", totals.done_count, totals.task_count));
+        }
+    }
+    type BarId = usize;
+    #[derive (Default)]
+    struct Drawn {
+        next_id: BarId,
+        bars: Vec<(BarId, indicatif::ProgressBar)>,
+        waiting: std::collections::VecDeque<(BarId, indicatif::ProgressBar)>,
+        done_count: usize,
+        failed_count: usize,
+        /// Drawn bars that finished with an error, collapsed as failed rather than done
+        failed: std::collections::HashSet<BarId>,
+        counter: Option<indicatif::ProgressBar>,
+    }
+    /// Keeps at most max_bars bars drawn. Once more are needed, finished bars are collapsed
+    /// into a counter line, making room for those waiting
+    struct Collapse {
+        multi: indicatif::MultiProgress,
+        max_bars: usize,
+        drawn: std::sync::Mutex<Drawn>,
+    }
+    impl Collapse {
+        fn show(&self, bar: indicatif::ProgressBar) -> (BarId, indicatif::ProgressBar) {
+            let mut drawn = self.drawn.lock().unwrap();
+            let id = drawn.next_id;
+            drawn.next_id += 1;
+            if drawn.bars.len() >= self.max_bars {
+                let finished: Vec<_> = drawn.bars.iter().filter(|(_, bar)| bar.is_finished()).map(|(id, _)| *id).collect();
+                for id in finished {
+                    let succeeded = !drawn.failed.remove(&id);
+                    self.collapse(&mut drawn, id, succeeded);
+                }
+            }
+            if drawn.bars.len() < self.max_bars {
+                drawn.bars.push((id, bar.clone()));
+                return (id, self.multi.add(bar));
+            }
+            drawn.waiting.push_back((id, bar.clone()));
+            (id, bar)
+        }
+        fn finished(&self, id: BarId, succeeded: bool) {
+            let mut drawn = self.drawn.lock().unwrap();
+            if drawn.counter.is_none() && drawn.waiting.is_empty() {
+                if !succeeded {
+                    drawn.failed.insert(id);
+                }
+                return;
+            }
+            self.collapse(&mut drawn, id, succeeded);
+            while drawn.bars.len() < self.max_bars {
+                let Some((id, next)) = drawn.waiting.pop_front() else { break };
+                drawn.bars.push((id, next.clone()));
+                self.multi.add(next);
+            }
+        }
+        fn hidden(&self, id: BarId) {
+            let mut drawn = self.drawn.lock().unwrap();
+            drawn.bars.retain(|(drawn, _)| *drawn != id);
+            drawn.waiting.retain(|(waiting, _)| *waiting != id);
+        }
+        fn collapse(&self, drawn: &mut Drawn, id: BarId, succeeded: bool) {
+            // Bars still waiting to be drawn finish too, and are counted the same
+            let bar = match drawn.bars.iter().position(|(drawn, _)| *drawn == id) {
+                Some(index) => {
+                    let (_, bar) = drawn.bars.remove(index);
+                    self.multi.remove(&bar);
+                    bar
+                },
+                None => match drawn.waiting.iter().position(|(waiting, _)| *waiting == id) {
+                    Some(index) => drawn.waiting.remove(index).expect("position found").1,
+                    None => return,
+                },
+            };
+            match succeeded {
+                true => drawn.done_count += 1,
+                false => {
+                    drawn.failed_count += 1;
+                    // Keeping why it failed on screen once its bar is gone
+                    let _ = self.multi.println(format!("{} {}", bar.prefix(), bar.message()));
+                },
+            }
+            let counter = drawn.counter.get_or_insert_with(|| {
+                let counter = indicatif::ProgressBar::new(0).with_style(indicatif::ProgressStyle::with_template("{msg}").unwrap());
+                self.multi.insert(0, counter)
+            });
+            let failed = match drawn.failed_count {
+                0 => String::new(),
+                count => format!(", {PREFIX_ERROR}{count} failed"),
+            };
+            counter.set_message(format!("{PREFIX_DONE}{} done{failed}", drawn.done_count));
+        }
+    }
+    const DEFAULT_TEMPLATE: &str = "{prefix:20.dim} {msg:>11.bold} {bytes:>10.cyan}/{total_bytes:>10.italic.250} {binary_bytes_per_sec:>11} {elapsed:>4} [{wide_bar:.cyan/blue.bold}]";
+    const UNKNOWN_LENGTH_TEMPLATE: &str = "{prefix:20.dim} {msg:>11.bold} {bytes:>10.cyan} {spinner:^23.cyan} {binary_bytes_per_sec:>11} {elapsed:>4}";
+    const COMPACT_TEMPLATE: &str = "{msg:.bold} {bytes:>10.cyan}/{total_bytes:>10.italic.250} {binary_bytes_per_sec:>11} eta {eta:>4} [{wide_bar:.cyan/blue.bold}]";
+
     pub struct Output {
         enabled: bool,
         verbose: bool,
+        style: indicatif::ProgressStyle,
+        unknown_length_style: indicatif::ProgressStyle,
         multi: indicatif::MultiProgress,
-        bars: std::sync::Mutex<Bars>,
+        bars: Arc<std::sync::Mutex<Bars>>,
+        terminal_status: Option<tokio::task::JoinHandle<()>>,
+        compact: Option<Arc<Compact>>,
+        collapse: Arc<Collapse>,
         hidden_path_prefix: String,
     }
     impl Output {
@@ -75,12 +259,53 @@ mod progress_enabled {
             let enabled = match args.progress {
                 ProgressOption::On => true,
                 ProgressOption::Off => false,
-                ProgressOption::Auto => console::user_attended() && console::user_attended_stderr(),
+                ProgressOption::Auto | ProgressOption::Compact => console::user_attended() && console::user_attended_stderr(),
             };
+            if args.no_color || std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                console::set_colors_enabled(false);
+                console::set_colors_enabled_stderr(false);
+            }
+            let enabled = enabled && !draw_target.is_hidden();
+            let compact = matches!(args.progress, ProgressOption::Compact);
+            let default_template = if compact { COMPACT_TEMPLATE } else { DEFAULT_TEMPLATE };
+            let style = indicatif::ProgressStyle::with_template(args.progress_template.as_deref().unwrap_or(default_template))
+                .expect("template validated when parsed")
+                .progress_chars(&args.progress_chars);
+            let unknown_length_style = indicatif::ProgressStyle::with_template(args.progress_template.as_deref().unwrap_or(UNKNOWN_LENGTH_TEMPLATE))
+                .expect("template validated when parsed")
+                .progress_chars(&args.progress_chars);
+            let multi = indicatif::MultiProgress::with_draw_target(draw_target);
+            let compact = (compact && enabled).then(|| Arc::new(Compact {
+                bar: multi.add(indicatif::ProgressBar::new(0).with_style(style.clone())),
+                totals: Default::default(),
+            }));
+            let collapse = Arc::new(Collapse {
+                multi: multi.clone(),
+                max_bars: args.progress_max_bars as usize,
+                drawn: Default::default(),
+            });
+            let bars: Arc<std::sync::Mutex<Bars>> = Default::default();
+            let terminal_status = (enabled && args.terminal_status).then(|| {
+                let (bars, compact) = (bars.clone(), compact.clone());
+                eprint!("{TITLE_PUSH}");
+                tokio::spawn(async move {
+                    loop {
+                        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                        let bars = bars.lock().unwrap();
+                        let all = bars.bars.iter().map(|bar| &bar.bar).chain(compact.as_ref().map(|compact| &compact.bar));
+                        eprint!("{}", terminal_status(all));
+                    }
+                })
+            });
             Output {
-                enabled: enabled && !draw_target.is_hidden(),
-                multi: indicatif::MultiProgress::with_draw_target(draw_target),
-                bars: Default::default(),
+                style,
+                unknown_length_style,
+                compact,
+                collapse,
+                enabled,
+                multi,
+                bars,
+                terminal_status,
                 verbose,
                 hidden_path_prefix: hidden_path_prefix.unwrap_or_default(),
             }
@@ -92,14 +317,32 @@ mod progress_enabled {
             if !self.enabled {
                 return Arc::new(move |_: Update| {});
             }
+            if let Some(compact) = &self.compact {
+                return self.add_compact(compact.clone());
+            }
 
-            let bar = indicatif::ProgressBar::new(1)
+            let bar = indicatif::ProgressBar::with_draw_target(Some(1), indicatif::ProgressDrawTarget::hidden())
                 .with_message(initial_state.into());
-            bar.set_style(indicatif::ProgressStyle::with_template("{prefix:20.dim} {msg:>11.bold} {bytes:>10.cyan}/{total_bytes:>10.italic.250} {binary_bytes_per_sec:>11} {elapsed:>4} [{wide_bar:.cyan/blue.bold}]")
-                .unwrap()
-                .progress_chars("#>-"));
+            bar.set_style(self.style.clone());
 
-            let bar = self.multi.add(bar);
+            let (id, bar) = self.collapse.show(bar);
+            let collapse = self.collapse.clone();
+            let unknown_length_style = self.unknown_length_style.clone();
+            // Bytes sent again after retries, kept out of the position so the ETA stays meaningful
+            let retried = std::sync::atomic::AtomicU64::new(0);
+            // Bytes read ahead of the network, shown apart so the bar only counts what was sent
+            let buffered = std::sync::atomic::AtomicU64::new(0);
+            let state = std::sync::Mutex::new(String::new());
+            let set_message = move |bar: &indicatif::ProgressBar, state: &str, retried: u64, buffered: u64| {
+                let mut message = state.to_owned();
+                if retried > 0 {
+                    message += &format!(", {} retried", indicatif::BinaryBytes(retried));
+                }
+                if buffered > 0 {
+                    message += &format!(", {} buffered", indicatif::BinaryBytes(buffered));
+                }
+                bar.set_message(message);
+            };
 
             self.add_bar(Bar {
                 bar: bar.clone(),
@@ -107,14 +350,76 @@ mod progress_enabled {
             });
 
             Arc::new(move |update: Update| {
+                use std::sync::atomic::Ordering::Relaxed;
                 match update {
-                    Update::State(state_name) => bar.set_message(state_name),
+                    Update::State(state_name) => {
+                        *state.lock().unwrap() = state_name.to_owned();
+                        set_message(&bar, state_name, retried.load(Relaxed), buffered.load(Relaxed));
+                    },
                     Update::StateLength(total) => bar.set_length(total as u64),
-                    Update::StateProgress(inc_completed) => bar.inc(inc_completed as u64),
-                    Update::StateRetried => bar.set_position(0),
-                    Update::Finished() => bar.finish_with_message("done"),
-                    Update::FinishedHide() => { bar.finish_and_clear(); bar.set_draw_target(indicatif::ProgressDrawTarget::hidden()); },
-                    Update::Error(err) => bar.abandon_with_message(format!("{PREFIX_ERROR}failed: {err}")),
+                    Update::StateLengthUnknown => {
+                        bar.unset_length();
+                        bar.set_style(unknown_length_style.clone());
+                        bar.enable_steady_tick(std::time::Duration::from_millis(200));
+                    },
+                    Update::StateProgress(inc_completed) => {
+                        bar.inc(inc_completed as u64);
+                        let sent = |held: u64| Some(held.saturating_sub(inc_completed as u64));
+                        if let Ok(held) = buffered.fetch_update(Relaxed, Relaxed, sent) {
+                            if held > 0 {
+                                set_message(&bar, &state.lock().unwrap(), retried.load(Relaxed), held.saturating_sub(inc_completed as u64));
+                            }
+                        }
+                    },
+                    Update::StateBuffered(read) => {
+                        let held = buffered.fetch_add(read as u64, Relaxed) + read as u64;
+                        set_message(&bar, &state.lock().unwrap(), retried.load(Relaxed), held);
+                    },
+                    Update::StateRetried => {
+                        let total = retried.fetch_add(bar.position(), Relaxed) + bar.position();
+                        bar.set_position(0);
+                        bar.reset_eta();
+                        buffered.store(0, Relaxed);
+                        set_message(&bar, &state.lock().unwrap(), total, 0);
+                    },
+                    Update::Finished() => { bar.finish_with_message("done"); collapse.finished(id, true); },
+                    Update::FinishedHide() => { bar.finish_and_clear(); bar.set_draw_target(indicatif::ProgressDrawTarget::hidden()); collapse.hidden(id); },
+                    Update::Error(err) => { bar.abandon_with_message(format!("{PREFIX_ERROR}failed: {err}")); collapse.finished(id, false); },
+                }
+            })
+        }
+        /// Task counted towards the single --progress compact line
+        fn add_compact(&self, compact: Arc<Compact>) -> ProgressFn {
+            let mut bars = self.bars.lock().unwrap();
+            match bars.incoming_task_count {
+                0 => compact.update(|totals| totals.task_count += 1),
+                _ => bars.incoming_task_count -= 1,
+            }
+            drop(bars);
+            let (length, position) = (std::sync::atomic::AtomicU64::new(0), std::sync::atomic::AtomicU64::new(0));
+            let finished = std::sync::atomic::AtomicBool::new(false);
+            use std::sync::atomic::Ordering::Relaxed;
+            Arc::new(move |update: Update| {
+                let bar = &compact.bar;
+                match update {
+                    Update::State(_) | Update::StateLengthUnknown | Update::StateBuffered(_) => {},
+                    Update::StateLength(total) => {
+                        let previous = length.swap(total as u64, Relaxed);
+                        bar.set_length(bar.length().unwrap_or(0) + total as u64 - previous);
+                    },
+                    Update::StateProgress(inc_completed) => {
+                        position.fetch_add(inc_completed as u64, Relaxed);
+                        bar.inc(inc_completed as u64);
+                    },
+                    Update::StateRetried => bar.set_position(bar.position() - position.swap(0, Relaxed)),
+                    _ if finished.swap(true, Relaxed) => {},
+                    Update::Finished() => compact.update(|totals| totals.done_count += 1),
+                    Update::FinishedHide() => {
+                        bar.set_length(bar.length().unwrap_or(0) - length.load(Relaxed));
+                        bar.set_position(bar.position() - position.load(Relaxed));
+                        compact.update(|totals| totals.task_count -= 1);
+                    },
+                    Update::Error(_) => compact.update(|totals| totals.failed_count += 1),
                 }
             })
         }
@@ -122,10 +427,16 @@ mod progress_enabled {
             if !self.enabled {
                 return
             }
+            if let Some(compact) = &self.compact {
+                compact.update(|totals| totals.task_count += count);
+                self.bars.lock().unwrap().incoming_task_count += count;
+                return
+            }
             let mut bars = self.bars.lock().unwrap();
             bars.incoming_task_count += count;
             self.update_bars(bars);
         }
+        #[allow(clippy::get_first)]
         fn add_bar(&self, added: Bar) {
             let mut bars = self.bars.lock().unwrap();
             bars.bars.push(added);
@@ -190,6 +501,47 @@ mod progress_enabled {
                 }
                 bar.bar.abandon_with_message(format!("{PREFIX_ERROR}cancelled"));
             }
+            if let Some(compact) = &self.compact {
+                compact.bar.abandon_with_message(format!("{PREFIX_ERROR}cancelled"));
+            }
+        }
+    }
+    /// xterm title stack, to restore the title when done
+    const TITLE_PUSH: &str = "\x1b[22;0t";
+    const TITLE_POP: &str = "\x1b[23;0t";
+    /// ConEmu/Windows Terminal progress, removing the indicator
+    const TERMINAL_PROGRESS_REMOVE: &str = "\x1b]9;4;0;0\x07";
+
+    /// Escape sequences showing overall progress in the taskbar, via OSC 9;4, and title, e.g. "sup3: 63% (1.20 GiB/s)"
+    fn terminal_status<'a>(bars: impl Iterator<Item = &'a indicatif::ProgressBar>) -> String {
+        let (mut position, mut length, mut rate) = (0, 0, 0.);
+        for bar in bars {
+            position += bar.position();
+            length += bar.length().unwrap_or(0);
+            if !bar.is_finished() {
+                rate += bar.per_sec();
+            }
+        }
+        let rate = indicatif::BinaryBytes(rate as u64);
+        match (position * 100).checked_div(length) {
+            Some(percent) => format!("\x1b]9;4;1;{}\x07\x1b]0;sup3: {}% ({rate}/s)\x07", percent.min(100), percent.min(100)),
+            None => format!("\x1b]9;4;3;0\x07\x1b]0;sup3: ({rate}/s)\x07"),
+        }
+    }
+
+    impl Drop for Output {
+        fn drop(&mut self) {
+            if let Some(terminal_status) = self.terminal_status.take() {
+                terminal_status.abort();
+                eprint!("{TERMINAL_PROGRESS_REMOVE}{TITLE_POP}");
+            }
+            if let Some(compact) = self.compact.as_ref().filter(|compact| !compact.bar.is_finished()) {
+                compact.bar.finish();
+            }
+            // Redraw without the bars collapsed since the last rate limited draw
+            if let Some(counter) = &self.collapse.drawn.lock().unwrap().counter {
+                counter.finish();
+            }
         }
     }
 }
@@ -202,6 +554,9 @@ mod progress_disabled {
     use super::*;
     pub fn empty_progress_fn(_update: Update) { }
     pub type ProgressFn = fn(Update);
+    pub fn no_progress() -> ProgressFn {
+        empty_progress_fn
+    }
 
     pub(super) const PREFIX_ERROR: &'static str = "❌ ";
     pub(super) const PREFIX_DONE: &'static str = "✅ ";
@@ -237,8 +592,92 @@ mod progress_disabled {
 #[cfg(not(feature = "progress"))]
 pub use progress_disabled::*;
 
+/// Quote a CSV field if required, as per RFC 4180
+pub fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if !field.contains([',', '"', '\n', '\r']) {
+        return field.into();
+    }
+    format!("\"{}\"", field.replace('"', "\"\"")).into()
+}
+
+pub fn println_csv(fields: &[&str]) {
+    let line: Vec<_> = fields.iter().map(|f| csv_field(f)).collect();
+    println!("{}", line.join(","));
+}
+
+/// Parse a byte size with an optional binary suffix, e.g. "512", "64K", "1.5GiB"
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size '{s}'"))?;
+    let multiplier: u64 = match suffix.trim().to_ascii_uppercase().trim_end_matches("IB").trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("invalid size suffix '{suffix}', expected K, M, G or T")),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// `bytes` in the largest binary unit it reaches, e.g. "512 B" or "1.50 MiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if bytes < 1 << 10 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.2} {}", UNITS[unit])
+}
+
+/// Quote and escape `s` as a JSON string
+pub fn json_string(s: &str) -> String {
+    use std::fmt::Write;
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => write!(quoted, "\\u{:04x}", c as u32).unwrap(),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Parse a duration with an optional unit, e.g. "90", "15m", "12h", "7d"
+pub fn parse_duration(s: &str) -> Result<std::time::Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid duration '{s}'"))?;
+    let multiplier = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(format!("invalid duration unit '{unit}', expected s, m, h or d")),
+    };
+    number.checked_mul(multiplier)
+        .map(std::time::Duration::from_secs)
+        .ok_or_else(|| format!("duration '{s}' too large"))
+}
+
 /// Common path component prefix
 /// e.g. ["/r/a1/b.txt", "/r/a2/b.txt"] -> "/r/"
+#[allow(clippy::single_match)]
 pub fn longest_file_display_prefix<T: AsRef<str>>(mut strings: impl Iterator<Item = T>) -> String {
     let mut longest: String = match strings.next() {
         None => return "".into(),
@@ -296,3 +735,55 @@ fn test_longest_file_display_prefix()
     );
 }
 
+
+#[test]
+fn test_csv_field()
+{
+    assert_eq!(csv_field("plain.txt"), "plain.txt");
+    assert_eq!(csv_field(""), "");
+    assert_eq!(csv_field("a,b"), "\"a,b\"");
+    assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+}
+
+#[test]
+fn test_parse_size()
+{
+    assert_eq!(parse_size("512"), Ok(512));
+    assert_eq!(parse_size("64K"), Ok(64 * 1024));
+    assert_eq!(parse_size("8MiB"), Ok(8 * 1024 * 1024));
+    assert_eq!(parse_size("1.5g"), Ok(3 * 512 * 1024 * 1024));
+    assert_eq!(parse_size("2 GB"), Ok(2 * 1024 * 1024 * 1024));
+    assert!(parse_size("ten").is_err());
+    assert!(parse_size("10X").is_err());
+}
+
+#[test]
+fn test_format_size()
+{
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1024), "1.00 KiB");
+    assert_eq!(format_size(3 << 19), "1.50 MiB");
+    assert_eq!(format_size(5 << 40), "5.00 TiB");
+}
+
+#[test]
+fn test_parse_duration()
+{
+    use std::time::Duration;
+    assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+    assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(15 * 60)));
+    assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 24 * 60 * 60)));
+    assert!(parse_duration("1w").is_err());
+    assert!(parse_duration("h").is_err());
+    assert!(parse_duration("18446744073709551615d").is_err());
+}
+
+#[cfg(feature = "progress")]
+#[test]
+fn test_parse_progress_chars() {
+    assert_eq!(parse_progress_chars("=> "), Ok("=> ".to_owned()));
+    assert_eq!(parse_progress_chars("█▉ "), Ok("█▉ ".to_owned()));
+    assert!(parse_progress_chars("#").is_err());
+    assert!(parse_progress_chars("#🦀").is_err());
+}
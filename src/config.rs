@@ -0,0 +1,159 @@
+use std::path::{Path, PathBuf};
+
+/// One `key = value` setting, within the `[section]` above it, "" if none
+#[derive(Debug, PartialEq)]
+struct Entry {
+    section: String,
+    key: String,
+    value: String,
+}
+
+/// Settings from sup3's TOML config file, as `key = value` settings within `[section]`s whose
+/// values are strings, numbers or booleans
+#[derive(Debug, Default)]
+pub struct Config {
+    path: Option<PathBuf>,
+    entries: Vec<Entry>,
+}
+
+/// $SUP3_CONFIG, or config.toml within the platform's config directory
+fn default_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("SUP3_CONFIG") {
+        return Some(path.into());
+    }
+    let config_dir = match (std::env::var_os("XDG_CONFIG_HOME"), std::env::var_os("APPDATA"), std::env::var_os("HOME")) {
+        (Some(xdg), _, _) => PathBuf::from(xdg),
+        (None, Some(appdata), _) if cfg!(windows) => PathBuf::from(appdata),
+        (None, _, Some(home)) => Path::new(&home).join(".config"),
+        _ => return None,
+    };
+    Some(config_dir.join("sup3").join("config.toml"))
+}
+
+/// Settings of `table` and the tables within it, in the order they appear, with their section
+/// named by the dotted path to their table
+fn flatten(section: &str, table: toml::Table, entries: &mut Vec<Entry>) -> Result<(), String> {
+    for (key, value) in table {
+        let value = match value {
+            toml::Value::String(value) => value,
+            toml::Value::Integer(value) => value.to_string(),
+            toml::Value::Float(value) => value.to_string(),
+            toml::Value::Boolean(value) => value.to_string(),
+            toml::Value::Table(table) => {
+                let section = match section {
+                    "" => key,
+                    section => format!("{section}.{key}"),
+                };
+                flatten(&section, table, entries)?;
+                continue;
+            },
+            toml::Value::Datetime(_) | toml::Value::Array(_) => {
+                let name = match section {
+                    "" => key,
+                    section => format!("{section}.{key}"),
+                };
+                return Err(format!("{name}: expected a string, number or boolean"));
+            },
+        };
+        entries.push(Entry { section: section.to_owned(), key, value });
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Read `path`, or the default location if not given, where a missing file is no settings
+    pub fn load(path: Option<&Path>) -> Result<Config, String> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => return Ok(Config::default()),
+            Err(e) => return Err(format!("failed to read config {path:?}: {e}")),
+        };
+        let mut config = Config::parse(&text).map_err(|e| format!("{path:?}: {e}"))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| match e.span() {
+            Some(span) => format!("line {}: {}", text[..span.start].matches('\n').count() + 1, e.message()),
+            None => e.message().to_owned(),
+        })?;
+        let mut entries = Vec::new();
+        flatten("", table, &mut entries)?;
+        Ok(Config { path: None, entries })
+    }
+
+    /// Value of `key` within `section`, "" for top level settings
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.entries.iter()
+            .find(|entry| entry.section == section && entry.key == key)
+            .map(|entry| entry.value.as_str())
+    }
+
+    /// Names of the `[prefix.NAME]` sections, in the order they appear
+    pub fn subsections(&self, prefix: &str) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            let name = entry.section.strip_prefix(prefix).and_then(|rest| rest.strip_prefix('.'));
+            if let Some(name) = name.filter(|name| !names.contains(name)) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Keys set within `section`, in the order they appear
+    pub fn keys<'a>(&'a self, section: &'a str) -> impl Iterator<Item = &'a str> {
+        self.entries.iter().filter(move |entry| entry.section == section).map(|entry| entry.key.as_str())
+    }
+
+    /// Parse the value of `key` with `parse`, describing where it came from if invalid
+    pub fn parse_with<T>(&self, section: &str, key: &str, parse: impl FnOnce(&str) -> Result<T, String>) -> Result<Option<T>, String> {
+        let Some(value) = self.get(section, key) else {
+            return Ok(None);
+        };
+        parse(value).map(Some).map_err(|e| self.invalid(section, key, e))
+    }
+
+    /// Describe a problem with `key` of `section`, naming the file it's in
+    pub fn invalid(&self, section: &str, key: &str, e: impl std::fmt::Display) -> String {
+        let name = match section {
+            "" => key.to_owned(),
+            section => format!("{section}.{key}"),
+        };
+        let path = self.path.as_deref().unwrap_or(Path::new("config"));
+        format!("{path:?}: {name}: {e}")
+    }
+}
+
+#[test]
+fn test_parse_config() {
+    let config = Config::parse("# providers\nprovider = \"scaleway\"  # Paris\nregion='fr-par'\ncredential_helper = \"pass show aws/minio\"\n\n[limits]\nparts = 4\nverbose = true\n").unwrap();
+    assert_eq!(config.get("", "provider"), Some("scaleway"));
+    assert_eq!(config.get("", "region"), Some("fr-par"));
+    assert_eq!(config.get("", "credential_helper"), Some("pass show aws/minio"));
+    assert_eq!(config.get("limits", "parts"), Some("4"));
+    assert_eq!(config.get("limits", "verbose"), Some("true"));
+    assert_eq!(config.get("", "parts"), None);
+    assert_eq!(Config::parse("a = \"x\\\"y\"").unwrap().get("", "a"), Some("x\"y"));
+
+    let config = Config::parse("[policy.b]\naction = \"deny\"\n[policy.a]\naction = \"allow\"\ncommand = \"rm\"\n[policy]\nx = 1\n").unwrap();
+    assert_eq!(config.subsections("policy"), ["b", "a"]);
+    assert_eq!(config.keys("policy.a").collect::<Vec<_>>(), ["action", "command"]);
+    assert!(config.subsections("profile").is_empty());
+
+    assert!(Config::parse("provider = scaleway fr").is_err());
+    assert!(Config::parse("provider = \"scaleway").is_err());
+    assert!(Config::parse("a = 1\na = 2").is_err());
+    assert!(Config::parse("[section").is_err());
+    assert!(Config::parse("just text").is_err());
+    assert!(Config::parse("ports = [1, 2]").is_err());
+    assert!(Config::parse("a = 1\nb = \"x").unwrap_err().starts_with("line 2:"));
+}
@@ -0,0 +1,196 @@
+use bytes::Bytes;
+use futures::Stream;
+use http_body_util::BodyExt;
+
+use crate::manifest::Checksum;
+
+type Connector = hyper_rustls::HttpsConnector<hyper_util::client::legacy::connect::HttpConnector>;
+type HttpsClient = hyper_util::client::legacy::Client<Connector, http_body_util::Empty<Bytes>>;
+
+const MAX_REDIRECTS: usize = 10;
+/// Attempts to continue an interrupted response with a Range request
+const MAX_RESUMES: u32 = 5;
+/// Longest wait to connect to the server
+const CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+/// Longest wait for response headers or the next body data, before treating the connection as stalled
+const READ_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid url: {0}")]
+    InvalidUrl(String),
+    #[error("tls setup: {0}")]
+    Tls(std::io::Error),
+    #[error("request: {0}")]
+    Request(#[from] hyper_util::client::legacy::Error),
+    #[error("server responded {0}")]
+    Status(http::StatusCode),
+    #[error("too many redirects")]
+    TooManyRedirects,
+    #[error("redirect without a valid location")]
+    InvalidRedirect,
+    #[error("no response within {}s", READ_TIMEOUT.as_secs())]
+    Timeout,
+}
+
+fn client() -> Result<HttpsClient, Error> {
+    let mut http = hyper_util::client::legacy::connect::HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(Some(CONNECT_TIMEOUT));
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_provider_and_native_roots(rustls::crypto::aws_lc_rs::default_provider())
+        .map_err(Error::Tls)?
+        .https_or_http()
+        .enable_http1()
+        .enable_http2()
+        .wrap_connector(http);
+    Ok(hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new()).build(connector))
+}
+
+fn to_http_uri(url: &url::Url) -> Result<http::Uri, Error> {
+    url.as_str().parse().map_err(|e: http::uri::InvalidUri| Error::InvalidUrl(e.to_string()))
+}
+
+/// GET following redirects, optionally resuming from `offset` if the resource is unchanged
+async fn get(client: &HttpsClient, url: &mut url::Url, resume: Option<(u64, &str)>) -> Result<http::Response<hyper::body::Incoming>, Error> {
+    for _ in 0..MAX_REDIRECTS {
+        let mut request = http::Request::get(to_http_uri(url)?)
+            .header(http::header::USER_AGENT, concat!("sup3/", env!("CARGO_PKG_VERSION")));
+        if let Some((offset, validator)) = resume {
+            request = request
+                .header(http::header::RANGE, format!("bytes={offset}-"))
+                .header(http::header::IF_RANGE, validator);
+        }
+        let request = request.body(http_body_util::Empty::new())
+            .map_err(|e| Error::InvalidUrl(e.to_string()))?;
+        let response = tokio::time::timeout(READ_TIMEOUT, client.request(request)).await
+            .map_err(|_| Error::Timeout)??;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response.headers().get(http::header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .ok_or(Error::InvalidRedirect)?;
+            *url = url.join(location).map_err(|_| Error::InvalidRedirect)?;
+            continue;
+        }
+        if !status.is_success() {
+            return Err(Error::Status(status));
+        }
+        return Ok(response);
+    }
+    Err(Error::TooManyRedirects)
+}
+
+/// First byte and total length of a `bytes START-END/TOTAL` Content-Range, None if the total is '*'
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _end) = range.split_once('-')?;
+    let total = match total {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((start.parse().ok()?, total))
+}
+
+/// An HTTP(S) resource being fetched
+pub struct Source {
+    client: HttpsClient,
+    /// Final location, after redirects
+    url: url::Url,
+    length: Option<u64>,
+    /// ETag or Last-Modified, sent as If-Range when resuming
+    validator: Option<String>,
+    response: http::Response<hyper::body::Incoming>,
+}
+
+impl Source {
+    pub async fn open(url: &url::Url) -> Result<Source, Error> {
+        let client = client()?;
+        let mut url = url.clone();
+        let response = get(&client, &mut url, None).await?;
+        let header = |name| response.headers().get(name).and_then(|v: &http::HeaderValue| v.to_str().ok());
+        let length = header(http::header::CONTENT_LENGTH).and_then(|l| l.parse().ok());
+        let accepts_ranges = header(http::header::ACCEPT_RANGES) == Some("bytes");
+        let validator = header(http::header::ETAG)
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(header(http::header::LAST_MODIFIED))
+            .filter(|_| accepts_ranges)
+            .map(str::to_owned);
+        Ok(Source { client, url, length, validator, response })
+    }
+    pub fn length(&self) -> Option<u64> {
+        self.length
+    }
+    /// Last path component of the final URL
+    pub fn filename(&self) -> Option<String> {
+        let last = self.url.path_segments()?.next_back()?;
+        let decoded = percent_encoding::percent_decode_str(last).decode_utf8().ok()?;
+        (!decoded.is_empty()).then(|| decoded.into_owned())
+    }
+    /// Body data, transparently resumed with Range requests on connection errors where the server allows
+    ///
+    /// With `checksum`, the stream ends in an error instead of completing if the content doesn't match
+    pub fn into_stream(self, checksum: Option<Checksum>) -> impl Stream<Item = Result<Bytes, std::io::Error>> {
+        async_stream::try_stream! {
+            let Source { client, mut url, length, validator, response } = self;
+            let mut hasher = checksum.as_ref().map(Checksum::hasher);
+            let mut body = response.into_body();
+            let mut offset = 0u64;
+            let mut resumes = 0;
+            loop {
+                // A stalled connection is resumed like a broken one
+                let frame = match tokio::time::timeout(READ_TIMEOUT, body.frame()).await {
+                    Ok(frame) => frame.map(|frame| frame.map_err(|e| e.to_string())),
+                    Err(_) => Some(Err(format!("no data within {}s", READ_TIMEOUT.as_secs()))),
+                };
+                match frame {
+                    None => break,
+                    Some(Ok(frame)) => {
+                        if let Ok(data) = frame.into_data() {
+                            offset += data.len() as u64;
+                            if let Some(hasher) = &mut hasher {
+                                hasher.update(&data);
+                            }
+                            yield data;
+                        }
+                    },
+                    Some(Err(reason)) => {
+                        let Some(validator) = validator.as_deref().filter(|_| resumes < MAX_RESUMES) else {
+                            Err(std::io::Error::other(reason))?;
+                            break;
+                        };
+                        resumes += 1;
+                        let response = get(&client, &mut url, Some((offset, validator))).await
+                            .map_err(std::io::Error::other)?;
+                        if response.status() != http::StatusCode::PARTIAL_CONTENT {
+                            Err(std::io::Error::other(format!("resuming after '{reason}': resource changed or range not supported")))?;
+                        }
+                        // Appending anything but the rest of the same resource would corrupt it
+                        let range = response.headers().get(http::header::CONTENT_RANGE)
+                            .and_then(|v| v.to_str().ok())
+                            .and_then(parse_content_range);
+                        match range {
+                            Some((start, total)) if start == offset && (total.is_none() || length.is_none() || total == length) => {},
+                            _ => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("resuming after '{reason}': response doesn't continue from byte {offset}")))?,
+                        }
+                        body = response.into_body();
+                    },
+                }
+            }
+            if let Some(length) = length.filter(|length| *length != offset) {
+                Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, format!("received {offset} of {length} bytes")))?;
+            }
+            if let (Some(checksum), Some(hasher)) = (checksum, hasher) {
+                checksum.verify(hasher).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_content_range() {
+    assert_eq!(parse_content_range("bytes 100-199/1234"), Some((100, Some(1234))));
+    assert_eq!(parse_content_range("bytes 100-199/*"), Some((100, None)));
+    assert_eq!(parse_content_range("bytes */1234"), None);
+    assert_eq!(parse_content_range("items 1-2/3"), None);
+}
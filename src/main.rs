@@ -4,16 +4,56 @@ mod s3;
 mod shared_options;
 mod cli;
 mod transfer;
+mod http_source;
+mod manifest;
+mod shell;
+mod prune;
+mod clean;
+mod checksum;
+mod config;
+mod policy;
+
+#[cfg(all(feature = "mount", not(target_os = "linux")))]
+compile_error!("the mount feature is only supported on Linux");
 
 use arguments::MainResult;
 use arguments::Commands;
-use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 
 #[tokio::main]
 async fn main() -> MainResult {
-    let args = arguments::Arguments::parse();
+    let command = arguments::Arguments::command();
+    let matches = command.clone().get_matches();
+    let mut args = arguments::Arguments::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let policy = match args.normalize_keys().and_then(|()| args.apply_config()) {
+        Ok(policy) => policy,
+        Err(e) => {
+            let _ = arguments::Arguments::command()
+                .error(clap::error::ErrorKind::InvalidValue, e)
+                .print();
+            return MainResult::ErrorArguments;
+        },
+    };
+    let invocation = policy::Invocation::from_matches(&command, &matches);
+    if let Err(e) = policy.check(&invocation) {
+        cli::println_error(format_args!("{e}"));
+        return MainResult::ErrorDenied;
+    }
+    if let Err(e) = args.provider.check_supported(args.merged_flags(&invocation)) {
+        cli::println_error(format_args!("{e}"));
+        return MainResult::ErrorArguments;
+    }
+
+    let audit_log = match args.audit_log.as_deref().map(s3::AuditLog::open).transpose() {
+        Ok(audit_log) => audit_log,
+        Err(e) => {
+            cli::println_error(format_args!("failed to open audit log: {e}"));
+            return MainResult::ErrorArguments;
+        },
+    };
 
-    let client = s3::init(args.region, args.endpoint, args.profile.as_deref()).await;
+    let static_credentials = args.static_credentials();
+    let client = s3::init(args.region, &args.endpoint, args.profile.as_deref(), static_credentials, args.credential_helper.as_deref(), args.accelerate, &args.provider, args.ip_version.unwrap_or_default(), audit_log).await;
 
     let exit_code = match &args.command {
         Commands::Upload(upload) => upload.run(&client, &args.shared).await,
@@ -22,11 +62,50 @@ async fn main() -> MainResult {
         Commands::Ls(list) => list.run(&client, &args.shared).await,
         Commands::ListBuckets(list_buckets) => list_buckets.run(&client, &args.shared).await,
         Commands::Cp(copy) => copy.run(&client, &args.shared).await,
+        Commands::Mv(mv) => mv.run(&client, &args.shared).await,
+        Commands::Sync(sync) => sync.run(&client, &args.shared).await,
+        Commands::Diff(diff) => diff.run(&client, &args.shared).await,
         Commands::Cat(cat) => cat.run(&client, &args.shared).await,
+        Commands::Presign(presign) => presign.run(&client, &args.shared).await,
         Commands::MakeBuckets(make_buckets) => make_buckets.run(&client, &args.shared).await,
+        Commands::Tag(tag) => tag.run(&client, &args.shared).await,
+        Commands::Policy(policy) => policy.run(&client, &args.shared).await,
+        Commands::Versioning(versioning) => versioning.run(&client, &args.shared).await,
+        Commands::Encryption(encryption) => encryption.run(&client, &args.shared).await,
+        Commands::Inventory(inventory) => inventory.run(&client, &args.shared).await,
+        Commands::Multipart(multipart) => multipart.run(&client, &args.shared).await,
+        Commands::RemoveBuckets(remove_buckets) => remove_buckets.run(&client, &args.shared).await,
+        Commands::Concat(concat) => concat.run(&client, &args.shared).await,
+        Commands::Append(append) => append.run(&client, &args.shared).await,
+        Commands::Parts(parts) => parts.run(&client, &args.shared).await,
+        Commands::Stat(stat) => stat.run(&client, &args.shared).await,
+        Commands::GetAcl(get_acl) => get_acl.run(&client, &args.shared).await,
+        Commands::SetAcl(set_acl) => set_acl.run(&client, &args.shared).await,
+        Commands::Api(api) => api.run(&client, &args.shared).await,
+        Commands::Wait(wait) => wait.run(&client, &args.shared).await,
+        Commands::Prune(prune) => prune.run(&client, &args.shared).await,
+        Commands::Clean(clean) => clean.run(&client, &args.shared).await,
+        Commands::Checksum(checksum) => checksum.run(&client, &args.shared).await,
+        Commands::Mirror(mirror) => mirror.run(&client, &args.shared).await,
+        Commands::Shell(shell) => shell.run(&client, &args.shared, &policy).await,
+        #[cfg(feature = "mount")]
+        Commands::Mount(mount) => mount.run(&client, &args.shared).await,
         #[cfg(feature = "gen-completion")]
         Commands::GenerateCompletion(cmd) => cmd.run(&client, &args.shared).await,
     };
+    // Throttles were retried, not failed, but explain failures of requests that ran out of attempts
+    let throttled = client.throttles().count();
+    if throttled > 0 && matches!(exit_code, MainResult::ErrorSomeOperationsFailed) {
+        eprintln!("⚠️: {throttled} request(s) were throttled by the server, consider lowering --concurrency");
+    }
+    // Operations that were made but couldn't be recorded
+    let unaudited = client.audit_failures();
+    if unaudited > 0 {
+        cli::println_error(format_args!("{unaudited} audit log record(s) couldn't be written"));
+        if matches!(exit_code, MainResult::Success) {
+            return MainResult::ErrorSomeOperationsFailed;
+        }
+    }
     exit_code
 }
 
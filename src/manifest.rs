@@ -0,0 +1,161 @@
+use sha2::Digest;
+
+/// Expected digest of a mirrored file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Checksum {
+    Md5(Vec<u8>),
+    Sha1(Vec<u8>),
+    Sha256(Vec<u8>),
+}
+
+/// Running digest matching a `Checksum`'s algorithm
+pub enum Hasher {
+    Md5(md5::Md5),
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl Checksum {
+    fn digest(&self) -> &[u8] {
+        match self {
+            Checksum::Md5(d) | Checksum::Sha1(d) | Checksum::Sha256(d) => d,
+        }
+    }
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            Checksum::Md5(_) => Hasher::Md5(md5::Md5::new()),
+            Checksum::Sha1(_) => Hasher::Sha1(sha1::Sha1::new()),
+            Checksum::Sha256(_) => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+    /// Compare against a finished hasher, describing the mismatch on failure
+    pub fn verify(&self, hasher: Hasher) -> Result<(), String> {
+        let actual = hasher.finalize();
+        if actual == self.digest() {
+            return Ok(());
+        }
+        Err(format!("checksum mismatch: expected {self}, got {}", encode_hex(&actual)))
+    }
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(h) => h.update(data),
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+        }
+    }
+    fn finalize(self) -> Vec<u8> {
+        match self {
+            Hasher::Md5(h) => h.finalize().to_vec(),
+            Hasher::Sha1(h) => h.finalize().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+        }
+    }
+}
+
+impl std::fmt::Display for Checksum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Checksum::Md5(_) => "md5",
+            Checksum::Sha1(_) => "sha1",
+            Checksum::Sha256(_) => "sha256",
+        };
+        write!(f, "{name}:{}", encode_hex(self.digest()))
+    }
+}
+
+impl std::str::FromStr for Checksum {
+    type Err = String;
+    /// `algorithm:hex`, or bare hex with the algorithm inferred from its length
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (algorithm, hex) = match s.split_once(':') {
+            Some((algorithm, hex)) => (Some(algorithm.to_ascii_lowercase()), hex),
+            None => (None, s),
+        };
+        let digest = decode_hex(hex).ok_or_else(|| format!("invalid hex digest '{hex}'"))?;
+        let checksum = match (algorithm.as_deref(), digest.len()) {
+            (Some("md5") | None, 16) => Checksum::Md5(digest),
+            (Some("sha1") | None, 20) => Checksum::Sha1(digest),
+            (Some("sha256") | None, 32) => Checksum::Sha256(digest),
+            (Some(other @ ("md5" | "sha1" | "sha256")), _) => return Err(format!("wrong digest length for {other}")),
+            (Some(other), _) => return Err(format!("unsupported checksum algorithm '{other}'")),
+            (None, _) => return Err(format!("cannot infer checksum algorithm of '{hex}'")),
+        };
+        Ok(checksum)
+    }
+}
+
+/// One file to mirror
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub url: url::Url,
+    pub checksum: Option<Checksum>,
+}
+
+/// Parse a manifest of `<url> [checksum]` lines, ignoring blank lines and `#` comments
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_error = |e| format!("line {}: {e}", index + 1);
+        let mut fields = line.split_whitespace();
+        let url = fields.next().expect("non-empty line");
+        let url = url::Url::parse(url).map_err(|e| line_error(format!("invalid URL '{url}': {e}")))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(line_error(format!("unsupported URL scheme '{}'", url.scheme())));
+        }
+        let checksum = fields.next().map(str::parse).transpose().map_err(line_error)?;
+        if fields.next().is_some() {
+            return Err(line_error("expected '<url> [checksum]'".into()));
+        }
+        entries.push(Entry { url, checksum });
+    }
+    Ok(entries)
+}
+
+#[test]
+fn test_parse_checksum() {
+    let sha256 = "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    assert_eq!(sha256.parse::<Checksum>().unwrap().to_string(), sha256);
+    assert!(matches!("d41d8cd98f00b204e9800998ecf8427e".parse(), Ok(Checksum::Md5(_))));
+    assert!(matches!("SHA1:da39a3ee5e6b4b0d3255bfef95601890afd80709".parse(), Ok(Checksum::Sha1(_))));
+    assert!("md5:da39a3ee5e6b4b0d3255bfef95601890afd80709".parse::<Checksum>().is_err());
+    assert!("crc32:00000000".parse::<Checksum>().is_err());
+    assert!("xyz".parse::<Checksum>().is_err());
+
+    let empty: Checksum = sha256.parse().unwrap();
+    assert!(empty.verify(empty.hasher()).is_ok());
+    let mut hasher = empty.hasher();
+    hasher.update(b"x");
+    assert!(empty.verify(hasher).is_err());
+}
+
+#[test]
+fn test_parse_manifest() {
+    let entries = parse("# datasets\n\nhttps://example.com/a.csv md5:d41d8cd98f00b204e9800998ecf8427e\n  http://example.com/b.csv\n").unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].url.as_str(), "https://example.com/a.csv");
+    assert!(matches!(entries[0].checksum, Some(Checksum::Md5(_))));
+    assert_eq!(entries[1].checksum, None);
+
+    assert_eq!(parse("ftp://example.com/a").unwrap_err(), "line 1: unsupported URL scheme 'ftp'");
+    assert!(parse("https://example.com/a md5:00 extra").is_err());
+}
@@ -0,0 +1,216 @@
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+
+use crate::config::Config;
+
+/// Keys a `[policy.NAME]` section may set
+const KEYS: [&str; 5] = ["action", "command", "flags", "buckets", "reason"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Allow,
+    Deny,
+}
+
+/// A `[policy.NAME]` section of the config file, e.g. denying 'rm -r' on buckets matching 'prod-*'
+#[derive(Debug)]
+struct Rule {
+    name: String,
+    action: Action,
+    /// Subcommand names, aliases resolved, e.g. ["policy", "delete"]. Empty for any command
+    command: Vec<String>,
+    /// Long names of flags that must all be given
+    flags: Vec<String>,
+    /// Glob at least one bucket named must match
+    buckets: Option<String>,
+    reason: Option<String>,
+}
+
+/// What a command line asks for, as policy rules see it
+#[derive(Debug, Default, PartialEq)]
+pub struct Invocation {
+    /// Subcommand names, aliases resolved, e.g. ["tag", "set"]
+    command: Vec<String>,
+    /// Long names of the flags and options given on the command line
+    flags: Vec<String>,
+    /// Buckets of the S3 URIs among the arguments
+    buckets: Vec<String>,
+}
+
+/// Shell commands checked as the top-level command they stand for
+const SHELL_COMMANDS: &[(&str, &str)] = &[("get", "download"), ("put", "upload")];
+
+impl Invocation {
+    /// Long names of the flags and options given on the command line
+    pub fn flags(&self) -> impl Iterator<Item = &str> {
+        self.flags.iter().map(String::as_str)
+    }
+
+    pub fn from_matches(command: &Command, matches: &ArgMatches) -> Invocation {
+        let mut invocation = Invocation::default();
+        let (mut command, mut matches) = (command, matches);
+        loop {
+            for arg in command.get_arguments() {
+                let id = arg.get_id().as_str();
+                if matches.value_source(id) != Some(ValueSource::CommandLine) {
+                    continue;
+                }
+                if let Some(long) = arg.get_long().filter(|long| !invocation.flags.iter().any(|flag| flag == long)) {
+                    invocation.flags.push(long.to_owned());
+                }
+                let buckets = matches.get_raw(id).into_iter().flatten()
+                    .filter_map(|value| value.to_str()?.parse::<crate::s3::Uri>().ok())
+                    .map(|uri| uri.bucket);
+                invocation.buckets.extend(buckets);
+            }
+            let Some((name, sub_matches)) = matches.subcommand() else {
+                return invocation;
+            };
+            let Some(sub_command) = command.find_subcommand(name) else {
+                return invocation;
+            };
+            invocation.command.push(name.to_owned());
+            (command, matches) = (sub_command, sub_matches);
+        }
+    }
+
+    /// Invocation of a shell line, as the top-level command it stands for. Paths relative to the
+    /// current location name its bucket, so `current_bucket` is counted whatever the paths
+    pub fn from_shell_matches(command: &Command, matches: &ArgMatches, current_bucket: Option<&str>) -> Invocation {
+        let mut invocation = Invocation::from_matches(command, matches);
+        if let Some(name) = invocation.command.first_mut() {
+            if let Some((_, top_level)) = SHELL_COMMANDS.iter().find(|(shell, _)| shell == name) {
+                *name = (*top_level).to_owned();
+            }
+        }
+        invocation.buckets.extend(current_bucket.map(str::to_owned));
+        invocation
+    }
+}
+
+/// Allow and deny rules from the config file, checked before a command runs. The first rule
+/// matching decides, commands no rule matches being allowed
+#[derive(Debug, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+/// Long name of `flag`, written as on the command line, among the arguments of `commands`
+fn resolve_flag(commands: &[&Command], flag: &str) -> Option<String> {
+    let mut arguments = commands.iter().flat_map(|command| command.get_arguments());
+    match (flag.strip_prefix("--"), flag.strip_prefix('-')) {
+        (Some(long), _) => arguments.filter_map(|arg| arg.get_long()).find(|name| *name == long).map(str::to_owned),
+        (None, Some(short)) if short.chars().count() == 1 => arguments
+            .find(|arg| arg.get_short().is_some_and(|c| short.starts_with(c)))
+            .and_then(|arg| arg.get_long())
+            .map(str::to_owned),
+        _ => None,
+    }
+}
+
+impl Rule {
+    fn from_config(config: &Config, root: &Command, name: &str) -> Result<Rule, String> {
+        let section = format!("policy.{name}");
+        let invalid = |key: &str, e: String| config.invalid(&section, key, e);
+        if let Some(key) = config.keys(&section).find(|key| !KEYS.contains(key)) {
+            return Err(invalid(key, format!("unknown key, expected one of {}", KEYS.join(", "))));
+        }
+        let action = match config.get(&section, "action") {
+            Some("allow") => Action::Allow,
+            Some("deny") => Action::Deny,
+            Some(other) => return Err(invalid("action", format!("'{other}' is neither 'allow' nor 'deny'"))),
+            None => return Err(invalid("action", "missing, expected 'allow' or 'deny'".into())),
+        };
+
+        let mut commands = vec![root];
+        let mut command = Vec::new();
+        for word in config.get(&section, "command").unwrap_or("*").split_whitespace().filter(|word| *word != "*") {
+            let sub_command = commands[commands.len() - 1].find_subcommand(word)
+                .ok_or_else(|| invalid("command", format!("unknown command '{word}'")))?;
+            command.push(sub_command.get_name().to_owned());
+            commands.push(sub_command);
+        }
+
+        let flags = config.get(&section, "flags").unwrap_or_default().split_whitespace()
+            .map(|flag| resolve_flag(&commands, flag).ok_or_else(|| invalid("flags", format!("unknown flag '{flag}'"))))
+            .collect::<Result<_, _>>()?;
+
+        let buckets = config.get(&section, "buckets").map(str::to_owned);
+        if let Some(pattern) = &buckets {
+            wax::Glob::new(pattern).map_err(|e| invalid("buckets", e.to_string()))?;
+        }
+        Ok(Rule {
+            name: name.to_owned(),
+            action,
+            command,
+            flags,
+            buckets,
+            reason: config.get(&section, "reason").map(str::to_owned),
+        })
+    }
+
+    fn matches(&self, invocation: &Invocation) -> bool {
+        use wax::Pattern;
+        invocation.command.starts_with(&self.command)
+            && self.flags.iter().all(|flag| invocation.flags.contains(flag))
+            && self.buckets.as_ref().is_none_or(|pattern| {
+                let glob = wax::Glob::new(pattern).expect("bucket glob checked when loaded");
+                invocation.buckets.iter().any(|bucket| glob.is_match(bucket.as_str()))
+            })
+    }
+}
+
+impl Policy {
+    /// Rules of the `[policy.NAME]` sections of `config`, naming commands and flags of `root`
+    pub fn from_config(config: &Config, root: &Command) -> Result<Policy, String> {
+        let rules = config.subsections("policy").into_iter()
+            .map(|name| Rule::from_config(config, root, name))
+            .collect::<Result<_, _>>()?;
+        Ok(Policy { rules })
+    }
+
+    /// Err describing the rule denying `invocation`, if one does
+    pub fn check(&self, invocation: &Invocation) -> Result<(), String> {
+        let Some(rule) = self.rules.iter().find(|rule| rule.matches(invocation)) else {
+            return Ok(());
+        };
+        match (rule.action, &rule.reason) {
+            (Action::Allow, _) => Ok(()),
+            (Action::Deny, Some(reason)) => Err(format!("denied by config policy '{}': {reason}", rule.name)),
+            (Action::Deny, None) => Err(format!("denied by config policy '{}'", rule.name)),
+        }
+    }
+}
+
+#[test]
+fn test_policy() {
+    use clap::CommandFactory;
+    let root = crate::arguments::Arguments::command();
+    let config = Config::parse(concat!(
+        "[policy.scratch]\naction = \"allow\"\nbuckets = \"prod-scratch\"\n",
+        "[policy.prod-rm]\naction = \"deny\"\ncommand = \"rm\"\nflags = \"-r\"\nbuckets = \"prod-*\"\nreason = \"use the console\"\n",
+        "[policy.no-rb]\naction = \"deny\"\ncommand = \"rb\"\n",
+    )).unwrap();
+    let policy = Policy::from_config(&config, &root).unwrap();
+    let check = |args: &[&str]| {
+        let matches = root.clone().try_get_matches_from(std::iter::once("sup3").chain(args.iter().copied())).unwrap();
+        policy.check(&Invocation::from_matches(&root, &matches))
+    };
+    assert_eq!(check(&["rm", "--recursive", "s3://prod-logs/2024/"]), Err("denied by config policy 'prod-rm': use the console".into()));
+    assert!(check(&["-v", "rm", "-r", "s3://dev/a", "s3://prod-logs/b"]).is_err());
+    assert!(check(&["rm", "s3://prod-logs/a.txt"]).is_ok());
+    assert!(check(&["rm", "-r", "s3://dev/2024/"]).is_ok());
+    assert!(check(&["rm", "-r", "s3://prod-scratch/tmp/"]).is_ok());
+    assert!(check(&["remove-buckets", "s3://dev"]).is_err());
+    assert!(check(&["ls", "s3://prod-logs/"]).is_ok());
+
+    let invocation = Invocation::from_matches(&root, &root.clone().try_get_matches_from(["sup3", "tag", "set", "s3://bkt/k", "--tag", "a=1"]).unwrap());
+    assert_eq!(invocation, Invocation { command: vec!["tag".into(), "set".into()], flags: vec!["tag".into()], buckets: vec!["bkt".into()] });
+
+    let invalid = |text: &str| Policy::from_config(&Config::parse(text).unwrap(), &root).unwrap_err();
+    assert!(invalid("[policy.a]\ncommand = \"rm\"").contains("missing"));
+    assert!(invalid("[policy.a]\naction = \"block\"").contains("neither"));
+    assert!(invalid("[policy.a]\naction = \"deny\"\ncommand = \"remove\"").contains("unknown command"));
+    assert!(invalid("[policy.a]\naction = \"deny\"\ncommand = \"rm\"\nflags = \"--force\"").contains("unknown flag"));
+    assert!(invalid("[policy.a]\naction = \"deny\"\nbucket = \"prod\"").contains("unknown key"));
+}
@@ -0,0 +1,208 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+
+/// How many of the newest backups, and of the newest in each period, to keep
+#[derive(clap::Args, Debug, Clone)]
+#[clap(next_help_heading = "Retention", group = clap::ArgGroup::new("keep").required(true).multiple(true))]
+pub struct Retention {
+    /// Keep the newest N backups
+    #[clap(long, value_name = "N", group = "keep")]
+    pub keep_last: Option<usize>,
+    /// Keep the newest backup of each of the last N days with backups
+    #[clap(long, value_name = "N", group = "keep")]
+    pub keep_daily: Option<usize>,
+    /// Keep the newest backup of each of the last N ISO weeks with backups
+    #[clap(long, value_name = "N", group = "keep")]
+    pub keep_weekly: Option<usize>,
+    /// Keep the newest backup of each of the last N months with backups
+    #[clap(long, value_name = "N", group = "keep")]
+    pub keep_monthly: Option<usize>,
+    /// Keep the newest backup of each of the last N years with backups
+    #[clap(long, value_name = "N", group = "keep")]
+    pub keep_yearly: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Name,
+    Date,
+}
+
+/// Backup key layout relative to the pruned prefix, e.g. '{name}-{date}.tar.zst'
+///
+/// `{date}` matches YYYY-MM-DD, YYYY/MM/DD or YYYYMMDD, optionally followed by a time
+/// as HH:MM[:SS] or HHMM[SS]. Backups are pruned separately for each distinct `{name}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern(Vec<Token>);
+
+impl std::str::FromStr for Pattern {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut tokens = Vec::new();
+        let mut rest = s;
+        while !rest.is_empty() {
+            let (literal, placeholder) = match rest.find('{') {
+                Some(start) => {
+                    let end = rest[start..].find('}').ok_or("unclosed '{' in pattern")? + start;
+                    (&rest[..start], Some(&rest[start + 1..end]))
+                },
+                None => (rest, None),
+            };
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(literal.to_owned()));
+            }
+            rest = &rest[literal.len()..];
+            let Some(placeholder) = placeholder else { break };
+            let token = match placeholder {
+                "name" => Token::Name,
+                "date" => Token::Date,
+                other => return Err(format!("unknown placeholder {{{other}}}, expected {{name}} or {{date}}")),
+            };
+            if tokens.contains(&token) {
+                return Err(format!("{{{placeholder}}} repeated in pattern"));
+            }
+            if token == Token::Name && matches!(tokens.last(), Some(Token::Date)) || token == Token::Date && matches!(tokens.last(), Some(Token::Name)) {
+                return Err("{name} and {date} must be separated".to_owned());
+            }
+            tokens.push(token);
+            rest = &rest[placeholder.len() + 2..];
+        }
+        Ok(Pattern(tokens))
+    }
+}
+
+fn take_digits(text: &str, count: usize) -> Option<(u32, &str)> {
+    let digits = text.get(..count)?;
+    digits.bytes().all(|b| b.is_ascii_digit()).then(|| (digits.parse().unwrap(), &text[count..]))
+}
+
+/// Dates at the start of `text`, longest first, with the remaining text
+fn dates_at(text: &str) -> Vec<(NaiveDateTime, &str)> {
+    let mut found = Vec::new();
+    let Some((year, rest)) = take_digits(text, 4) else { return found };
+    for separator in ["-", "/", ""] {
+        let date = (|| {
+            let (month, rest) = take_digits(rest.strip_prefix(separator)?, 2)?;
+            let (day, rest) = take_digits(rest.strip_prefix(separator)?, 2)?;
+            Some((NaiveDate::from_ymd_opt(year as i32, month, day)?, rest))
+        })();
+        let Some((date, rest)) = date else { continue };
+        for time_separator in ["T", "_", "-", " "] {
+            let Some(time) = rest.strip_prefix(time_separator) else { continue };
+            for colon in [":", ""] {
+                let time = (|| {
+                    let (hour, time) = take_digits(time, 2)?;
+                    let (minute, time) = take_digits(time.strip_prefix(colon)?, 2)?;
+                    let (second, time) = time.strip_prefix(colon).and_then(|time| take_digits(time, 2)).unwrap_or((0, time));
+                    Some((NaiveTime::from_hms_opt(hour, minute, second)?, time))
+                })();
+                if let Some((time, rest)) = time {
+                    found.push((date.and_time(time), rest));
+                }
+            }
+        }
+        found.push((date.and_time(NaiveTime::MIN), rest));
+    }
+    found.sort_by_key(|(_, rest)| rest.len());
+    found
+}
+
+impl Pattern {
+    /// The name and date of a matching key
+    pub fn matches(&self, key: &str) -> Option<(String, Option<NaiveDateTime>)> {
+        fn matches_from(tokens: &[Token], text: &str) -> Option<(String, Option<NaiveDateTime>)> {
+            let Some((token, tokens)) = tokens.split_first() else {
+                return text.is_empty().then(|| (String::new(), None));
+            };
+            match token {
+                Token::Literal(literal) => matches_from(tokens, text.strip_prefix(literal.as_str())?),
+                Token::Date => dates_at(text).into_iter()
+                    .find_map(|(date, rest)| matches_from(tokens, rest).map(|(name, _)| (name, Some(date)))),
+                Token::Name => text.char_indices().skip(1).map(|(i, _)| i).chain([text.len()])
+                    .find_map(|end| matches_from(tokens, &text[end..]).map(|(_, date)| (text[..end].to_owned(), date))),
+            }
+        }
+        matches_from(&self.0, key)
+    }
+}
+
+/// A candidate for pruning
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub key: String,
+    pub name: String,
+    pub time: NaiveDateTime,
+}
+
+/// Keys to keep, each with why. Backups are considered separately per name
+pub fn keep(backups: &[Backup], retention: &Retention) -> BTreeMap<String, Vec<&'static str>> {
+    let mut kept: BTreeMap<String, Vec<&'static str>> = BTreeMap::new();
+    let mut names: Vec<&str> = backups.iter().map(|backup| backup.name.as_str()).collect::<HashSet<_>>().into_iter().collect();
+    names.sort();
+    for name in names {
+        let mut group: Vec<&Backup> = backups.iter().filter(|backup| backup.name == name).collect();
+        group.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| b.key.cmp(&a.key)));
+
+        if let Some(count) = retention.keep_last {
+            for backup in group.iter().take(count) {
+                kept.entry(backup.key.clone()).or_default().push("last");
+            }
+        }
+        type Period = fn(&NaiveDateTime) -> (i32, u32);
+        let rules: [(Option<usize>, &'static str, Period); 4] = [
+            (retention.keep_daily, "daily", |t| (t.year(), t.ordinal())),
+            (retention.keep_weekly, "weekly", |t| (t.iso_week().year(), t.iso_week().week())),
+            (retention.keep_monthly, "monthly", |t| (t.year(), t.month())),
+            (retention.keep_yearly, "yearly", |t| (t.year(), 0)),
+        ];
+        for (count, reason, period) in rules {
+            let Some(count) = count else { continue };
+            let mut seen = HashSet::new();
+            for backup in &group {
+                if seen.len() == count {
+                    break;
+                }
+                if seen.insert(period(&backup.time)) {
+                    kept.entry(backup.key.clone()).or_default().push(reason);
+                }
+            }
+        }
+    }
+    kept
+}
+
+#[test]
+fn test_pattern() {
+    let pattern: Pattern = "{name}-{date}.tar.zst".parse().unwrap();
+    let date = |y, m, d, h, min| NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, 0).unwrap();
+    assert_eq!(pattern.matches("my-db-2024-01-02.tar.zst"), Some(("my-db".to_owned(), Some(date(2024, 1, 2, 0, 0)))));
+    assert_eq!(pattern.matches("db-20240102T0330.tar.zst"), Some(("db".to_owned(), Some(date(2024, 1, 2, 3, 30)))));
+    assert_eq!(pattern.matches("db-2024-13-02.tar.zst"), None);
+    assert_eq!(pattern.matches("db-2024-01-02.tar.gz"), None);
+    let dated: Pattern = "{date}/{name}".parse().unwrap();
+    assert_eq!(dated.matches("2024/01/02/db.tar"), Some(("db.tar".to_owned(), Some(date(2024, 1, 2, 0, 0)))));
+    assert!("{name}{date}".parse::<Pattern>().is_err());
+    assert!("{other}".parse::<Pattern>().is_err());
+    assert!("{name".parse::<Pattern>().is_err());
+}
+
+#[test]
+fn test_keep() {
+    let backup = |key: &str, day: u32, month: u32| Backup {
+        key: key.to_owned(),
+        name: String::new(),
+        time: NaiveDate::from_ymd_opt(2024, month, day).unwrap().and_time(NaiveTime::MIN),
+    };
+    let backups = [
+        backup("mar-10", 10, 3), backup("mar-09", 9, 3), backup("mar-08", 8, 3),
+        backup("feb-28", 28, 2), backup("feb-01", 1, 2), backup("jan-15", 15, 1),
+    ];
+    let retention = |daily, monthly| Retention { keep_last: None, keep_daily: daily, keep_weekly: None, keep_monthly: monthly, keep_yearly: None };
+    let kept = keep(&backups, &retention(Some(2), Some(3)));
+    assert_eq!(kept.keys().collect::<Vec<_>>(), ["feb-28", "jan-15", "mar-09", "mar-10"]);
+    assert_eq!(kept["mar-10"], ["daily", "monthly"]);
+    let last = Retention { keep_last: Some(3), ..retention(None, None) };
+    assert_eq!(keep(&backups, &last).keys().collect::<Vec<_>>(), ["mar-08", "mar-09", "mar-10"]);
+}
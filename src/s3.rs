@@ -1,14 +1,17 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use aws_sdk_s3::primitives::SdkBody;
 use aws_sdk_s3::config::Region;
 use aws_config::meta::region::RegionProviderChain;
+use aws_config::credential_process::CredentialProcessProvider;
 use aws_sdk_s3::{primitives::ByteStream, operation::list_objects_v2::ListObjectsV2Output};
 use aws_sdk_s3::operation::get_object::GetObjectError;
 use futures::stream::Stream;
 use futures::TryStreamExt;
 use tokio::io::AsyncWriteExt;
-use clap::builder::PossibleValuesParser;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
 use std::fmt::Debug;
 use aws_sdk_s3::error::ProvideErrorMetadata;
 
@@ -17,17 +20,100 @@ use crate::cli;
 
 mod uri;
 mod partial_file;
+mod disk_jobs;
 mod seen_directories;
 mod glob;
+mod head_cache;
+mod append;
+mod parts;
+mod url_style;
+mod multipart;
+mod cache;
+mod lifecycle;
+mod storage_class;
+mod failover;
+mod ranged;
+mod visibility;
+mod lock;
+mod copy;
+mod chunked;
+mod presign;
+mod resume;
+mod class_rules;
+mod cache_control;
+mod index;
+mod provider;
+mod filter;
+mod audit;
+mod clock_skew;
+mod tagging;
+mod quota;
+mod checksum;
+mod api;
+mod versions;
+mod bucket_settings;
+mod wait;
+mod colors;
+mod rate_limit;
+mod rewrite;
+mod ip_version;
+mod acl;
+mod throttle;
+mod inventory;
+mod incomplete;
+mod concat;
+mod customer_key;
+#[cfg(feature = "mount")]
+mod mount;
+#[cfg(feature = "parquet")]
+mod parquet_export;
 
 pub use uri::{Uri, UriError, Key};
 
-pub use glob::Options as GlobOptions;
+pub use glob::{Options as GlobOptions, Glob};
+pub use url_style::UrlStyle;
+pub use customer_key::CustomerKey;
+pub use cache::{OptionsCache, is_temporary as is_cache_temporary};
+pub use partial_file::PARTIAL_SUFFIX;
+pub use ranged::RangedDownload;
+pub use visibility::OptionsVisibility;
+pub use lock::{OptionsLock, LOCK_PREFIX};
+pub use chunked::{manifest_uri, CHUNK_DIRECTORY, MANIFEST_SUFFIX};
+pub use presign::MAX_PRESIGN_EXPIRY;
+pub use resume::OptionsMultipart;
+pub use class_rules::ClassRules;
+pub use cache_control::CacheRules;
+pub use provider::{OptionsProvider, parse_region};
+pub use audit::AuditLog;
+pub use rewrite::OptionsRewrite;
+pub use api::{Operation as ApiOperation, parse_parameter as parse_api_parameter};
+pub use tagging::parse_tag;
+pub use filter::{OptionsFilter, OptionsSize, OptionsAge};
+pub use wait::Condition as WaitCondition;
+pub use ip_version::IpVersion;
+pub use bucket_settings::{DefaultEncryption, parse_default_encryption};
+pub use inventory::Format as InventoryFormat;
+pub use incomplete::IncompleteUpload;
+pub use concat::OptionsConcat;
+pub use checksum::OpaqueEncryption;
+pub use parts::ObjectParts;
+
+/// Most keys removed by a single DeleteObjects request
+pub const MAX_DELETE_BATCH: usize = 1000;
 
 #[derive(Clone)]
 pub struct Client {
     client: aws_sdk_s3::Client,
     region: Option<Region>,
+    head_cache: head_cache::HeadCache,
+    disk_jobs: disk_jobs::DiskJobs,
+    rate_limits: rate_limit::RateLimits,
+    quota: quota::Quota,
+    part_retries: resume::PartRetries,
+    in_flight: resume::InFlight,
+    throttles: throttle::Throttles,
+    endpoint: url_style::Endpoint,
+    audit_log: Option<AuditLog>,
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -41,8 +127,170 @@ pub struct OptionsUpload {
     #[clap(long, verbatim_doc_comment, help_heading="Access Control")]
     pub canned_acl: Option<aws_sdk_s3::types::ObjectCannedAcl>,
     /// Storage Class
-    #[clap(long, value_parser=PossibleValuesParser::new(aws_sdk_s3::types::StorageClass::values()))]
+    #[clap(long, value_parser=PossibleValuesParser::new(aws_sdk_s3::types::StorageClass::values()).map(|s| aws_sdk_s3::types::StorageClass::from(s.as_str())))]
     pub class: Option<aws_sdk_s3::types::StorageClass>,
+    /// Storage class per file from the first matching rule, e.g.
+    ///   'size>1G:GLACIER_IR;ext=log:STANDARD_IA;default:STANDARD'
+    /// Conditions are size>SIZE, size<SIZE, ext=EXTENSION and default
+    #[clap(long, verbatim_doc_comment, value_name="RULES", conflicts_with="class")]
+    pub class_rules: Option<ClassRules>,
+    /// Cache-Control per file from the first rule with a matching glob, e.g.
+    ///   '*.html:no-cache;*.css,*.js:max-age=31536000,immutable'
+    /// Globs without a '/' match the file name, others the destination key
+    #[clap(long, verbatim_doc_comment, value_name="RULES")]
+    pub cache_rules: Option<CacheRules>,
+    /// User metadata stored with each uploaded object as an x-amz-meta-KEY header, e.g.
+    /// 'build-id=1234'. Repeatable
+    #[clap(long, value_name="KEY=VALUE", value_parser=parse_metadata)]
+    pub metadata: Vec<(String, String)>,
+    /// Tag each uploaded object, e.g. 'team=data'. Repeatable
+    #[clap(long, value_name="KEY=VALUE", value_parser=parse_tag)]
+    pub tag: Vec<(String, String)>,
+    /// Have S3 verify each upload against a checksum computed while sending, and store it with
+    /// the object. Multipart uploads send one per part
+    #[clap(long, value_name="ALGORITHM", value_parser=PossibleValuesParser::new(checksum::ALGORITHMS).map(|s| aws_sdk_s3::types::ChecksumAlgorithm::from(s.as_str())))]
+    pub checksum_algorithm: Option<aws_sdk_s3::types::ChecksumAlgorithm>,
+    /// Encrypt each uploaded object with this customer-provided key (SSE-C), base64 of 256 bits.
+    /// S3 only keeps a hash of it, so reading the objects back needs the same key. Prefer the
+    /// variable, as command lines are visible to other users
+    #[clap(long, env="SUP3_SSE_C_KEY", hide_env_values=true, value_name="KEY", value_parser=customer_key::parse_customer_key, conflicts_with="verify_visibility")]
+    pub sse_c_key: Option<CustomerKey>,
+    #[clap(flatten)]
+    pub headers: OptionsHeaders,
+    #[clap(flatten)]
+    pub conditional: OptionsConditional,
+    #[clap(flatten)]
+    pub visibility: OptionsVisibility,
+    #[clap(flatten)]
+    pub lock: OptionsLock,
+    #[clap(flatten)]
+    pub multipart: OptionsMultipart,
+    /// What to do when a local file changes size while being uploaded, e.g. a log being appended to
+    #[clap(long, value_enum, value_name="ACTION", default_value="fail")]
+    pub changed_file: ChangedFile,
+}
+
+/// What to do with a local file found to change size while being uploaded
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ChangedFile {
+    /// Upload it again from the start, up to 3 times in all
+    Restart,
+    /// Fail its upload, leaving any existing object in place
+    Fail,
+    /// Upload as much as it had when opened, only failing if it shrank
+    Ignore,
+}
+
+/// Standard HTTP headers stored with the object and returned when it's served, e.g. via CloudFront
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct OptionsHeaders {
+    /// Cache-Control for objects not matching --cache-rules, e.g. 'max-age=3600'
+    #[clap(long, value_name="VALUE", help_heading="Headers")]
+    pub cache_control: Option<String>,
+    /// Content-Encoding, e.g. 'gzip' for pre-compressed files
+    #[clap(long, value_name="VALUE", help_heading="Headers")]
+    pub content_encoding: Option<String>,
+    /// Content-Disposition, e.g. 'attachment; filename="report.pdf"'
+    #[clap(long, value_name="VALUE", help_heading="Headers")]
+    pub content_disposition: Option<String>,
+    /// Expires, as an RFC 3339 time
+    #[clap(long, value_name="TIME", value_parser=parse_timestamp, help_heading="Headers")]
+    pub expires: Option<aws_sdk_s3::primitives::DateTime>,
+}
+
+impl OptionsHeaders {
+    pub fn is_enabled(&self) -> bool {
+        self.cache_control.is_some() || self.content_encoding.is_some() || self.content_disposition.is_some() || self.expires.is_some()
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct OptionsConditional {
+    /// Only write if the existing destination object has this ETag
+    #[clap(long, value_name="ETAG", help_heading="Conditional Writes")]
+    pub if_match: Option<String>,
+    /// Only write if the destination is unmodified since this RFC 3339 time (provider support varies)
+    #[clap(long, value_name="TIME", value_parser=parse_timestamp, help_heading="Conditional Writes")]
+    pub if_unmodified_since: Option<aws_sdk_s3::primitives::DateTime>,
+}
+
+fn parse_timestamp(s: &str) -> Result<aws_sdk_s3::primitives::DateTime, String> {
+    aws_sdk_s3::primitives::DateTime::from_str(s, aws_sdk_s3::primitives::DateTimeFormat::DateTime)
+        .map_err(|e| format!("expected RFC 3339 timestamp, e.g. 2024-01-31T12:00:00Z: {e}"))
+}
+
+fn parse_metadata(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got '{s}'"))?;
+    let key = key.strip_prefix("x-amz-meta-").unwrap_or(key);
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c)) {
+        return Err(format!("invalid metadata key '{key}', expected letters, digits, '-', '_' or '.'"));
+    }
+    if !value.chars().all(|c| c == ' ' || c.is_ascii_graphic()) {
+        return Err(format!("invalid metadata value '{value}', only printable ASCII is sent unencoded"));
+    }
+    Ok((key.to_ascii_lowercase(), value.to_owned()))
+}
+
+impl OptionsUpload {
+    /// --metadata as sent, None if not given
+    pub fn user_metadata(&self) -> Option<std::collections::HashMap<String, String>> {
+        (!self.metadata.is_empty()).then(|| self.metadata.iter().cloned().collect())
+    }
+}
+
+/// ETags are sent quoted, accept them unquoted as printed by ls
+fn quoted_etag(etag: &str) -> String {
+    if etag.starts_with('"') || etag == "*" {
+        etag.to_owned()
+    } else {
+        format!("\"{etag}\"")
+    }
+}
+
+impl OptionsConditional {
+    /// Headers not modelled by the SDK for PutObject
+    fn extra_headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if let Some(since) = &self.if_unmodified_since {
+            let formatted = since.fmt(aws_sdk_s3::primitives::DateTimeFormat::HttpDate).expect("valid parsed date");
+            headers.push(("if-unmodified-since", formatted));
+        }
+        headers
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct OptionsDownload {
+    /// Download multipart objects part by part, verifying each part's checksum as it arrives
+    #[clap(long)]
+    pub verify_parts: bool,
+
+    #[clap(flatten)]
+    pub cache: OptionsCache,
+
+    #[clap(flatten)]
+    pub age: OptionsAge,
+    /// Skip objects modified within this long, e.g. 5m, which other writers may still be
+    /// uploading to an active drop zone
+    #[clap(long, value_name="DURATION", value_parser=cli::parse_duration)]
+    pub settle_time: Option<std::time::Duration>,
+}
+
+impl OptionsDownload {
+    /// Whether an object last modified at `last_modified` is within --newer-than and --older-than,
+    /// and has settled
+    pub fn matches_age(&self, last_modified: Option<&aws_sdk_s3::primitives::DateTime>) -> bool {
+        if !self.age.matches(last_modified) {
+            return false;
+        }
+        let Some(settle_time) = self.settle_time else {
+            return true;
+        };
+        let Some(settled) = std::time::SystemTime::now().checked_sub(settle_time) else {
+            return false;
+        };
+        OptionsAge { newer_than: None, older_than: Some(settled.into()) }.matches(last_modified)
+    }
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -50,11 +298,13 @@ pub struct OptionsMakeBucket {
     #[clap(flatten)]
     pub access_control: OptionsAccessControl,
     /// Canned access control list
-    #[clap(long, value_parser=PossibleValuesParser::new(aws_sdk_s3::types::BucketCannedAcl::values()), help_heading="Access Control")]
+    #[clap(long, value_parser=PossibleValuesParser::new(aws_sdk_s3::types::BucketCannedAcl::values()).map(|s| aws_sdk_s3::types::BucketCannedAcl::from(s.as_str())), help_heading="Access Control")]
     pub canned_acl: Option<aws_sdk_s3::types::BucketCannedAcl>,
     /// Storage Class
-    #[clap(long, value_parser=PossibleValuesParser::new(aws_sdk_s3::types::StorageClass::values()))]
+    #[clap(long, value_parser=PossibleValuesParser::new(aws_sdk_s3::types::StorageClass::values()).map(|s| aws_sdk_s3::types::StorageClass::from(s.as_str())))]
     pub class: Option<aws_sdk_s3::types::StorageClass>,
+    #[clap(flatten)]
+    pub settings: bucket_settings::OptionsBucketSettings,
 }
 
 #[derive(clap::Args, Debug, Clone)]
@@ -73,8 +323,14 @@ pub struct OptionsAccessControl {
     pub grant_write_acp: Option<String>,
 }
 
-pub async fn init(region: Option<String>, endpoint: Option<http::uri::Uri>, profile_name: Option<&str>) -> Client {
-    let provided_region = region.map(Region::new);
+#[allow(clippy::too_many_arguments)]
+pub async fn init(region: Option<String>, endpoints: &[http::uri::Uri], profile_name: Option<&str>, static_credentials: Option<aws_sdk_s3::config::Credentials>, credential_helper: Option<&str>, accelerate: bool, provider: &OptionsProvider, ip_version: IpVersion, audit_log: Option<AuditLog>) -> Client {
+    let endpoints = match provider.endpoint(region.as_deref()) {
+        Some(endpoint) => vec![endpoint],
+        None => endpoints.to_vec(),
+    };
+    let endpoint = endpoints.first().cloned();
+    let provided_region = region.or_else(|| provider.region().map(str::to_owned)).map(Region::new);
 
     let mut region_provider_builder = aws_config::default_provider::region::Builder::default();
     let mut credentials_provider_builder = aws_config::default_provider::credentials::Builder::default();
@@ -83,31 +339,79 @@ pub async fn init(region: Option<String>, endpoint: Option<http::uri::Uri>, prof
         credentials_provider_builder = credentials_provider_builder.profile_name(profile_name);
     }
     let region_provider = region_provider_builder.build();
-    let credentials_provider = credentials_provider_builder.build();
 
     let region_provider = match provided_region {
         Some(r) => RegionProviderChain::first_try(r),
         None => RegionProviderChain::first_try(region_provider).or_else("eu-west-1"),
     };
 
-    let shared_config = aws_config::defaults(aws_config::BehaviorVersion::v2024_03_28())
-        .region(region_provider)
-        .credentials_provider(credentials_provider.await)
-        .load()
-        .await;
+    // Pinned, so SDK upgrades don't change request defaults unnoticed
+    #[allow(deprecated)]
+    let behavior_version = aws_config::BehaviorVersion::v2024_03_28();
+    let mut shared_config = aws_config::defaults(behavior_version).region(region_provider);
+    if let Some(http_client) = ip_version.http_client() {
+        shared_config = shared_config.http_client(http_client);
+    }
+    let shared_config = match (static_credentials, credential_helper) {
+        (Some(credentials), _) => shared_config.credentials_provider(credentials),
+        (None, Some(command)) => shared_config.credentials_provider(CredentialProcessProvider::new(command.to_owned())),
+        (None, None) => shared_config.credentials_provider(credentials_provider_builder.build().await),
+    };
+    let shared_config = shared_config.load().await;
 
     let mut client_config_builder = aws_sdk_s3::config::Builder::from(&shared_config);
 
-    if let Some(uri) = endpoint {
+    if let Some(uri) = &endpoint {
         client_config_builder = client_config_builder
             .endpoint_url(uri.to_string())
             .force_path_style(true);
     }
+    if endpoints.len() > 1 {
+        client_config_builder = client_config_builder.interceptor(failover::Failover::new(&endpoints));
+    }
+    client_config_builder = client_config_builder.accelerate(accelerate);
+    // Requests are signed at the server's time once a response shows the local clock is off
+    client_config_builder = client_config_builder
+        .disable_clock_skew_correction(false)
+        .interceptor(clock_skew::SkewWarning::default());
+    // Gateways throttling with 429 rather than 503 SlowDown are backed off from the same way
+    let throttles = throttle::Throttles::default();
+    client_config_builder = client_config_builder
+        .retry_classifier(throttle::RetryAfter)
+        .interceptor(throttles.clone());
+    client_config_builder = provider.apply(client_config_builder);
+    let audit_log = match audit_log {
+        Some(audit_log) => {
+            use aws_sdk_s3::config::ProvideCredentials;
+            let credentials = match shared_config.credentials_provider() {
+                Some(provider) => provider.provide_credentials().await.ok(),
+                None => None,
+            };
+            let principal = credentials.map(|c| c.access_key_id().to_owned()).unwrap_or_else(|| "anonymous".into());
+            let audit_log = audit_log.with_principal(principal);
+            client_config_builder = client_config_builder.interceptor(audit_log.clone());
+            Some(audit_log)
+        },
+        None => None,
+    };
 
     let client = aws_sdk_s3::Client::from_conf(client_config_builder.build());
     Client {
         client,
         region: shared_config.region().cloned(),
+        head_cache: Default::default(),
+        disk_jobs: Default::default(),
+        rate_limits: Default::default(),
+        quota: Default::default(),
+        part_retries: Default::default(),
+        in_flight: Default::default(),
+        throttles,
+        endpoint: url_style::Endpoint {
+            custom: endpoint.or_else(|| shared_config.endpoint_url().and_then(|u| u.parse().ok())),
+            region: shared_config.region().map(|r| r.to_string()),
+            accelerate,
+        },
+        audit_log,
     }
 }
 
@@ -116,6 +420,9 @@ pub struct ListArguments {
     /// Display full S3 paths
     #[clap(long, short='F')]
     full_path: bool,
+    /// URL style for full paths, implies --full-path if not s3
+    #[clap(long, value_enum, default_value="s3")]
+    url_style: UrlStyle,
     /// Display long format (size, date, name)
     #[clap(long, short='l')]
     long: bool,
@@ -134,17 +441,105 @@ pub struct ListArguments {
     only_files: bool,
     #[clap(flatten)]
     glob_options: GlobOptions,
+    #[clap(flatten)]
+    filter: OptionsFilter,
+    #[clap(flatten)]
+    size: OptionsSize,
+    #[clap(flatten)]
+    age: OptionsAge,
+    /// Output format
+    #[clap(long, value_enum, default_value="text")]
+    output: cli::OutputFormat,
+    #[clap(flatten)]
+    color: colors::OptionsColor,
+    /// Write output to a local file (parquet output only)
+    #[cfg(feature = "parquet")]
+    #[clap(long, value_hint=clap::ValueHint::FilePath)]
+    to: Option<PathBuf>,
+    /// Fail unless exactly this many entries are listed
+    #[clap(long, help_heading = "Assertions")]
+    expect_count: Option<usize>,
+    /// Fail if any entries are listed
+    #[clap(long, help_heading = "Assertions", conflicts_with = "expect_count")]
+    expect_empty: bool,
+    /// Annotate objects with the lifecycle rules that will transition or expire them, and when
+    #[clap(long)]
+    explain_lifecycle: bool,
+    /// List every version and delete marker of objects in versioned buckets, newest first, with
+    /// their version IDs and which is the latest
+    #[clap(long, conflicts_with = "explain_lifecycle")]
+    versions: bool,
+    /// Don't print an 's3://bucket/prefix:' header before each URI's entries when listing several
+    #[clap(long)]
+    no_header: bool,
+}
+
+/// Destination for listing entries not printed directly to stdout
+pub enum ListOutput {
+    Stdout,
+    #[cfg(feature = "parquet")]
+    Parquet(Box<parquet_export::Writer>),
+}
+
+impl ListOutput {
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            ListOutput::Stdout => Ok(()),
+            #[cfg(feature = "parquet")]
+            ListOutput::Parquet(writer) => writer.finish().map(|_| ()),
+        }
+    }
 }
 
 impl ListArguments {
+    /// Describe why `count` listed entries fail --expect-count/--expect-empty, if they do
+    pub fn unmet_expectation(&self, count: usize) -> Option<String> {
+        let expected = if self.expect_empty { 0 } else { self.expect_count? };
+        (count != expected).then(|| format!("expected {expected} entries, listed {count}"))
+    }
     pub fn validate(&self) -> Result<(), (clap::error::ErrorKind, &'static str)> {
-        if self.glob_options.is_enabled() && self.recurse {
-            return Err((clap::error::ErrorKind::ArgumentConflict, "recurse with glob currently not supported"));
+        #[cfg(feature = "parquet")]
+        match (self.output, &self.to) {
+            (cli::OutputFormat::Parquet, None) => return Err((clap::error::ErrorKind::MissingRequiredArgument, "--output parquet requires --to <FILE>")),
+            (cli::OutputFormat::Parquet, Some(_)) => {},
+            (_, Some(_)) => return Err((clap::error::ErrorKind::ArgumentConflict, "--to is only supported with --output parquet")),
+            (_, None) => {},
+        }
+        if self.explain_lifecycle && self.output != cli::OutputFormat::Text {
+            return Err((clap::error::ErrorKind::ArgumentConflict, "--explain-lifecycle is only supported with text output"));
+        }
+        #[cfg(feature = "parquet")]
+        if self.versions && self.output == cli::OutputFormat::Parquet {
+            return Err((clap::error::ErrorKind::ArgumentConflict, "--versions isn't supported with --output parquet"));
         }
         Ok(())
     }
+    /// Whether to print a header before each group of entries, as ls does for several directories
+    pub fn group_headers(&self, uri_count: usize) -> bool {
+        uri_count > 1 && !self.no_header && self.output == cli::OutputFormat::Text
+    }
+    /// Open the output once per invocation, printing any preamble required by the format
+    pub fn open_output(&self) -> Result<ListOutput, Error> {
+        match self.output {
+            cli::OutputFormat::Text => Ok(ListOutput::Stdout),
+            cli::OutputFormat::Csv if self.versions => {
+                cli::println_csv(&["key", "version_id", "state", "size", "last_modified", "etag"]);
+                Ok(ListOutput::Stdout)
+            },
+            cli::OutputFormat::Csv => {
+                cli::println_csv(&["key", "type", "size", "last_modified", "storage_class", "etag"]);
+                Ok(ListOutput::Stdout)
+            },
+            #[cfg(feature = "parquet")]
+            cli::OutputFormat::Parquet => {
+                let path = self.to.as_ref().expect("validated");
+                Ok(ListOutput::Parquet(Box::new(parquet_export::Writer::create(path)?)))
+            },
+        }
+    }
 }
 
+#[allow(clippy::needless_lifetimes, clippy::needless_return)]
 fn error_source<'a>(error: &'a dyn std::error::Error) -> &'a dyn std::error::Error {
     let mut source = error;
     for _ in 0..1 {
@@ -158,6 +553,7 @@ fn error_source<'a>(error: &'a dyn std::error::Error) -> &'a dyn std::error::Err
 }
 
 #[derive (thiserror::Error, Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("no filename in either source or destination")]
     NoFilename,
@@ -169,14 +565,33 @@ pub enum Error {
     Streaming(#[from] aws_sdk_s3::primitives::ByteStreamError),
     #[error("no such remote file: {0}")]
     NoSuchKey(Uri),
+    #[error("{0} changed since it was listed")]
+    Modified(Uri),
+    #[error("local file '{}' changed while being uploaded", .0.to_string_lossy())]
+    LocalFileChanged(PathBuf),
     #[error("io: {0}")]
     Io(std::io::Error),
+    #[error("http source: {0}")]
+    Http(#[from] crate::http_source::Error),
+    #[error("{0} not visible after {1} seconds")]
+    NotVisible(Uri, u64),
+    #[error("invalid chunk manifest or chunk: {0}")]
+    InvalidManifest(Uri),
+    #[error("{0} is locked by {1}")]
+    Locked(Uri, String),
+    #[error("{0} can't be synced, its key has an empty, '.' or '..' segment below the prefix")]
+    UnsafeKey(Uri),
+    #[error("{0} would be written by more than one object or overwrite one still to be copied, refusing to start")]
+    DestinationCollision(Uri),
     #[error("{0}{}", error_source(&**.1))]
     S3SdkError(&'static str, Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("{0}{:?}", error_source(&**.1))]
     S3SdkErrorDebug(&'static str, Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("{}: {}", .0.code().unwrap(), .0.message().unwrap())]
     S3SdkErrorMeta(aws_sdk_s3::error::ErrorMetadata),
+    #[cfg(feature = "parquet")]
+    #[error("parquet: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
 }
 
 impl<E: std::error::Error + Send + Sync + 'static + ProvideErrorMetadata, R> From<aws_sdk_s3::error::SdkError<E, R>> for Error {
@@ -249,12 +664,13 @@ impl Target {
     }
 }
 
-async fn get_write_loop(local_file: &mut partial_file::PartialFile, mut body: aws_sdk_s3::primitives::ByteStream, progress_fn: &cli::ProgressFn) -> Result<(), Error> {
+async fn get_write_loop(local_file: &mut partial_file::PartialFile, mut body: aws_sdk_s3::primitives::ByteStream, limiter: &rate_limit::Limiter, progress_fn: &cli::ProgressFn) -> Result<(), Error> {
     loop {
         let next_block = body.try_next();
         match next_block.await {
             Ok(Some(bytes)) => {
-                local_file.writer().write_all(&bytes).await?;
+                limiter.acquire(bytes.len()).await;
+                local_file.write_all(&bytes).await?;
                 progress_fn(cli::Update::StateProgress(bytes.len()));
             },
             Ok(None) => break,
@@ -271,7 +687,15 @@ pub enum GetRecursiveResultStream<'a> {
 
 pub enum RecursiveStreamItem {
     Directory(Key),
-    File(Key),
+    File(Key, ObjectSummary),
+}
+
+/// What a listing says about an object
+#[derive(Debug, Clone)]
+pub struct ObjectSummary {
+    pub size: u64,
+    pub last_modified: Option<aws_sdk_s3::primitives::DateTime>,
+    pub etag: Option<String>,
 }
 
 pub struct RecursiveListStream<'a> {
@@ -283,6 +707,7 @@ pub struct RecursiveListStream<'a> {
 }
 
 impl<'a> RecursiveListStream<'a> {
+    #[allow(clippy::unused_unit)]
     pub fn stream(&'a mut self) -> impl Stream<Item = Result<Vec<RecursiveStreamItem>, Error>> + 'a {
         async_stream::try_stream! {
             loop {
@@ -307,34 +732,135 @@ impl<'a> RecursiveListStream<'a> {
 
 use futures::future::TryFutureExt;
 
-fn path_to_sdk_body(path: PathBuf, progress: cli::ProgressFn) -> SdkBody
+/// Size of each read of a file being uploaded
+const READ_BLOCK_SIZE: usize = 64 * 1024;
+/// Uploads of a file changing size meanwhile, with --changed-file restart
+const CHANGED_FILE_ATTEMPTS: u32 = 3;
+
+/// Each block read counts as buffered until the connection asks for the next, then as sent.
+/// The last is left in `unsent` for the caller to count once the response acknowledges it.
+///
+/// Sends the first `length` bytes, failing and marking `changed` if the file is shorter, or
+/// unless `ignore_growth` if it's longer
+#[allow(clippy::too_many_arguments)]
+fn path_to_sdk_body(path: PathBuf, length: u64, ignore_growth: bool, disk_jobs: disk_jobs::DiskJobs, limiter: rate_limit::Limiter, progress: cli::ProgressFn, unsent: Arc<AtomicUsize>, changed: Arc<AtomicBool>) -> SdkBody
 {
     let open_fut = async move {
         let file = tokio::fs::File::open(path).await?;
-        Ok(tokio_util::io::ReaderStream::new(file))
+        Ok(futures::stream::try_unfold((file, length), move |(mut file, remaining)| {
+            let disk_jobs = disk_jobs.clone();
+            let limiter = limiter.clone();
+            let changed = changed.clone();
+            async move {
+                use tokio::io::AsyncReadExt;
+                if remaining == 0 {
+                    if !ignore_growth {
+                        let _permit = disk_jobs.acquire().await;
+                        if file.read(&mut [0]).await? > 0 {
+                            changed.store(true, Ordering::Relaxed);
+                            return Err(std::io::Error::other("file grew while being uploaded"));
+                        }
+                    }
+                    return Ok(None);
+                }
+                let mut block = bytes::BytesMut::with_capacity(READ_BLOCK_SIZE);
+                let read = {
+                    let _permit = disk_jobs.acquire().await;
+                    (&mut file).take(remaining).read_buf(&mut block).await?
+                };
+                if read == 0 {
+                    changed.store(true, Ordering::Relaxed);
+                    return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "file shrank while being uploaded"));
+                }
+                limiter.acquire(read).await;
+                Ok::<_, std::io::Error>(Some((block.freeze(), (file, remaining - read as u64))))
+            }
+        }))
     };
     let flattened = open_fut.try_flatten_stream();
-    let inspected = flattened.inspect_ok(move |bytes| progress(cli::Update::StateProgress(bytes.len())));
+    unsent.store(0, Ordering::Relaxed);
+    let inspected = flattened.inspect_ok(move |bytes| {
+        progress(cli::Update::StateBuffered(bytes.len()));
+        match unsent.swap(bytes.len(), Ordering::Relaxed) {
+            0 => {},
+            sent => progress(cli::Update::StateProgress(sent)),
+        }
+    });
     let body = http_body_util::StreamBody::new(inspected.map_ok(hyper::body::Frame::data));
     SdkBody::from_body_1_x(body)
 }
 
-fn path_to_bytestream(path: PathBuf, progress: cli::ProgressFn) -> ByteStream
+#[allow(clippy::too_many_arguments)]
+fn path_to_bytestream(path: PathBuf, length: u64, ignore_growth: bool, disk_jobs: disk_jobs::DiskJobs, limiter: rate_limit::Limiter, progress: cli::ProgressFn, unsent: Arc<AtomicUsize>, changed: Arc<AtomicBool>) -> ByteStream
 {
     let retryable = SdkBody::retryable(move || {
         progress(cli::Update::StateRetried);
-        path_to_sdk_body(path.clone(), progress.clone())
+        path_to_sdk_body(path.clone(), length, ignore_growth, disk_jobs.clone(), limiter.clone(), progress.clone(), unsent.clone(), changed.clone())
     });
     ByteStream::from(retryable)
 }
 
 impl Client {
-    pub async fn put(&self, verbose: bool, options_upload: &OptionsUpload, path: &std::path::Path, s3_uri: &Uri, progress_fn: cli::ProgressFn) -> Result<String, Error> {
+    /// Clone bounding local file reads and writes to `jobs` at once, shared with its own clones
+    pub fn with_disk_jobs(&self, jobs: Option<usize>) -> Client {
+        Client { disk_jobs: disk_jobs::DiskJobs::new(jobs), ..self.clone() }
+    }
+    /// Clone limiting its transfers to `global` bytes per second between them, and each to `per_file`
+    pub fn with_rate_limits(&self, global: Option<u64>, per_file: Option<u64>) -> Client {
+        Client { rate_limits: rate_limit::RateLimits::new(global, per_file), ..self.clone() }
+    }
+    /// Region `bucket` is in, as HeadBucket reports it even when asked in another region
+    pub async fn bucket_region(&self, bucket: &str) -> Option<String> {
+        match self.client.head_bucket().bucket(bucket).send().await {
+            Ok(output) => output.bucket_region().map(str::to_owned),
+            Err(e) => e.raw_response()?.headers().get("x-amz-bucket-region").map(str::to_owned),
+        }
+    }
+    /// Clone sending its requests to `bucket`'s region, where known and not its own
+    pub async fn for_bucket(&self, bucket: &str) -> Client {
+        match self.bucket_region(bucket).await {
+            Some(region) if self.region.as_ref().map(Region::as_ref) != Some(region.as_str()) => {
+                let region = Region::new(region);
+                let config = self.client.config().to_builder().region(region.clone()).build();
+                Client { client: aws_sdk_s3::Client::from_conf(config), region: Some(region), ..self.clone() }
+            },
+            _ => self.clone(),
+        }
+    }
+    /// Whether the provider has refused writes for lack of quota, and what wasn't attempted since
+    pub fn quota(&self) -> &quota::Quota {
+        &self.quota
+    }
+    /// Multipart upload parts sent more than once
+    pub fn part_retries(&self) -> &resume::PartRetries {
+        &self.part_retries
+    }
+    /// Requests the server throttled, retried rather than failed unless out of attempts
+    pub fn throttles(&self) -> &throttle::Throttles {
+        &self.throttles
+    }
+    /// Upload a local file, handling it changing size meanwhile as --changed-file says.
+    /// Returns the URI of the object written
+    pub async fn put(&self, verbose: bool, options_upload: &OptionsUpload, path: &std::path::Path, s3_uri: &Uri, progress_fn: cli::ProgressFn) -> Result<Uri, Error> {
+        let mut attempt = 1;
+        loop {
+            match self.put_once(verbose, options_upload, path, s3_uri, progress_fn.clone()).await {
+                Err(Error::LocalFileChanged(_)) if options_upload.changed_file == ChangedFile::Restart && attempt < CHANGED_FILE_ATTEMPTS => {
+                    if verbose {
+                        println!("🏁 '{}' changed size while being uploaded, restarting", path.to_string_lossy());
+                    }
+                    progress_fn(cli::Update::StateRetried);
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+    async fn put_once(&self, verbose: bool, options_upload: &OptionsUpload, path: &std::path::Path, s3_uri: &Uri, progress_fn: cli::ProgressFn) -> Result<Uri, Error> {
         progress_fn(cli::Update::State("opening"));
         let length = tokio::fs::metadata(path)
             .await?
             .len();
-        let stream = path_to_bytestream(path.to_path_buf(), progress_fn.clone());
         let mut key = s3_uri.key.clone();
         let size_hint = Some(length as usize);
         if s3_uri.filename().is_none() {
@@ -345,34 +871,109 @@ impl Client {
             key.push(local_filename);
         }
         let path_printable = path.to_string_lossy();
-        let destination = format!("s3://{}/{key}", s3_uri.bucket);
+        let uploaded = Uri::new(s3_uri.bucket.clone(), key);
+        let destination = uploaded.to_string();
         if verbose {
             match size_hint {
                 Some(size) => println!("🏁 uploading '{path_printable}' [{size} bytes] to {destination}"),
                 None => println!("🏁 uploading '{path_printable}' to {destination}"),
             };
+            options_upload.println_checksum_algorithm();
         }
         progress_fn(cli::Update::State("uploading"));
         progress_fn(cli::Update::StateLength(length as usize));
-        self.client.put_object()
-            .bucket(s3_uri.bucket.clone())
-            .key(key.to_string())
+        let limiter = self.rate_limits.transfer();
+        if length > options_upload.multipart.multipart_threshold {
+            let result = self.put_resumable(verbose, options_upload, path, length, &uploaded, &limiter, &progress_fn).await;
+            self.head_cache.invalidate(&uploaded);
+            let etag = result?;
+            self.await_visible(verbose, &options_upload.visibility, &uploaded, length, etag.as_deref(), &progress_fn).await?;
+            progress_fn(cli::Update::Finished());
+            return Ok(uploaded);
+        }
+        // Empty marker files are common in trees, don't open and stream them
+        let unsent = Arc::new(AtomicUsize::new(0));
+        let changed = Arc::new(AtomicBool::new(false));
+        let ignore_growth = options_upload.changed_file == ChangedFile::Ignore;
+        let stream = match length {
+            0 => ByteStream::from_static(b""),
+            _ => path_to_bytestream(path.to_path_buf(), length, ignore_growth, self.disk_jobs.clone(), limiter, progress_fn.clone(), unsent.clone(), changed.clone()),
+        };
+        let extra_headers = options_upload.conditional.extra_headers();
+        let result = self.client.put_object()
+            .bucket(uploaded.bucket.clone())
+            .key(uploaded.key.to_string())
             .content_length(length as i64)
             .set_acl(options_upload.canned_acl.to_owned())
             .set_grant_read(options_upload.access_control.grant_read.to_owned())
             .set_grant_full_control(options_upload.access_control.grant_full.to_owned())
             .set_grant_read_acp(options_upload.access_control.grant_read_acp.to_owned())
             .set_grant_write_acp(options_upload.access_control.grant_write_acp.to_owned())
-            .set_storage_class(options_upload.class.to_owned())
+            .set_storage_class(options_upload.class_for(path, length))
+            .set_cache_control(options_upload.cache_control_for(&uploaded.key))
+            .set_metadata(options_upload.user_metadata())
+            .set_tagging(options_upload.tagging())
+            .set_content_encoding(options_upload.headers.content_encoding.to_owned())
+            .set_content_disposition(options_upload.headers.content_disposition.to_owned())
+            .set_expires(options_upload.headers.expires)
+            .set_if_match(options_upload.conditional.if_match.as_deref().map(quoted_etag))
+            .set_checksum_algorithm(options_upload.checksum_algorithm.clone())
+            .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+            .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+            .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
             .body(stream)
+            .customize()
+            .mutate_request(move |request| {
+                for (name, value) in &extra_headers {
+                    request.headers_mut().insert(*name, value.clone());
+                }
+            })
             .send()
-            .await?;
+            .await;
+        if changed.load(Ordering::Relaxed) {
+            return Err(Error::LocalFileChanged(path.to_owned()));
+        }
+        let put = result?;
+        progress_fn(cli::Update::StateProgress(unsent.swap(0, Ordering::Relaxed)));
+        self.head_cache.invalidate(&uploaded);
+        self.await_visible(verbose, &options_upload.visibility, &uploaded, length, put.e_tag(), &progress_fn).await?;
+        progress_fn(cli::Update::Finished());
+        Ok(uploaded)
+    }
+    /// Stream an HTTP(S) resource into S3, resuming the download with Range requests on interruption
+    ///
+    /// Nothing is written if the content doesn't match the manifest entry's checksum. Returns the destination and bytes uploaded
+    pub async fn put_url(&self, verbose: bool, options_upload: &OptionsUpload, entry: &crate::manifest::Entry, s3_uri: &Uri, progress_fn: cli::ProgressFn) -> Result<(Uri, u64), Error> {
+        progress_fn(cli::Update::State("connecting"));
+        let url = &entry.url;
+        let source = crate::http_source::Source::open(url).await?;
+        let mut key = s3_uri.key.clone();
+        if s3_uri.filename().is_none() {
+            key.push(&source.filename().ok_or(Error::NoFilename)?);
+        }
+        let destination = Uri::new(s3_uri.bucket.clone(), key);
+        if verbose {
+            match source.length() {
+                Some(size) => println!("🏁 uploading '{url}' [{size} bytes] to {destination}"),
+                None => println!("🏁 uploading '{url}' to {destination}"),
+            };
+            options_upload.println_checksum_algorithm();
+        }
+        progress_fn(cli::Update::State("uploading"));
+        match source.length() {
+            Some(length) => progress_fn(cli::Update::StateLength(length as usize)),
+            None => progress_fn(cli::Update::StateLengthUnknown),
+        }
+        let source_length = source.length();
+        let (length, etag) = self.put_stream(options_upload, source.into_stream(entry.checksum.clone()).map_err(Error::Io), source_length, None, &destination, progress_fn.clone()).await?;
+        self.await_visible(verbose, &options_upload.visibility, &destination, length, etag.as_deref(), &progress_fn).await?;
         progress_fn(cli::Update::Finished());
-        Ok(destination)
+        Ok((destination, length))
     }
-    pub async fn get_recursive_stream(&self, verbose: bool, recursive: bool, from: Uri, to: Target, progress_fn: cli::ProgressFn) -> Result<GetRecursiveResultStream, Error> {
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get_recursive_stream(&self, verbose: bool, recursive: bool, options: &OptionsDownload, ranged: Option<RangedDownload>, from: Uri, listed_etag: Option<&str>, to: Target, progress_fn: cli::ProgressFn) -> Result<GetRecursiveResultStream<'_>, Error> {
         progress_fn(cli::Update::State("listing"));
-        match self.get(verbose, &from, &to, progress_fn.clone()).await {
+        match self.get(verbose, options, ranged, &from, listed_etag, &to, progress_fn.clone()).await {
             Err(Error::NoSuchKey(uri)) if recursive => {
                 let recursive_stream = self.get_recursive_list_stream(&uri, progress_fn).await?;
                 Ok(GetRecursiveResultStream::Many(recursive_stream))
@@ -385,29 +986,98 @@ impl Client {
             Err(err) => Err(err),
         }
     }
-    pub async fn get(&self, verbose: bool, from: &Uri, to: &Target, progress_fn: cli::ProgressFn) -> Result<PathBuf, Error> {
+    /// Download `from`, which must still have `listed_etag` if given, failing with Error::Modified otherwise
+    #[allow(clippy::too_many_arguments)]
+    pub async fn get(&self, verbose: bool, options: &OptionsDownload, ranged: Option<RangedDownload>, from: &Uri, listed_etag: Option<&str>, to: &Target, progress_fn: cli::ProgressFn) -> Result<PathBuf, Error> {
         // S3 errors on root key requests, wrap into no such key
         if from.key.is_empty() {
             return Err(Error::NoSuchKey(from.clone()));
         }
         progress_fn(cli::Update::State("connecting"));
+        let cache = options.cache.open();
+        let etag = match (listed_etag, &cache) {
+            (Some(etag), _) => Some(etag.to_owned()),
+            (None, Some(_)) => self.head(from).await?.e_tag().map(str::to_owned),
+            (None, None) => None,
+        };
+        let (Some(cache), Some(etag)) = (cache, &etag) else {
+            return self.get_remote(verbose, options, ranged, from, etag.as_deref(), to, progress_fn).await;
+        };
+        if let Some(cached) = cache.lookup(etag).await {
+            return self.get_cached(verbose, from, &cached, to, progress_fn).await;
+        }
+        let local_path = self.get_remote(verbose, options, ranged, from, Some(etag), to, progress_fn).await?;
+        if let Err(e) = cache.insert(etag, &local_path).await {
+            cli::println_error(format_args!("failed to cache '{from}': {e}"));
+        }
+        Ok(local_path)
+    }
+    async fn get_cached(&self, verbose: bool, from: &Uri, cached: &std::path::Path, to: &Target, progress_fn: cli::ProgressFn) -> Result<PathBuf, Error> {
+        progress_fn(cli::Update::State("opening"));
+        let local_path = to.local_path(from)?;
+        let mut local_file = partial_file::PartialFile::new(local_path, self.disk_jobs.clone()).await?;
+        let length = tokio::fs::metadata(cached).await?.len();
+        progress_fn(cli::Update::State("copying cached"));
+        progress_fn(cli::Update::StateLength(length as usize));
+        if verbose {
+            println!("🏁 copying cached '{from}' [{length} bytes] to {path_printable}", path_printable = local_file.path_printable());
+        }
+        let body = ByteStream::from_path(cached).await?;
+        let local_path = match get_write_loop(&mut local_file, body, &Default::default(), &progress_fn).await {
+            Ok(_) => local_file.finished().await?,
+            Err(err) => {
+                local_file.cancelled().await?;
+                return Err(err);
+            }
+        };
+        progress_fn(cli::Update::Finished());
+        Ok(local_path)
+    }
+    /// Download from S3, requiring `etag` to still match if given
+    #[allow(clippy::too_many_arguments)]
+    async fn get_remote(&self, verbose: bool, options: &OptionsDownload, ranged: Option<RangedDownload>, from: &Uri, etag: Option<&str>, to: &Target, progress_fn: cli::ProgressFn) -> Result<PathBuf, Error> {
+        let limiter = self.rate_limits.transfer();
+        // Objects uploaded in one part may still carry a whole object checksum for a plain GET to verify
+        let mut single_part = false;
+        if options.verify_parts {
+            let object = self.object_parts(from).await?;
+            if !object.parts.is_empty() && object.parts.iter().all(|p| p.checksum.is_some()) {
+                let concurrency = ranged.map_or(1, |r| r.concurrency);
+                return self.get_parts(verbose, concurrency, from, &object, etag, to, &limiter, progress_fn).await;
+            }
+            if verbose {
+                println!("🏁 no per-part checksums for '{from}', verifying whole object checksum if present");
+            }
+            single_part = object.parts.is_empty();
+        }
+        if let (false, Some(ranged)) = (single_part, ranged) {
+            if let Some(local_path) = self.get_ranged(verbose, ranged, from, etag, to, &limiter, progress_fn.clone()).await? {
+                return Ok(local_path);
+            }
+        }
+        let checksum_mode = options.verify_parts.then_some(aws_sdk_s3::types::ChecksumMode::Enabled);
         let response = self.client.get_object()
             .bucket(from.bucket.clone())
             .key(from.key.to_string())
+            .set_checksum_mode(checksum_mode)
+            .set_if_match(etag.map(str::to_owned))
             .send()
             .await
             .map_err(|e| error_from_get(from, e))?;
 
         progress_fn(cli::Update::State("opening"));
         let local_path = to.local_path(from)?;
-        let mut local_file = partial_file::PartialFile::new(local_path).await?;
+        if response.content_length() == Some(0) {
+            return self.get_empty(verbose, from, local_path, &progress_fn).await;
+        }
+        let mut local_file = partial_file::PartialFile::new(local_path, self.disk_jobs.clone()).await?;
 
         progress_fn(cli::Update::State("downloading"));
         progress_fn(cli::Update::StateLength(response.content_length().unwrap_or(0i64) as usize));
         if verbose {
             println!("🏁 downloading '{from}' [{size} bytes] to {path_printable}", size = response.content_length().unwrap_or(0i64), path_printable = local_file.path_printable());
         }
-        let local_path = match get_write_loop(&mut local_file, response.body, &progress_fn).await {
+        let local_path = match get_write_loop(&mut local_file, response.body, &limiter, &progress_fn).await {
             Ok(_) => local_file.finished().await?,
             Err(err) => {
                 local_file.cancelled().await?;
@@ -417,7 +1087,20 @@ impl Client {
         progress_fn(cli::Update::Finished());
         Ok(local_path)
     }
-    pub async fn get_recursive_list_stream(&self, uri: &Uri, progress_fn: cli::ProgressFn) -> Result<RecursiveListStream, Error> {
+    /// Create an empty local file directly, with nothing to stream there's no partial file to clean up
+    async fn get_empty(&self, verbose: bool, from: &Uri, local_path: PathBuf, progress_fn: &cli::ProgressFn) -> Result<PathBuf, Error> {
+        progress_fn(cli::Update::StateLength(0));
+        if verbose {
+            println!("🏁 downloading '{from}' [0 bytes] to {}", local_path.to_string_lossy());
+        }
+        {
+            let _permit = self.disk_jobs.acquire().await;
+            tokio::fs::File::create(&local_path).await?;
+        }
+        progress_fn(cli::Update::Finished());
+        Ok(local_path)
+    }
+    pub async fn get_recursive_list_stream(&self, uri: &Uri, progress_fn: cli::ProgressFn) -> Result<RecursiveListStream<'_>, Error> {
         let key = uri.key.to_explicit_directory();
         let seen_directories = seen_directories::SeenDirectories::new(key.as_str());
         Ok(RecursiveListStream {
@@ -432,15 +1115,15 @@ impl Client {
         let files = self.ls_inner(&uri.bucket, &uri.key, None, continuation_token)
             .await?;
         let mut ret = Vec::new();
-        for key in files.contents.unwrap_or_default()
+        for (key, summary) in files.contents.unwrap_or_default()
             .into_iter()
-            .flat_map(|f| f.key) {
+            .flat_map(|f| Some((f.key?, ObjectSummary { size: f.size.unwrap_or(0) as u64, last_modified: f.last_modified, etag: f.e_tag }))) {
             for dir in seen_directories.add_key(&key) {
                 ret.push(RecursiveStreamItem::Directory(Key::new(dir)));
             }
-            ret.push(RecursiveStreamItem::File(Key::new(key)));
+            ret.push(RecursiveStreamItem::File(Key::new(key), summary));
         }
-        let next_continuation_token = files.continuation_token;
+        let next_continuation_token = files.next_continuation_token;
         if ret.is_empty() {
             if next_continuation_token.is_some() {
                 return Ok(None);
@@ -459,8 +1142,70 @@ impl Client {
             .key(s3_uri.key.to_string())
             .send()
             .await?;
+        self.head_cache.invalidate(s3_uri);
         Ok(())
     }
+    /// Remove keys of one bucket with DeleteObjects, MAX_DELETE_BATCH keys per request, returning
+    /// those that failed and why. Falls back to removing one at a time where DeleteObjects isn't implemented
+    pub async fn remove_batch(&self, opts: &SharedOptions, bucket: &str, keys: &[Key]) -> Result<Vec<(Key, String)>, Error> {
+        use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+        let mut failed = Vec::new();
+        for batch in keys.chunks(MAX_DELETE_BATCH) {
+            if opts.verbose {
+                println!("🏁 removing {} object(s) from s3://{bucket}/... ", batch.len());
+            }
+            let objects = batch.iter()
+                .map(|key| ObjectIdentifier::builder().key(key.to_string()).build().expect("key set"))
+                .collect();
+            let result = self.client.delete_objects()
+                .bucket(bucket)
+                .delete(Delete::builder().set_objects(Some(objects)).quiet(true).build().expect("objects set"))
+                .send()
+                .await;
+            let response = match result {
+                Ok(response) => response,
+                Err(e) if e.code() == Some("NotImplemented") => {
+                    for key in batch {
+                        if let Err(e) = self.remove(opts, &Uri::new(bucket.to_owned(), key.clone())).await {
+                            failed.push((key.clone(), e.to_string()));
+                        }
+                    }
+                    continue;
+                },
+                Err(e) => return Err(e.into()),
+            };
+            for key in batch {
+                self.head_cache.invalidate(&Uri::new(bucket.to_owned(), key.clone()));
+            }
+            for error in response.errors() {
+                let reason = error.message().or(error.code()).unwrap_or("unknown error");
+                failed.push((Key::new(error.key().unwrap_or_default().to_owned()), reason.to_owned()));
+            }
+        }
+        Ok(failed)
+    }
+    /// HeadObject, cached for the rest of the invocation until the object is written or removed
+    pub async fn head(&self, uri: &Uri) -> Result<aws_sdk_s3::operation::head_object::HeadObjectOutput, Error> {
+        if let Some(cached) = self.head_cache.get(uri) {
+            return cached.ok_or_else(|| Error::NoSuchKey(uri.clone()));
+        }
+        let result = self.client.head_object()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .send()
+            .await;
+        match result {
+            Ok(head) => {
+                self.head_cache.insert(uri, Some(head.clone()));
+                Ok(head)
+            },
+            Err(e) if e.as_service_error().map(|e| e.is_not_found()).unwrap_or(false) => {
+                self.head_cache.insert(uri, None);
+                Err(Error::NoSuchKey(uri.clone()))
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
 
     async fn ls_inner(&self, bucket: &str, key: &Key, delimiter: Option<char>, continuation: Option<String>) -> Result<ListObjectsV2Output, Error> {
         self.client.list_objects_v2()
@@ -472,7 +1217,12 @@ impl Client {
             .await
             .map_err(|e| e.into())
     }
-    pub async fn ls(&self, opts: &SharedOptions, args: &ListArguments, s3_uri: &Uri) -> Result<(), Error> {
+    /// List matching entries, returning how many were output
+    #[allow(clippy::needless_borrow)]
+    pub async fn ls(&self, opts: &SharedOptions, args: &ListArguments, output: &mut ListOutput, s3_uri: &Uri) -> Result<usize, Error> {
+        if args.versions {
+            return self.ls_versions(opts, args, s3_uri).await;
+        }
         if opts.verbose {
             println!("🏁 listing s3://{}/{}... ", s3_uri.bucket, s3_uri.key);
         }
@@ -518,8 +1268,16 @@ impl Client {
             relative_root.basename_key()
         };
 
+        let lifecycle = match args.explain_lifecycle {
+            true => self.lifecycle_rules(&s3_uri.bucket).await?,
+            false => None,
+        };
+        if args.explain_lifecycle && lifecycle.is_none() {
+            eprintln!("⚠️: no readable lifecycle configuration for s3://{}", s3_uri.bucket);
+        }
+
         let mut seen_directories = seen_directories::SeenDirectories::new(&relative_root);
-        ls_consume_response(args, &response, &directory_prefix, &s3_uri.bucket, &mut seen_directories, glob.as_ref());
+        let mut count = ls_consume_response(args, &self.endpoint, output, &response, &directory_prefix, &s3_uri.bucket, &mut seen_directories, glob.as_ref(), lifecycle.as_deref())?;
 
         let mut continuation_token = response.next_continuation_token;
         let mut page = 2;
@@ -530,11 +1288,24 @@ impl Client {
             let continuation_response = self.ls_inner(&s3_uri.bucket, &relative_root, separator, continuation_token.take())
                 .await?;
 
-            ls_consume_response(args, &continuation_response, &relative_root, &s3_uri.bucket, &mut seen_directories, glob.as_ref());
+            count += ls_consume_response(args, &self.endpoint, output, &continuation_response, &relative_root, &s3_uri.bucket, &mut seen_directories, glob.as_ref(), lifecycle.as_deref())?;
             continuation_token = continuation_response.next_continuation_token;
             page += 1;
         }
-        Ok(())
+        Ok(count)
+    }
+    /// Every object under a prefix, without grouping into directories
+    pub async fn list_objects(&self, uri: &Uri) -> Result<Vec<aws_sdk_s3::types::Object>, Error> {
+        let mut objects = Vec::new();
+        let mut continuation = None;
+        loop {
+            let response = self.ls_inner(&uri.bucket, &uri.key, None, continuation).await?;
+            objects.extend(response.contents.unwrap_or_default());
+            continuation = response.next_continuation_token;
+            if continuation.is_none() {
+                return Ok(objects);
+            }
+        }
     }
     pub async fn list_buckets(&self, opts: &SharedOptions) -> Result<(), Error> {
         if opts.verbose {
@@ -552,20 +1323,72 @@ impl Client {
 
         Ok(())
     }
-    pub async fn cat(&self, uri: &Uri) -> Result<(), Error> {
+    pub async fn cat(&self, options: &OptionsCache, uri: &Uri) -> Result<(), Error> {
+        let mut stdout = tokio::io::stdout();
+        let cache = options.open();
+        let etag = match cache {
+            Some(_) => self.head(uri).await?.e_tag().map(str::to_owned),
+            None => None,
+        };
+        if let (Some(cache), Some(etag)) = (&cache, &etag) {
+            if let Some(cached) = cache.lookup(etag).await {
+                let mut file = tokio::fs::File::open(cached).await?;
+                return tokio::io::copy(&mut file, &mut stdout)
+                    .await
+                    .map(|_| ())
+                    .map_err(Error::Io);
+            }
+        }
+
         let response = self.client.get_object()
             .bucket(uri.bucket.clone())
             .key(uri.key.to_string())
+            .set_if_match(etag.clone())
             .send()
             .await
             .map_err(|e| error_from_get(uri, e))?;
 
-        let mut stdout = tokio::io::stdout();
-        let mut body = response.body.into_async_read();
-        tokio::io::copy(&mut body, &mut stdout)
+        let (Some(cache), Some(etag)) = (cache, etag) else {
+            let mut body = response.body.into_async_read();
+            return tokio::io::copy(&mut body, &mut stdout)
+                .await
+                .map(|_| ())
+                .map_err(Error::Io);
+        };
+        let mut writer = cache.writer(&etag).await?;
+        let mut body = response.body;
+        while let Some(bytes) = body.try_next().await? {
+            stdout.write_all(&bytes).await.map_err(Error::Io)?;
+            writer.file.write_all(&bytes).await?;
+        }
+        stdout.flush().await.map_err(Error::Io)?;
+        cache.commit(writer).await?;
+        Ok(())
+    }
+    /// Inclusive byte range of an object, requiring `etag` to still match if given, received at
+    /// the pace of `limiter`
+    pub async fn get_range(&self, uri: &Uri, start: u64, end: u64, etag: Option<&str>, limiter: &rate_limit::Limiter) -> Result<bytes::Bytes, Error> {
+        let response = self.client.get_object()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .range(format!("bytes={start}-{end}"))
+            .set_if_match(etag.map(str::to_owned))
+            .send()
             .await
-            .map(|_| ())
-            .map_err(Error::Io)
+            .map_err(|e| error_from_get(uri, e))?;
+        Ok(limiter.collect(response.body).await?)
+    }
+    /// Store `body` at `uri` in a single request, e.g. a small generated file
+    pub async fn put_bytes(&self, uri: &Uri, content_type: &str, body: Vec<u8>) -> Result<(), Error> {
+        self.client.put_object()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .content_type(content_type)
+            .body(ByteStream::from(body))
+            .send()
+            .await?;
+        self.head_cache.invalidate(uri);
+        Ok(())
     }
     pub async fn make_bucket(&self, uri: &Uri, options: &OptionsMakeBucket) -> Result<(), Error> {
         let location_constraint = self.region.as_ref()
@@ -582,6 +1405,14 @@ impl Client {
             .set_grant_full_control(options.access_control.grant_full.to_owned())
             .set_grant_read_acp(options.access_control.grant_read_acp.to_owned())
             .set_grant_write_acp(options.access_control.grant_write_acp.to_owned())
+            .set_object_ownership(options.settings.ownership.to_owned())
+            .send()
+            .await?;
+        self.apply_bucket_settings(&uri.bucket, &options.settings).await
+    }
+    pub async fn remove_bucket(&self, uri: &Uri) -> Result<(), Error> {
+        self.client.delete_bucket()
+            .bucket(uri.bucket.clone())
             .send()
             .await?;
         Ok(())
@@ -590,6 +1421,8 @@ impl Client {
 
 fn error_from_get(uri: &Uri, sdk: aws_sdk_s3::error::SdkError<GetObjectError>) -> Error {
     match sdk {
+        // If-Match no longer matching
+        aws_sdk_s3::error::SdkError::ServiceError(e) if e.raw().status().as_u16() == 412 => Error::Modified(uri.clone()),
         aws_sdk_s3::error::SdkError::ServiceError(_) => Error::NoSuchKey(uri.clone()),
         _ => sdk.into(),
     }
@@ -610,12 +1443,11 @@ fn key_matches_requested(requested: &Key, key: &str, args: &ListArguments, glob:
         return true;
     }
 
-    /* TODO: When adding support for glob + recursive
-     * add matching against a list of recursively matched
-     * directories here
-     */
     if let Some(glob) = glob {
-        return glob.matches(key)
+        return match args.recurse {
+            true => glob.matches_recursive(key),
+            false => glob.matches(key),
+        };
     }
 
     let requested_directory = requested.is_explicitly_directory();
@@ -657,38 +1489,52 @@ const fn storage_class_field_len() -> usize {
 }
 const STORAGE_CLASS_FIELD_LEN: usize = storage_class_field_len();
 
-fn printable_filename<'a>(key: &'a str, bucket: &str, args: &ListArguments, directory_prefix: &Key) -> std::borrow::Cow<'a, str> {
-    let c: std::borrow::Cow<str> = if args.full_path {
-        format!("s3://{bucket}/{}", if key == "/" { "" } else { key }).into()
+fn printable_filename<'a>(key: &'a str, directory: bool, bucket: &str, args: &ListArguments, endpoint: &url_style::Endpoint, directory_prefix: &Key) -> std::borrow::Cow<'a, str> {
+    let c: std::borrow::Cow<str> = if args.full_path || args.url_style != UrlStyle::S3 {
+        endpoint.url(args.url_style, bucket, if key == "/" { "" } else { key }).into()
     } else {
-        key.strip_prefix(directory_prefix.as_str()).unwrap_or(key).into()
+        let relative = key.strip_prefix(directory_prefix.as_str()).unwrap_or(key);
+        match args.output {
+            // Escaped like the key of an S3 URI, so a name appended to the listed URI parses back to it
+            cli::OutputFormat::Text => uri::encode_key_uri(relative),
+            _ => relative.into(),
+        }
     };
-    shell_escape::escape(c)
+    match args.output {
+        cli::OutputFormat::Text => args.color.paint(key, shell_escape::escape(c), directory),
+        _ => c,
+    }
 }
 
-fn ls_consume_response(args: &ListArguments, response: &ListObjectsV2Output, directory_prefix: &Key, bucket: &str, seen_directories: &mut seen_directories::SeenDirectories, glob: Option<&glob::Glob>) {
+#[allow(clippy::too_many_arguments, clippy::collapsible_if)]
+fn ls_consume_response(args: &ListArguments, endpoint: &url_style::Endpoint, output: &mut ListOutput, response: &ListObjectsV2Output, directory_prefix: &Key, bucket: &str, seen_directories: &mut seen_directories::SeenDirectories, glob: Option<&glob::Glob>, lifecycle: Option<&[aws_sdk_s3::types::LifecycleRule]>) -> Result<usize, Error> {
     let max_file_size = response.contents.as_ref()
         .and_then(|c| c.iter().map(|file| file.size().unwrap_or(0)).max())
         .unwrap_or(0);
 
     let size_width = cli::digit_count(max_file_size as u64);
 
+    let print_directories = matches!(output, ListOutput::Stdout);
+    let mut count = 0;
     let print_directory = |name: &str| {
-        if !key_matches_requested(directory_prefix, name, args, glob) {
-            return;
+        if !print_directories || !key_matches_requested(directory_prefix, name, args, glob) {
+            return 0;
         }
-        let name = printable_filename(name, bucket, args, directory_prefix);
-        if args.long {
+        let name = printable_filename(name, true, bucket, args, endpoint, directory_prefix);
+        if args.output == cli::OutputFormat::Csv {
+            cli::println_csv(&[&name, "directory", "", "", "", ""]);
+        } else if args.long {
             println!("{:size_width$} {:DATE_LEN$} {:storage_class_len$} {name}", 0, "-", "-", storage_class_len = STORAGE_CLASS_FIELD_LEN);
         } else {
             println!("{name}");
         }
+        1
     };
 
     if !args.only_files {
         for dir in response.common_prefixes() {
             if let Some(name) = &dir.prefix {
-                print_directory(name);
+                count += print_directory(name);
             }
         }
     }
@@ -698,19 +1544,49 @@ fn ls_consume_response(args: &ListArguments, response: &ListObjectsV2Output, dir
             if !key_matches_requested(directory_prefix, name, args, glob) {
                 continue;
             }
+            let relative = name.strip_prefix(directory_prefix.as_str()).unwrap_or(name);
+            if !args.filter.matches(relative.trim_start_matches('/')) || !args.size.matches(file.size().unwrap_or(0) as u64) || !args.age.matches(file.last_modified()) {
+                continue;
+            }
             if !args.only_files {
                 if args.recurse || glob.is_some() {
                     let dir_path = basename(name);
                     if dir_path != directory_prefix.as_str() {
                         for unseen_directory in seen_directories.add_key(dir_path) {
-                            print_directory(&unseen_directory);
+                            count += print_directory(&unseen_directory);
                         }
                     }
                 }
             }
             if !args.only_directories {
-                let name = printable_filename(name, bucket, args, directory_prefix);
-                if args.long {
+                count += 1;
+                #[cfg(feature = "parquet")]
+                if let ListOutput::Parquet(writer) = output {
+                    writer.push(parquet_export::Row {
+                        key: name,
+                        size: file.size().unwrap_or(0),
+                        last_modified: file.last_modified(),
+                        etag: file.e_tag(),
+                        storage_class: file.storage_class().map(|c| c.as_str()).unwrap_or("STANDARD"),
+                    })?;
+                    continue;
+                }
+                let explanation = lifecycle.and_then(|rules| {
+                    let storage_class = file.storage_class().map(|c| c.as_str()).unwrap_or("STANDARD");
+                    lifecycle::explain(rules, name, file.size().unwrap_or(0), file.last_modified(), storage_class)
+                });
+                let name = match explanation {
+                    Some(explanation) => format!("{}\t{explanation}", printable_filename(name, false, bucket, args, endpoint, directory_prefix)).into(),
+                    None => printable_filename(name, false, bucket, args, endpoint, directory_prefix),
+                };
+                if args.output == cli::OutputFormat::Csv {
+                    let date = file.last_modified()
+                        .and_then(|d| d.fmt(aws_sdk_s3::primitives::DateTimeFormat::DateTime).ok())
+                        .unwrap_or_default();
+                    let storage_class = file.storage_class().unwrap_or(&aws_sdk_s3::types::ObjectStorageClass::Standard);
+                    let etag = file.e_tag().unwrap_or("").trim_matches('"');
+                    cli::println_csv(&[&name, "file", &file.size().unwrap_or(0).to_string(), &date, storage_class.as_str(), etag]);
+                } else if args.long {
                     let date = file.last_modified()
                         .and_then(|d| d.fmt(aws_sdk_s3::primitives::DateTimeFormat::DateTime).ok())
                         .unwrap_or_else(|| "".to_owned());
@@ -722,4 +1598,56 @@ fn ls_consume_response(args: &ListArguments, response: &ListObjectsV2Output, dir
             }
         }
     }
+    Ok(count)
+}
+
+#[test]
+fn test_ls_names_parse_back() {
+    use clap::Parser;
+    #[derive(clap::Parser)]
+    struct Ls {
+        #[clap(flatten)]
+        args: ListArguments,
+    }
+    let args = |extra: &[&str]| Ls::parse_from(["ls", "--color", "never"].iter().chain(extra)).args;
+    let endpoint = url_style::Endpoint::default();
+    let prefix = Key::new("dir/".into());
+    for key in ["dir/a%41", "dir/50% off #1?.txt", "dir/a b+c/", "dir/%2525"] {
+        let full = printable_filename(key, false, "bucket", &args(&["--output", "csv", "--full-path"]), &endpoint, &prefix);
+        assert_eq!(full.parse::<Uri>().unwrap().normalized().unwrap().key.as_str(), key);
+        // Machine readable names are the keys themselves
+        let relative = printable_filename(key, false, "bucket", &args(&["--output", "csv"]), &endpoint, &prefix);
+        assert_eq!(format!("dir/{relative}"), key);
+    }
+    for key in ["dir/a%41", "dir/50% off #1?.txt", "dir/%2525"] {
+        // Text names are shell quoted, as given back on a command line
+        let relative = printable_filename(key, false, "bucket", &args(&[]), &endpoint, &prefix);
+        let relative = shlex::split(&relative).unwrap().remove(0);
+        assert_eq!(format!("s3://bucket/dir/{relative}").parse::<Uri>().unwrap().normalized().unwrap().key.as_str(), key);
+    }
+}
+
+#[test]
+fn test_path_to_sdk_body_changed_size() {
+    let path = std::env::temp_dir().join(format!("sup3-test-body-{}", std::process::id()));
+    std::fs::write(&path, b"0123456789").unwrap();
+    let progress = cli::no_progress();
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let send = |length, ignore_growth| {
+        let changed = Arc::new(AtomicBool::new(false));
+        let body = path_to_sdk_body(path.clone(), length, ignore_growth, Default::default(), Default::default(), progress.clone(), Default::default(), changed.clone());
+        let sent = runtime.block_on(ByteStream::new(body).collect()).map(|data| data.into_bytes());
+        (sent, changed.load(Ordering::Relaxed))
+    };
+    assert_eq!(send(10, false).0.unwrap().as_ref(), b"0123456789");
+    // Shrank since its length was taken
+    let (sent, changed) = send(12, true);
+    assert!(sent.is_err() && changed);
+    // Grew since its length was taken
+    let (sent, changed) = send(8, false);
+    assert!(sent.is_err() && changed);
+    let (sent, changed) = send(8, true);
+    assert_eq!(sent.unwrap().as_ref(), b"01234567");
+    assert!(!changed);
+    std::fs::remove_file(&path).unwrap();
 }
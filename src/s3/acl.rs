@@ -0,0 +1,126 @@
+use aws_sdk_s3::types::{Grant, ObjectCannedAcl, Owner};
+
+use super::{Client, Error, OptionsAccessControl, Uri};
+use crate::cli::json_string;
+
+/// Owner and grants of a bucket or object
+#[derive(Debug, Default, PartialEq)]
+pub struct Acl {
+    pub owner_id: Option<String>,
+    pub owner_name: Option<String>,
+    pub grants: Vec<AclGrant>,
+}
+
+/// A permission granted to a grantee, named as the --grant-* flags take them
+#[derive(Debug, PartialEq)]
+pub struct AclGrant {
+    /// 'id', 'uri' or 'emailAddress'
+    pub kind: &'static str,
+    pub grantee: String,
+    pub display_name: Option<String>,
+    pub permission: String,
+}
+
+impl AclGrant {
+    fn from_sdk(grant: &Grant) -> AclGrant {
+        let grantee = grant.grantee();
+        let (kind, value) = match grantee {
+            Some(g) if g.id().is_some() => ("id", g.id()),
+            Some(g) if g.uri().is_some() => ("uri", g.uri()),
+            Some(g) => ("emailAddress", g.email_address()),
+            None => ("id", None),
+        };
+        AclGrant {
+            kind,
+            grantee: value.unwrap_or_default().to_owned(),
+            display_name: grantee.and_then(|g| g.display_name()).map(str::to_owned),
+            permission: grant.permission().map(|p| p.as_str().to_owned()).unwrap_or_default(),
+        }
+    }
+}
+
+fn json_or_null(s: Option<&str>) -> String {
+    s.map(json_string).unwrap_or_else(|| "null".into())
+}
+
+impl Acl {
+    fn new(owner: Option<&Owner>, grants: &[Grant]) -> Acl {
+        Acl {
+            owner_id: owner.and_then(|o| o.id()).map(str::to_owned),
+            owner_name: owner.and_then(|o| o.display_name()).map(str::to_owned),
+            grants: grants.iter().map(AclGrant::from_sdk).collect(),
+        }
+    }
+
+    /// One line JSON object of `uri`'s ACL
+    pub fn to_json(&self, uri: &Uri) -> String {
+        let grants: Vec<String> = self.grants.iter()
+            .map(|grant| format!(
+                "{{\"grantee\": {{{}: {}, \"display_name\": {}}}, \"permission\": {}}}",
+                json_string(grant.kind),
+                json_string(&grant.grantee),
+                json_or_null(grant.display_name.as_deref()),
+                json_string(&grant.permission)))
+            .collect();
+        format!(
+            "{{\"uri\": {}, \"owner\": {{\"id\": {}, \"display_name\": {}}}, \"grants\": [{}]}}",
+            json_string(&uri.to_string()),
+            json_or_null(self.owner_id.as_deref()),
+            json_or_null(self.owner_name.as_deref()),
+            grants.join(", "))
+    }
+}
+
+impl Client {
+    /// ACL of the bucket of `uri` if it has no key, otherwise of the object
+    pub async fn acl(&self, uri: &Uri) -> Result<Acl, Error> {
+        if uri.key.as_str().is_empty() {
+            let output = self.client.get_bucket_acl().bucket(uri.bucket.clone()).send().await?;
+            return Ok(Acl::new(output.owner(), output.grants()));
+        }
+        let output = self.client.get_object_acl()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .send()
+            .await?;
+        Ok(Acl::new(output.owner(), output.grants()))
+    }
+
+    /// Replace the ACL of the object at `uri` with a canned ACL or grants, as uploads set them
+    pub async fn put_acl(&self, uri: &Uri, canned_acl: Option<&ObjectCannedAcl>, access_control: &OptionsAccessControl) -> Result<(), Error> {
+        self.client.put_object_acl()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .set_acl(canned_acl.cloned())
+            .set_grant_read(access_control.grant_read.to_owned())
+            .set_grant_full_control(access_control.grant_full.to_owned())
+            .set_grant_read_acp(access_control.grant_read_acp.to_owned())
+            .set_grant_write_acp(access_control.grant_write_acp.to_owned())
+            .send()
+            .await?;
+        self.head_cache.invalidate(uri);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_acl_json() {
+    use aws_sdk_s3::types::{Grantee, Permission, Type};
+    let grants = [
+        Grant::builder()
+            .grantee(Grantee::builder().r#type(Type::CanonicalUser).id("abc").display_name("me").build().unwrap())
+            .permission(Permission::FullControl)
+            .build(),
+        Grant::builder()
+            .grantee(Grantee::builder().r#type(Type::Group).uri("http://acs.amazonaws.com/groups/global/AllUsers").build().unwrap())
+            .permission(Permission::Read)
+            .build(),
+    ];
+    let acl = Acl::new(Some(&Owner::builder().id("abc").build()), &grants);
+    assert_eq!(acl.grants[1].kind, "uri");
+    let uri = Uri::new("bucket".into(), super::Key::new("a.txt".into()));
+    assert_eq!(acl.to_json(&uri), concat!(
+        r#"{"uri": "s3://bucket/a.txt", "owner": {"id": "abc", "display_name": null}, "grants": ["#,
+        r#"{"grantee": {"id": "abc", "display_name": "me"}, "permission": "FULL_CONTROL"}, "#,
+        r#"{"grantee": {"uri": "http://acs.amazonaws.com/groups/global/AllUsers", "display_name": null}, "permission": "READ"}]}"#));
+}
@@ -0,0 +1,283 @@
+use std::collections::HashMap;
+
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_s3::types::Tag;
+
+use super::{Client, Error};
+use crate::cli::json_string;
+
+/// The read-only operations `sup3 api` can make, named as in the S3 API reference. Each needs its
+/// request built and response converted below, so others aren't reachable
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+#[clap(rename_all = "PascalCase")]
+pub enum Operation {
+    HeadBucket,
+    GetBucketLocation,
+    GetBucketVersioning,
+    GetBucketPolicy,
+    GetBucketTagging,
+    GetBucketEncryption,
+    ListMultipartUploads,
+    HeadObject,
+    GetObjectTagging,
+}
+
+/// Parameters by name as accepted by `Operation::parameters`
+pub type Parameters = HashMap<&'static str, String>;
+
+impl Operation {
+    /// Names of the required and optional request parameters
+    fn accepted(self) -> (&'static [&'static str], &'static [&'static str]) {
+        use Operation::*;
+        match self {
+            HeadBucket | GetBucketLocation | GetBucketVersioning | GetBucketPolicy | GetBucketTagging | GetBucketEncryption => (&["Bucket"], &[]),
+            ListMultipartUploads => (&["Bucket"], &["Prefix", "KeyMarker", "UploadIdMarker"]),
+            HeadObject | GetObjectTagging => (&["Bucket", "Key"], &["VersionId"]),
+        }
+    }
+
+    /// `NAME=VALUE` parameters checked against those the operation takes, names matched ignoring case
+    pub fn parameters(self, given: &[(String, String)]) -> Result<Parameters, String> {
+        let (required, optional) = self.accepted();
+        let mut parameters = HashMap::new();
+        for (name, value) in given {
+            let Some(accepted) = required.iter().chain(optional).find(|accepted| accepted.eq_ignore_ascii_case(name)) else {
+                let names: Vec<&str> = required.iter().chain(optional).copied().collect();
+                return Err(format!("{self:?} doesn't take parameter '{name}', expected {}", names.join(", ")));
+            };
+            if parameters.insert(*accepted, value.clone()).is_some() {
+                return Err(format!("parameter '{accepted}' given more than once"));
+            }
+        }
+        match required.iter().find(|name| !parameters.contains_key(*name)) {
+            Some(missing) => Err(format!("{self:?} requires parameter '{missing}', e.g. --param {missing}=...")),
+            None => Ok(parameters),
+        }
+    }
+}
+
+/// A `NAME=VALUE` request parameter
+pub fn parse_parameter(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("expected NAME=VALUE, got '{s}'")),
+    }
+}
+
+/// Response fields, printed like the AWS CLI's JSON output
+#[derive(Debug, PartialEq)]
+pub enum Json {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    /// Fields in order, those without a value left out
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn object<'a>(fields: impl IntoIterator<Item = (&'a str, Option<Json>)>) -> Json {
+        Json::Object(fields.into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name.to_owned(), value)))
+            .collect())
+    }
+
+    fn string(s: Option<&str>) -> Option<Json> {
+        s.map(|s| Json::String(s.to_owned()))
+    }
+
+    fn date(date: Option<&DateTime>) -> Option<Json> {
+        date.and_then(|date| date.fmt(DateTimeFormat::DateTime).ok()).map(Json::String)
+    }
+
+    fn tags(tags: &[Tag]) -> Json {
+        Json::Array(tags.iter()
+            .map(|tag| Json::object([("Key", Json::string(Some(tag.key()))), ("Value", Json::string(Some(tag.value())))]))
+            .collect())
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        const INDENT: &str = "    ";
+        let (open, close, items): (char, char, Vec<(Option<&str>, &Json)>) = match self {
+            Json::Bool(b) => return out.push_str(&b.to_string()),
+            Json::Number(n) => return out.push_str(&n.to_string()),
+            Json::String(s) => return out.push_str(&json_string(s)),
+            Json::Array(values) => ('[', ']', values.iter().map(|value| (None, value)).collect()),
+            Json::Object(fields) => ('{', '}', fields.iter().map(|(name, value)| (Some(name.as_str()), value)).collect()),
+        };
+        out.push(open);
+        for (i, (name, value)) in items.iter().enumerate() {
+            out.push_str(if i == 0 { "\n" } else { ",\n" });
+            out.push_str(&INDENT.repeat(indent + 1));
+            if let Some(name) = name {
+                out.push_str(&json_string(name));
+                out.push_str(": ");
+            }
+            value.write(out, indent + 1);
+        }
+        if !items.is_empty() {
+            out.push('\n');
+            out.push_str(&INDENT.repeat(indent));
+        }
+        out.push(close);
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        f.write_str(&out)
+    }
+}
+
+impl Client {
+    /// Make a single `operation` request with `parameters` from `Operation::parameters`
+    pub async fn api(&self, operation: Operation, parameters: &Parameters) -> Result<Json, Error> {
+        let bucket = parameters["Bucket"].clone();
+        let key = || parameters["Key"].clone();
+        let optional = |name: &str| parameters.get(name).cloned();
+        let json = match operation {
+            Operation::HeadBucket => {
+                let output = self.client.head_bucket().bucket(bucket).send().await?;
+                Json::object([
+                    ("BucketRegion", Json::string(output.bucket_region())),
+                    ("BucketLocationType", Json::string(output.bucket_location_type().map(|t| t.as_str()))),
+                    ("BucketLocationName", Json::string(output.bucket_location_name())),
+                    ("AccessPointAlias", output.access_point_alias().map(Json::Bool)),
+                ])
+            },
+            Operation::GetBucketLocation => {
+                let output = self.client.get_bucket_location().bucket(bucket).send().await?;
+                Json::object([("LocationConstraint", Json::string(output.location_constraint().map(|c| c.as_str())))])
+            },
+            Operation::GetBucketVersioning => {
+                let output = self.client.get_bucket_versioning().bucket(bucket).send().await?;
+                Json::object([
+                    ("Status", Json::string(output.status().map(|s| s.as_str()))),
+                    ("MFADelete", Json::string(output.mfa_delete().map(|s| s.as_str()))),
+                ])
+            },
+            Operation::GetBucketPolicy => {
+                let output = self.client.get_bucket_policy().bucket(bucket).send().await?;
+                Json::object([("Policy", Json::string(output.policy()))])
+            },
+            Operation::GetBucketTagging => {
+                let output = self.client.get_bucket_tagging().bucket(bucket).send().await?;
+                Json::object([("TagSet", Some(Json::tags(output.tag_set())))])
+            },
+            Operation::GetBucketEncryption => {
+                let output = self.client.get_bucket_encryption().bucket(bucket).send().await?;
+                let rules = output.server_side_encryption_configuration().map(|configuration| configuration.rules()).unwrap_or_default();
+                let rules = rules.iter().map(|rule| Json::object([
+                    ("ApplyServerSideEncryptionByDefault", rule.apply_server_side_encryption_by_default().map(|default| Json::object([
+                        ("SSEAlgorithm", Json::string(Some(default.sse_algorithm().as_str()))),
+                        ("KMSMasterKeyID", Json::string(default.kms_master_key_id())),
+                    ]))),
+                    ("BucketKeyEnabled", rule.bucket_key_enabled().map(Json::Bool)),
+                ])).collect();
+                Json::object([("ServerSideEncryptionConfiguration", Some(Json::object([("Rules", Some(Json::Array(rules)))])))])
+            },
+            Operation::ListMultipartUploads => {
+                let output = self.client.list_multipart_uploads()
+                    .bucket(bucket)
+                    .set_prefix(optional("Prefix"))
+                    .set_key_marker(optional("KeyMarker"))
+                    .set_upload_id_marker(optional("UploadIdMarker"))
+                    .send()
+                    .await?;
+                let uploads = output.uploads().iter().map(|upload| Json::object([
+                    ("UploadId", Json::string(upload.upload_id())),
+                    ("Key", Json::string(upload.key())),
+                    ("Initiated", Json::date(upload.initiated())),
+                    ("StorageClass", Json::string(upload.storage_class().map(|c| c.as_str()))),
+                ])).collect();
+                Json::object([
+                    ("Bucket", Json::string(output.bucket())),
+                    ("NextKeyMarker", Json::string(output.next_key_marker())),
+                    ("NextUploadIdMarker", Json::string(output.next_upload_id_marker())),
+                    ("IsTruncated", output.is_truncated().map(Json::Bool)),
+                    ("Uploads", Some(Json::Array(uploads))),
+                ])
+            },
+            Operation::HeadObject => {
+                let output = self.client.head_object()
+                    .bucket(bucket)
+                    .key(key())
+                    .set_version_id(optional("VersionId"))
+                    .send()
+                    .await?;
+                let mut metadata: Vec<_> = output.metadata().into_iter().flatten().collect();
+                metadata.sort();
+                Json::object([
+                    ("AcceptRanges", Json::string(output.accept_ranges())),
+                    ("LastModified", Json::date(output.last_modified())),
+                    ("ContentLength", output.content_length().map(Json::Number)),
+                    ("ETag", Json::string(output.e_tag())),
+                    ("VersionId", Json::string(output.version_id())),
+                    ("CacheControl", Json::string(output.cache_control())),
+                    ("ContentDisposition", Json::string(output.content_disposition())),
+                    ("ContentEncoding", Json::string(output.content_encoding())),
+                    ("ContentLanguage", Json::string(output.content_language())),
+                    ("ContentType", Json::string(output.content_type())),
+                    ("ExpiresString", Json::string(output.expires_string())),
+                    ("ServerSideEncryption", Json::string(output.server_side_encryption().map(|s| s.as_str()))),
+                    ("StorageClass", Json::string(output.storage_class().map(|c| c.as_str()))),
+                    ("Restore", Json::string(output.restore())),
+                    ("PartsCount", output.parts_count().map(|count| Json::Number(count.into()))),
+                    ("ObjectLockMode", Json::string(output.object_lock_mode().map(|m| m.as_str()))),
+                    ("ObjectLockRetainUntilDate", Json::date(output.object_lock_retain_until_date())),
+                    ("ObjectLockLegalHoldStatus", Json::string(output.object_lock_legal_hold_status().map(|s| s.as_str()))),
+                    ("Metadata", Some(Json::Object(metadata.into_iter().map(|(name, value)| (name.clone(), Json::String(value.clone()))).collect()))),
+                ])
+            },
+            Operation::GetObjectTagging => {
+                let output = self.client.get_object_tagging()
+                    .bucket(bucket)
+                    .key(key())
+                    .set_version_id(optional("VersionId"))
+                    .send()
+                    .await?;
+                Json::object([
+                    ("VersionId", Json::string(output.version_id())),
+                    ("TagSet", Some(Json::tags(output.tag_set()))),
+                ])
+            },
+        };
+        Ok(json)
+    }
+}
+
+#[test]
+fn test_api_parameters() {
+    let given = |params: &[&str]| -> Vec<(String, String)> { params.iter().map(|p| parse_parameter(p).unwrap()).collect() };
+    let parameters = Operation::HeadObject.parameters(&given(&["bucket=b", "Key=a=b", "VersionId="])).unwrap();
+    assert_eq!(parameters["Bucket"], "b");
+    assert_eq!(parameters["Key"], "a=b");
+    assert_eq!(parameters["VersionId"], "");
+    assert!(Operation::HeadObject.parameters(&given(&["Bucket=b"])).unwrap_err().contains("requires parameter 'Key'"));
+    assert!(Operation::HeadBucket.parameters(&given(&["Bucket=b", "Key=k"])).unwrap_err().contains("doesn't take parameter 'Key'"));
+    assert!(Operation::HeadBucket.parameters(&given(&["Bucket=b", "bucket=c"])).is_err());
+    assert!(parse_parameter("=value").is_err());
+    assert!(parse_parameter("novalue").is_err());
+}
+
+#[test]
+fn test_api_json() {
+    assert_eq!(Json::object([("Empty", Some(Json::Array(Vec::new()))), ("Missing", None)]).to_string(), "{\n    \"Empty\": []\n}");
+    let json = Json::object([
+        ("Name", Json::string(Some("a \"b\""))),
+        ("TagSet", Some(Json::tags(&[Tag::builder().key("k").value("v").build().unwrap()]))),
+        ("Count", Some(Json::Number(2))),
+    ]);
+    assert_eq!(json.to_string(), r#"{
+    "Name": "a \"b\"",
+    "TagSet": [
+        {
+            "Key": "k",
+            "Value": "v"
+        }
+    ],
+    "Count": 2
+}"#);
+}
@@ -0,0 +1,236 @@
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+
+use super::{Client, Error, Uri};
+
+/// Minimum size of all but the last part of a multipart upload
+pub const MIN_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// Maximum size of a single UploadPartCopy range
+pub const MAX_COPY_PART_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+/// Attempts at appending while other writers change the object in between
+const APPEND_ATTEMPTS: u32 = 5;
+
+/// Split an existing object of `size` bytes into server-side copy ranges
+///
+/// Returns inclusive byte ranges to copy, and the offset from which the
+/// remaining tail (too small to be a non-final part) must be downloaded
+pub(super) fn plan_copy_ranges(size: u64) -> (Vec<(u64, u64)>, u64) {
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    while size - start >= MIN_PART_SIZE {
+        let end = (start + MAX_COPY_PART_SIZE).min(size);
+        ranges.push((start, end - 1));
+        start = end;
+    }
+    (ranges, start)
+}
+
+pub(super) fn copy_source(uri: &Uri) -> String {
+    format!("{}/{}", uri.bucket, super::uri::encode_key_path(uri.key.as_str()))
+}
+
+/// Whether a write failed its If-Match or If-None-Match, the object having been changed or
+/// created since it was read
+fn is_conflict(e: &Error) -> bool {
+    matches!(e, Error::S3SdkErrorMeta(meta) if matches!(meta.code(), Some("PreconditionFailed" | "ConditionalRequestConflict")))
+}
+
+impl Client {
+    /// Append data to an object, creating it if missing
+    ///
+    /// Existing content is composed server-side with UploadPartCopy where
+    /// possible, only the small trailing remainder is downloaded. With
+    /// `native`, uses the provider's append (PutObject with a write offset).
+    ///
+    /// Writes are conditional on the object being unchanged, so a concurrent
+    /// append is never lost, and appending starts over if one got in first.
+    pub async fn append(&self, verbose: bool, data: &[u8], to: &Uri, native: bool) -> Result<(), Error> {
+        let mut attempt = 1;
+        loop {
+            match self.append_once(verbose, data.to_vec(), to, native).await {
+                Err(e) if is_conflict(&e) && attempt < APPEND_ATTEMPTS => {
+                    if verbose {
+                        eprintln!("🏁 {to} changed while appending, starting over");
+                    }
+                    // Writers racing each other wait differing times, so one gets through
+                    let jitter = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.subsec_nanos() % 100);
+                    tokio::time::sleep(std::time::Duration::from_millis(u64::from(100 * attempt + jitter))).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    async fn append_once(&self, verbose: bool, data: Vec<u8>, to: &Uri, native: bool) -> Result<(), Error> {
+        let existing = match self.head(to).await {
+            Ok(head) => Some(head),
+            Err(Error::NoSuchKey(_)) => None,
+            Err(e) => return Err(e),
+        };
+        let size = existing.as_ref().and_then(|h| h.content_length()).unwrap_or(0) as u64;
+        let etag = existing.as_ref().and_then(|h| h.e_tag()).map(str::to_owned);
+
+        let result = match existing {
+            None => {
+                if verbose {
+                    eprintln!("🏁 {to} not found, creating [{} bytes]", data.len());
+                }
+                self.client.put_object()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .if_none_match("*")
+                    .body(ByteStream::from(data))
+                    .send()
+                    .await
+                    .map(|_| ())
+                    .map_err(Error::from)
+            },
+            Some(_) if native => {
+                if verbose {
+                    eprintln!("🏁 appending natively to {to} at offset {size} [{} bytes]", data.len());
+                }
+                self.client.put_object()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .write_offset_bytes(size as i64)
+                    .body(ByteStream::from(data))
+                    .send()
+                    .await
+                    .map(|_| ())
+                    .map_err(Error::from)
+            },
+            Some(_) => self.append_composed(verbose, data, to, size, etag.as_deref().unwrap_or("*")).await,
+        };
+        self.head_cache.invalidate(to);
+        result
+    }
+
+    async fn append_composed(&self, verbose: bool, data: Vec<u8>, to: &Uri, size: u64, etag: &str) -> Result<(), Error> {
+        let (ranges, tail_start) = plan_copy_ranges(size);
+
+        let mut tail = Vec::new();
+        if tail_start < size {
+            if verbose {
+                eprintln!("🏁 downloading {} trailing bytes of {to}", size - tail_start);
+            }
+            let response = self.client.get_object()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .range(format!("bytes={tail_start}-{}", size - 1))
+                .if_match(etag)
+                .send()
+                .await?;
+            tail = response.body.collect().await?.to_vec();
+        }
+        tail.extend_from_slice(&data);
+
+        if ranges.is_empty() {
+            if verbose {
+                eprintln!("🏁 rewriting {to} [{} bytes]", tail.len());
+            }
+            self.client.put_object()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .if_match(etag)
+                .body(ByteStream::from(tail))
+                .send()
+                .await?;
+            return Ok(());
+        }
+
+        if verbose {
+            eprintln!("🏁 composing {to} from {} copied part(s) and {} new bytes", ranges.len(), tail.len());
+        }
+        let upload = self.client.create_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .send()
+            .await?;
+        let upload_id = upload.upload_id().expect("upload id in response").to_owned();
+
+        let composed = self.append_parts(to, &upload_id, &ranges, tail, etag).await;
+        let parts = match composed {
+            Ok(parts) => parts,
+            Err(e) => {
+                let _ = self.client.abort_multipart_upload()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .upload_id(&upload_id)
+                    .send()
+                    .await;
+                return Err(e);
+            },
+        };
+        self.client.complete_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .upload_id(&upload_id)
+            .if_match(etag)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    async fn append_parts(&self, to: &Uri, upload_id: &str, ranges: &[(u64, u64)], tail: Vec<u8>, etag: &str) -> Result<Vec<CompletedPart>, Error> {
+        let mut parts = Vec::new();
+        for (index, (start, end)) in ranges.iter().enumerate() {
+            let part_number = index as i32 + 1;
+            let response = self.client.upload_part_copy()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .upload_id(upload_id)
+                .part_number(part_number)
+                .copy_source(copy_source(to))
+                .copy_source_range(format!("bytes={start}-{end}"))
+                .copy_source_if_match(etag)
+                .send()
+                .await?;
+            parts.push(CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(response.copy_part_result().and_then(|r| r.e_tag()).map(str::to_owned))
+                .build());
+        }
+        let part_number = ranges.len() as i32 + 1;
+        let response = self.client.upload_part()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(tail))
+            .send()
+            .await?;
+        parts.push(CompletedPart::builder()
+            .part_number(part_number)
+            .set_e_tag(response.e_tag().map(str::to_owned))
+            .build());
+        Ok(parts)
+    }
+}
+
+#[test]
+fn test_plan_copy_ranges() {
+    assert_eq!(plan_copy_ranges(0), (vec![], 0));
+    assert_eq!(plan_copy_ranges(MIN_PART_SIZE - 1), (vec![], 0));
+    assert_eq!(plan_copy_ranges(MIN_PART_SIZE), (vec![(0, MIN_PART_SIZE - 1)], MIN_PART_SIZE));
+    let large = MAX_COPY_PART_SIZE + 10;
+    assert_eq!(plan_copy_ranges(large), (vec![(0, MAX_COPY_PART_SIZE - 1)], MAX_COPY_PART_SIZE));
+    let two_parts = MAX_COPY_PART_SIZE + MIN_PART_SIZE;
+    assert_eq!(plan_copy_ranges(two_parts), (vec![(0, MAX_COPY_PART_SIZE - 1), (MAX_COPY_PART_SIZE, two_parts - 1)], two_parts));
+}
+
+#[test]
+fn test_copy_source() {
+    let uri = Uri::new("bucket".into(), super::Key::new("dir/file name+1.txt".into()));
+    assert_eq!(copy_source(&uri), "bucket/dir/file%20name%2B1.txt");
+}
+
+#[test]
+fn test_is_conflict() {
+    let error = |code| Error::S3SdkErrorMeta(aws_sdk_s3::error::ErrorMetadata::builder().code(code).message("").build());
+    assert!(is_conflict(&error("PreconditionFailed")));
+    assert!(is_conflict(&error("ConditionalRequestConflict")));
+    assert!(!is_conflict(&error("AccessDenied")));
+    assert!(!is_conflict(&Error::NoFilename));
+}
@@ -0,0 +1,318 @@
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+
+use aws_sdk_s3::config::interceptors::{AfterDeserializationInterceptorContextRef, BeforeDeserializationInterceptorContextMut, BeforeSerializationInterceptorContextRef, BeforeTransmitInterceptorContextRef};
+use aws_sdk_s3::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_sdk_s3::operation::{complete_multipart_upload, copy_object, delete_object, delete_objects, get_object, put_object};
+use aws_smithy_types::body::SdkBody;
+use aws_smithy_types::config_bag::{Storable, StoreReplace};
+use sha2::Digest;
+
+use super::{Client, Uri};
+use crate::cli::{self, json_string};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Appends a JSON line per successful object write, read or removal. Each line holds the SHA-256 of
+/// the line before, so edits to earlier records are detectable
+///
+/// Reads are recorded once their body has been received in full. Ranged and part reads aren't
+/// recorded themselves, downloads made of them are recorded once when complete
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    principal: String,
+    /// The file, and the SHA-256 of its last line
+    file: Arc<Mutex<(std::fs::File, Option<String>)>>,
+    /// Records that couldn't be written, reported apart from the operations they were of
+    failures: Arc<AtomicU64>,
+}
+
+/// The operation being audited, noted before it's sent
+#[derive(Debug, Clone)]
+struct Pending {
+    action: &'static str,
+    source: Option<String>,
+    /// Each key, and its URI as recorded
+    objects: Vec<(String, String)>,
+    bytes: Option<u64>,
+}
+
+impl Storable for Pending {
+    type Storer = StoreReplace<Self>;
+}
+
+struct Record<'a> {
+    timestamp: String,
+    principal: &'a str,
+    action: &'a str,
+    source: Option<&'a str>,
+    uri: &'a str,
+    bytes: Option<u64>,
+    checksum: Option<&'a str>,
+    request_id: Option<&'a str>,
+}
+
+fn sha256_hex(line: &str) -> String {
+    sha2::Sha256::digest(line.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn json_or_null(s: Option<&str>) -> String {
+    s.map(json_string).unwrap_or_else(|| "null".into())
+}
+
+impl Record<'_> {
+    fn to_json(&self, previous: Option<&str>) -> String {
+        format!(
+            "{{\"timestamp\": {}, \"principal\": {}, \"action\": {}, \"source\": {}, \"uri\": {}, \"bytes\": {}, \"checksum\": {}, \"request_id\": {}, \"previous_sha256\": {}}}",
+            json_string(&self.timestamp),
+            json_string(self.principal),
+            json_string(self.action),
+            json_or_null(self.source),
+            json_string(self.uri),
+            self.bytes.map(|bytes| bytes.to_string()).unwrap_or_else(|| "null".into()),
+            json_or_null(self.checksum),
+            json_or_null(self.request_id),
+            json_or_null(previous))
+    }
+}
+
+fn uri(bucket: Option<&str>, key: Option<&str>) -> String {
+    format!("s3://{}/{}", bucket.unwrap_or_default(), super::uri::encode_key_uri(key.unwrap_or_default()))
+}
+
+fn object(bucket: Option<&str>, key: Option<&str>) -> (String, String) {
+    (key.unwrap_or_default().to_owned(), uri(bucket, key))
+}
+
+/// A response body calling `on_complete` with its length once read to the end
+struct Completion {
+    inner: SdkBody,
+    read: u64,
+    on_complete: Option<Box<dyn FnOnce(u64) + Send + Sync>>,
+}
+
+impl hyper::body::Body for Completion {
+    type Data = bytes::Bytes;
+    type Error = aws_smithy_types::body::Error;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<hyper::body::Frame<Self::Data>, Self::Error>>> {
+        let polled = Pin::new(&mut self.inner).poll_frame(cx);
+        match &polled {
+            Poll::Ready(Some(Ok(frame))) => self.read += frame.data_ref().map_or(0, |data| data.len() as u64),
+            Poll::Ready(None) => if let Some(on_complete) = self.on_complete.take() {
+                on_complete(self.read);
+            },
+            _ => {},
+        }
+        polled
+    }
+
+    fn is_end_stream(&self) -> bool {
+        // Polled once more regardless, so completion is noticed
+        false
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl AuditLog {
+    /// Open `path` for appending, continuing the chain of any records already in it
+    pub fn open(path: &Path) -> std::io::Result<AuditLog> {
+        let previous = match std::fs::read_to_string(path) {
+            Ok(existing) => existing.lines().last().map(sha256_hex),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { principal: String::new(), file: Arc::new(Mutex::new((file, previous))), failures: Default::default() })
+    }
+
+    /// Credentials the operations are made with, e.g. the access key ID
+    pub fn with_principal(self, principal: String) -> AuditLog {
+        AuditLog { principal, ..self }
+    }
+
+    fn append(&self, record: &Record) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        let (file, previous) = &mut *file;
+        let line = record.to_json(previous.as_deref());
+        writeln!(file, "{line}")?;
+        *previous = Some(sha256_hex(&line));
+        Ok(())
+    }
+
+    /// Append, reporting a record that couldn't be written without failing what it records
+    fn record(&self, record: &Record) {
+        if let Err(e) = self.append(record) {
+            cli::println_error(format_args!("failed to write audit log record for {}: {e}", record.uri));
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn now() -> String {
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    }
+}
+
+impl Client {
+    /// Record a download fetched in ranges or parts, once all of it has been received
+    pub(super) fn audit_read(&self, from: &Uri, bytes: u64, etag: Option<&str>) {
+        let Some(audit_log) = &self.audit_log else { return };
+        audit_log.record(&Record {
+            timestamp: AuditLog::now(),
+            principal: &audit_log.principal,
+            action: "GetObject",
+            source: None,
+            uri: &uri(Some(&from.bucket), Some(&from.key)),
+            bytes: Some(bytes),
+            checksum: etag.map(|etag| etag.trim_matches('"')),
+            request_id: None,
+        });
+    }
+    /// Audit records that couldn't be written
+    pub fn audit_failures(&self) -> u64 {
+        self.audit_log.as_ref().map_or(0, |audit_log| audit_log.failures.load(Ordering::Relaxed))
+    }
+}
+
+impl Intercept for AuditLog {
+    fn name(&self) -> &'static str {
+        "AuditLog"
+    }
+
+    fn read_before_execution(&self, context: &BeforeSerializationInterceptorContextRef<'_>, cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        let input = context.input();
+        let pending = if let Some(input) = input.downcast_ref::<put_object::PutObjectInput>() {
+            Pending { action: "PutObject", source: None, objects: vec![object(input.bucket(), input.key())], bytes: None }
+        } else if let Some(input) = input.downcast_ref::<complete_multipart_upload::CompleteMultipartUploadInput>() {
+            Pending { action: "CompleteMultipartUpload", source: None, objects: vec![object(input.bucket(), input.key())], bytes: None }
+        } else if let Some(input) = input.downcast_ref::<copy_object::CopyObjectInput>() {
+            let source = input.copy_source().map(|source| format!("s3://{}", super::uri::encode_key_uri(&percent_encoding::percent_decode_str(source).decode_utf8_lossy())));
+            Pending { action: "CopyObject", source, objects: vec![object(input.bucket(), input.key())], bytes: None }
+        } else if let Some(input) = input.downcast_ref::<get_object::GetObjectInput>() {
+            if input.range().is_some() || input.part_number().is_some() {
+                return Ok(());
+            }
+            Pending { action: "GetObject", source: None, objects: vec![object(input.bucket(), input.key())], bytes: None }
+        } else if let Some(input) = input.downcast_ref::<delete_object::DeleteObjectInput>() {
+            Pending { action: "DeleteObject", source: None, objects: vec![object(input.bucket(), input.key())], bytes: None }
+        } else if let Some(input) = input.downcast_ref::<delete_objects::DeleteObjectsInput>() {
+            let objects = input.delete().map(|delete| delete.objects().iter().map(|o| object(input.bucket(), Some(o.key()))).collect()).unwrap_or_default();
+            Pending { action: "DeleteObjects", source: None, objects, bytes: None }
+        } else {
+            return Ok(());
+        };
+        cfg.interceptor_state().store_put(pending);
+        Ok(())
+    }
+
+    fn read_before_transmit(&self, context: &BeforeTransmitInterceptorContextRef<'_>, _runtime_components: &RuntimeComponents, cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        let Some(mut pending) = cfg.load::<Pending>().cloned() else {
+            return Ok(());
+        };
+        if pending.action == "PutObject" {
+            // Bodies sent with trailing checksums are longer than the object
+            let headers = context.request().headers();
+            pending.bytes = headers.get("x-amz-decoded-content-length").or_else(|| headers.get("content-length")).and_then(|length| length.parse().ok());
+            cfg.interceptor_state().store_put(pending);
+        }
+        Ok(())
+    }
+
+    fn modify_before_deserialization(&self, context: &mut BeforeDeserializationInterceptorContextMut<'_>, _runtime_components: &RuntimeComponents, cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        let Some(pending) = cfg.load::<Pending>() else {
+            return Ok(());
+        };
+        let response = context.response_mut();
+        if pending.action != "GetObject" || !response.status().is_success() {
+            return Ok(());
+        }
+        // Only recorded once the whole body has arrived, an interrupted read having read nothing
+        let (audit_log, (_, uri)) = (self.clone(), pending.objects[0].clone());
+        let checksum = response.headers().get("etag").map(|etag| etag.trim_matches('"').to_owned());
+        let request_id = response.headers().get("x-amz-request-id").map(str::to_owned);
+        let on_complete = move |bytes| audit_log.record(&Record {
+            timestamp: AuditLog::now(),
+            principal: &audit_log.principal,
+            action: "GetObject",
+            source: None,
+            uri: &uri,
+            bytes: Some(bytes),
+            checksum: checksum.as_deref(),
+            request_id: request_id.as_deref(),
+        });
+        let inner = response.take_body();
+        *response.body_mut() = SdkBody::from_body_1_x(Completion { inner, read: 0, on_complete: Some(Box::new(on_complete)) });
+        Ok(())
+    }
+
+    fn read_after_deserialization(&self, context: &AfterDeserializationInterceptorContextRef<'_>, _runtime_components: &RuntimeComponents, cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        let (Some(pending), Ok(output)) = (cfg.load::<Pending>(), context.output_or_error()) else {
+            return Ok(());
+        };
+        if pending.action == "GetObject" {
+            return Ok(());
+        }
+        let headers = context.response().headers();
+        let checksum = headers.get("etag")
+            .or_else(|| output.downcast_ref::<copy_object::CopyObjectOutput>().and_then(|output| output.copy_object_result()?.e_tag()))
+            .or_else(|| output.downcast_ref::<complete_multipart_upload::CompleteMultipartUploadOutput>().and_then(|output| output.e_tag()))
+            .map(|etag| etag.trim_matches('"'));
+        // Quiet batch removals only list the keys that failed
+        let failed: Vec<&str> = output.downcast_ref::<delete_objects::DeleteObjectsOutput>()
+            .map(|output| output.errors().iter().filter_map(|error| error.key()).collect())
+            .unwrap_or_default();
+        let timestamp = AuditLog::now();
+        for (key, uri) in &pending.objects {
+            if failed.contains(&key.as_str()) {
+                continue;
+            }
+            self.record(&Record {
+                timestamp: timestamp.clone(),
+                principal: &self.principal,
+                action: pending.action,
+                source: pending.source.as_deref(),
+                uri,
+                bytes: pending.bytes,
+                checksum,
+                request_id: headers.get("x-amz-request-id"),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_audit_chain() {
+    let path = std::env::temp_dir().join(format!("sup3-test-audit-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let record = |uri| Record {
+        timestamp: "2024-01-31T12:00:00.000Z".into(),
+        principal: "AKIAEXAMPLE",
+        action: "PutObject",
+        source: None,
+        uri,
+        bytes: Some(3),
+        checksum: Some("abc"),
+        request_id: None,
+    };
+    let log = AuditLog::open(&path).unwrap().with_principal("AKIAEXAMPLE".into());
+    log.append(&record("s3://bucket/a")).unwrap();
+    log.append(&record("s3://bucket/b")).unwrap();
+    drop(log);
+    AuditLog::open(&path).unwrap().append(&record("s3://bucket/c")).unwrap();
+
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let lines: Vec<&str> = written.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0], "{\"timestamp\": \"2024-01-31T12:00:00.000Z\", \"principal\": \"AKIAEXAMPLE\", \"action\": \"PutObject\", \"source\": null, \"uri\": \"s3://bucket/a\", \"bytes\": 3, \"checksum\": \"abc\", \"request_id\": null, \"previous_sha256\": null}");
+    assert!(lines[1].ends_with(&format!("\"previous_sha256\": \"{}\"}}", sha256_hex(lines[0]))));
+    assert!(lines[2].ends_with(&format!("\"previous_sha256\": \"{}\"}}", sha256_hex(lines[1]))));
+}
@@ -0,0 +1,213 @@
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::types::{
+    BucketVersioningStatus, MfaDeleteStatus, ObjectOwnership, PublicAccessBlockConfiguration, ServerSideEncryption,
+    ServerSideEncryptionByDefault, ServerSideEncryptionConfiguration, ServerSideEncryptionRule, VersioningConfiguration,
+};
+
+use super::{Client, Error};
+
+/// Settings applied by `mb` straight after creating each bucket
+#[derive(clap::Args, Debug, Clone, Default)]
+#[clap(next_help_heading = "Bucket Settings")]
+pub struct OptionsBucketSettings {
+    /// Keep every version of each object
+    #[clap(long)]
+    pub versioning: bool,
+    /// Who owns objects uploaded by other accounts: bucket-owner-enforced, which disables ACLs,
+    /// bucket-owner-preferred or object-writer
+    #[clap(long, value_name = "OWNERSHIP", value_parser = parse_ownership)]
+    pub ownership: Option<ObjectOwnership>,
+    /// Encrypt new objects unless uploaded otherwise: 'AES256', 'aws:kms' with the AWS managed
+    /// key, or 'aws:kms:KEY' with a KMS key ID or ARN. KMS uses an S3 Bucket Key
+    #[clap(long, value_name = "ALGORITHM[:KEY]", value_parser = parse_default_encryption)]
+    pub default_encryption: Option<DefaultEncryption>,
+    /// Block public ACLs and policies, existing and new
+    #[clap(long)]
+    pub block_public_access: bool,
+}
+
+/// Encryption of new objects in a bucket, as `mb --default-encryption` and `encryption set` take it
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefaultEncryption {
+    algorithm: ServerSideEncryption,
+    kms_key: Option<String>,
+}
+
+fn parse_ownership(s: &str) -> Result<ObjectOwnership, String> {
+    match s {
+        "bucket-owner-enforced" => Ok(ObjectOwnership::BucketOwnerEnforced),
+        "bucket-owner-preferred" => Ok(ObjectOwnership::BucketOwnerPreferred),
+        "object-writer" => Ok(ObjectOwnership::ObjectWriter),
+        _ => Err(format!("unknown ownership '{s}', expected bucket-owner-enforced, bucket-owner-preferred or object-writer")),
+    }
+}
+
+pub fn parse_default_encryption(s: &str) -> Result<DefaultEncryption, String> {
+    let (algorithm, kms_key) = match s.strip_prefix("aws:kms") {
+        Some("") => (ServerSideEncryption::AwsKms, None),
+        Some(key) => match key.strip_prefix(':') {
+            Some(key) if !key.is_empty() => (ServerSideEncryption::AwsKms, Some(key.to_owned())),
+            _ => return Err(format!("invalid encryption '{s}', expected 'aws:kms:KEY'")),
+        },
+        None if s.eq_ignore_ascii_case("AES256") => (ServerSideEncryption::Aes256, None),
+        None => return Err(format!("unknown encryption '{s}', expected AES256, aws:kms or aws:kms:KEY")),
+    };
+    Ok(DefaultEncryption { algorithm, kms_key })
+}
+
+/// A bucket's encryption rule in the form --default-encryption takes, noting an S3 Bucket Key
+fn describe_encryption(rule: &ServerSideEncryptionRule) -> String {
+    let mut description = match rule.apply_server_side_encryption_by_default() {
+        Some(by_default) => match by_default.kms_master_key_id() {
+            Some(key) => format!("{}:{key}", by_default.sse_algorithm().as_str()),
+            None => by_default.sse_algorithm().as_str().to_owned(),
+        },
+        None => "none".to_owned(),
+    };
+    if rule.bucket_key_enabled() == Some(true) {
+        description += ", bucket key";
+    }
+    description
+}
+
+impl Client {
+    /// Versioning state of `bucket`: 'enabled', 'suspended' or 'unversioned' if it never was,
+    /// noting MFA delete
+    pub async fn versioning(&self, bucket: &str) -> Result<String, Error> {
+        let output = self.client.get_bucket_versioning().bucket(bucket).send().await?;
+        let status = match output.status() {
+            Some(BucketVersioningStatus::Enabled) => "enabled",
+            Some(BucketVersioningStatus::Suspended) => "suspended",
+            Some(other) => other.as_str(),
+            None => "unversioned",
+        };
+        Ok(match output.mfa_delete() {
+            Some(MfaDeleteStatus::Enabled) => format!("{status}, MFA delete"),
+            _ => status.to_owned(),
+        })
+    }
+
+    /// Enable versioning of `bucket`, or suspend it, keeping existing versions
+    pub async fn set_versioning(&self, bucket: &str, enabled: bool) -> Result<(), Error> {
+        let status = if enabled { BucketVersioningStatus::Enabled } else { BucketVersioningStatus::Suspended };
+        self.client.put_bucket_versioning()
+            .bucket(bucket)
+            .versioning_configuration(VersioningConfiguration::builder().status(status).build())
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Policy JSON of `bucket`, as stored
+    pub async fn policy(&self, bucket: &str) -> Result<String, Error> {
+        let output = self.client.get_bucket_policy().bucket(bucket).send().await?;
+        Ok(output.policy().unwrap_or_default().to_owned())
+    }
+
+    /// Replace the policy of `bucket` with `policy` JSON
+    pub async fn put_policy(&self, bucket: &str, policy: String) -> Result<(), Error> {
+        self.client.put_bucket_policy().bucket(bucket).policy(policy).send().await?;
+        Ok(())
+    }
+
+    pub async fn delete_policy(&self, bucket: &str) -> Result<(), Error> {
+        self.client.delete_bucket_policy().bucket(bucket).send().await?;
+        Ok(())
+    }
+
+    /// Default encryption of `bucket`, one rule per line, None if it has none
+    pub async fn encryption(&self, bucket: &str) -> Result<Option<String>, Error> {
+        match self.client.get_bucket_encryption().bucket(bucket).send().await {
+            Ok(output) => {
+                let rules = output.server_side_encryption_configuration().map(|c| c.rules()).unwrap_or_default();
+                Ok(Some(rules.iter().map(describe_encryption).collect::<Vec<_>>().join("\n")))
+            },
+            Err(e) if e.code() == Some("ServerSideEncryptionConfigurationNotFoundError") => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the default encryption of `bucket`, using an S3 Bucket Key with KMS
+    pub async fn set_encryption(&self, bucket: &str, encryption: &DefaultEncryption) -> Result<(), Error> {
+        let by_default = ServerSideEncryptionByDefault::builder()
+            .sse_algorithm(encryption.algorithm.clone())
+            .set_kms_master_key_id(encryption.kms_key.clone())
+            .build()
+            .map_err(|e| Error::S3SdkError("invalid encryption: ", Box::new(e)))?;
+        let rule = ServerSideEncryptionRule::builder()
+            .apply_server_side_encryption_by_default(by_default)
+            .bucket_key_enabled(encryption.algorithm == ServerSideEncryption::AwsKms)
+            .build();
+        let configuration = ServerSideEncryptionConfiguration::builder()
+            .rules(rule)
+            .build()
+            .map_err(|e| Error::S3SdkError("invalid encryption: ", Box::new(e)))?;
+        self.client.put_bucket_encryption()
+            .bucket(bucket)
+            .server_side_encryption_configuration(configuration)
+            .send()
+            .await?;
+        Ok(())
+    }
+
+    /// Remove the default encryption of `bucket`, which AWS reverts to AES256
+    pub async fn delete_encryption(&self, bucket: &str) -> Result<(), Error> {
+        self.client.delete_bucket_encryption().bucket(bucket).send().await?;
+        Ok(())
+    }
+
+    /// Apply `settings` to the just created `bucket`, stopping at the first to fail
+    pub(super) async fn apply_bucket_settings(&self, bucket: &str, settings: &OptionsBucketSettings) -> Result<(), Error> {
+        if settings.block_public_access {
+            let block = PublicAccessBlockConfiguration::builder()
+                .block_public_acls(true)
+                .ignore_public_acls(true)
+                .block_public_policy(true)
+                .restrict_public_buckets(true)
+                .build();
+            self.client.put_public_access_block()
+                .bucket(bucket)
+                .public_access_block_configuration(block)
+                .send()
+                .await
+                .map_err(|e| Error::S3SdkError("created, but failed to block public access: ", Box::new(Error::from(e))))?;
+        }
+        if settings.versioning {
+            self.set_versioning(bucket, true)
+                .await
+                .map_err(|e| Error::S3SdkError("created, but failed to enable versioning: ", Box::new(e)))?;
+        }
+        if let Some(encryption) = &settings.default_encryption {
+            self.set_encryption(bucket, encryption)
+                .await
+                .map_err(|e| Error::S3SdkError("created, but failed to set default encryption: ", Box::new(e)))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parse_bucket_settings() {
+    assert_eq!(parse_default_encryption("AES256"), Ok(DefaultEncryption { algorithm: ServerSideEncryption::Aes256, kms_key: None }));
+    assert_eq!(parse_default_encryption("aws:kms"), Ok(DefaultEncryption { algorithm: ServerSideEncryption::AwsKms, kms_key: None }));
+    let arn = "arn:aws:kms:eu-west-1:111122223333:key/1234abcd";
+    assert_eq!(parse_default_encryption(&format!("aws:kms:{arn}")), Ok(DefaultEncryption { algorithm: ServerSideEncryption::AwsKms, kms_key: Some(arn.to_owned()) }));
+    assert!(parse_default_encryption("aws:kms:").is_err());
+    assert!(parse_default_encryption("aws:kmsx").is_err());
+    assert!(parse_default_encryption("des").is_err());
+
+    let rule = |algorithm, key: Option<&str>, bucket_key| ServerSideEncryptionRule::builder()
+        .apply_server_side_encryption_by_default(ServerSideEncryptionByDefault::builder()
+            .sse_algorithm(algorithm)
+            .set_kms_master_key_id(key.map(str::to_owned))
+            .build()
+            .unwrap())
+        .set_bucket_key_enabled(bucket_key)
+        .build();
+    assert_eq!(describe_encryption(&rule(ServerSideEncryption::Aes256, None, None)), "AES256");
+    assert_eq!(describe_encryption(&rule(ServerSideEncryption::AwsKms, Some(arn), Some(true))), format!("aws:kms:{arn}, bucket key"));
+    assert_eq!(describe_encryption(&rule(ServerSideEncryption::AwsKms, None, Some(false))), "aws:kms");
+
+    assert_eq!(parse_ownership("bucket-owner-enforced"), Ok(ObjectOwnership::BucketOwnerEnforced));
+    assert!(parse_ownership("BucketOwnerEnforced").is_err());
+}
@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use sha2::Digest;
+use tokio::io::AsyncWriteExt;
+
+#[derive(clap::Args, Debug, Clone)]
+#[clap(next_help_heading = "Cache")]
+pub struct OptionsCache {
+    /// Keep downloaded objects in this directory, serving repeated reads locally while the ETag is unchanged
+    #[clap(long, value_hint=clap::ValueHint::DirPath)]
+    pub cache_dir: Option<PathBuf>,
+    /// Maximum total size of the cache, least recently used objects are evicted first
+    #[clap(long, default_value="1G", value_parser=crate::cli::parse_size)]
+    pub cache_size: u64,
+}
+
+impl OptionsCache {
+    pub fn open(&self) -> Option<Cache> {
+        self.cache_dir.as_ref().map(|dir| Cache { dir: dir.clone(), max_size: self.cache_size })
+    }
+}
+
+/// Content-addressed object cache, keyed by ETag
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+const TEMPORARY_SUFFIX: &str = ".tmp";
+/// Distinguishes the temporary files of concurrent writers within this process
+static WRITERS: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+fn entry_name(etag: &str) -> String {
+    sha2::Sha256::digest(etag.as_bytes()).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Whether `file_name` is an entry being written by `Cache::writer`, '<sha256>.<pid>-<writer>.tmp',
+/// or '<sha256>.<pid>.tmp' as named by earlier versions
+pub fn is_temporary(file_name: &str) -> bool {
+    let Some((entry, unique)) = file_name.strip_suffix(TEMPORARY_SUFFIX).and_then(|name| name.split_once('.')) else {
+        return false;
+    };
+    let number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let unique_valid = match unique.split_once('-') {
+        Some((pid, writer)) => number(pid) && number(writer),
+        None => number(unique),
+    };
+    entry.len() == 64 && entry.chars().all(|c| c.is_ascii_hexdigit()) && unique_valid
+}
+
+impl Cache {
+    fn path(&self, etag: &str) -> PathBuf {
+        self.dir.join(entry_name(etag))
+    }
+
+    /// Cached copy of the content with `etag`, marked as recently used
+    pub async fn lookup(&self, etag: &str) -> Option<PathBuf> {
+        let path = self.path(etag);
+        let file = tokio::fs::File::options().write(true).open(&path).await.ok()?;
+        let _ = file.into_std().await.set_modified(SystemTime::now());
+        Some(path)
+    }
+
+    /// Start writing a new entry, only visible once committed
+    pub async fn writer(&self, etag: &str) -> std::io::Result<Writer> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.path(etag);
+        let writer = WRITERS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let temporary = path.with_extension(format!("{}-{writer}{TEMPORARY_SUFFIX}", std::process::id()));
+        let file = tokio::fs::File::create(&temporary).await?;
+        Ok(Writer { file, temporary, path })
+    }
+
+    /// Add an existing local file as the entry for `etag`
+    pub async fn insert(&self, etag: &str, source: &Path) -> std::io::Result<()> {
+        let mut writer = self.writer(etag).await?;
+        let mut file = tokio::fs::File::open(source).await?;
+        tokio::io::copy(&mut file, &mut writer.file).await?;
+        self.commit(writer).await
+    }
+
+    pub async fn commit(&self, mut writer: Writer) -> std::io::Result<()> {
+        writer.file.flush().await?;
+        tokio::fs::rename(&writer.temporary, &writer.path).await?;
+        self.evict().await
+    }
+
+    /// Remove least recently used entries until within `max_size`
+    async fn evict(&self) -> std::io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        let mut dir = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            if entry.file_name().to_string_lossy().ends_with(TEMPORARY_SUFFIX) {
+                continue;
+            }
+            let metadata = entry.metadata().await?;
+            total += metadata.len();
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            tokio::fs::remove_file(&path).await?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Entry being written, discarded unless committed
+pub struct Writer {
+    pub file: tokio::fs::File,
+    temporary: PathBuf,
+    path: PathBuf,
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        // No-op once renamed by commit
+        let _ = std::fs::remove_file(&self.temporary);
+    }
+}
+
+#[test]
+fn test_entry_name() {
+    assert_eq!(entry_name("\"abc\""), entry_name("\"abc\""));
+    assert_ne!(entry_name("\"abc\""), entry_name("\"abd\""));
+    assert_eq!(entry_name("x").len(), 64);
+}
+
+#[test]
+fn test_is_temporary() {
+    let entry = entry_name("x");
+    assert!(is_temporary(&format!("{entry}.123-4.tmp")));
+    assert!(is_temporary(&format!("{entry}.123.tmp")));
+    assert!(!is_temporary(&format!("{entry}.123-.tmp")));
+    assert!(!is_temporary(&format!("{entry}.tmp")));
+    assert!(!is_temporary("other.123-4.tmp"));
+}
@@ -0,0 +1,74 @@
+use wax::Pattern;
+
+/// Cache-Control per uploaded file, from the first of `;` separated `GLOB[,GLOB..]:VALUE` rules matching it,
+/// e.g. '*.html:no-cache;*.css,*.js:max-age=31536000,immutable'. Globs without a '/' match the file name,
+/// others the destination key
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheRules(Vec<(Vec<String>, String)>);
+
+impl std::str::FromStr for CacheRules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s.split(';')
+            .filter(|rule| !rule.trim().is_empty())
+            .map(|rule| {
+                let (globs, value) = rule.trim().split_once(':')
+                    .ok_or_else(|| format!("invalid rule '{rule}', expected GLOB:CACHE-CONTROL"))?;
+                if value.trim().is_empty() {
+                    return Err(format!("empty Cache-Control in rule '{rule}'"));
+                }
+                let globs: Vec<String> = globs.split(',').map(|glob| glob.trim().to_owned()).collect();
+                for glob in &globs {
+                    wax::Glob::new(glob).map_err(|e| format!("invalid glob '{glob}': {e}"))?;
+                }
+                Ok((globs, value.trim().to_owned()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match rules.is_empty() {
+            true => Err("no rules given".into()),
+            false => Ok(CacheRules(rules)),
+        }
+    }
+}
+
+impl CacheRules {
+    /// Cache-Control of the first rule with a glob matching `key`
+    pub fn cache_control_for(&self, key: &str) -> Option<&str> {
+        let filename = key.rsplit('/').next().unwrap_or(key);
+        self.0.iter()
+            .find(|(globs, _)| globs.iter().any(|glob| {
+                let candidate = match glob.contains('/') {
+                    true => key,
+                    false => filename,
+                };
+                wax::Glob::new(glob).is_ok_and(|glob| glob.is_match(candidate))
+            }))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl super::OptionsUpload {
+    /// Cache-Control to upload `key` with, from the first matching rule or else --cache-control
+    pub fn cache_control_for(&self, key: &super::Key) -> Option<String> {
+        self.cache_rules.as_ref()
+            .and_then(|rules| rules.cache_control_for(key.as_str()))
+            .or(self.headers.cache_control.as_deref())
+            .map(str::to_owned)
+    }
+}
+
+#[test]
+fn test_cache_rules() {
+    let rules: CacheRules = "*.html:no-cache;*.css,*.js:max-age=31536000,immutable;assets/**:max-age=3600".parse().unwrap();
+    assert_eq!(rules.cache_control_for("site/index.html"), Some("no-cache"));
+    assert_eq!(rules.cache_control_for("site/css/main.css"), Some("max-age=31536000,immutable"));
+    assert_eq!(rules.cache_control_for("app.js"), Some("max-age=31536000,immutable"));
+    assert_eq!(rules.cache_control_for("assets/logo.png"), Some("max-age=3600"));
+    assert_eq!(rules.cache_control_for("site/assets/logo.png"), None);
+    assert_eq!(rules.cache_control_for("readme.txt"), None);
+
+    assert!("*.html".parse::<CacheRules>().is_err());
+    assert!("*.html:".parse::<CacheRules>().is_err());
+    assert!("".parse::<CacheRules>().is_err());
+}
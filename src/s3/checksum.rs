@@ -0,0 +1,96 @@
+use aws_sdk_s3::operation::upload_part::UploadPartOutput;
+use aws_sdk_s3::types::builders::CompletedPartBuilder;
+use aws_sdk_s3::types::{ChecksumAlgorithm, CopyPartResult, Part, ServerSideEncryption};
+
+use super::{Client, Error, Uri};
+
+/// Checksum algorithms the SDK computes while sending, for --checksum-algorithm
+pub const ALGORITHMS: [&str; 5] = ["CRC32", "CRC32C", "CRC64NVME", "SHA1", "SHA256"];
+
+/// The checksum for `algorithm` of a part, given its CRC32, CRC32C, CRC64NVME, SHA1 and SHA256
+/// checksums in that order
+fn select(algorithm: &ChecksumAlgorithm, checksums: [Option<&str>; 5]) -> Option<String> {
+    ALGORITHMS.iter()
+        .zip(checksums)
+        .find(|(name, _)| **name == algorithm.as_str())
+        .and_then(|(_, checksum)| checksum.map(str::to_owned))
+}
+
+/// Checksum S3 computed of a part just uploaded
+pub fn of_upload(output: &UploadPartOutput, algorithm: &ChecksumAlgorithm) -> Option<String> {
+    select(algorithm, [output.checksum_crc32(), output.checksum_crc32_c(), output.checksum_crc64_nvme(), output.checksum_sha1(), output.checksum_sha256()])
+}
+
+/// Checksum S3 computed of a part just copied with UploadPartCopy
+pub fn of_copy(result: &CopyPartResult, algorithm: &ChecksumAlgorithm) -> Option<String> {
+    select(algorithm, [result.checksum_crc32(), result.checksum_crc32_c(), result.checksum_crc64_nvme(), result.checksum_sha1(), result.checksum_sha256()])
+}
+
+/// Checksum of a part listed as already uploaded
+pub fn of_part(part: &Part, algorithm: &ChecksumAlgorithm) -> Option<String> {
+    select(algorithm, [part.checksum_crc32(), part.checksum_crc32_c(), part.checksum_crc64_nvme(), part.checksum_sha1(), part.checksum_sha256()])
+}
+
+/// `part` with its checksum, which CompleteMultipartUpload requires when uploading with an algorithm
+pub fn with_checksum(part: CompletedPartBuilder, algorithm: &ChecksumAlgorithm, checksum: Option<String>) -> CompletedPartBuilder {
+    match algorithm {
+        ChecksumAlgorithm::Crc32 => part.set_checksum_crc32(checksum),
+        ChecksumAlgorithm::Crc32C => part.set_checksum_crc32_c(checksum),
+        ChecksumAlgorithm::Crc64Nvme => part.set_checksum_crc64_nvme(checksum),
+        ChecksumAlgorithm::Sha1 => part.set_checksum_sha1(checksum),
+        ChecksumAlgorithm::Sha256 => part.set_checksum_sha256(checksum),
+        _ => part,
+    }
+}
+
+/// Server-side encryption leaving an object's ETag something other than the MD5 of its content
+#[derive(Debug, PartialEq)]
+pub enum OpaqueEncryption {
+    Kms,
+    /// SSE-C, with which GetObjectAttributes needs the key as well
+    CustomerKey,
+}
+
+impl Client {
+    /// Encryption of `uri` hiding the MD5 of its content, if any. HeadObject of SSE-C objects is
+    /// refused without their key, so a Bad Request is taken as SSE-C
+    pub async fn opaque_encryption(&self, uri: &Uri) -> Result<Option<OpaqueEncryption>, Error> {
+        let head = self.client.head_object()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .send()
+            .await;
+        match head {
+            Ok(head) if head.sse_customer_algorithm().is_some() => Ok(Some(OpaqueEncryption::CustomerKey)),
+            Ok(head) => match head.server_side_encryption() {
+                Some(ServerSideEncryption::AwsKms | ServerSideEncryption::AwsKmsDsse) => Ok(Some(OpaqueEncryption::Kms)),
+                _ => Ok(None),
+            },
+            Err(e) if e.raw_response().map(|response| response.status().as_u16()) == Some(400) => Ok(Some(OpaqueEncryption::CustomerKey)),
+            Err(e) if e.as_service_error().map(|e| e.is_not_found()).unwrap_or(false) => Err(Error::NoSuchKey(uri.clone())),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl super::OptionsUpload {
+    /// Verbose note of --checksum-algorithm
+    pub fn println_checksum_algorithm(&self) {
+        if let Some(algorithm) = &self.checksum_algorithm {
+            println!("🏁 sending {} checksum for S3 to verify", algorithm.as_str());
+        }
+    }
+}
+
+#[test]
+fn test_select_checksum() {
+    let checksums = [Some("crc32"), None, None, Some("sha1"), Some("sha256")];
+    assert_eq!(select(&ChecksumAlgorithm::Crc32, checksums).as_deref(), Some("crc32"));
+    assert_eq!(select(&ChecksumAlgorithm::Sha256, checksums).as_deref(), Some("sha256"));
+    assert_eq!(select(&ChecksumAlgorithm::Crc32C, checksums), None);
+    assert_eq!(select(&ChecksumAlgorithm::Md5, checksums), None);
+
+    let part = with_checksum(aws_sdk_s3::types::CompletedPart::builder(), &ChecksumAlgorithm::Crc32C, Some("abc".into())).build();
+    assert_eq!(part.checksum_crc32_c(), Some("abc"));
+    assert_eq!(part.checksum_crc32(), None);
+}
@@ -0,0 +1,397 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use aws_sdk_s3::primitives::ByteStream;
+use futures::StreamExt;
+use sha2::Digest;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::{ChangedFile, Client, Error, Key, OptionsUpload, Uri, CHANGED_FILE_ATTEMPTS};
+use crate::cli;
+
+/// Below a synced prefix, chunks shared by all its files are stored here named by their SHA-256
+pub const CHUNK_DIRECTORY: &str = ".sup3-chunks/";
+/// Appended to a file's key to name the manifest listing its chunks
+pub const MANIFEST_SUFFIX: &str = ".sup3-manifest";
+
+const MANIFEST_HEADER: &str = "sup3-chunked 1";
+const MIN_CHUNK: usize = 512 * 1024;
+const MAX_CHUNK: usize = 8 * 1024 * 1024;
+/// Leading zero bits of the rolling hash marking a boundary, averaging 2 MiB past the minimum
+const BOUNDARY_BITS: u32 = 21;
+/// Chunks transferred at once for a single file
+const CHUNK_CONCURRENCY: usize = 4;
+
+/// Per-byte values of the gear rolling hash, fixed so boundaries are stable between runs
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64
+    let mut table = [0; 256];
+    let mut state: u64 = 0x5375_7033_4765_6172;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Content-defined chunk boundaries, so an insertion only changes the chunks around it
+#[derive(Default)]
+struct Chunker {
+    hash: u64,
+    length: usize,
+}
+
+impl Chunker {
+    /// Whether the current chunk ends with `byte`
+    fn push(&mut self, byte: u8) -> bool {
+        self.hash = (self.hash << 1).wrapping_add(GEAR[byte as usize]);
+        self.length += 1;
+        let boundary = self.length >= MAX_CHUNK || (self.length >= MIN_CHUNK && self.hash.leading_zeros() >= BOUNDARY_BITS);
+        if boundary {
+            *self = Chunker::default();
+        }
+        boundary
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// Hex SHA-256 of the content
+    pub hash: String,
+    pub size: u64,
+}
+
+/// A chunked file: its size, modification time in seconds since the epoch, and chunks in order
+#[derive(Debug, PartialEq)]
+pub struct Manifest {
+    pub size: u64,
+    pub mtime: i64,
+    pub chunks: Vec<Chunk>,
+}
+
+impl Manifest {
+    fn to_text(&self) -> String {
+        let mut text = format!("{MANIFEST_HEADER}\nsize {}\nmtime {}\n", self.size, self.mtime);
+        for chunk in &self.chunks {
+            text.push_str(&format!("{} {}\n", chunk.hash, chunk.size));
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Option<Manifest> {
+        let mut lines = text.lines();
+        if lines.next()? != MANIFEST_HEADER {
+            return None;
+        }
+        let size = lines.next()?.strip_prefix("size ")?.parse().ok()?;
+        let mtime = lines.next()?.strip_prefix("mtime ")?.parse().ok()?;
+        let chunks = lines
+            .map(|line| {
+                let (hash, size) = line.split_once(' ')?;
+                Some(Chunk { hash: hash.to_owned(), size: size.parse().ok()? })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        (chunks.iter().map(|chunk| chunk.size).sum::<u64>() == size).then_some(Manifest { size, mtime, chunks })
+    }
+
+    /// Chunk offsets within the file
+    fn offsets(&self) -> impl Iterator<Item = (u64, &Chunk)> {
+        self.chunks.iter().scan(0, |offset, chunk| {
+            let start = *offset;
+            *offset += chunk.size;
+            Some((start, chunk))
+        })
+    }
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Split a local file into chunks, reporting progress as it's read
+async fn chunk_file(path: &Path, progress_fn: &cli::ProgressFn) -> std::io::Result<Manifest> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let metadata = file.metadata().await?;
+    let mtime = metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|since| since.as_secs() as i64)
+        .unwrap_or(0);
+
+    let mut chunks = Vec::new();
+    let mut chunker = Chunker::default();
+    let mut hasher = sha2::Sha256::new();
+    let mut chunk_size = 0;
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        let mut start = 0;
+        for (i, byte) in buffer[..read].iter().enumerate() {
+            if chunker.push(*byte) {
+                hasher.update(&buffer[start..=i]);
+                chunk_size += (i + 1 - start) as u64;
+                chunks.push(Chunk { hash: hex(&hasher.finalize_reset()), size: chunk_size });
+                chunk_size = 0;
+                start = i + 1;
+            }
+        }
+        hasher.update(&buffer[start..read]);
+        chunk_size += (read - start) as u64;
+        progress_fn(cli::Update::StateProgress(read));
+    }
+    if chunk_size > 0 {
+        chunks.push(Chunk { hash: hex(&hasher.finalize()), size: chunk_size });
+    }
+    let size = chunks.iter().map(|chunk| chunk.size).sum();
+    Ok(Manifest { size, mtime, chunks })
+}
+
+fn chunk_uri(root: &Uri, hash: &str) -> Uri {
+    let mut key = root.key.clone();
+    key.push(CHUNK_DIRECTORY);
+    key.push(hash);
+    Uri::new(root.bucket.clone(), key)
+}
+
+/// Where the manifest of the file at `uri` is stored
+pub fn manifest_uri(uri: &Uri) -> Uri {
+    let mut key = uri.key.clone();
+    key.push(MANIFEST_SUFFIX);
+    Uri::new(uri.bucket.clone(), key)
+}
+
+impl Client {
+    /// Hashes of the chunks stored below the synced prefix `root`
+    pub async fn stored_chunks(&self, root: &Uri) -> Result<HashSet<String>, Error> {
+        let directory = Uri::new(root.bucket.clone(), Key::new(format!("{}{CHUNK_DIRECTORY}", root.key)));
+        Ok(self.list_objects(&directory).await?
+            .iter()
+            .filter_map(|object| object.key()?.strip_prefix(directory.key.as_str()).map(str::to_owned))
+            .collect())
+    }
+
+    pub async fn manifest(&self, uri: &Uri) -> Result<Manifest, Error> {
+        let response = self.client.get_object()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .send()
+            .await
+            .map_err(|e| super::error_from_get(uri, e))?;
+        let text = response.body.collect().await?.into_bytes();
+        std::str::from_utf8(&text).ok()
+            .and_then(Manifest::parse)
+            .ok_or_else(|| Error::InvalidManifest(uri.clone()))
+    }
+
+    /// Upload the chunks of `path` not yet in `stored`, then the manifest for `to`
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put_chunked(&self, verbose: bool, options_upload: &OptionsUpload, path: &Path, to: &Uri, root: &Uri, stored: &Mutex<HashSet<String>>, progress_fn: cli::ProgressFn) -> Result<String, Error> {
+        let mut attempt = 1;
+        loop {
+            match self.put_chunked_once(verbose, options_upload, path, to, root, stored, progress_fn.clone()).await {
+                Err(Error::LocalFileChanged(_)) if options_upload.changed_file == ChangedFile::Restart && attempt < CHANGED_FILE_ATTEMPTS => {
+                    if verbose {
+                        println!("🏁 '{}' changed while being uploaded, restarting", path.to_string_lossy());
+                    }
+                    progress_fn(cli::Update::StateRetried);
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+    #[allow(clippy::too_many_arguments)]
+    async fn put_chunked_once(&self, verbose: bool, options_upload: &OptionsUpload, path: &Path, to: &Uri, root: &Uri, stored: &Mutex<HashSet<String>>, progress_fn: cli::ProgressFn) -> Result<String, Error> {
+        progress_fn(cli::Update::State("chunking"));
+        let length = tokio::fs::metadata(path).await?.len();
+        progress_fn(cli::Update::StateLength(length as usize));
+        let manifest = chunk_file(path, &progress_fn).await?;
+
+        // Only recorded as stored once uploaded, so no manifest can reference a chunk that failed
+        let missing: Vec<(u64, &Chunk)> = {
+            let stored = stored.lock().unwrap();
+            let mut seen = HashSet::new();
+            manifest.offsets().filter(|(_, chunk)| !stored.contains(&chunk.hash) && seen.insert(&chunk.hash)).collect()
+        };
+        let missing_bytes: u64 = missing.iter().map(|(_, chunk)| chunk.size).sum();
+        if verbose {
+            println!("🏁 uploading '{}' to {to} as {} chunk(s), {} new [{missing_bytes} bytes]", path.to_string_lossy(), manifest.chunks.len(), missing.len());
+        }
+        progress_fn(cli::Update::State("uploading"));
+        progress_fn(cli::Update::StateRetried);
+        progress_fn(cli::Update::StateLength(missing_bytes as usize));
+
+        let limiter = self.rate_limits.transfer();
+        let mut uploads = futures::stream::iter(&missing)
+            .map(|(offset, chunk)| async {
+                let mut file = tokio::fs::File::open(path).await?;
+                file.seek(std::io::SeekFrom::Start(*offset)).await?;
+                let mut data = vec![0; chunk.size as usize];
+                match file.read_exact(&mut data).await {
+                    Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Err(Error::LocalFileChanged(path.to_path_buf())),
+                    read => read?,
+                };
+                // Re-read since chunking, so stored under its hash only if still the same bytes
+                if hex(&sha2::Sha256::digest(&data)) != chunk.hash {
+                    return Err(Error::LocalFileChanged(path.to_path_buf()));
+                }
+                let uri = chunk_uri(root, &chunk.hash);
+                progress_fn(cli::Update::StateBuffered(data.len()));
+                self.client.put_object()
+                    .bucket(uri.bucket.clone())
+                    .key(uri.key.to_string())
+                    .set_acl(options_upload.canned_acl.to_owned())
+                    .set_storage_class(options_upload.class_for(path, length))
+                    .content_length(chunk.size as i64)
+                    .body(limiter.body(data.into()))
+                    .send()
+                    .await?;
+                progress_fn(cli::Update::StateProgress(chunk.size as usize));
+                stored.lock().unwrap().insert(chunk.hash.clone());
+                Ok::<_, Error>(())
+            })
+            .buffer_unordered(CHUNK_CONCURRENCY);
+        while let Some(result) = uploads.next().await {
+            result?;
+        }
+        // Chunks only cover the file as it was when chunked, so no other size may be recorded as it
+        let now = tokio::fs::metadata(path).await?.len();
+        let grew_ignored = now > manifest.size && options_upload.changed_file == ChangedFile::Ignore;
+        if now != manifest.size && !grew_ignored {
+            return Err(Error::LocalFileChanged(path.to_path_buf()));
+        }
+
+        let destination = manifest_uri(to);
+        self.client.put_object()
+            .bucket(destination.bucket.clone())
+            .key(destination.key.to_string())
+            .set_acl(options_upload.canned_acl.to_owned())
+            .body(ByteStream::from(manifest.to_text().into_bytes()))
+            .send()
+            .await?;
+        self.head_cache.invalidate(&destination);
+        progress_fn(cli::Update::Finished());
+        Ok(destination.to_string())
+    }
+
+    /// Reassemble the file at `from` from its manifest and chunks, verifying each chunk
+    pub async fn get_chunked(&self, verbose: bool, from: &Uri, root: &Uri, to: &Path, progress_fn: cli::ProgressFn) -> Result<PathBuf, Error> {
+        progress_fn(cli::Update::State("opening"));
+        let manifest = self.manifest(&manifest_uri(from)).await?;
+        let mut local_file = super::partial_file::PartialFile::new(to.to_path_buf(), self.disk_jobs.clone()).await?;
+        if verbose {
+            println!("🏁 downloading '{from}' [{} bytes, {} chunks] to {}", manifest.size, manifest.chunks.len(), local_file.path_printable());
+        }
+        progress_fn(cli::Update::State("downloading"));
+        progress_fn(cli::Update::StateLength(manifest.size as usize));
+
+        let mut chunks = futures::stream::iter(&manifest.chunks)
+            .map(|chunk| async move {
+                let uri = chunk_uri(root, &chunk.hash);
+                let response = self.client.get_object()
+                    .bucket(uri.bucket.clone())
+                    .key(uri.key.to_string())
+                    .send()
+                    .await
+                    .map_err(|e| super::error_from_get(&uri, e))?;
+                let data = response.body.collect().await?.into_bytes();
+                match hex(&sha2::Sha256::digest(&data)) == chunk.hash {
+                    true => Ok(data),
+                    false => Err(Error::InvalidManifest(uri)),
+                }
+            })
+            .buffered(CHUNK_CONCURRENCY);
+        let mut result = Ok(());
+        while let Some(chunk) = chunks.next().await {
+            let written = match chunk {
+                Ok(data) => local_file.write_all(&data).await.map(|_| data.len()).map_err(Error::from),
+                Err(e) => Err(e),
+            };
+            match written {
+                Ok(size) => progress_fn(cli::Update::StateProgress(size)),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                },
+            }
+        }
+        let local_path = match result {
+            Ok(()) => local_file.finished().await?,
+            Err(e) => {
+                local_file.cancelled().await?;
+                return Err(e);
+            },
+        };
+        progress_fn(cli::Update::Finished());
+        Ok(local_path)
+    }
+
+    /// Remove chunks below `root` no longer referenced by any manifest, returning how many
+    pub async fn remove_unreferenced_chunks(&self, verbose: bool, root: &Uri) -> Result<usize, Error> {
+        let mut referenced = HashSet::new();
+        for object in self.list_objects(root).await? {
+            let Some(key) = object.key().filter(|key| key.ends_with(MANIFEST_SUFFIX)) else { continue };
+            let manifest = self.manifest(&Uri::new(root.bucket.clone(), Key::new(key.to_owned()))).await?;
+            referenced.extend(manifest.chunks.into_iter().map(|chunk| chunk.hash));
+        }
+        let unreferenced: Vec<String> = self.stored_chunks(root).await?.into_iter().filter(|hash| !referenced.contains(hash)).collect();
+        for hash in &unreferenced {
+            let uri = chunk_uri(root, hash);
+            if verbose {
+                println!("🏁 removing unreferenced chunk {uri}");
+            }
+            self.client.delete_object()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .send()
+                .await?;
+        }
+        Ok(unreferenced.len())
+    }
+}
+
+#[test]
+fn test_manifest() {
+    let manifest = Manifest {
+        size: 30,
+        mtime: 1700000000,
+        chunks: vec![Chunk { hash: "aa".into(), size: 10 }, Chunk { hash: "bb".into(), size: 20 }],
+    };
+    let text = manifest.to_text();
+    assert_eq!(text, "sup3-chunked 1\nsize 30\nmtime 1700000000\naa 10\nbb 20\n");
+    assert_eq!(Manifest::parse(&text), Some(manifest));
+    assert_eq!(Manifest::parse("sup3-chunked 1\nsize 31\nmtime 0\naa 10\n"), None);
+    assert_eq!(Manifest::parse("something else"), None);
+}
+
+#[test]
+fn test_chunk_boundaries() {
+    // Pseudo-random content, then the same with bytes inserted near the start
+    let mut state = 1u64;
+    let data: Vec<u8> = (0..12 << 20).map(|_| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 56) as u8
+    }).collect();
+    let boundaries = |data: &[u8]| {
+        let mut chunker = Chunker::default();
+        data.iter().enumerate().filter(|(_, byte)| chunker.push(**byte)).map(|(i, _)| data.len() - i).collect::<Vec<_>>()
+    };
+    let original = boundaries(&data);
+    assert!(original.len() > 2);
+    let mut edited = data[..1000].to_vec();
+    edited.extend_from_slice(b"inserted");
+    edited.extend_from_slice(&data[1000..]);
+    // Counted from the end, all boundaries after the first chunk are unchanged
+    assert_eq!(boundaries(&edited)[1..], original[1..]);
+}
@@ -0,0 +1,90 @@
+use std::path::Path;
+
+use aws_sdk_s3::types::StorageClass;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    SizeAbove(u64),
+    SizeBelow(u64),
+    Extension(String),
+    Default,
+}
+
+/// Storage class per uploaded file, from the first of `;` separated `condition:CLASS` rules it matches,
+/// e.g. 'size>1G:GLACIER_IR;ext=log:STANDARD_IA;default:STANDARD'
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassRules(Vec<(Condition, StorageClass)>);
+
+fn parse_condition(s: &str) -> Result<Condition, String> {
+    if s == "default" {
+        return Ok(Condition::Default);
+    }
+    if let Some(size) = s.strip_prefix("size>") {
+        return crate::cli::parse_size(size).map(Condition::SizeAbove);
+    }
+    if let Some(size) = s.strip_prefix("size<") {
+        return crate::cli::parse_size(size).map(Condition::SizeBelow);
+    }
+    if let Some(extension) = s.strip_prefix("ext=") {
+        return Ok(Condition::Extension(extension.trim_start_matches('.').to_ascii_lowercase()));
+    }
+    Err(format!("invalid condition '{s}', expected size>SIZE, size<SIZE, ext=EXTENSION or default"))
+}
+
+impl std::str::FromStr for ClassRules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s.split(';')
+            .filter(|rule| !rule.trim().is_empty())
+            .map(|rule| {
+                let (condition, class) = rule.trim().rsplit_once(':')
+                    .ok_or_else(|| format!("invalid rule '{rule}', expected CONDITION:CLASS"))?;
+                if !StorageClass::values().contains(&class) {
+                    return Err(format!("unknown storage class '{class}' in rule '{rule}'"));
+                }
+                Ok((parse_condition(condition)?, StorageClass::from(class)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match rules.is_empty() {
+            true => Err("no rules given".into()),
+            false => Ok(ClassRules(rules)),
+        }
+    }
+}
+
+impl ClassRules {
+    /// Class of the first rule matching a file at `path` of `size` bytes
+    pub fn class_for(&self, path: &Path, size: u64) -> Option<&StorageClass> {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase());
+        self.0.iter()
+            .find(|(condition, _)| match condition {
+                Condition::SizeAbove(limit) => size > *limit,
+                Condition::SizeBelow(limit) => size < *limit,
+                Condition::Extension(wanted) => extension.as_deref() == Some(wanted.as_str()),
+                Condition::Default => true,
+            })
+            .map(|(_, class)| class)
+    }
+
+    /// Every class a rule could pick
+    pub fn classes(&self) -> impl Iterator<Item = &StorageClass> {
+        self.0.iter().map(|(_, class)| class)
+    }
+}
+
+#[test]
+fn test_class_rules() {
+    let rules: ClassRules = "size>1G:GLACIER_IR;ext=log:STANDARD_IA;default:STANDARD".parse().unwrap();
+    assert_eq!(rules.class_for(Path::new("backup.tar"), 2 << 30), Some(&StorageClass::GlacierIr));
+    assert_eq!(rules.class_for(Path::new("app.LOG"), 10), Some(&StorageClass::StandardIa));
+    assert_eq!(rules.class_for(Path::new("notes"), 10), Some(&StorageClass::Standard));
+
+    let no_default: ClassRules = "size<1K:ONEZONE_IA".parse().unwrap();
+    assert_eq!(no_default.class_for(Path::new("big"), 4096), None);
+
+    assert!("size>1G".parse::<ClassRules>().is_err());
+    assert!("size>1G:COLD".parse::<ClassRules>().is_err());
+    assert!("age>1:STANDARD".parse::<ClassRules>().is_err());
+    assert!("".parse::<ClassRules>().is_err());
+}
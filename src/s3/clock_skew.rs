@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::config::interceptors::BeforeDeserializationInterceptorContextRef;
+use aws_sdk_s3::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Skew worth mentioning, S3 rejects requests signed more than 15 minutes out
+const WARN_ABOVE: Duration = Duration::from_secs(4 * 60);
+
+/// Warns once when the server's Date shows the local clock is off.
+///
+/// The SDK measures the same skew from each response and signs later requests at the server's
+/// time, retrying those rejected with RequestTimeTooSkewed, so this only explains the retries
+#[derive(Debug, Default)]
+pub struct SkewWarning {
+    warned: AtomicBool,
+}
+
+/// Seconds the server's clock is ahead of `now`, negative if behind
+fn skew_seconds(date: &str, now: SystemTime) -> Option<i64> {
+    let server = DateTime::from_str(date, DateTimeFormat::HttpDate).ok()?;
+    let local = DateTime::from(now);
+    Some(server.secs() - local.secs())
+}
+
+fn describe(skew_seconds: i64) -> String {
+    let direction = if skew_seconds > 0 { "behind" } else { "ahead of" };
+    let minutes = skew_seconds.unsigned_abs() / 60;
+    format!("local clock is {minutes} minute(s) {direction} the server's, signing requests at the server's time for the rest of the run")
+}
+
+impl Intercept for SkewWarning {
+    fn name(&self) -> &'static str {
+        "SkewWarning"
+    }
+
+    fn read_before_deserialization(&self, context: &BeforeDeserializationInterceptorContextRef<'_>, _runtime_components: &RuntimeComponents, _cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        if self.warned.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let headers = context.response().headers();
+        // Cached responses carry the Date they were first served with
+        if headers.get("age").is_some() {
+            return Ok(());
+        }
+        let Some(skew) = headers.get("date").and_then(|date| skew_seconds(date, SystemTime::now())) else {
+            return Ok(());
+        };
+        if skew.unsigned_abs() > WARN_ABOVE.as_secs() && !self.warned.swap(true, Ordering::Relaxed) {
+            eprintln!("⚠️: {}", describe(skew));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_skew_seconds() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(skew_seconds("Tue, 14 Nov 2023 22:13:20 GMT", now), Some(0));
+    assert_eq!(skew_seconds("Tue, 14 Nov 2023 22:33:20 GMT", now), Some(20 * 60));
+    assert_eq!(skew_seconds("Tue, 14 Nov 2023 21:13:20 GMT", now), Some(-60 * 60));
+    assert_eq!(skew_seconds("yesterday", now), None);
+    assert!(describe(20 * 60).starts_with("local clock is 20 minute(s) behind"));
+    assert!(describe(-60 * 60).starts_with("local clock is 60 minute(s) ahead of"));
+}
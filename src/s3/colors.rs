@@ -0,0 +1,135 @@
+use std::borrow::Cow;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+/// Colors used without LS_COLORS, those of GNU dircolors' defaults for these types
+const DEFAULT_LS_COLORS: &str = "di=01;34:\
+    *.tar=01;31:*.tgz=01;31:*.gz=01;31:*.bz2=01;31:*.xz=01;31:*.zst=01;31:*.zip=01;31:*.7z=01;31:*.rar=01;31:*.jar=01;31:\
+    *.jpg=01;35:*.jpeg=01;35:*.png=01;35:*.gif=01;35:*.webp=01;35:*.svg=01;35:*.tif=01;35:*.tiff=01;35:*.bmp=01;35:\
+    *.mp4=01;35:*.mkv=01;35:*.mov=01;35:*.webm=01;35:*.avi=01;35:\
+    *.mp3=00;36:*.flac=00;36:*.ogg=00;36:*.wav=00;36:*.m4a=00;36";
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct OptionsColor {
+    /// Color names by type from LS_COLORS, as ls does. auto colors when stdout is a terminal
+    /// and NO_COLOR is unset
+    #[clap(long, value_enum, default_value="auto")]
+    color: ColorChoice,
+    /// Prefix names with Nerd Font icons of their type, when coloring
+    #[clap(long)]
+    icons: bool,
+    #[clap(skip)]
+    palette: OnceLock<Option<Palette>>,
+}
+
+/// SGR codes of LS_COLORS for directories, other files, and by name suffix
+#[derive(Debug, Clone, Default)]
+struct Palette {
+    directory: Option<String>,
+    file: Option<String>,
+    /// Lowercase suffixes, e.g. '.tar.gz'
+    suffixes: Vec<(String, String)>,
+}
+
+impl Palette {
+    fn parse(ls_colors: &str) -> Palette {
+        let mut palette = Palette::default();
+        for (key, code) in ls_colors.split(':').filter_map(|entry| entry.split_once('=')) {
+            match key {
+                "di" => palette.directory = Some(code.to_owned()),
+                "fi" => palette.file = Some(code.to_owned()),
+                _ => if let Some(suffix) = key.strip_prefix('*') {
+                    palette.suffixes.push((suffix.to_ascii_lowercase(), code.to_owned()));
+                },
+            }
+        }
+        palette
+    }
+
+    /// Code for `name`, the longest matching suffix taking precedence
+    fn code(&self, name: &str, directory: bool) -> Option<&str> {
+        if directory {
+            return self.directory.as_deref();
+        }
+        let name = name.to_ascii_lowercase();
+        self.suffixes.iter()
+            .filter(|(suffix, _)| name.ends_with(suffix.as_str()))
+            .max_by_key(|(suffix, _)| suffix.len())
+            .map(|(_, code)| code.as_str())
+            .or(self.file.as_deref())
+    }
+}
+
+/// Nerd Font icon by type
+fn icon(name: &str, directory: bool) -> char {
+    if directory {
+        return '\u{f07b}';
+    }
+    let extension = name.rsplit_once('.').map(|(_, extension)| extension.to_ascii_lowercase()).unwrap_or_default();
+    match extension.as_str() {
+        "tar" | "tgz" | "gz" | "bz2" | "xz" | "zst" | "zip" | "7z" | "rar" | "jar" => '\u{f410}',
+        "jpg" | "jpeg" | "png" | "gif" | "webp" | "svg" | "tif" | "tiff" | "bmp" => '\u{f1c5}',
+        "mp4" | "mkv" | "mov" | "webm" | "avi" => '\u{f1c8}',
+        "mp3" | "flac" | "ogg" | "wav" | "m4a" => '\u{f1c7}',
+        "pdf" => '\u{f1c1}',
+        "txt" | "md" | "log" | "csv" | "json" | "yaml" | "yml" | "toml" => '\u{f15c}',
+        _ => '\u{f15b}',
+    }
+}
+
+impl OptionsColor {
+    fn palette(&self) -> Option<&Palette> {
+        self.palette.get_or_init(|| {
+            let enabled = match self.color {
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+                ColorChoice::Auto => std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+            };
+            enabled.then(|| Palette::parse(&std::env::var("LS_COLORS").unwrap_or_else(|_| DEFAULT_LS_COLORS.to_owned())))
+        }).as_ref()
+    }
+
+    /// `name` as printed, colored and with an icon if enabled. Both are chosen by the `key` it
+    /// names, as quoting or escaping would hide its extension
+    pub fn paint<'a>(&self, key: &str, name: Cow<'a, str>, directory: bool) -> Cow<'a, str> {
+        let Some(palette) = self.palette() else {
+            return name;
+        };
+        let icon = match self.icons {
+            true => format!("{} ", icon(key, directory)),
+            false => String::new(),
+        };
+        match palette.code(key, directory) {
+            Some(code) => format!("\x1b[{code}m{icon}{name}\x1b[0m").into(),
+            None if self.icons => format!("{icon}{name}").into(),
+            None => name,
+        }
+    }
+}
+
+#[test]
+fn test_ls_colors() {
+    let palette = Palette::parse("rs=0:di=01;34:*.gz=01;31:*.tar.gz=01;33:*README=04");
+    assert_eq!(palette.code("logs/", true), Some("01;34"));
+    assert_eq!(palette.code("a.GZ", false), Some("01;31"));
+    assert_eq!(palette.code("a.tar.gz", false), Some("01;33"));
+    assert_eq!(palette.code("docs/README", false), Some("04"));
+    assert_eq!(palette.code("a.txt", false), None);
+    assert_eq!(Palette::parse(DEFAULT_LS_COLORS).code("photo.JPG", false), Some("01;35"));
+
+    let always = OptionsColor { color: ColorChoice::Always, icons: true, palette: OnceLock::new() };
+    always.palette.set(Some(palette)).unwrap();
+    assert_eq!(always.paint("a.gz", "a.gz".into(), false), "\x1b[01;31m\u{f410} a.gz\x1b[0m");
+    assert_eq!(always.paint("a.txt", "a.txt".into(), false), "\u{f15c} a.txt");
+    // Quoted names are colored by the key they name
+    assert_eq!(always.paint("a b.gz", "'a b.gz'".into(), false), "\x1b[01;31m\u{f410} 'a b.gz'\x1b[0m");
+    let never = OptionsColor { color: ColorChoice::Never, icons: true, palette: OnceLock::new() };
+    assert_eq!(never.paint("a.gz", "a.gz".into(), false), "a.gz");
+}
@@ -0,0 +1,292 @@
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::types::{ObjectCannedAcl, StorageClass};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use futures::{StreamExt, TryStreamExt};
+
+use super::append::{copy_source, plan_copy_ranges, MIN_PART_SIZE};
+use super::{ClassRules, Client, Error, OptionsAccessControl, OptionsConditional, Uri};
+
+/// Parts copied or uploaded at once
+const CONCAT_PART_CONCURRENCY: usize = 4;
+
+/// What concat applies to the object it writes, the content type coming from the first source
+#[derive(clap::Args, Debug, Clone)]
+pub struct OptionsConcat {
+    #[clap(flatten)]
+    pub access_control: OptionsAccessControl,
+    /// Canned access control list, e.g. private or public-read
+    #[clap(long, help_heading="Access Control", value_parser=PossibleValuesParser::new(ObjectCannedAcl::values()).map(|s| ObjectCannedAcl::from(s.as_str())))]
+    pub canned_acl: Option<ObjectCannedAcl>,
+    /// Storage Class
+    #[clap(long, value_parser=PossibleValuesParser::new(StorageClass::values()).map(|s| StorageClass::from(s.as_str())))]
+    pub class: Option<StorageClass>,
+    /// Storage class from the first rule matching the joined object, as for upload
+    #[clap(long, value_name="RULES", conflicts_with="class")]
+    pub class_rules: Option<ClassRules>,
+    #[clap(flatten)]
+    pub conditional: OptionsConditional,
+}
+
+impl OptionsConcat {
+    fn class_for(&self, to: &Uri, size: u64) -> Option<StorageClass> {
+        match &self.class_rules {
+            Some(rules) => rules.class_for(std::path::Path::new(to.key.as_str()), size).cloned(),
+            None => self.class.clone(),
+        }
+    }
+}
+
+/// An object to be joined, with the ETag its ranges are read at
+#[derive(Debug)]
+struct Source {
+    uri: Uri,
+    size: u64,
+    etag: String,
+}
+
+/// One part of the joined object
+#[derive(Debug, PartialEq)]
+enum Part {
+    /// UploadPartCopy of an inclusive byte range of a source, by index
+    Copy(usize, u64, u64),
+    /// Inclusive byte ranges of sources too small to be parts of their own, downloaded and
+    /// uploaded together
+    Upload(Vec<(usize, u64, u64)>),
+}
+
+/// Parts joining sources of `sizes` bytes in order, copying server-side all but the ranges
+/// needed to bring short sources up to the minimum part size
+fn plan_parts(sizes: &[u64]) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut pending = Vec::new();
+    let mut pending_length = 0;
+    for (index, &size) in sizes.iter().enumerate() {
+        let mut offset = 0;
+        if pending_length > 0 {
+            offset = (MIN_PART_SIZE - pending_length).min(size);
+            if offset > 0 {
+                pending.push((index, 0, offset - 1));
+                pending_length += offset;
+            }
+            if pending_length < MIN_PART_SIZE {
+                continue;
+            }
+            parts.push(Part::Upload(std::mem::take(&mut pending)));
+            pending_length = 0;
+        }
+        let (ranges, tail_start) = plan_copy_ranges(size - offset);
+        parts.extend(ranges.into_iter().map(|(start, end)| Part::Copy(index, offset + start, offset + end)));
+        if offset + tail_start < size {
+            pending.push((index, offset + tail_start, size - 1));
+            pending_length += size - offset - tail_start;
+        }
+    }
+    if !pending.is_empty() {
+        parts.push(Part::Upload(pending));
+    }
+    parts
+}
+
+impl Client {
+    /// Objects to join for each of `uris`, a prefix ending in '/' standing for the objects below
+    /// it in key order
+    async fn concat_sources(&self, uris: &[Uri]) -> Result<Vec<Source>, Error> {
+        let mut sources = Vec::new();
+        for uri in uris {
+            if !uri.key.is_explicitly_directory() && !uri.key.as_str().is_empty() {
+                let head = self.head(uri).await?;
+                sources.push(Source {
+                    uri: uri.clone(),
+                    size: head.content_length().unwrap_or(0) as u64,
+                    etag: head.e_tag().unwrap_or("*").to_owned(),
+                });
+                continue;
+            }
+            let objects = self.list_objects(uri).await?;
+            if objects.is_empty() {
+                return Err(Error::NoSuchKey(uri.clone()));
+            }
+            sources.extend(objects.into_iter().filter_map(|object| Some(Source {
+                uri: Uri::new(uri.bucket.clone(), super::Key::new(object.key?)),
+                size: object.size.unwrap_or(0) as u64,
+                etag: object.e_tag.unwrap_or_else(|| "*".into()),
+            })));
+        }
+        Ok(sources)
+    }
+
+    /// Download and join `ranges` of `sources`, failing if any source changed since listed
+    async fn concat_download(&self, sources: &[Source], ranges: &[(usize, u64, u64)]) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::new();
+        for &(index, start, end) in ranges {
+            let source = &sources[index];
+            let response = self.client.get_object()
+                .bucket(source.uri.bucket.clone())
+                .key(source.uri.key.to_string())
+                .range(format!("bytes={start}-{end}"))
+                .if_match(&source.etag)
+                .send()
+                .await
+                .map_err(|e| super::error_from_get(&source.uri, e))?;
+            data.extend_from_slice(&response.body.collect().await?.into_bytes());
+        }
+        Ok(data)
+    }
+
+    async fn concat_part(&self, sources: &[Source], to: &Uri, upload_id: &str, part_number: i32, part: &Part) -> Result<CompletedPart, Error> {
+        let etag = match part {
+            Part::Copy(index, start, end) => {
+                let source = &sources[*index];
+                let response = self.client.upload_part_copy()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .copy_source(copy_source(&source.uri))
+                    .copy_source_range(format!("bytes={start}-{end}"))
+                    .copy_source_if_match(&source.etag)
+                    .send()
+                    .await?;
+                response.copy_part_result().and_then(|r| r.e_tag()).map(str::to_owned)
+            },
+            Part::Upload(ranges) => {
+                let data = self.concat_download(sources, ranges).await?;
+                let response = self.client.upload_part()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(ByteStream::from(data))
+                    .send()
+                    .await?;
+                response.e_tag().map(str::to_owned)
+            },
+        };
+        Ok(CompletedPart::builder().part_number(part_number).set_e_tag(etag).build())
+    }
+
+    /// Join objects into a new object at `to`, copying server-side with UploadPartCopy. Only
+    /// objects or ranges under the 5MiB minimum part size are downloaded, to be uploaded together
+    pub async fn concat(&self, verbose: bool, options: &OptionsConcat, from: &[Uri], to: &Uri) -> Result<(), Error> {
+        let sources = self.concat_sources(from).await?;
+        let sizes: Vec<u64> = sources.iter().map(|source| source.size).collect();
+        let length: u64 = sizes.iter().sum();
+        let parts = plan_parts(&sizes);
+        let content_type = match sources.first() {
+            Some(first) => self.head(&first.uri).await?.content_type().map(str::to_owned),
+            None => None,
+        };
+        let class = options.class_for(to, length);
+        let if_match = options.conditional.if_match.as_deref().map(super::quoted_etag);
+        let extra_headers = options.conditional.extra_headers();
+
+        if let [] | [Part::Upload(_)] = parts.as_slice() {
+            if verbose {
+                eprintln!("🏁 joining {} object(s) into {to} [{length} bytes]", sources.len());
+            }
+            let ranges = match parts.first() {
+                Some(Part::Upload(ranges)) => ranges.as_slice(),
+                _ => &[],
+            };
+            let data = self.concat_download(&sources, ranges).await?;
+            self.client.put_object()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .set_content_type(content_type)
+                .set_acl(options.canned_acl.to_owned())
+                .set_grant_read(options.access_control.grant_read.to_owned())
+                .set_grant_full_control(options.access_control.grant_full.to_owned())
+                .set_grant_read_acp(options.access_control.grant_read_acp.to_owned())
+                .set_grant_write_acp(options.access_control.grant_write_acp.to_owned())
+                .set_storage_class(class)
+                .set_if_match(if_match)
+                .body(ByteStream::from(data))
+                .customize()
+                .mutate_request(move |request| {
+                    for (name, value) in &extra_headers {
+                        request.headers_mut().insert(*name, value.clone());
+                    }
+                })
+                .send()
+                .await?;
+            self.head_cache.invalidate(to);
+            return Ok(());
+        }
+
+        if verbose {
+            let copied = parts.iter().filter(|part| matches!(part, Part::Copy(..))).count();
+            eprintln!("🏁 joining {} object(s) into {to} [{length} bytes, {copied} of {} parts copied]", sources.len(), parts.len());
+        }
+        let upload = self.client.create_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .set_content_type(content_type)
+            .set_acl(options.canned_acl.to_owned())
+            .set_grant_read(options.access_control.grant_read.to_owned())
+            .set_grant_full_control(options.access_control.grant_full.to_owned())
+            .set_grant_read_acp(options.access_control.grant_read_acp.to_owned())
+            .set_grant_write_acp(options.access_control.grant_write_acp.to_owned())
+            .set_storage_class(class)
+            .send()
+            .await?;
+        let upload_id = upload.upload_id().expect("upload id in response").to_owned();
+        self.in_flight.add(to, &upload_id);
+
+        let joined = futures::stream::iter(parts.iter().enumerate())
+            .map(|(index, part)| self.concat_part(&sources, to, &upload_id, index as i32 + 1, part))
+            .buffered(CONCAT_PART_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await;
+        let completed = match joined {
+            Ok(completed) => completed,
+            Err(e) => {
+                let _ = self.client.abort_multipart_upload()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .upload_id(&upload_id)
+                    .send()
+                    .await;
+                self.in_flight.remove(&upload_id);
+                return Err(e);
+            },
+        };
+        let result = self.client.complete_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .upload_id(&upload_id)
+            .set_if_match(if_match)
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(completed)).build())
+            .customize()
+            .mutate_request(move |request| {
+                for (name, value) in &extra_headers {
+                    request.headers_mut().insert(*name, value.clone());
+                }
+            })
+            .send()
+            .await;
+        self.in_flight.remove(&upload_id);
+        self.head_cache.invalidate(to);
+        result?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_plan_parts() {
+    const MIB: u64 = 1024 * 1024;
+    assert_eq!(plan_parts(&[]), []);
+    assert_eq!(plan_parts(&[10, 0, 20]), [Part::Upload(vec![(0, 0, 9), (2, 0, 19)])]);
+    assert_eq!(plan_parts(&[6 * MIB, 8 * MIB]), [Part::Copy(0, 0, 6 * MIB - 1), Part::Copy(1, 0, 8 * MIB - 1)]);
+    // A short source is topped up from the next, the rest of which is still copied
+    assert_eq!(plan_parts(&[MIB, 10 * MIB]), [
+        Part::Upload(vec![(0, 0, MIB - 1), (1, 0, 4 * MIB - 1)]),
+        Part::Copy(1, 4 * MIB, 10 * MIB - 1),
+    ]);
+    // Too little left to copy goes with the next source
+    assert_eq!(plan_parts(&[MIB, 7 * MIB, 20]), [
+        Part::Upload(vec![(0, 0, MIB - 1), (1, 0, 4 * MIB - 1)]),
+        Part::Upload(vec![(1, 4 * MIB, 7 * MIB - 1), (2, 0, 19)]),
+    ]);
+    assert_eq!(plan_parts(&[6 * MIB, MIB]), [Part::Copy(0, 0, 6 * MIB - 1), Part::Upload(vec![(1, 0, MIB - 1)])]);
+}
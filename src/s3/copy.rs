@@ -0,0 +1,364 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use futures::StreamExt;
+
+use super::append::MAX_COPY_PART_SIZE;
+use super::{checksum, Client, CustomerKey, Error, Key, OptionsUpload, Uri};
+use crate::cli;
+
+/// Objects moved at once within a prefix
+const MOVE_CONCURRENCY: usize = 16;
+/// Part size when copying objects too large for a single CopyObject
+const COPY_PART_SIZE: u64 = 1024 * 1024 * 1024;
+/// Parts copied at once for a single object
+const COPY_PART_CONCURRENCY: usize = 4;
+
+/// Inclusive byte ranges covering an object of `size` bytes
+fn copy_ranges(size: u64, part_size: u64) -> impl Iterator<Item = (u64, u64)> {
+    (0..size).step_by(part_size as usize)
+        .map(move |start| (start, (start + part_size).min(size) - 1))
+}
+
+/// Destination of a single object, `to` naming a directory when it has no filename
+pub(super) fn object_destination(from: &Uri, to: &Uri) -> Result<Uri, Error> {
+    let mut key = to.key.clone();
+    if to.filename().is_none() {
+        key.push(from.filename().ok_or(Error::NoFilename)?);
+    }
+    Ok(Uri::new(to.bucket.clone(), key))
+}
+
+/// Expires of an existing object, sent back as received
+fn expires(head: &aws_sdk_s3::operation::head_object::HeadObjectOutput) -> Option<aws_sdk_s3::primitives::DateTime> {
+    aws_sdk_s3::primitives::DateTime::from_str(head.expires_string()?, aws_sdk_s3::primitives::DateTimeFormat::HttpDate).ok()
+}
+
+/// Destination prefix for the objects below `from`: inside `to` if it ends with '/', like
+/// `mv dir existing/`, otherwise `to` itself replaces `from`
+fn prefix_destination(from: &Uri, to: &Uri) -> Uri {
+    let mut key = to.key.clone();
+    if to.key.is_explicitly_directory() {
+        if let Some(name) = from.key.trim_end_matches('/').rsplit('/').next().filter(|name| !name.is_empty()) {
+            key.push(name);
+        }
+    }
+    Uri::new(to.bucket.clone(), key.to_explicit_directory())
+}
+
+impl Client {
+    /// Server-side copy of a single object, returning its destination. Upload options apply
+    /// to the copy, otherwise the destination gets the bucket defaults. --metadata and --tag
+    /// replace the source's metadata and tags
+    pub async fn copy_object(&self, verbose: bool, options_upload: Option<&OptionsUpload>, from: &Uri, to: &Uri) -> Result<Uri, Error> {
+        let destination = object_destination(from, to)?;
+        let head = self.head(from).await?;
+        let size = head.content_length().unwrap_or(0) as u64;
+        if size > MAX_COPY_PART_SIZE {
+            if verbose {
+                println!("🏁 copying {from} to {destination} [{size} bytes, {} parts]", size.div_ceil(COPY_PART_SIZE));
+            }
+            self.copy_multipart(options_upload, from, &head, &destination).await?;
+            self.head_cache.invalidate(&destination);
+            return Ok(destination);
+        }
+        if verbose {
+            println!("🏁 copying {from} to {destination}");
+        }
+        let mut request = self.client.copy_object()
+            .copy_source(super::append::copy_source(from))
+            .copy_source_if_match(head.e_tag().unwrap_or("*"))
+            .bucket(destination.bucket.clone())
+            .key(destination.key.to_string())
+            .set_acl(options_upload.and_then(|o| o.canned_acl.to_owned()))
+            .set_grant_read(options_upload.and_then(|o| o.access_control.grant_read.to_owned()))
+            .set_grant_full_control(options_upload.and_then(|o| o.access_control.grant_full.to_owned()))
+            .set_grant_read_acp(options_upload.and_then(|o| o.access_control.grant_read_acp.to_owned()))
+            .set_grant_write_acp(options_upload.and_then(|o| o.access_control.grant_write_acp.to_owned()))
+            .set_storage_class(options_upload.and_then(|o| o.class_for(Path::new(destination.key.as_str()), size)))
+            .set_checksum_algorithm(options_upload.and_then(|o| o.checksum_algorithm.clone()))
+            .set_if_match(options_upload.and_then(|o| o.conditional.if_match.as_deref()).map(super::quoted_etag))
+            .set_sse_customer_algorithm(options_upload.and_then(|o| o.sse_c_key.as_ref()).map(CustomerKey::algorithm))
+            .set_sse_customer_key(options_upload.and_then(|o| o.sse_c_key.as_ref()).map(CustomerKey::key))
+            .set_sse_customer_key_md5(options_upload.and_then(|o| o.sse_c_key.as_ref()).map(CustomerKey::key_md5));
+        let metadata = options_upload.and_then(OptionsUpload::user_metadata);
+        if let Some(tagging) = options_upload.and_then(OptionsUpload::tagging) {
+            request = request
+                .tagging_directive(aws_sdk_s3::types::TaggingDirective::Replace)
+                .tagging(tagging);
+        }
+        // Headers can only be changed by replacing all of them, so carry over the rest
+        if let Some(headers) = options_upload.map(|o| &o.headers).filter(|headers| headers.is_enabled() || metadata.is_some()) {
+            request = request
+                .metadata_directive(aws_sdk_s3::types::MetadataDirective::Replace)
+                .set_content_type(head.content_type().map(str::to_owned))
+                .set_content_language(head.content_language().map(str::to_owned))
+                .set_content_encoding(headers.content_encoding.clone().or(head.content_encoding().map(str::to_owned)))
+                .set_content_disposition(headers.content_disposition.clone().or(head.content_disposition().map(str::to_owned)))
+                .set_cache_control(headers.cache_control.clone().or(head.cache_control().map(str::to_owned)))
+                .set_expires(headers.expires.or_else(|| expires(&head)))
+                .set_metadata(metadata.or_else(|| head.metadata().cloned()));
+        }
+        let extra_headers = options_upload.map(|o| o.conditional.extra_headers()).unwrap_or_default();
+        request
+            .customize()
+            .mutate_request(move |request| {
+                for (name, value) in &extra_headers {
+                    request.headers_mut().insert(*name, value.clone());
+                }
+            })
+            .send()
+            .await
+            .map_err(|e| match e.code() {
+                Some("NoSuchKey") => Error::NoSuchKey(from.clone()),
+                _ => e.into(),
+            })?;
+        self.head_cache.invalidate(&destination);
+        Ok(destination)
+    }
+
+    /// UploadPartCopy of objects above the CopyObject limit, carrying over the metadata CopyObject would
+    async fn copy_multipart(&self, options_upload: Option<&OptionsUpload>, from: &Uri, head: &aws_sdk_s3::operation::head_object::HeadObjectOutput, to: &Uri) -> Result<(), Error> {
+        let size = head.content_length().unwrap_or(0) as u64;
+        let etag = head.e_tag().unwrap_or("*");
+        let headers = options_upload.map(|o| &o.headers);
+        let algorithm = options_upload.and_then(|o| o.checksum_algorithm.as_ref());
+        let upload = self.client.create_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .set_content_type(head.content_type().map(str::to_owned))
+            .set_content_language(head.content_language().map(str::to_owned))
+            .set_content_encoding(headers.and_then(|h| h.content_encoding.clone()).or(head.content_encoding().map(str::to_owned)))
+            .set_content_disposition(headers.and_then(|h| h.content_disposition.clone()).or(head.content_disposition().map(str::to_owned)))
+            .set_cache_control(headers.and_then(|h| h.cache_control.clone()).or(head.cache_control().map(str::to_owned)))
+            .set_expires(headers.and_then(|h| h.expires).or_else(|| expires(head)))
+            .set_metadata(options_upload.and_then(OptionsUpload::user_metadata).or_else(|| head.metadata().cloned()))
+            .set_tagging(options_upload.and_then(OptionsUpload::tagging))
+            .set_checksum_algorithm(algorithm.cloned())
+            .set_acl(options_upload.and_then(|o| o.canned_acl.to_owned()))
+            .set_grant_read(options_upload.and_then(|o| o.access_control.grant_read.to_owned()))
+            .set_grant_full_control(options_upload.and_then(|o| o.access_control.grant_full.to_owned()))
+            .set_grant_read_acp(options_upload.and_then(|o| o.access_control.grant_read_acp.to_owned()))
+            .set_grant_write_acp(options_upload.and_then(|o| o.access_control.grant_write_acp.to_owned()))
+            .set_storage_class(options_upload.and_then(|o| o.class_for(Path::new(to.key.as_str()), size)))
+            .set_sse_customer_algorithm(options_upload.and_then(|o| o.sse_c_key.as_ref()).map(CustomerKey::algorithm))
+            .set_sse_customer_key(options_upload.and_then(|o| o.sse_c_key.as_ref()).map(CustomerKey::key))
+            .set_sse_customer_key_md5(options_upload.and_then(|o| o.sse_c_key.as_ref()).map(CustomerKey::key_md5))
+            .send()
+            .await?;
+        let upload_id = upload.upload_id().expect("upload id in response").to_owned();
+        self.in_flight.add(to, &upload_id);
+        let result = self.copy_parts(options_upload, from, etag, size, to, &upload_id).await;
+        self.in_flight.remove(&upload_id);
+        result
+    }
+
+    /// Copy the parts of `from` into the multipart upload `upload_id` and complete it, aborting it on failure
+    async fn copy_parts(&self, options_upload: Option<&OptionsUpload>, from: &Uri, etag: &str, size: u64, to: &Uri, upload_id: &str) -> Result<(), Error> {
+        let algorithm = options_upload.and_then(|o| o.checksum_algorithm.as_ref());
+        let customer_key = options_upload.and_then(|o| o.sse_c_key.as_ref());
+
+        let copied = futures::stream::iter(copy_ranges(size, COPY_PART_SIZE).enumerate())
+            .map(|(index, (start, end))| {
+                async move {
+                    let part_number = index as i32 + 1;
+                    let response = self.client.upload_part_copy()
+                        .bucket(to.bucket.clone())
+                        .key(to.key.to_string())
+                        .upload_id(upload_id)
+                        .part_number(part_number)
+                        .copy_source(super::append::copy_source(from))
+                        .copy_source_range(format!("bytes={start}-{end}"))
+                        .copy_source_if_match(etag)
+                        .set_sse_customer_algorithm(customer_key.map(CustomerKey::algorithm))
+                        .set_sse_customer_key(customer_key.map(CustomerKey::key))
+                        .set_sse_customer_key_md5(customer_key.map(CustomerKey::key_md5))
+                        .send()
+                        .await?;
+                    let result = response.copy_part_result();
+                    let part = CompletedPart::builder()
+                        .part_number(part_number)
+                        .set_e_tag(result.and_then(|r| r.e_tag()).map(str::to_owned));
+                    let part = match algorithm {
+                        Some(algorithm) => checksum::with_checksum(part, algorithm, result.and_then(|r| checksum::of_copy(r, algorithm))),
+                        None => part,
+                    };
+                    Ok::<_, Error>(part.build())
+                }
+            })
+            .buffered(COPY_PART_CONCURRENCY)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>();
+        let parts = match copied {
+            Ok(parts) => parts,
+            Err(e) => {
+                let _ = self.client.abort_multipart_upload()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .upload_id(upload_id)
+                    .send()
+                    .await;
+                return Err(e);
+            },
+        };
+        let extra_headers = options_upload.map(|o| o.conditional.extra_headers()).unwrap_or_default();
+        let completed = self.client.complete_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .upload_id(upload_id)
+            .set_if_match(options_upload.and_then(|o| o.conditional.if_match.as_deref()).map(super::quoted_etag))
+            .set_sse_customer_algorithm(customer_key.map(CustomerKey::algorithm))
+            .set_sse_customer_key(customer_key.map(CustomerKey::key))
+            .set_sse_customer_key_md5(customer_key.map(CustomerKey::key_md5))
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .customize()
+            .mutate_request(move |request| {
+                for (name, value) in &extra_headers {
+                    request.headers_mut().insert(*name, value.clone());
+                }
+            })
+            .send()
+            .await;
+        if let Err(e) = completed {
+            let _ = self.client.abort_multipart_upload()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .upload_id(upload_id)
+                .send()
+                .await;
+            return Err(e.into());
+        }
+        Ok(())
+    }
+
+    /// Copy an object server-side then remove the original, returning its destination
+    pub async fn move_object(&self, verbose: bool, from: &Uri, to: &Uri) -> Result<Uri, Error> {
+        let destination = self.copy_object(verbose, None, from, to).await?;
+        self.client.delete_object()
+            .bucket(from.bucket.clone())
+            .key(from.key.to_string())
+            .send()
+            .await?;
+        self.head_cache.invalidate(from);
+        Ok(destination)
+    }
+
+    /// Move every object below the prefix `from`, returning the number that failed
+    pub async fn move_recursive(&self, verbose: bool, from: &Uri, to: &Uri, continue_on_error: bool, rewrite: &super::OptionsRewrite) -> Result<u32, Error> {
+        self.copy_prefix(verbose, None, from, to, true, MOVE_CONCURRENCY, continue_on_error, &Default::default(), rewrite).await
+    }
+
+    /// Copy every object below the prefix `from`, returning the number that failed
+    #[allow(clippy::too_many_arguments)]
+    pub async fn copy_recursive(&self, verbose: bool, options_upload: &OptionsUpload, from: &Uri, to: &Uri, concurrency: usize, continue_on_error: bool, filter: &super::OptionsFilter, rewrite: &super::OptionsRewrite) -> Result<u32, Error> {
+        self.copy_prefix(verbose, Some(options_upload), from, to, false, concurrency, continue_on_error, filter, rewrite).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn copy_prefix(&self, verbose: bool, options_upload: Option<&OptionsUpload>, from: &Uri, to: &Uri, remove_source: bool, concurrency: usize, continue_on_error: bool, filter: &super::OptionsFilter, rewrite: &super::OptionsRewrite) -> Result<u32, Error> {
+        let root = Uri::new(from.bucket.clone(), from.key.to_explicit_directory());
+        let destination = prefix_destination(from, to);
+        let mut objects = self.list_objects(&root).await?;
+        if objects.is_empty() {
+            return Err(Error::NoSuchKey(root));
+        }
+        objects.retain(|object| object.key().is_some_and(|key| filter.matches(&key[root.key.len()..])));
+        let (action, acting) = match remove_source {
+            true => ("move", "moving"),
+            false => ("copy", "copying"),
+        };
+        if verbose {
+            println!("🏁 {acting} {} object(s) from {root} to {destination}", objects.len());
+        }
+
+        let sources: Vec<Uri> = objects.iter()
+            .filter_map(|object| object.key())
+            .map(|key| Uri::new(root.bucket.clone(), Key::new(key.to_owned())))
+            .collect();
+        let pairs: Vec<(Uri, Uri)> = sources.iter()
+            .map(|from| {
+                let mut to_key = destination.key.clone();
+                to_key.push(&from.key.as_str()[root.key.len()..]);
+                (from.clone(), Uri::new(destination.bucket.clone(), rewrite.key(&to_key)))
+            })
+            // Keys a rewrite leaves in place are already where they belong
+            .filter(|(from, to)| from.bucket != to.bucket || from.key.as_str() != to.key.as_str())
+            .collect();
+        if let Some(collision) = collision(&sources, &pairs) {
+            return Err(Error::DestinationCollision(collision.clone()));
+        }
+        let mut copies = futures::stream::iter(pairs)
+            .map(|(from, to)| {
+                async move {
+                    let result = match remove_source {
+                        true => self.move_object(verbose, &from, &to).await,
+                        false => self.copy_object(verbose, options_upload, &from, &to).await,
+                    };
+                    result.map_err(|e| (from, e))
+                }
+            })
+            .buffer_unordered(concurrency);
+        let mut error_count = 0;
+        while let Some(result) = copies.next().await {
+            if let Err((from, e)) = result {
+                cli::println_error(format_args!("failed to {action} {from}: {e}"));
+                error_count += 1;
+                if !continue_on_error {
+                    break;
+                }
+            }
+        }
+        Ok(error_count)
+    }
+}
+
+/// A destination written by more than one of `pairs`, or that's one of `sources`, which would be
+/// overwritten before or after being copied itself
+fn collision<'a>(sources: &[Uri], pairs: &'a [(Uri, Uri)]) -> Option<&'a Uri> {
+    let sources: HashSet<(&str, &str)> = sources.iter().map(|uri| (uri.bucket.as_str(), uri.key.as_str())).collect();
+    let mut destinations = HashSet::new();
+    pairs.iter()
+        .map(|(_, to)| to)
+        .find(|to| {
+            let destination = (to.bucket.as_str(), to.key.as_str());
+            sources.contains(&destination) || !destinations.insert(destination)
+        })
+}
+
+#[test]
+fn test_copy_ranges() {
+    assert_eq!(copy_ranges(250, 100).collect::<Vec<_>>(), [(0, 99), (100, 199), (200, 249)]);
+    assert_eq!(copy_ranges(200, 100).collect::<Vec<_>>(), [(0, 99), (100, 199)]);
+    assert_eq!(copy_ranges(0, 100).count(), 0);
+}
+
+#[test]
+fn test_destinations() {
+    let uri = |s: &str| -> Uri { s.parse().unwrap() };
+    let object = |from, to| object_destination(&uri(from), &uri(to)).unwrap().to_string();
+    assert_eq!(object("s3://one/dir/file", "s3://two/other/"), "s3://two/other/file");
+    assert_eq!(object("s3://one/dir/file", "s3://two/renamed"), "s3://two/renamed");
+    assert!(object_destination(&uri("s3://one/dir/"), &uri("s3://two/")).is_err());
+
+    let prefix = |from, to| prefix_destination(&uri(from), &uri(to)).to_string();
+    assert_eq!(prefix("s3://one/dir", "s3://one/into/"), "s3://one/into/dir/");
+    assert_eq!(prefix("s3://one/dir/", "s3://one/into/"), "s3://one/into/dir/");
+    assert_eq!(prefix("s3://one/dir/", "s3://one/renamed"), "s3://one/renamed/");
+    assert_eq!(prefix("s3://one/", "s3://two/"), "s3://two/");
+}
+
+#[test]
+fn test_collision() {
+    let uri = |s: &str| -> Uri { s.parse().unwrap() };
+    let sources = [uri("s3://bkt/d/A"), uri("s3://bkt/d/a"), uri("s3://bkt/d/c")];
+    let pairs = |pairs: &[(&str, &str)]| pairs.iter().map(|(from, to)| (uri(from), uri(to))).collect::<Vec<_>>();
+    let collides = |pairs: &[(Uri, Uri)]| collision(&sources, pairs).map(|uri| uri.to_string());
+    assert_eq!(collides(&pairs(&[("s3://bkt/d/A", "s3://bkt/e/a"), ("s3://bkt/d/a", "s3://bkt/e/b")])), None);
+    assert_eq!(collides(&pairs(&[("s3://bkt/d/A", "s3://bkt/e/a"), ("s3://bkt/d/a", "s3://bkt/e/a")])), Some("s3://bkt/e/a".into()));
+    assert_eq!(collides(&pairs(&[("s3://bkt/d/A", "s3://bkt/d/a")])), Some("s3://bkt/d/a".into()));
+    assert_eq!(collides(&pairs(&[("s3://bkt/d/A", "s3://other/d/a")])), None);
+}
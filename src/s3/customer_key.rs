@@ -0,0 +1,49 @@
+use aws_smithy_types::base64;
+use md5::Digest;
+
+/// A key given by the customer to encrypt objects with (SSE-C), of which S3 only keeps a hash
+#[derive(Clone, PartialEq)]
+pub struct CustomerKey {
+    /// Base64 of the 256-bit key
+    key: String,
+    /// Base64 of the key's MD5, for S3 to check it arrived intact
+    key_md5: String,
+}
+
+/// Never printed, not even in debug output
+impl std::fmt::Debug for CustomerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomerKey(..)")
+    }
+}
+
+/// A 256-bit key, base64 encoded
+pub fn parse_customer_key(s: &str) -> Result<CustomerKey, String> {
+    let key = base64::decode(s.trim()).map_err(|_| "expected a base64 encoded key".to_owned())?;
+    if key.len() != 32 {
+        return Err(format!("expected a 256-bit key, not {} bits", key.len() * 8));
+    }
+    Ok(CustomerKey { key: base64::encode(&key), key_md5: base64::encode(md5::Md5::digest(&key)) })
+}
+
+impl CustomerKey {
+    pub fn algorithm(&self) -> String {
+        "AES256".into()
+    }
+    pub fn key(&self) -> String {
+        self.key.clone()
+    }
+    pub fn key_md5(&self) -> String {
+        self.key_md5.clone()
+    }
+}
+
+#[test]
+fn test_parse_customer_key() {
+    let key = parse_customer_key("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=\n").unwrap();
+    assert_eq!(key.key(), "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=");
+    assert_eq!(key.key_md5(), "tP/LI3N87DFaSk0aoqYgzg==");
+    assert_eq!(format!("{key:?}"), "CustomerKey(..)");
+    assert!(parse_customer_key("AAECAwQ=").unwrap_err().contains("40 bits"));
+    assert!(parse_customer_key("not base64!").is_err());
+}
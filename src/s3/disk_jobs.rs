@@ -0,0 +1,20 @@
+use std::sync::Arc;
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Bound on local file reads and writes in progress at once, shared by clones of a Client
+#[derive(Clone, Default)]
+pub struct DiskJobs(Option<Arc<Semaphore>>);
+
+impl DiskJobs {
+    pub fn new(jobs: Option<usize>) -> DiskJobs {
+        DiskJobs(jobs.map(|jobs| Arc::new(Semaphore::new(jobs))))
+    }
+    /// Held while reading or writing one block of a local file, None if unbounded
+    pub async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        match &self.0 {
+            Some(semaphore) => Some(semaphore.acquire().await.expect("semaphore never closed")),
+            None => None,
+        }
+    }
+}
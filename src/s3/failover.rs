@@ -0,0 +1,113 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use aws_sdk_s3::config::interceptors::{BeforeTransmitInterceptorContextMut, FinalizerInterceptorContextRef};
+use aws_sdk_s3::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_smithy_types::config_bag::{Storable, StoreReplace};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long a failed endpoint is avoided before reads are tried against it again
+const RECHECK_AFTER: Duration = Duration::from_secs(30);
+
+/// Routes reads to the first healthy of several equivalent endpoints, writes always to the first
+#[derive(Debug)]
+pub struct Failover {
+    /// Without trailing '/', the first is the one the SDK is configured with
+    endpoints: Vec<String>,
+    failed_at: Mutex<Vec<Option<Instant>>>,
+}
+
+/// Endpoint index used by the current attempt
+#[derive(Debug, Clone)]
+struct Attempt(usize);
+
+impl Storable for Attempt {
+    type Storer = StoreReplace<Self>;
+}
+
+impl Failover {
+    pub fn new(endpoints: &[http::uri::Uri]) -> Self {
+        Failover {
+            endpoints: endpoints.iter().map(|uri| uri.to_string().trim_end_matches('/').to_owned()).collect(),
+            failed_at: Mutex::new(vec![None; endpoints.len()]),
+        }
+    }
+
+    /// First endpoint not recently failed, otherwise the one failed longest ago
+    fn choose(&self) -> usize {
+        let failed_at = self.failed_at.lock().unwrap();
+        failed_at.iter()
+            .position(|failed| failed.map(|at| at.elapsed() >= RECHECK_AFTER).unwrap_or(true))
+            .or_else(|| (0..failed_at.len()).min_by_key(|i| failed_at[*i]))
+            .unwrap_or(0)
+    }
+
+    fn record(&self, index: usize, healthy: bool) {
+        let mut failed_at = self.failed_at.lock().unwrap();
+        match healthy {
+            true => failed_at[index] = None,
+            false => {
+                if failed_at[index].is_none() {
+                    eprintln!("⚠️: endpoint {} unavailable, failing over reads", self.endpoints[index]);
+                }
+                failed_at[index] = Some(Instant::now());
+            },
+        }
+    }
+}
+
+fn is_read(method: &str) -> bool {
+    matches!(method, "GET" | "HEAD")
+}
+
+impl Intercept for Failover {
+    fn name(&self) -> &'static str {
+        "Failover"
+    }
+
+    fn modify_before_signing(&self, context: &mut BeforeTransmitInterceptorContextMut<'_>, _runtime_components: &RuntimeComponents, cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        if !is_read(context.request().method()) {
+            return Ok(());
+        }
+        let index = self.choose();
+        cfg.interceptor_state().store_put(Attempt(index));
+        if index == 0 {
+            return Ok(());
+        }
+        let Some(path) = context.request().uri().strip_prefix(self.endpoints[0].as_str()) else {
+            return Ok(());
+        };
+        let uri = format!("{}{path}", self.endpoints[index]);
+        context.request_mut().set_uri(uri)?;
+        Ok(())
+    }
+
+    fn read_after_attempt(&self, context: &FinalizerInterceptorContextRef<'_>, _runtime_components: &RuntimeComponents, cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        let Some(Attempt(index)) = cfg.load::<Attempt>().cloned() else {
+            return Ok(());
+        };
+        // Gateways report unreachable backends as 502-504
+        let healthy = match (context.response(), context.output_or_error()) {
+            (Some(response), _) => !matches!(response.status().as_u16(), 502..=504),
+            (None, Some(Err(e))) if e.is_connector_error() || e.is_timeout_error() => false,
+            (None, _) => return Ok(()),
+        };
+        self.record(index, healthy);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_choose() {
+    let endpoints: Vec<http::uri::Uri> = vec!["http://a:9000".parse().unwrap(), "http://b:9000/".parse().unwrap()];
+    let failover = Failover::new(&endpoints);
+    assert_eq!(failover.endpoints, ["http://a:9000", "http://b:9000"]);
+    assert_eq!(failover.choose(), 0);
+    failover.record(0, false);
+    assert_eq!(failover.choose(), 1);
+    failover.record(1, false);
+    assert_eq!(failover.choose(), 0);
+    failover.record(0, true);
+    assert_eq!(failover.choose(), 0);
+}
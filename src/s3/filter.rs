@@ -0,0 +1,199 @@
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use clap::{Arg, ArgAction, ArgMatches, Args, Command, FromArgMatches};
+
+/// Repeatable --exclude and --include patterns, applied in the order given like aws-cli's, later
+/// ones taking precedence. Patterns are matched against paths relative to what's being transferred
+/// or listed, '*' matching any characters including '/' and '?' any one character
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OptionsFilter {
+    /// Patterns in command line order, and whether each includes
+    rules: Vec<(bool, String)>,
+}
+
+/// fnmatch style match without special treatment of '/'. On a mismatch only the last '*' is
+/// retried one character further along, taking at most pattern length times path length steps
+fn pattern_matches(pattern: &[u8], path: &[u8]) -> bool {
+    let (mut p, mut c) = (0, 0);
+    // Position after the last '*' and the path position it's currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while c < path.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, c));
+                p += 1;
+            },
+            Some(&expected) if expected == b'?' || expected == path[c] => {
+                p += 1;
+                c += 1;
+            },
+            _ => match star {
+                Some((after_star, matched)) => {
+                    star = Some((after_star, matched + 1));
+                    p = after_star;
+                    c = matched + 1;
+                },
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+impl OptionsFilter {
+    /// Whether `relative_path` is still included after every pattern
+    pub fn matches(&self, relative_path: &str) -> bool {
+        self.rules.iter()
+            .rev()
+            .find(|(_, pattern)| pattern_matches(pattern.as_bytes(), relative_path.as_bytes()))
+            .map(|(include, _)| *include)
+            .unwrap_or(true)
+    }
+}
+
+impl FromArgMatches for OptionsFilter {
+    fn from_arg_matches(matches: &ArgMatches) -> Result<Self, clap::Error> {
+        let mut indexed = Vec::new();
+        for (id, include) in [("exclude", false), ("include", true)] {
+            if let (Some(patterns), Some(indices)) = (matches.get_many::<String>(id), matches.indices_of(id)) {
+                indexed.extend(indices.zip(patterns).map(|(index, pattern)| (index, include, pattern.clone())));
+            }
+        }
+        indexed.sort_by_key(|(index, ..)| *index);
+        Ok(OptionsFilter { rules: indexed.into_iter().map(|(_, include, pattern)| (include, pattern)).collect() })
+    }
+    fn update_from_arg_matches(&mut self, matches: &ArgMatches) -> Result<(), clap::Error> {
+        *self = Self::from_arg_matches(matches)?;
+        Ok(())
+    }
+}
+
+impl Args for OptionsFilter {
+    fn augment_args(command: Command) -> Command {
+        command
+            .arg(Arg::new("exclude").long("exclude").value_name("PATTERN").action(ArgAction::Append)
+                .help("Skip files matching PATTERN, e.g. '*.tmp', unless a later --include matches"))
+            .arg(Arg::new("include").long("include").value_name("PATTERN").action(ArgAction::Append)
+                .help("Don't skip files matching PATTERN, even if an earlier --exclude matches"))
+    }
+    fn augment_args_for_update(command: Command) -> Command {
+        Self::augment_args(command)
+    }
+}
+
+/// Only objects within a size range, e.g. to find large or empty ones
+#[derive(clap::Args, Debug, Clone, Default, PartialEq)]
+pub struct OptionsSize {
+    /// Skip objects smaller than this, e.g. 10M
+    #[clap(long, value_name="SIZE", value_parser=crate::cli::parse_size)]
+    pub min_size: Option<u64>,
+    /// Skip objects larger than this, e.g. 0 for only empty objects
+    #[clap(long, value_name="SIZE", value_parser=crate::cli::parse_size)]
+    pub max_size: Option<u64>,
+}
+
+impl OptionsSize {
+    pub fn is_enabled(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+
+    /// Whether an object of `size` bytes is within the range
+    pub fn matches(&self, size: u64) -> bool {
+        self.min_size.is_none_or(|min| size >= min) && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+/// Only objects last modified within a time range, given as RFC 3339 timestamps or durations ago
+#[derive(clap::Args, Debug, Clone, Default, PartialEq)]
+pub struct OptionsAge {
+    /// Skip objects last modified before this, e.g. 2h, 7d or 2024-01-31T12:00:00Z
+    #[clap(long, value_name="AGE|TIME", value_parser=parse_time)]
+    pub newer_than: Option<DateTime>,
+    /// Skip objects last modified after this, e.g. 30d or 2024-01-31T12:00:00Z
+    #[clap(long, value_name="AGE|TIME", value_parser=parse_time)]
+    pub older_than: Option<DateTime>,
+}
+
+/// A duration before now, or an RFC 3339 timestamp
+fn parse_time(s: &str) -> Result<DateTime, String> {
+    if s.starts_with(|c: char| c.is_ascii_digit()) && !s.contains('-') {
+        let ago = crate::cli::parse_duration(s)?;
+        let time = std::time::SystemTime::now().checked_sub(ago).ok_or_else(|| format!("duration '{s}' too long"))?;
+        return Ok(DateTime::from(time));
+    }
+    DateTime::from_str(s, DateTimeFormat::DateTime)
+        .map_err(|e| format!("expected a duration like 7d or an RFC 3339 timestamp like 2024-01-31T12:00:00Z: {e}"))
+}
+
+impl OptionsAge {
+    pub fn is_enabled(&self) -> bool {
+        self.newer_than.is_some() || self.older_than.is_some()
+    }
+
+    /// Whether an object last modified at `last_modified` is within the range, never if unknown
+    pub fn matches(&self, last_modified: Option<&DateTime>) -> bool {
+        let Some(last_modified) = last_modified else {
+            return !self.is_enabled();
+        };
+        self.newer_than.is_none_or(|newer| last_modified.as_nanos() >= newer.as_nanos())
+            && self.older_than.is_none_or(|older| last_modified.as_nanos() <= older.as_nanos())
+    }
+}
+
+#[test]
+fn test_pattern_matches() {
+    assert!(pattern_matches(b"*.tmp", b"a/b/c.tmp"));
+    assert!(pattern_matches(b"build/*", b"build/x/y"));
+    assert!(!pattern_matches(b"build/*", b"src/build/x"));
+    assert!(pattern_matches(b"?.log", b"a.log"));
+    assert!(!pattern_matches(b"?.log", b"ab.log"));
+    assert!(pattern_matches(b"*", b""));
+    assert!(pattern_matches(b"a*b*c", b"aXbYbZc"));
+    assert!(!pattern_matches(b"a*b*c", b"aXbYbZ"));
+    assert!(pattern_matches(b"*a?", b"aaab"));
+    assert!(!pattern_matches(b"", b"a"));
+    let path = [b'a'; 100];
+    assert!(!pattern_matches(&[b"*a".repeat(30), b"b".to_vec()].concat(), &path));
+}
+
+#[test]
+fn test_filter_order() {
+    let filter = |args: &[&str]| {
+        let command = OptionsFilter::augment_args(Command::new("test"));
+        OptionsFilter::from_arg_matches(&command.get_matches_from(std::iter::once(&"test").chain(args))).unwrap()
+    };
+    let none = filter(&[]);
+    assert!(none.matches("anything"));
+
+    let exclude_then_include = filter(&["--exclude", "*", "--include", "*.txt"]);
+    assert!(exclude_then_include.matches("a/b.txt"));
+    assert!(!exclude_then_include.matches("a/b.tmp"));
+
+    let include_then_exclude = filter(&["--include", "*.txt", "--exclude", "*"]);
+    assert!(!include_then_exclude.matches("a/b.txt"));
+}
+
+#[test]
+fn test_size_range() {
+    assert!(OptionsSize::default().matches(0));
+    let large = OptionsSize { min_size: Some(crate::cli::parse_size("10M").unwrap()), max_size: None };
+    assert!(large.matches(10 << 20));
+    assert!(!large.matches((10 << 20) - 1));
+    let empty = OptionsSize { min_size: None, max_size: Some(0) };
+    assert!(empty.matches(0));
+    assert!(!empty.matches(1));
+}
+
+#[test]
+fn test_age_range() {
+    let at = |s: &str| DateTime::from_str(s, DateTimeFormat::DateTime).unwrap();
+    assert!(OptionsAge::default().matches(None));
+    let old = OptionsAge { newer_than: None, older_than: Some(parse_time("2024-01-31T12:00:00Z").unwrap()) };
+    assert!(old.matches(Some(&at("2024-01-01T00:00:00Z"))));
+    assert!(!old.matches(Some(&at("2024-02-01T00:00:00Z"))));
+    assert!(!old.matches(None));
+    let recent = OptionsAge { newer_than: Some(parse_time("2h").unwrap()), older_than: None };
+    assert!(recent.matches(Some(&DateTime::from(std::time::SystemTime::now()))));
+    assert!(!recent.matches(Some(&at("2024-01-01T00:00:00Z"))));
+    assert!(parse_time("7x").is_err());
+    assert!(parse_time("yesterday").is_err());
+}
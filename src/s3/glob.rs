@@ -2,26 +2,21 @@ use crate::s3::uri;
 
 use wax::Pattern;
 
-#[derive(clap::ValueEnum, Debug, Clone, PartialEq)]
+#[derive(clap::ValueEnum, Debug, Clone, PartialEq, Default)]
 pub enum GlobOption {
     Auto,
     On,
+    #[default]
     Off,
 }
 
-#[derive(clap::Args, Debug, Clone, PartialEq)]
+#[derive(clap::Args, Debug, Clone, PartialEq, Default)]
 pub struct Options {
     /// EXPERIMENTAL: Enable glob path specification (auto enables when glob characters found)
     #[clap(long, short='G', value_enum, default_value="off")]
     glob: GlobOption,
 }
 
-impl Options {
-    pub fn is_enabled(&self) -> bool {
-        !matches!(self.glob, GlobOption::Off)
-    }
-}
-
 #[derive(Debug)]
 pub struct Glob<'a> {
     prefix: uri::Key,
@@ -30,6 +25,7 @@ pub struct Glob<'a> {
 }
 
 impl<'a> Glob<'a> {
+    #[allow(clippy::len_zero)]
     pub fn new(key: &'a uri::Key, options: &Options) -> Option<Glob<'a>> {
         if options.glob == GlobOption::Off {
             return None;
@@ -72,16 +68,50 @@ impl<'a> Glob<'a> {
         let without_trailing_slash = without_prefix_slash.strip_suffix('/').unwrap_or(without_prefix_slash);
         self.glob.is_match(without_trailing_slash)
     }
+    /// Whether the glob matches `key` or any directory containing it, for recursive listings
+    pub fn matches_recursive(&self, key: &str) -> bool {
+        let relative = self.relative(key);
+        let relative = relative.strip_suffix('/').unwrap_or(relative);
+        let mut ancestors = relative.match_indices('/').map(|(i, _)| &relative[..i]);
+        self.glob.is_match(relative) || ancestors.any(|directory| self.glob.is_match(directory))
+    }
     pub fn has_recursive_wildcard(&self) -> bool {
         self.has_recursive_wildcard
     }
+    /// Part of a matching key below the prefix
+    pub fn relative<'k>(&self, key: &'k str) -> &'k str {
+        let without_prefix = key.strip_prefix(self.prefix.as_str()).unwrap_or(key);
+        without_prefix.strip_prefix('/').unwrap_or(without_prefix)
+    }
+}
 
+impl super::Client {
+    /// Objects in `bucket` matching `glob`, by listing everything below its prefix
+    pub async fn glob_objects(&self, bucket: &str, glob: &Glob<'_>) -> Result<Vec<(uri::Key, super::ObjectSummary)>, super::Error> {
+        let mut objects = Vec::new();
+        let mut continuation = None;
+        loop {
+            let response = self.ls_inner(bucket, glob.prefix(), None, continuation).await?;
+            for object in response.contents() {
+                let Some(key) = object.key().filter(|key| !key.ends_with('/') && glob.matches(key)) else {
+                    continue;
+                };
+                let summary = super::ObjectSummary { size: object.size().unwrap_or(0) as u64, last_modified: object.last_modified().cloned(), etag: object.e_tag().map(str::to_owned) };
+                objects.push((uri::Key::new(key.to_owned()), summary));
+            }
+            continuation = response.next_continuation_token().map(str::to_owned);
+            if continuation.is_none() {
+                return Ok(objects);
+            }
+        }
+    }
 }
 
 pub fn as_key_and_glob<'a>(key: &'a uri::Key, options: &Options) -> Option<Glob<'a>> {
     Glob::new(key, options)
 }
 
+#[allow(clippy::iter_nth_zero)]
 fn glob_has_resursive_wildcard(glob_str: &str) -> bool {
     let Some(index) = glob_str.find("**") else {
         return false
@@ -98,6 +128,7 @@ fn glob_has_resursive_wildcard(glob_str: &str) -> bool {
 }
 
 #[test]
+#[allow(clippy::bool_assert_comparison)]
 fn test_has_recusive_wildcard() {
     assert_eq!(glob_has_resursive_wildcard("test/**"), true);
     assert_eq!(glob_has_resursive_wildcard("test/**/*.txt"), true);
@@ -113,3 +144,28 @@ fn test_has_recusive_wildcard() {
     assert_eq!(glob_has_resursive_wildcard("test/*"), false);
 }
 
+
+#[test]
+fn test_glob_relative() {
+    let key = uri::Key::new("logs/2024-*/app-*.log".to_owned());
+    let glob = Glob::new(&key, &Options { glob: GlobOption::Auto }).unwrap();
+    assert_eq!(glob.prefix().as_str(), "logs/");
+    assert!(glob.matches("logs/2024-01/app-1.log"));
+    assert!(!glob.matches("logs/2024-01/db-1.log"));
+    assert_eq!(glob.relative("logs/2024-01/app-1.log"), "2024-01/app-1.log");
+}
+
+#[test]
+fn test_glob_matches_recursive() {
+    let key = uri::Key::new("data/202?-*".to_owned());
+    let glob = Glob::new(&key, &Options { glob: GlobOption::Auto }).unwrap();
+    assert!(glob.matches_recursive("data/2024-01"));
+    assert!(glob.matches_recursive("data/2024-01/"));
+    assert!(glob.matches_recursive("data/2024-01/deep/a.csv"));
+    assert!(!glob.matches_recursive("data/1999-01/a.csv"));
+
+    let key = uri::Key::new("**/*.csv".to_owned());
+    let glob = Glob::new(&key, &Options { glob: GlobOption::Auto }).unwrap();
+    assert!(glob.matches_recursive("a/b/c.csv"));
+    assert!(!glob.matches_recursive("a/b/c.log"));
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+
+use super::Uri;
+
+/// (bucket, key)
+type CacheKey = (String, String);
+
+/// HeadObject results for the lifetime of one invocation, shared between client clones
+///
+/// `None` entries record that the object was not found
+#[derive(Clone, Default)]
+pub struct HeadCache {
+    entries: Arc<Mutex<HashMap<CacheKey, Option<HeadObjectOutput>>>>,
+}
+
+fn cache_key(uri: &Uri) -> CacheKey {
+    (uri.bucket.clone(), uri.key.to_string())
+}
+
+impl HeadCache {
+    pub fn get(&self, uri: &Uri) -> Option<Option<HeadObjectOutput>> {
+        self.entries.lock().unwrap().get(&cache_key(uri)).cloned()
+    }
+    pub fn insert(&self, uri: &Uri, head: Option<HeadObjectOutput>) {
+        self.entries.lock().unwrap().insert(cache_key(uri), head);
+    }
+    /// Must be called after any write or delete of the object
+    pub fn invalidate(&self, uri: &Uri) {
+        self.entries.lock().unwrap().remove(&cache_key(uri));
+    }
+}
+
+#[test]
+fn test_invalidate() {
+    let cache = HeadCache::default();
+    let uri: Uri = "s3://bucket/dir/file".parse().unwrap();
+    let other: Uri = "s3://bucket/dir/other".parse().unwrap();
+    assert!(cache.get(&uri).is_none());
+    cache.insert(&uri, Some(HeadObjectOutput::builder().content_length(5).build()));
+    cache.insert(&other, None);
+    assert_eq!(cache.get(&uri).unwrap().unwrap().content_length(), Some(5));
+    assert!(cache.get(&other).unwrap().is_none());
+    cache.clone().invalidate(&uri);
+    assert!(cache.get(&uri).is_none());
+    assert!(cache.get(&other).is_some());
+}
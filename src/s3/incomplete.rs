@@ -0,0 +1,86 @@
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::types::MultipartUpload;
+
+use super::{Client, Error, Key, Uri};
+
+/// A multipart upload started and neither completed nor aborted, its parts stored and billed
+#[derive(Debug, Clone)]
+pub struct IncompleteUpload {
+    pub uri: Uri,
+    pub upload_id: String,
+    pub initiated: Option<SystemTime>,
+}
+
+impl IncompleteUpload {
+    fn from_sdk(bucket: &str, upload: &MultipartUpload) -> Option<IncompleteUpload> {
+        Some(IncompleteUpload {
+            uri: Uri::new(bucket.to_owned(), Key::new(upload.key()?.to_owned())),
+            upload_id: upload.upload_id()?.to_owned(),
+            initiated: upload.initiated().and_then(|t| SystemTime::try_from(*t).ok()),
+        })
+    }
+
+    /// Time since the upload was started, zero if unknown
+    pub fn age(&self, now: SystemTime) -> Duration {
+        self.initiated.and_then(|initiated| now.duration_since(initiated).ok()).unwrap_or_default()
+    }
+}
+
+impl Client {
+    /// Multipart uploads in progress to keys below `prefix`, oldest first
+    pub async fn incomplete_uploads(&self, prefix: &Uri) -> Result<Vec<IncompleteUpload>, Error> {
+        let mut uploads = Vec::new();
+        let mut markers: (Option<String>, Option<String>) = (None, None);
+        loop {
+            let response = self.client.list_multipart_uploads()
+                .bucket(prefix.bucket.clone())
+                .prefix(prefix.key.to_string())
+                .set_key_marker(markers.0.take())
+                .set_upload_id_marker(markers.1.take())
+                .send()
+                .await?;
+            uploads.extend(response.uploads().iter().filter_map(|upload| IncompleteUpload::from_sdk(&prefix.bucket, upload)));
+            match (response.is_truncated(), response.next_key_marker()) {
+                (Some(true), Some(key)) => markers = (Some(key.to_owned()), response.next_upload_id_marker().map(str::to_owned)),
+                _ => break,
+            }
+        }
+        uploads.sort_by_key(|upload| upload.initiated);
+        Ok(uploads)
+    }
+
+    /// Bytes stored so far by the parts of `upload`
+    pub async fn incomplete_upload_bytes(&self, upload: &IncompleteUpload) -> Result<u64, Error> {
+        let parts = self.uploaded_parts(&upload.uri, &upload.upload_id, None).await?;
+        Ok(parts.values().map(|(size, _, _)| *size as u64).sum())
+    }
+
+    /// Abort `upload`, deleting the parts it stored
+    pub async fn abort_incomplete_upload(&self, upload: &IncompleteUpload) -> Result<(), Error> {
+        self.client.abort_multipart_upload()
+            .bucket(upload.uri.bucket.clone())
+            .key(upload.uri.key.to_string())
+            .upload_id(&upload.upload_id)
+            .send()
+            .await?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_incomplete_upload() {
+    let upload = MultipartUpload::builder()
+        .key("dir/a.bin")
+        .upload_id("abc")
+        .initiated(aws_sdk_s3::primitives::DateTime::from_secs(1_700_000_000))
+        .build();
+    let upload = IncompleteUpload::from_sdk("bucket", &upload).unwrap();
+    assert_eq!(upload.uri.to_string(), "s3://bucket/dir/a.bin");
+    assert_eq!(upload.upload_id, "abc");
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + 8 * 24 * 60 * 60);
+    assert_eq!(upload.age(now), Duration::from_secs(8 * 24 * 60 * 60));
+    assert_eq!(upload.age(SystemTime::UNIX_EPOCH), Duration::ZERO);
+
+    assert!(IncompleteUpload::from_sdk("bucket", &MultipartUpload::builder().key("a").build()).is_none());
+}
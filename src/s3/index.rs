@@ -0,0 +1,181 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use aws_sdk_s3::primitives::ByteStream;
+
+use super::{Client, Error, Key, OptionsUpload, Uri};
+
+/// Name of generated directory listings
+pub const INDEX_FILENAME: &str = "index.html";
+/// Metadata marking an index as generated, so hand-written ones are never replaced
+const GENERATED_BY: (&str, &str) = ("generated-by", "sup3");
+
+/// Entries directly within one directory
+#[derive(Default, Debug, PartialEq)]
+struct Listing {
+    directories: BTreeSet<String>,
+    files: Vec<(String, i64)>,
+    has_index: bool,
+}
+
+/// Directories from `root` down to the one holding each of `keys`, with a trailing '/'
+fn directories_of<'a>(root: &str, keys: impl Iterator<Item = &'a str>) -> BTreeSet<String> {
+    let mut directories = BTreeSet::new();
+    for key in keys {
+        let Some(relative) = key.strip_prefix(root) else {
+            continue;
+        };
+        directories.insert(root.to_owned());
+        let Some((parents, _)) = relative.rsplit_once('/') else {
+            continue;
+        };
+        let mut directory = root.to_owned();
+        for component in parents.split('/') {
+            directory = format!("{directory}{component}/");
+            directories.insert(directory.clone());
+        }
+    }
+    directories
+}
+
+/// Listings of `root` and every directory below it, keyed by directory key with a trailing '/'
+fn listings<'a>(root: &str, objects: impl Iterator<Item = (&'a str, i64)>) -> BTreeMap<String, Listing> {
+    let mut listings = BTreeMap::from([(root.to_owned(), Listing::default())]);
+    for (key, size) in objects {
+        let Some(relative) = key.strip_prefix(root) else {
+            continue;
+        };
+        let mut directory = root.to_owned();
+        let mut components = relative.split('/').peekable();
+        while let Some(component) = components.next() {
+            if components.peek().is_some() {
+                listings.entry(directory.clone()).or_default().directories.insert(component.to_owned());
+                directory = format!("{directory}{component}/");
+                listings.entry(directory.clone()).or_default();
+                continue;
+            }
+            let listing = listings.entry(directory.clone()).or_default();
+            match component {
+                "" => {},
+                INDEX_FILENAME => listing.has_index = true,
+                name => listing.files.push((name.to_owned(), size)),
+            }
+        }
+    }
+    listings
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn index_html(directory: &str, listing: &Listing, has_parent: bool) -> String {
+    let title = escape_html(&format!("Index of /{directory}"));
+    let mut items = Vec::new();
+    if has_parent {
+        items.push("<li><a href=\"../\">../</a></li>".to_owned());
+    }
+    for name in &listing.directories {
+        items.push(format!("<li><a href=\"{}/\">{}/</a></li>", super::uri::encode_key_path(name), escape_html(name)));
+    }
+    for (name, size) in &listing.files {
+        items.push(format!("<li><a href=\"{}\">{}</a> {size}</li>", super::uri::encode_key_path(name), escape_html(name)));
+    }
+    format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<ul>\n{}\n</ul>\n</body>\n</html>\n", items.join("\n"))
+}
+
+impl Client {
+    async fn is_generated_index(&self, uri: &Uri) -> Result<bool, Error> {
+        let head = self.head(uri).await?;
+        Ok(head.metadata().and_then(|m| m.get(GENERATED_BY.0)).map(String::as_str) == Some(GENERATED_BY.1))
+    }
+
+    /// Upload an index.html listing each directory from `root`'s down to those holding the `written`
+    /// keys, skipping any written by hand. Returns the number uploaded
+    pub async fn put_indexes(&self, verbose: bool, options_upload: &OptionsUpload, root: &Uri, written: &[&Key]) -> Result<usize, Error> {
+        let root_key = match root.key.filename() {
+            Some(filename) => &root.key[..root.key.len() - filename.len()],
+            None => root.key.as_str(),
+        };
+        let changed = directories_of(root_key, written.iter().map(|key| key.as_str()));
+        if changed.is_empty() {
+            return Ok(0);
+        }
+        let mut objects = Vec::new();
+        let mut continuation = None;
+        loop {
+            let response = self.ls_inner(&root.bucket, &Key::new(root_key.to_owned()), None, continuation).await?;
+            objects.extend(response.contents().iter().filter_map(|object| Some((object.key()?.to_owned(), object.size().unwrap_or(0)))));
+            continuation = response.next_continuation_token().map(str::to_owned);
+            if continuation.is_none() {
+                break;
+            }
+        }
+
+        let mut uploaded = 0;
+        let listings = listings(root_key, objects.iter().map(|(key, size)| (key.as_str(), *size)));
+        for (directory, listing) in listings.into_iter().filter(|(directory, _)| changed.contains(directory)) {
+            let uri = Uri::new(root.bucket.clone(), Key::new(format!("{directory}{INDEX_FILENAME}")));
+            if listing.has_index && !self.is_generated_index(&uri).await? {
+                if verbose {
+                    println!("🏁 keeping {uri}, not generated by sup3");
+                }
+                continue;
+            }
+            // The root's parent wasn't generated, so isn't linked
+            let html = index_html(&directory, &listing, directory != root_key);
+            let length = html.len() as u64;
+            self.client.put_object()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .content_type("text/html; charset=utf-8")
+                .set_acl(options_upload.canned_acl.to_owned())
+                .set_grant_read(options_upload.access_control.grant_read.to_owned())
+                .set_grant_full_control(options_upload.access_control.grant_full.to_owned())
+                .set_grant_read_acp(options_upload.access_control.grant_read_acp.to_owned())
+                .set_grant_write_acp(options_upload.access_control.grant_write_acp.to_owned())
+                .set_storage_class(options_upload.class_for(std::path::Path::new(INDEX_FILENAME), length))
+                .set_cache_control(options_upload.cache_control_for(&uri.key))
+                .metadata(GENERATED_BY.0, GENERATED_BY.1)
+                .body(ByteStream::from(html.into_bytes()))
+                .send()
+                .await?;
+            self.head_cache.invalidate(&uri);
+            if verbose {
+                println!("🏁 generated {uri}");
+            }
+            uploaded += 1;
+        }
+        Ok(uploaded)
+    }
+}
+
+#[test]
+fn test_listings() {
+    let objects = [("site/index.html", 5), ("site/a.txt", 1), ("site/css/main.css", 2), ("site/img/", 0), ("other", 3)];
+    let listings = listings("site/", objects.into_iter());
+    assert_eq!(listings.keys().collect::<Vec<_>>(), ["site/", "site/css/", "site/img/"]);
+    let root = &listings["site/"];
+    assert!(root.has_index);
+    assert_eq!(root.directories.iter().collect::<Vec<_>>(), ["css", "img"]);
+    assert_eq!(root.files, [("a.txt".to_owned(), 1)]);
+    assert_eq!(listings["site/css/"].files, [("main.css".to_owned(), 2)]);
+    assert_eq!(listings["site/img/"], Listing::default());
+}
+
+#[test]
+fn test_directories_of() {
+    let directories = directories_of("site/", ["site/a.txt", "site/css/v2/main.css", "other/b.txt"].into_iter());
+    assert_eq!(directories.iter().collect::<Vec<_>>(), ["site/", "site/css/", "site/css/v2/"]);
+    assert!(directories_of("site/", ["other/b.txt"].into_iter()).is_empty());
+    assert_eq!(directories_of("", ["a/b"].into_iter()).iter().collect::<Vec<_>>(), ["", "a/"]);
+}
+
+#[test]
+fn test_index_html() {
+    let listing = Listing { directories: BTreeSet::from(["a b".to_owned()]), files: vec![("<x>.txt".to_owned(), 3)], has_index: false };
+    let html = index_html("site/", &listing, true);
+    assert!(html.contains("<title>Index of /site/</title>"));
+    assert!(html.contains("<li><a href=\"../\">../</a></li>"));
+    assert!(html.contains("<li><a href=\"a%20b/\">a b/</a></li>"));
+    assert!(html.contains("<li><a href=\"%3Cx%3E.txt\">&lt;x&gt;.txt</a> 3</li>"));
+}
@@ -0,0 +1,178 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use aws_sdk_s3::primitives::DateTimeFormat;
+use aws_sdk_s3::types::Object;
+use bytes::Bytes;
+
+use super::{Client, Error, Key, OptionsUpload, Uri};
+use crate::cli::{self, csv_field};
+
+/// Columns of an inventory, in the order written
+const COLUMNS: [&str; 5] = ["key", "size", "etag", "last_modified", "storage_class"];
+
+/// File format of an inventory
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Zstandard compressed CSV with a header row
+    Csv,
+    /// Apache Parquet, zstd compressed
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl Format {
+    fn filename(self) -> &'static str {
+        match self {
+            Format::Csv => "inventory.csv.zst",
+            #[cfg(feature = "parquet")]
+            Format::Parquet => "inventory.parquet",
+        }
+    }
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "application/zstd",
+            #[cfg(feature = "parquet")]
+            Format::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+/// Where the inventory of a run at `created` goes below `to`, a directory per run so scheduled
+/// runs keep their history, as S3 Inventory does
+fn inventory_key(to: &Key, created: &chrono::DateTime<chrono::Utc>, format: Format) -> Key {
+    Key::new(format!("{}{}/{}", to.to_explicit_directory(), created.format("%Y-%m-%dT%H-%M-%SZ"), format.filename()))
+}
+
+fn storage_class(object: &Object) -> &str {
+    object.storage_class().map(|class| class.as_str()).unwrap_or("STANDARD")
+}
+
+fn csv_row(object: &Object) -> String {
+    let last_modified = object.last_modified()
+        .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok())
+        .unwrap_or_default();
+    let fields = [
+        object.key().unwrap_or_default(),
+        &object.size().unwrap_or(0).to_string(),
+        object.e_tag().unwrap_or_default().trim_matches('"'),
+        &last_modified,
+        storage_class(object),
+    ];
+    let fields: Vec<_> = fields.iter().map(|field| csv_field(field)).collect();
+    format!("{}\n", fields.join(","))
+}
+
+/// Inventory file being written, its output taken as it's produced
+enum Writer {
+    Csv(zstd::Encoder<'static, Vec<u8>>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<super::parquet_export::Writer<Vec<u8>>>),
+}
+
+impl Writer {
+    fn new(format: Format) -> Result<Writer, Error> {
+        match format {
+            Format::Csv => {
+                let mut encoder = zstd::Encoder::new(Vec::new(), zstd::DEFAULT_COMPRESSION_LEVEL).map_err(Error::Io)?;
+                encoder.write_all(format!("{}\n", COLUMNS.join(",")).as_bytes()).map_err(Error::Io)?;
+                Ok(Writer::Csv(encoder))
+            },
+            #[cfg(feature = "parquet")]
+            Format::Parquet => Ok(Writer::Parquet(Box::new(super::parquet_export::Writer::new(Vec::new())?))),
+        }
+    }
+    fn push(&mut self, object: &Object) -> Result<(), Error> {
+        match self {
+            Writer::Csv(encoder) => encoder.write_all(csv_row(object).as_bytes()).map_err(Error::Io),
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(writer) => writer.push(super::parquet_export::Row {
+                key: object.key().unwrap_or_default(),
+                size: object.size().unwrap_or(0),
+                last_modified: object.last_modified(),
+                etag: object.e_tag(),
+                storage_class: storage_class(object),
+            }),
+        }
+    }
+    /// What's been written since last taken
+    fn take(&mut self) -> Vec<u8> {
+        match self {
+            Writer::Csv(encoder) => std::mem::take(encoder.get_mut()),
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(writer) => std::mem::take(writer.sink_mut()),
+        }
+    }
+    /// What remains to be written
+    fn finish(self) -> Result<Vec<u8>, Error> {
+        match self {
+            Writer::Csv(encoder) => encoder.finish().map_err(Error::Io),
+            #[cfg(feature = "parquet")]
+            Writer::Parquet(writer) => writer.finish(),
+        }
+    }
+}
+
+impl Client {
+    /// Write an inventory of every object below `source` into a new directory below `to`,
+    /// leaving out earlier inventories there. Uploaded as it's listed, so never held in memory
+    /// whole. Returns where it was written
+    pub async fn create_inventory(&self, verbose: bool, options_upload: &OptionsUpload, source: &Uri, to: &Uri, format: Format, created: &chrono::DateTime<chrono::Utc>) -> Result<Uri, Error> {
+        let uri = Uri::new(to.bucket.clone(), inventory_key(&to.key, created, format));
+        let inventories = (to.bucket == source.bucket).then(|| to.key.to_explicit_directory());
+        if verbose {
+            println!("🏁 writing inventory of {source} to {uri}");
+        }
+        let count = AtomicUsize::new(0);
+        let counted = &count;
+        let inventory = async_stream::try_stream! {
+            let mut writer = Writer::new(format)?;
+            let mut continuation = None;
+            loop {
+                let response = self.ls_inner(&source.bucket, &source.key, None, continuation).await?;
+                for object in response.contents() {
+                    let key = object.key().unwrap_or_default();
+                    if inventories.as_ref().is_some_and(|inventories| key.starts_with(inventories.as_str())) {
+                        continue;
+                    }
+                    writer.push(object)?;
+                    counted.fetch_add(1, Ordering::Relaxed);
+                }
+                yield Bytes::from(writer.take());
+                continuation = response.next_continuation_token().map(str::to_owned);
+                if continuation.is_none() {
+                    break;
+                }
+            }
+            yield Bytes::from(writer.finish()?);
+        };
+        let (length, _) = self.put_stream(options_upload, inventory, None, Some(format.content_type()), &uri, cli::no_progress()).await?;
+        if verbose {
+            println!("🏁 wrote inventory of {} object(s) [{length} bytes] to {uri}", count.load(Ordering::Relaxed));
+        }
+        Ok(uri)
+    }
+}
+
+#[test]
+fn test_inventory() {
+    use chrono::TimeZone;
+    let created = chrono::Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    assert_eq!(inventory_key(&Key::new("inventory".into()), &created, Format::Csv).as_str(), "inventory/2024-01-02T03-04-05Z/inventory.csv.zst");
+    assert_eq!(inventory_key(&Key::new("".into()), &created, Format::Csv).as_str(), "2024-01-02T03-04-05Z/inventory.csv.zst");
+
+    let object = Object::builder()
+        .key("a,b.txt")
+        .size(12)
+        .e_tag("\"abc\"")
+        .last_modified(aws_sdk_s3::primitives::DateTime::from_secs(1_700_000_000))
+        .build();
+    assert_eq!(csv_row(&object), "\"a,b.txt\",12,abc,2023-11-14T22:13:20Z,STANDARD\n");
+
+    let mut writer = Writer::new(Format::Csv).unwrap();
+    writer.push(&object).unwrap();
+    let mut compressed = writer.take();
+    compressed.extend(writer.finish().unwrap());
+    let csv = zstd::decode_all(compressed.as_slice()).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), "key,size,etag,last_modified,storage_class\n\"a,b.txt\",12,abc,2023-11-14T22:13:20Z,STANDARD\n");
+}
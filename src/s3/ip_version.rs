@@ -0,0 +1,79 @@
+use std::net::IpAddr;
+
+use aws_smithy_http_client::tls::{rustls_provider::CryptoMode, Provider};
+use aws_smithy_runtime_api::client::dns::{DnsFuture, ResolveDns, ResolveDnsError};
+use aws_smithy_runtime_api::client::http::SharedHttpClient;
+
+/// Address families endpoints are connected over
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum IpVersion {
+    /// Both, racing the other family 300ms after the first stalls (happy eyeballs)
+    #[default]
+    Auto,
+    #[value(name = "4")]
+    V4,
+    #[value(name = "6")]
+    V6,
+}
+
+impl std::str::FromStr for IpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <IpVersion as clap::ValueEnum>::from_str(s, true)
+            .map_err(|_| format!("unknown IP version '{s}', expected 4, 6 or auto"))
+    }
+}
+
+impl IpVersion {
+    fn allows(self, address: &IpAddr) -> bool {
+        match self {
+            IpVersion::Auto => true,
+            IpVersion::V4 => address.is_ipv4(),
+            IpVersion::V6 => address.is_ipv6(),
+        }
+    }
+
+    /// HTTP client connecting over this family only, None for auto as the SDK's own races both
+    pub(super) fn http_client(self) -> Option<SharedHttpClient> {
+        if self == IpVersion::Auto {
+            return None;
+        }
+        Some(aws_smithy_http_client::Builder::new()
+            .tls_provider(Provider::Rustls(CryptoMode::AwsLc))
+            .build_with_resolver(Resolver(self)))
+    }
+}
+
+/// The system resolver, keeping addresses of one family
+#[derive(Debug, Clone)]
+struct Resolver(IpVersion);
+
+impl ResolveDns for Resolver {
+    fn resolve_dns<'a>(&'a self, name: &'a str) -> DnsFuture<'a> {
+        DnsFuture::new(async move {
+            let addresses: Vec<IpAddr> = tokio::net::lookup_host((name, 0))
+                .await
+                .map_err(ResolveDnsError::new)?
+                .map(|address| address.ip())
+                .filter(|address| self.0.allows(address))
+                .collect();
+            match addresses.is_empty() {
+                true => Err(ResolveDnsError::new(format!("{name} has no IPv{} address", if self.0 == IpVersion::V4 { 4 } else { 6 }))),
+                false => Ok(addresses),
+            }
+        })
+    }
+}
+
+#[test]
+fn test_ip_version() {
+    let v4: IpAddr = "192.0.2.1".parse().unwrap();
+    let v6: IpAddr = "2001:db8::1".parse().unwrap();
+    assert!(IpVersion::V4.allows(&v4) && !IpVersion::V4.allows(&v6));
+    assert!(IpVersion::V6.allows(&v6) && !IpVersion::V6.allows(&v4));
+    assert!(IpVersion::Auto.allows(&v4) && IpVersion::Auto.allows(&v6));
+    assert_eq!("6".parse(), Ok(IpVersion::V6));
+    assert_eq!("AUTO".parse(), Ok(IpVersion::Auto));
+    assert!("5".parse::<IpVersion>().is_err());
+}
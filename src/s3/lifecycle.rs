@@ -0,0 +1,138 @@
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_sdk_s3::types::{ExpirationStatus, LifecycleRule};
+
+use super::{Client, Error};
+
+const DAY_SECONDS: i64 = 24 * 60 * 60;
+
+impl Client {
+    /// Lifecycle rules of a bucket, None if it has none or they can't be read
+    pub async fn lifecycle_rules(&self, bucket: &str) -> Result<Option<Vec<LifecycleRule>>, Error> {
+        let response = self.client.get_bucket_lifecycle_configuration()
+            .bucket(bucket)
+            .send()
+            .await;
+        match response {
+            Ok(configuration) => Ok(Some(configuration.rules.unwrap_or_default())),
+            Err(e) if matches!(e.code(), Some("NoSuchLifecycleConfiguration" | "AccessDenied")) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Whether a rule's filter selects an object, and whether that also depends on its tags
+fn rule_applies(rule: &LifecycleRule, key: &str, size: i64) -> Option<bool> {
+    #[allow(deprecated)]
+    let mut prefix = rule.prefix();
+    let (mut greater_than, mut less_than, mut tagged) = (None, None, false);
+    if let Some(filter) = rule.filter() {
+        match filter.and() {
+            Some(and) => {
+                prefix = and.prefix();
+                greater_than = and.object_size_greater_than();
+                less_than = and.object_size_less_than();
+                tagged = !and.tags().is_empty();
+            },
+            None => {
+                prefix = filter.prefix().or(prefix);
+                greater_than = filter.object_size_greater_than();
+                less_than = filter.object_size_less_than();
+                tagged = filter.tag().is_some();
+            },
+        }
+    }
+    let matches = key.starts_with(prefix.unwrap_or(""))
+        && greater_than.map(|limit| size > limit).unwrap_or(true)
+        && less_than.map(|limit| size < limit).unwrap_or(true);
+    matches.then_some(tagged)
+}
+
+/// When an action configured as `days` after creation, or on a fixed `date`, takes effect
+fn action_time(created: i64, days: Option<i32>, date: Option<&DateTime>) -> Option<i64> {
+    if let Some(date) = date {
+        return Some(date.secs());
+    }
+    // S3 rounds up to the following midnight UTC
+    let due = created + days? as i64 * DAY_SECONDS;
+    Some((due.div_euclid(DAY_SECONDS) + 1) * DAY_SECONDS)
+}
+
+fn format_date(secs: i64) -> String {
+    let formatted = DateTime::from_secs(secs).fmt(DateTimeFormat::DateTime).unwrap_or_default();
+    formatted.get(..10).unwrap_or(&formatted).to_owned()
+}
+
+/// Describe upcoming transitions and expiry of an object, if any enabled rule applies
+pub fn explain(rules: &[LifecycleRule], key: &str, size: i64, last_modified: Option<&DateTime>, storage_class: &str) -> Option<String> {
+    let created = last_modified?.secs();
+    let mut events = Vec::new();
+    for rule in rules.iter().filter(|rule| *rule.status() == ExpirationStatus::Enabled) {
+        let Some(tagged) = rule_applies(rule, key, size) else { continue };
+        let source = match (rule.id(), tagged) {
+            (Some(id), false) => format!("rule {id}"),
+            (Some(id), true) => format!("rule {id}, if tagged"),
+            (None, false) => "unnamed rule".to_owned(),
+            (None, true) => "unnamed rule, if tagged".to_owned(),
+        };
+        for transition in rule.transitions() {
+            let Some(class) = transition.storage_class() else { continue };
+            if class.as_str() == storage_class {
+                continue;
+            }
+            if let Some(time) = action_time(created, transition.days(), transition.date()) {
+                events.push((time, false, format!("→ {} {} ({source})", class.as_str(), format_date(time))));
+            }
+        }
+        if let Some(expiration) = rule.expiration() {
+            if let Some(time) = action_time(created, expiration.days(), expiration.date()) {
+                events.push((time, !tagged, format!("expires {} ({source})", format_date(time))));
+            }
+        }
+    }
+    events.sort();
+    // Nothing happens to an object after it's certainly gone
+    if let Some(expiry) = events.iter().position(|(_, removed, _)| *removed) {
+        events.truncate(expiry + 1);
+    }
+    if events.is_empty() {
+        return None;
+    }
+    Some(events.into_iter().map(|(_, _, description)| description).collect::<Vec<_>>().join(", "))
+}
+
+#[test]
+fn test_explain() {
+    use aws_sdk_s3::types::{LifecycleExpiration, LifecycleRuleFilter, Tag, Transition, TransitionStorageClass};
+
+    let rule = |id: &str, filter: LifecycleRuleFilter, status| LifecycleRule::builder()
+        .id(id)
+        .filter(filter)
+        .status(status);
+    let rules = [
+        rule("archive", LifecycleRuleFilter::builder().prefix("logs/").build(), ExpirationStatus::Enabled)
+            .transitions(Transition::builder().days(30).storage_class(TransitionStorageClass::Glacier).build())
+            .expiration(LifecycleExpiration::builder().days(365).build())
+            .build().unwrap(),
+        rule("cleanup", LifecycleRuleFilter::builder().prefix("logs/tmp/").build(), ExpirationStatus::Enabled)
+            .expiration(LifecycleExpiration::builder().days(7).build())
+            .build().unwrap(),
+        rule("tagged", LifecycleRuleFilter::builder().tag(Tag::builder().key("k").value("v").build().unwrap()).build(), ExpirationStatus::Enabled)
+            .expiration(LifecycleExpiration::builder().days(1).build())
+            .build().unwrap(),
+        rule("disabled", LifecycleRuleFilter::builder().build(), ExpirationStatus::Disabled)
+            .expiration(LifecycleExpiration::builder().days(1).build())
+            .build().unwrap(),
+    ];
+    // 2024-01-01T12:00:00Z
+    let created = DateTime::from_secs(1704110400);
+
+    assert_eq!(explain(&rules, "data/x", 10, Some(&created), "STANDARD"), Some("expires 2024-01-03 (rule tagged, if tagged)".into()));
+    assert_eq!(explain(&rules[..2], "data/x", 10, Some(&created), "STANDARD"), None);
+    assert_eq!(
+        explain(&rules[..2], "logs/a", 10, Some(&created), "STANDARD"),
+        Some("→ GLACIER 2024-02-01 (rule archive), expires 2025-01-01 (rule archive)".into()));
+    assert_eq!(explain(&rules[..2], "logs/a", 10, Some(&created), "GLACIER"), Some("expires 2025-01-01 (rule archive)".into()));
+    assert_eq!(explain(&rules[..2], "logs/tmp/a", 10, Some(&created), "STANDARD"), Some("expires 2024-01-09 (rule cleanup)".into()));
+    assert_eq!(explain(&rules, "logs/a", 10, None, "STANDARD"), None);
+}
@@ -0,0 +1,176 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::primitives::ByteStream;
+use sha2::Digest;
+
+use super::{Client, Error, Key, Uri};
+use crate::cli;
+
+pub const LOCK_PREFIX: &str = ".sup3-locks/";
+
+#[derive(clap::Args, Debug, Clone)]
+#[clap(next_help_heading = "Locking")]
+pub struct OptionsLock {
+    /// Hold a lock object under .sup3-locks/ in the destination bucket while writing,
+    /// refusing to start if another writer holds it and stopping with an error if it's lost
+    /// (needs conditional write support)
+    #[clap(long)]
+    pub lock: bool,
+    /// Seconds after which a lock that's no longer refreshed is considered abandoned
+    #[clap(long, default_value = "60", value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(3..))]
+    pub lock_ttl: u64,
+}
+
+/// Lock object for a destination, refreshed in the background until released
+pub struct Lock {
+    client: Client,
+    uri: Uri,
+    etag: Arc<Mutex<Option<String>>>,
+    refresher: tokio::task::JoinHandle<()>,
+    /// Cancelled once the lock can no longer be counted on
+    lost: tokio_util::sync::CancellationToken,
+}
+
+/// Lock object guarding writes under `destination`, the same with or without a trailing '/'
+fn lock_uri(destination: &Uri) -> Uri {
+    let digest: String = sha2::Sha256::digest(destination.key.as_str().trim_end_matches('/').as_bytes()).iter().map(|b| format!("{b:02x}")).collect();
+    Uri::new(destination.bucket.clone(), Key::new(format!("{LOCK_PREFIX}{digest}")))
+}
+
+/// Who holds a lock, stored as its content for whoever finds it taken
+fn holder_description(destination: &Uri) -> String {
+    let host = std::fs::read_to_string("/etc/hostname")
+        .map(|name| name.trim().to_owned())
+        .unwrap_or_else(|_| "unknown host".to_owned());
+    format!("{host} pid {} writing {destination}", std::process::id())
+}
+
+fn now_seconds() -> i64 {
+    std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+fn is_precondition_failure<E: ProvideErrorMetadata>(e: &E) -> bool {
+    matches!(e.code(), Some("PreconditionFailed" | "ConditionalRequestConflict"))
+}
+
+impl Client {
+    /// Write the lock object, only if absent or still carrying `replacing`, returning its new ETag
+    async fn put_lock(&self, uri: &Uri, holder: &str, ttl: u64, replacing: Option<&str>) -> Result<Option<String>, Error> {
+        let request = self.client.put_object()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .metadata("ttl", ttl.to_string())
+            .body(ByteStream::from(holder.as_bytes().to_vec()));
+        let request = match replacing {
+            Some(etag) => request.if_match(etag),
+            None => request.if_none_match("*"),
+        };
+        match request.send().await {
+            Ok(response) => Ok(Some(response.e_tag().unwrap_or("*").to_owned())),
+            Err(e) if is_precondition_failure(&e) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Take the lock for writing under `destination`, None if locking wasn't requested
+    pub async fn lock(&self, verbose: bool, options: &OptionsLock, destination: &Uri) -> Result<Option<Lock>, Error> {
+        if !options.lock {
+            return Ok(None);
+        }
+        let uri = lock_uri(destination);
+        let holder = holder_description(destination);
+        let mut etag = self.put_lock(&uri, &holder, options.lock_ttl, None).await?;
+        if etag.is_none() {
+            let existing = self.client.get_object()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .send()
+                .await
+                .map_err(|e| super::error_from_get(&uri, e))?;
+            let ttl = existing.metadata().and_then(|m| m.get("ttl")).and_then(|ttl| ttl.parse().ok()).unwrap_or(options.lock_ttl);
+            let refreshed = existing.last_modified().map(|t| t.secs()).unwrap_or(0);
+            let existing_etag = existing.e_tag().map(str::to_owned);
+            let existing_holder = String::from_utf8_lossy(&existing.body.collect().await?.into_bytes()).into_owned();
+            let abandoned = refreshed + ttl as i64 <= now_seconds();
+            if let (true, Some(existing_etag)) = (abandoned, existing_etag) {
+                if verbose {
+                    println!("🏁 taking over abandoned lock of {existing_holder}");
+                }
+                etag = self.put_lock(&uri, &holder, options.lock_ttl, Some(&existing_etag)).await?;
+            }
+            if etag.is_none() {
+                return Err(Error::Locked(destination.clone(), existing_holder));
+            }
+        }
+        if verbose {
+            println!("🏁 locked {destination} with {uri}");
+        }
+
+        let etag = Arc::new(Mutex::new(etag));
+        let lost = tokio_util::sync::CancellationToken::new();
+        let refresher = {
+            let (client, uri, etag, lost) = (self.clone(), uri.clone(), etag.clone(), lost.clone());
+            let interval = Duration::from_secs(options.lock_ttl / 3);
+            let ttl = options.lock_ttl;
+            tokio::spawn(async move {
+                let mut refreshed_at = now_seconds();
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let current = etag.lock().unwrap().clone();
+                    match client.put_lock(&uri, &holder, ttl, current.as_deref()).await {
+                        Ok(Some(refreshed)) => {
+                            *etag.lock().unwrap() = Some(refreshed);
+                            refreshed_at = now_seconds();
+                        },
+                        Ok(None) => {
+                            cli::println_error(format_args!("lost lock {uri} to another writer"));
+                            lost.cancel();
+                            return;
+                        },
+                        // Past its TTL another writer may take it over as abandoned
+                        Err(e) if refreshed_at + ttl as i64 <= now_seconds() => {
+                            cli::println_error(format_args!("lock {uri} expired, failing to refresh it: {e}"));
+                            lost.cancel();
+                            return;
+                        },
+                        Err(e) => cli::println_error(format_args!("failed to refresh lock {uri}: {e}")),
+                    }
+                }
+            })
+        };
+        Ok(Some(Lock { client: self.clone(), uri, etag, refresher, lost }))
+    }
+}
+
+impl Lock {
+    /// Resolves once another writer has taken the lock, or it expired unrefreshed
+    pub async fn lost(&self) {
+        self.lost.cancelled().await
+    }
+
+    /// Stop refreshing and remove the lock object, unless another writer has since taken it
+    pub async fn release(self) -> Result<(), Error> {
+        self.refresher.abort();
+        let etag = self.etag.lock().unwrap().clone();
+        let result = self.client.client.delete_object()
+            .bucket(self.uri.bucket.clone())
+            .key(self.uri.key.to_string())
+            .set_if_match(etag)
+            .send()
+            .await;
+        match result {
+            Err(e) if is_precondition_failure(&e) => Ok(()),
+            other => other.map(|_| ()).map_err(Error::from),
+        }
+    }
+}
+
+#[test]
+fn test_lock_uri() {
+    let lock = |uri: &str| lock_uri(&uri.parse().unwrap()).to_string();
+    assert_eq!(lock("s3://bucket/data/"), lock("s3://bucket/data"));
+    assert_ne!(lock("s3://bucket/data/"), lock("s3://bucket/data/sub/"));
+    assert!(lock("s3://bucket/data/").starts_with("s3://bucket/.sup3-locks/"));
+}
@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use fuser::{FileAttr, FileType, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen, ReplyStatfs, Request};
+
+use crate::cli;
+use super::{Client, Error, Key, Uri};
+
+/// How long the kernel may cache attributes, and listings are reused
+const TTL: Duration = Duration::from_secs(30);
+const ROOT_ID: u64 = fuser::FUSE_ROOT_ID;
+const BLOCK_SIZE: u32 = 4096;
+
+struct Node {
+    /// Directories end with '/', or are empty for the bucket root
+    key: Key,
+    /// Directory listing this node, itself for the root
+    parent: u64,
+    directory: bool,
+    size: u64,
+    mtime: i64,
+    /// Child names and ids, with when they were listed
+    children: Option<(Instant, Vec<(String, u64)>)>,
+}
+
+#[derive(Default)]
+struct Tree {
+    nodes: Vec<Node>,
+    by_key: HashMap<String, u64>,
+}
+
+impl Tree {
+    fn get(&self, id: u64) -> Option<&Node> {
+        self.nodes.get(id.checked_sub(ROOT_ID)? as usize)
+    }
+    fn get_mut(&mut self, id: u64) -> Option<&mut Node> {
+        self.nodes.get_mut(id.checked_sub(ROOT_ID)? as usize)
+    }
+    /// Ids stay stable for the life of the mount, so entries are updated in place
+    fn insert(&mut self, key: Key, parent: u64, directory: bool, size: u64, mtime: i64) -> u64 {
+        if let Some(&id) = self.by_key.get(key.as_str()) {
+            let node = self.get_mut(id).expect("indexed node exists");
+            node.size = size;
+            node.mtime = mtime;
+            return id;
+        }
+        let id = self.nodes.len() as u64 + ROOT_ID;
+        self.by_key.insert(key.to_string(), id);
+        self.nodes.push(Node { key, parent, directory, size, mtime, children: None });
+        id
+    }
+}
+
+/// Name as stored in a listing, rejecting components the kernel would never look up
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('\0')
+}
+
+struct Filesystem {
+    client: Client,
+    bucket: String,
+    verbose: bool,
+    tree: Mutex<Tree>,
+    uid: u32,
+    gid: u32,
+    /// Reported as the modification time of directories, which S3 doesn't track
+    mounted: i64,
+}
+
+impl Filesystem {
+    fn attr(&self, id: u64) -> Result<FileAttr, i32> {
+        let tree = self.tree.lock().unwrap();
+        let node = tree.get(id).ok_or(libc::ENOENT)?;
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(node.mtime.max(0) as u64);
+        Ok(FileAttr {
+            ino: id,
+            size: node.size,
+            blocks: node.size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind: if node.directory { FileType::Directory } else { FileType::RegularFile },
+            perm: if node.directory { 0o555 } else { 0o444 },
+            nlink: if node.directory { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        })
+    }
+
+    fn s3_error(&self, uri: &Uri, e: Error) -> i32 {
+        if self.verbose {
+            cli::println_error(format_args!("{uri}: {e}"));
+        }
+        match e {
+            Error::NoSuchKey(_) => libc::ENOENT,
+            _ => libc::EIO,
+        }
+    }
+
+    /// Children of a directory, listed from S3 unless recently done
+    async fn children(&self, id: u64) -> Result<Vec<(String, u64)>, i32> {
+        let key = {
+            let tree = self.tree.lock().unwrap();
+            let node = tree.get(id).ok_or(libc::ENOENT)?;
+            if !node.directory {
+                return Err(libc::ENOTDIR);
+            }
+            if let Some((listed, children)) = &node.children {
+                if listed.elapsed() < TTL {
+                    return Ok(children.clone());
+                }
+            }
+            node.key.clone()
+        };
+
+        let uri = Uri::new(self.bucket.clone(), key.clone());
+        let mut entries = Vec::new();
+        let mut names = HashSet::new();
+        let mut continuation = None;
+        loop {
+            let response = self.client.ls_inner(&self.bucket, &key, Some('/'), continuation).await
+                .map_err(|e| self.s3_error(&uri, e))?;
+            for prefix in response.common_prefixes().iter().filter_map(|p| p.prefix()) {
+                let name = prefix[key.len()..].trim_end_matches('/');
+                if valid_name(name) && names.insert(name.to_owned()) {
+                    entries.push((name.to_owned(), Key::new(prefix.to_owned()), true, 0, self.mounted));
+                }
+            }
+            for object in response.contents() {
+                let Some(object_key) = object.key() else { continue };
+                let name = &object_key[key.len()..];
+                if valid_name(name) && !name.contains('/') && names.insert(name.to_owned()) {
+                    let mtime = object.last_modified().map(|t| t.secs()).unwrap_or(0);
+                    entries.push((name.to_owned(), Key::new(object_key.to_owned()), false, object.size().unwrap_or(0) as u64, mtime));
+                }
+            }
+            continuation = response.next_continuation_token().map(str::to_owned);
+            if continuation.is_none() {
+                break;
+            }
+        }
+
+        let mut tree = self.tree.lock().unwrap();
+        let children: Vec<_> = entries.into_iter()
+            .map(|(name, key, directory, size, mtime)| (name, tree.insert(key, id, directory, size, mtime)))
+            .collect();
+        tree.get_mut(id).expect("listed node exists").children = Some((Instant::now(), children.clone()));
+        Ok(children)
+    }
+
+    async fn read(&self, id: u64, offset: u64, size: u32) -> Result<Vec<u8>, i32> {
+        let (key, length) = {
+            let tree = self.tree.lock().unwrap();
+            let node = tree.get(id).ok_or(libc::ENOENT)?;
+            (node.key.clone(), node.size)
+        };
+        if offset >= length || size == 0 {
+            return Ok(Vec::new());
+        }
+        let end = (offset + size as u64).min(length) - 1;
+        let uri = Uri::new(self.bucket.clone(), key);
+        self.client.get_range(&uri, offset, end, None, &super::rate_limit::Limiter::default()).await
+            .map(|bytes| bytes.to_vec())
+            .map_err(|e| self.s3_error(&uri, e))
+    }
+
+    async fn lookup(&self, parent: u64, name: &str) -> Result<FileAttr, i32> {
+        let children = self.children(parent).await?;
+        let (_, id) = children.iter().find(|(child, _)| child == name).ok_or(libc::ENOENT)?;
+        self.attr(*id)
+    }
+
+    /// Entries from `offset` on, each with the offset of the one after it
+    async fn entries(&self, id: u64, offset: usize) -> Result<Vec<(u64, i64, FileType, String)>, i32> {
+        let children = self.children(id).await?;
+        let parent = self.tree.lock().unwrap().get(id).map_or(id, |node| node.parent);
+        let dots = [(".".to_owned(), id), ("..".to_owned(), parent)];
+        Ok(dots.into_iter().chain(children).enumerate().skip(offset)
+            .map(|(index, (name, id))| {
+                let kind = match self.attr(id) {
+                    Ok(attr) => attr.kind,
+                    Err(_) => FileType::RegularFile,
+                };
+                (id, index as i64 + 1, kind, name)
+            })
+            .collect())
+    }
+}
+
+/// Serves kernel requests on the runtime, replying once S3 has answered
+struct Mounted {
+    filesystem: Arc<Filesystem>,
+    runtime: tokio::runtime::Handle,
+}
+
+impl Mounted {
+    fn spawn<F: std::future::Future<Output = ()> + Send + 'static>(&self, work: impl FnOnce(Arc<Filesystem>) -> F) {
+        self.runtime.spawn(work(self.filesystem.clone()));
+    }
+}
+
+impl fuser::Filesystem for Mounted {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let name = name.to_string_lossy().into_owned();
+        self.spawn(|filesystem| async move {
+            match filesystem.lookup(parent, &name).await {
+                Ok(attr) => reply.entry(&TTL, &attr, 0),
+                Err(e) => reply.error(e),
+            }
+        });
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.filesystem.attr(ino) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(e) => reply.error(e),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, _ino: u64, flags: i32, reply: ReplyOpen) {
+        match flags & libc::O_ACCMODE {
+            libc::O_RDONLY => reply.opened(0, fuser::consts::FOPEN_KEEP_CACHE),
+            _ => reply.error(libc::EROFS),
+        }
+    }
+
+    fn read(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, size: u32, _flags: i32, _lock_owner: Option<u64>, reply: ReplyData) {
+        self.spawn(|filesystem| async move {
+            match filesystem.read(ino, offset.max(0) as u64, size).await {
+                Ok(data) => reply.data(&data),
+                Err(e) => reply.error(e),
+            }
+        });
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        self.spawn(|filesystem| async move {
+            match filesystem.children(ino).await {
+                Ok(_) => reply.opened(0, 0),
+                Err(e) => reply.error(e),
+            }
+        });
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        self.spawn(|filesystem| async move {
+            match filesystem.entries(ino, offset.max(0) as usize).await {
+                Ok(entries) => {
+                    for (id, next, kind, name) in entries {
+                        if reply.add(id, next, kind, &name) {
+                            break;
+                        }
+                    }
+                    reply.ok();
+                },
+                Err(e) => reply.error(e),
+            }
+        });
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        reply.statfs(0, 0, 0, 0, 0, BLOCK_SIZE, 1024, BLOCK_SIZE);
+    }
+}
+
+impl Client {
+    /// Expose a prefix read-only at `mountpoint` until unmounted or interrupted
+    pub async fn mount(&self, verbose: bool, uri: &Uri, mountpoint: &Path) -> Result<(), Error> {
+        let mut tree = Tree::default();
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+        tree.insert(uri.key.to_explicit_directory(), ROOT_ID, true, 0, now);
+        // SAFETY: getuid/getgid cannot fail
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let filesystem = Arc::new(Filesystem {
+            client: self.clone(),
+            bucket: uri.bucket.clone(),
+            verbose,
+            tree: Mutex::new(tree),
+            uid,
+            gid,
+            mounted: now,
+        });
+
+        let mounted = Mounted { filesystem, runtime: tokio::runtime::Handle::current() };
+        let options = [
+            MountOption::RO,
+            MountOption::NoSuid,
+            MountOption::NoDev,
+            MountOption::DefaultPermissions,
+            MountOption::FSName("sup3".into()),
+            MountOption::Subtype("sup3".into()),
+        ];
+        let mut session = fuser::Session::new(mounted, mountpoint, &options).map_err(Error::Io)?;
+        if verbose {
+            println!("🏁 mounted {uri} read-only at {mountpoint:?}, interrupt to unmount");
+        }
+
+        let mut unmounter = session.unmount_callable();
+        let unmount_path = mountpoint.to_owned();
+        let interrupted = tokio::spawn(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            if let Err(e) = unmounter.unmount() {
+                cli::println_error(format_args!("failed to unmount {unmount_path:?}: {e}"));
+            }
+        });
+        // Requests are read on a thread of its own, blocking until unmounted
+        let served = tokio::task::spawn_blocking(move || session.run()).await.expect("session thread panicked");
+        interrupted.abort();
+        served.map_err(Error::Io)?;
+        if verbose {
+            println!("🏁 unmounted {mountpoint:?}");
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_parent_entries() {
+    let mut tree = Tree::default();
+    let root = tree.insert(Key::new("data/".to_owned()), ROOT_ID, true, 0, 0);
+    let logs = tree.insert(Key::new("data/logs/".to_owned()), root, true, 0, 0);
+    let file = tree.insert(Key::new("data/logs/a.txt".to_owned()), logs, false, 3, 0);
+    assert_eq!(root, ROOT_ID);
+    assert_eq!(tree.get(file).map(|node| node.parent), Some(logs));
+    assert_eq!(tree.get(logs).map(|node| node.parent), Some(root));
+    assert_eq!(tree.get(root).map(|node| node.parent), Some(ROOT_ID));
+    // Listed again, ids and parents stay as they were
+    assert_eq!(tree.insert(Key::new("data/logs/".to_owned()), root, true, 0, 5), logs);
+
+    assert!(valid_name("a.txt"));
+    assert!(!valid_name(".."));
+    assert!(!valid_name(""));
+}
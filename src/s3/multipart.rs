@@ -0,0 +1,172 @@
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+
+use super::{checksum, Client, CustomerKey, Error, OptionsUpload, Uri};
+use crate::cli;
+
+/// Part size for uploads of unknown length, above the 5 MiB minimum
+pub const STREAM_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Fill `buffer` to at least `STREAM_PART_SIZE`, returns false once the stream is exhausted
+async fn fill_part<S>(stream: &mut S, buffer: &mut BytesMut) -> Result<bool, Error>
+    where S: Stream<Item = Result<Bytes, Error>> + Unpin
+{
+    while buffer.len() < STREAM_PART_SIZE {
+        match stream.next().await {
+            Some(chunk) => buffer.extend_from_slice(&chunk?),
+            None => return Ok(false),
+        }
+    }
+    Ok(true)
+}
+
+impl Client {
+    /// Upload a stream of possibly unknown length without buffering more than one part
+    ///
+    /// Streams shorter than one part are sent with a single PutObject. Storage class rules see the
+    /// stream's `length` if known, else at least one part. Returns the uploaded length and ETag
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put_stream<S>(&self, options_upload: &OptionsUpload, stream: S, length: Option<u64>, content_type: Option<&str>, to: &Uri, progress_fn: cli::ProgressFn) -> Result<(u64, Option<String>), Error>
+        where S: Stream<Item = Result<Bytes, Error>>
+    {
+        futures::pin_mut!(stream);
+        let limiter = self.rate_limits.transfer();
+        let mut buffer = BytesMut::with_capacity(STREAM_PART_SIZE);
+        let mut more = fill_part(&mut stream, &mut buffer).await?;
+        let extra_headers = options_upload.conditional.extra_headers();
+        let class = options_upload.class_for(std::path::Path::new(to.key.as_str()), length.unwrap_or(buffer.len() as u64));
+        if !more && buffer.len() < STREAM_PART_SIZE {
+            let length = buffer.len();
+            progress_fn(cli::Update::StateBuffered(length));
+            let put = self.client.put_object()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .set_acl(options_upload.canned_acl.to_owned())
+                .set_grant_read(options_upload.access_control.grant_read.to_owned())
+                .set_grant_full_control(options_upload.access_control.grant_full.to_owned())
+                .set_grant_read_acp(options_upload.access_control.grant_read_acp.to_owned())
+                .set_grant_write_acp(options_upload.access_control.grant_write_acp.to_owned())
+                .set_storage_class(class.clone())
+                .set_content_type(content_type.map(str::to_owned))
+                .set_cache_control(options_upload.cache_control_for(&to.key))
+                .set_metadata(options_upload.user_metadata())
+                .set_tagging(options_upload.tagging())
+                .set_content_encoding(options_upload.headers.content_encoding.to_owned())
+                .set_content_disposition(options_upload.headers.content_disposition.to_owned())
+                .set_expires(options_upload.headers.expires)
+                .set_if_match(options_upload.conditional.if_match.as_deref().map(super::quoted_etag))
+                .set_checksum_algorithm(options_upload.checksum_algorithm.clone())
+                .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+                .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+                .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
+                .content_length(length as i64)
+                .body(limiter.body(buffer.freeze()))
+                .customize()
+                .mutate_request(move |request| {
+                    for (name, value) in &extra_headers {
+                        request.headers_mut().insert(*name, value.clone());
+                    }
+                })
+                .send()
+                .await?;
+            progress_fn(cli::Update::StateProgress(length));
+            self.head_cache.invalidate(to);
+            return Ok((length as u64, put.e_tag().map(str::to_owned)));
+        }
+
+        let upload = self.client.create_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .set_acl(options_upload.canned_acl.to_owned())
+            .set_grant_read(options_upload.access_control.grant_read.to_owned())
+            .set_grant_full_control(options_upload.access_control.grant_full.to_owned())
+            .set_grant_read_acp(options_upload.access_control.grant_read_acp.to_owned())
+            .set_grant_write_acp(options_upload.access_control.grant_write_acp.to_owned())
+            .set_storage_class(class)
+            .set_content_type(content_type.map(str::to_owned))
+            .set_cache_control(options_upload.cache_control_for(&to.key))
+            .set_metadata(options_upload.user_metadata())
+            .set_tagging(options_upload.tagging())
+            .set_content_encoding(options_upload.headers.content_encoding.to_owned())
+            .set_content_disposition(options_upload.headers.content_disposition.to_owned())
+            .set_expires(options_upload.headers.expires)
+            .set_checksum_algorithm(options_upload.checksum_algorithm.clone())
+            .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+            .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+            .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
+            .send()
+            .await?;
+        let upload_id = upload.upload_id().expect("upload id in response").to_owned();
+        self.in_flight.add(to, &upload_id);
+
+        let mut parts = Vec::new();
+        let mut total = 0;
+        let result: Result<(), Error> = async {
+            loop {
+                let part = if buffer.len() > STREAM_PART_SIZE { buffer.split_to(STREAM_PART_SIZE) } else { buffer.split() };
+                let part_number = parts.len() as i32 + 1;
+                let length = part.len();
+                progress_fn(cli::Update::StateBuffered(length));
+                let response = self.client.upload_part()
+                    .bucket(to.bucket.clone())
+                    .key(to.key.to_string())
+                    .upload_id(&upload_id)
+                    .part_number(part_number)
+                    .set_checksum_algorithm(options_upload.checksum_algorithm.clone())
+                    .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+                    .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+                    .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
+                    .content_length(length as i64)
+                    .body(limiter.body(part.freeze()))
+                    .send()
+                    .await?;
+                progress_fn(cli::Update::StateProgress(length));
+                total += length as u64;
+                let mut completed = CompletedPart::builder()
+                    .part_number(part_number)
+                    .set_e_tag(response.e_tag().map(str::to_owned));
+                if let Some(algorithm) = &options_upload.checksum_algorithm {
+                    completed = checksum::with_checksum(completed, algorithm, checksum::of_upload(&response, algorithm));
+                }
+                parts.push(completed.build());
+                if more {
+                    more = fill_part(&mut stream, &mut buffer).await?;
+                }
+                if buffer.is_empty() {
+                    return Ok(());
+                }
+            }
+        }.await;
+        self.in_flight.remove(&upload_id);
+
+        if let Err(e) = result {
+            let _ = self.client.abort_multipart_upload()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .upload_id(&upload_id)
+                .send()
+                .await;
+            return Err(e);
+        }
+        let completed = self.client.complete_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .upload_id(&upload_id)
+            .set_if_match(options_upload.conditional.if_match.as_deref().map(super::quoted_etag))
+            .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+            .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+            .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .customize()
+            .mutate_request(move |request| {
+                for (name, value) in &extra_headers {
+                    request.headers_mut().insert(*name, value.clone());
+                }
+            })
+            .send()
+            .await?;
+        self.head_cache.invalidate(to);
+        Ok((total, completed.e_tag().map(str::to_owned)))
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, StringArray, TimestampMillisecondArray};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+
+/// Rows buffered before a record batch is flushed, bounds memory use for large listings
+const BATCH_ROWS: usize = 64 * 1024;
+
+pub struct Row<'a> {
+    pub key: &'a str,
+    pub size: i64,
+    pub last_modified: Option<&'a aws_sdk_s3::primitives::DateTime>,
+    pub etag: Option<&'a str>,
+    pub storage_class: &'a str,
+}
+
+/// Writes object listings to a parquet file, or any other sink, one row per object
+pub struct Writer<W: std::io::Write + Send = std::fs::File> {
+    writer: ArrowWriter<W>,
+    schema: Arc<Schema>,
+    keys: Vec<String>,
+    sizes: Vec<i64>,
+    last_modified: Vec<Option<i64>>,
+    etags: Vec<Option<String>>,
+    storage_classes: Vec<String>,
+}
+
+impl Writer {
+    pub fn create(path: &std::path::Path) -> Result<Writer, super::Error> {
+        Writer::new(std::fs::File::create(path)?)
+    }
+}
+
+impl<W: std::io::Write + Send> Writer<W> {
+    pub fn new(sink: W) -> Result<Writer<W>, super::Error> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Utf8, false),
+            Field::new("size", DataType::Int64, false),
+            Field::new("last_modified", DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())), true),
+            Field::new("etag", DataType::Utf8, true),
+            Field::new("storage_class", DataType::Utf8, false),
+        ]));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(BATCH_ROWS)
+            .build();
+        let writer = ArrowWriter::try_new(sink, schema.clone(), Some(properties))?;
+        Ok(Writer {
+            writer,
+            schema,
+            keys: Vec::new(),
+            sizes: Vec::new(),
+            last_modified: Vec::new(),
+            etags: Vec::new(),
+            storage_classes: Vec::new(),
+        })
+    }
+    pub fn push(&mut self, row: Row) -> Result<(), super::Error> {
+        self.keys.push(row.key.to_owned());
+        self.sizes.push(row.size);
+        self.last_modified.push(row.last_modified.and_then(|d| d.to_millis().ok()));
+        self.etags.push(row.etag.map(|e| e.trim_matches('"').to_owned()));
+        self.storage_classes.push(row.storage_class.to_owned());
+        if self.keys.len() >= BATCH_ROWS {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+    fn flush_batch(&mut self) -> Result<(), super::Error> {
+        if self.keys.is_empty() {
+            return Ok(());
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(std::mem::take(&mut self.keys))),
+            Arc::new(Int64Array::from(std::mem::take(&mut self.sizes))),
+            Arc::new(TimestampMillisecondArray::from(std::mem::take(&mut self.last_modified)).with_timezone("UTC")),
+            Arc::new(StringArray::from(std::mem::take(&mut self.etags))),
+            Arc::new(StringArray::from(std::mem::take(&mut self.storage_classes))),
+        ];
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)
+            .map_err(|e| parquet::errors::ParquetError::ArrowError(e.to_string()))?;
+        self.writer.write(&batch)?;
+        Ok(())
+    }
+    /// The sink, for what's been written so far to be taken from it
+    pub fn sink_mut(&mut self) -> &mut W {
+        self.writer.inner_mut()
+    }
+    /// Write the footer, returning the sink
+    pub fn finish(mut self) -> Result<W, super::Error> {
+        self.flush_batch()?;
+        Ok(self.writer.into_inner()?)
+    }
+}
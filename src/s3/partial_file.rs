@@ -1,22 +1,27 @@
 use std::path::PathBuf;
 use tokio::io::AsyncWriteExt;
 
+/// Appended to the destination path while downloading, renamed away once complete
+pub const PARTIAL_SUFFIX: &str = ".sup3.partial";
+
 pub struct PartialFile {
     pub writer: Option<tokio::io::BufWriter<tokio::fs::File>>,
     path_partial: PathBuf,
     path_final: PathBuf,
+    disk_jobs: super::disk_jobs::DiskJobs,
 }
 
 impl PartialFile {
-    pub async fn new(path_final: PathBuf) -> Result<PartialFile, super::Error> {
+    pub async fn new(path_final: PathBuf, disk_jobs: super::disk_jobs::DiskJobs) -> Result<PartialFile, super::Error> {
         let mut path_string_temporary = path_final.as_os_str().to_owned();
-        path_string_temporary.push(".sup3.partial");
+        path_string_temporary.push(PARTIAL_SUFFIX);
         let path_partial = std::path::PathBuf::from(path_string_temporary);
         let local_file = tokio::fs::File::create(&path_partial).await?;
         Ok(PartialFile {
             writer: Some(tokio::io::BufWriter::new(local_file)),
             path_partial,
             path_final,
+            disk_jobs,
         })
     }
     pub async fn finished(mut self) -> Result<PathBuf, super::Error> {
@@ -39,6 +44,11 @@ impl PartialFile {
     pub fn path_printable(&self) -> std::borrow::Cow<'_, str> {
         self.path_final.to_string_lossy()
     }
+    /// Write a block, waiting for a disk job if bounded
+    pub async fn write_all(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        let _permit = self.disk_jobs.acquire().await;
+        self.writer.as_mut().expect("writer not taken").write_all(bytes).await
+    }
     pub fn writer(&mut self) -> &mut tokio::io::BufWriter<tokio::fs::File> {
         self.writer.as_mut().expect("writer not taken")
     }
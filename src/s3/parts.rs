@@ -0,0 +1,178 @@
+use std::path::PathBuf;
+
+use aws_sdk_s3::types::{Checksum, ChecksumMode, ChecksumType, ObjectAttributes, ObjectPart};
+use futures::StreamExt;
+
+use super::{Client, Error, Uri, Target};
+use crate::cli;
+
+/// One part of a multipart object
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    pub number: i32,
+    pub size: i64,
+    /// e.g. "CRC32C:yZRlqg==", if uploaded with a checksum
+    pub checksum: Option<String>,
+}
+
+pub struct ObjectParts {
+    pub etag: Option<String>,
+    pub size: i64,
+    /// Empty for objects not uploaded with multipart
+    pub parts: Vec<Part>,
+    /// Checksum of the whole content, e.g. "CRC64NVME:jC6QSBPnqY8=", if uploaded with one of that type
+    pub checksum: Option<String>,
+}
+
+fn named_checksum(checksums: [Option<&str>; 5]) -> Option<String> {
+    ["CRC32", "CRC32C", "CRC64NVME", "SHA1", "SHA256"].into_iter()
+        .zip(checksums)
+        .find_map(|(name, value)| value.map(|v| format!("{name}:{v}")))
+}
+
+fn part_checksum(part: &ObjectPart) -> Option<String> {
+    named_checksum([part.checksum_crc32(), part.checksum_crc32_c(), part.checksum_crc64_nvme(), part.checksum_sha1(), part.checksum_sha256()])
+}
+
+/// Composite checksums of multipart objects are of their parts' checksums, not of the content
+fn object_checksum(checksum: &Checksum) -> Option<String> {
+    match checksum.checksum_type() {
+        Some(ChecksumType::Composite) => None,
+        _ => named_checksum([checksum.checksum_crc32(), checksum.checksum_crc32_c(), checksum.checksum_crc64_nvme(), checksum.checksum_sha1(), checksum.checksum_sha256()]),
+    }
+}
+
+/// Part count encoded in a multipart ETag, e.g. "d41d8cd9-3"
+fn etag_part_count(etag: &str) -> Option<i32> {
+    etag.trim_matches('"').rsplit_once('-')?.1.parse().ok()
+}
+
+/// Parts of `first_size` bytes, the last holding what remains of `total`
+fn computed_parts(first_size: i64, total: i64, count: i32) -> Vec<Part> {
+    (1..=count)
+        .map(|number| {
+            let start = first_size * (number as i64 - 1);
+            let size = if number == count { total - start } else { first_size };
+            Part { number, size, checksum: None }
+        })
+        .collect()
+}
+
+impl Client {
+    /// Part layout of an object, via GetObjectAttributes
+    ///
+    /// Providers only return per-part details for objects uploaded with
+    /// checksums, otherwise sizes are computed from the first part's size,
+    /// as uploaders use the same size for every part but the last
+    pub async fn object_parts(&self, uri: &Uri) -> Result<ObjectParts, Error> {
+        let mut parts = Vec::new();
+        let mut marker: Option<String> = None;
+        let mut etag;
+        let mut size;
+        let mut checksum;
+        let mut total_parts = None;
+        loop {
+            let response = self.client.get_object_attributes()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .object_attributes(ObjectAttributes::ObjectParts)
+                .object_attributes(ObjectAttributes::Etag)
+                .object_attributes(ObjectAttributes::ObjectSize)
+                .object_attributes(ObjectAttributes::Checksum)
+                .set_part_number_marker(marker.take())
+                .send()
+                .await
+                .map_err(|e| match e.as_service_error() {
+                    Some(service) if service.is_no_such_key() => Error::NoSuchKey(uri.clone()),
+                    _ => e.into(),
+                })?;
+            etag = response.e_tag().map(str::to_owned);
+            size = response.object_size().unwrap_or(0);
+            checksum = response.checksum().and_then(object_checksum);
+            let Some(object_parts) = response.object_parts() else {
+                break;
+            };
+            total_parts = object_parts.total_parts_count();
+            parts.extend(object_parts.parts().iter().map(|part| Part {
+                number: part.part_number().unwrap_or(0),
+                size: part.size().unwrap_or(0),
+                checksum: part_checksum(part),
+            }));
+            match (object_parts.is_truncated(), object_parts.next_part_number_marker()) {
+                (Some(true), Some(next)) => marker = Some(next.to_owned()),
+                _ => break,
+            }
+        }
+
+        let total_parts = total_parts.or_else(|| etag.as_deref().and_then(etag_part_count));
+        if let (true, Some(count)) = (parts.is_empty(), total_parts) {
+            let head = self.client.head_object()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .part_number(1)
+                .send()
+                .await?;
+            let count = head.parts_count().unwrap_or(count);
+            parts = computed_parts(head.content_length().unwrap_or(0), size, count);
+        }
+        Ok(ObjectParts { etag, size, parts, checksum })
+    }
+
+    /// Download aligned to the original part boundaries, so each part's
+    /// checksum is validated by the SDK as soon as that part completes,
+    /// fetching up to `concurrency` parts at once
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn get_parts(&self, verbose: bool, concurrency: usize, from: &Uri, object: &ObjectParts, etag: Option<&str>, to: &Target, limiter: &super::rate_limit::Limiter, progress_fn: cli::ProgressFn) -> Result<PathBuf, Error> {
+        progress_fn(cli::Update::State("opening"));
+        let local_path = to.local_path(from)?;
+        let mut local_file = super::partial_file::PartialFile::new(local_path, self.disk_jobs.clone()).await?;
+
+        progress_fn(cli::Update::State("downloading"));
+        progress_fn(cli::Update::StateLength(object.size as usize));
+        if verbose {
+            println!("🏁 downloading '{from}' [{size} bytes, {count} verified parts, {concurrency} concurrent] to {path_printable}", size = object.size, count = object.parts.len(), path_printable = local_file.path_printable());
+        }
+        // Every part must come from the version that was listed, or else the one the layout is of
+        let etag = etag.map(str::to_owned).or_else(|| object.etag.clone());
+        let numbers: Vec<i32> = object.parts.iter().map(|part| part.number).collect();
+        let parts = futures::stream::iter(numbers)
+            .map(|number| self.get_part(from, number, etag.as_deref(), limiter))
+            .buffered(concurrency);
+        // Removing the partial file whether a part failed to be fetched or written
+        if let Err(err) = super::ranged::write_in_order(&mut local_file, parts, &progress_fn).await {
+            local_file.cancelled().await?;
+            return Err(err);
+        }
+        let local_path = local_file.finished().await?;
+        self.audit_read(from, object.size as u64, object.etag.as_deref());
+        progress_fn(cli::Update::Finished());
+        Ok(local_path)
+    }
+    async fn get_part(&self, from: &Uri, number: i32, etag: Option<&str>, limiter: &super::rate_limit::Limiter) -> Result<bytes::Bytes, Error> {
+        let response = self.client.get_object()
+            .bucket(from.bucket.clone())
+            .key(from.key.to_string())
+            .part_number(number)
+            .set_if_match(etag.map(str::to_owned))
+            .checksum_mode(ChecksumMode::Enabled)
+            .send()
+            .await
+            .map_err(|e| super::error_from_get(from, e))?;
+        Ok(limiter.collect(response.body).await?)
+    }
+}
+
+#[test]
+fn test_etag_part_count() {
+    assert_eq!(etag_part_count("\"d41d8cd98f00b204e9800998ecf8427e-3\""), Some(3));
+    assert_eq!(etag_part_count("d41d8cd98f00b204e9800998ecf8427e-12"), Some(12));
+    assert_eq!(etag_part_count("\"d41d8cd98f00b204e9800998ecf8427e\""), None);
+}
+
+#[test]
+fn test_computed_parts() {
+    let sizes = |parts: Vec<Part>| parts.into_iter().map(|p| (p.number, p.size)).collect::<Vec<_>>();
+    assert_eq!(sizes(computed_parts(100, 250, 3)), [(1, 100), (2, 100), (3, 50)]);
+    assert_eq!(sizes(computed_parts(100, 200, 2)), [(1, 100), (2, 100)]);
+    assert_eq!(sizes(computed_parts(100, 100, 1)), [(1, 100)]);
+}
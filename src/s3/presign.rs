@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+use aws_sdk_s3::presigning::PresigningConfig;
+
+use super::{Client, Error, Uri};
+
+/// Longest validity S3 accepts for a presigned URL
+pub const MAX_PRESIGN_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+impl Client {
+    /// URL allowing whoever holds it to GET the object, or with `put` to upload it, until it expires
+    pub async fn presign(&self, uri: &Uri, put: bool, expires_in: Duration) -> Result<String, Error> {
+        let config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| Error::S3SdkError("presigning: ", Box::new(e)))?;
+        let request = match put {
+            false => self.client.get_object()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .presigned(config)
+                .await?,
+            true => self.client.put_object()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .presigned(config)
+                .await?,
+        };
+        Ok(request.uri().to_owned())
+    }
+}
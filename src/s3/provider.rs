@@ -0,0 +1,179 @@
+use aws_sdk_s3::config::{RequestChecksumCalculation, ResponseChecksumValidation};
+
+/// Providers whose endpoint only depends on the region
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub enum Preset {
+    Scaleway,
+    Hetzner,
+    Ovh,
+}
+
+impl Preset {
+    fn default_region(self) -> &'static str {
+        match self {
+            Preset::Scaleway => "fr-par",
+            Preset::Hetzner => "fsn1",
+            Preset::Ovh => "gra",
+        }
+    }
+
+    fn host(self, region: &str) -> String {
+        match self {
+            Preset::Scaleway => format!("s3.{region}.scw.cloud"),
+            Preset::Hetzner => format!("{region}.your-objectstorage.com"),
+            Preset::Ovh => format!("s3.{region}.io.cloud.ovh.net"),
+        }
+    }
+}
+
+impl std::str::FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        <Preset as clap::ValueEnum>::from_str(s, true)
+            .map_err(|_| format!("unknown provider '{s}', expected scaleway, hetzner or ovh"))
+    }
+}
+
+/// Flags for what Backblaze B2's S3-compatible API doesn't implement, with why
+const B2_UNSUPPORTED: &[(&str, &str)] = &[
+    ("accelerate", "B2 has no transfer acceleration"),
+    ("class", "B2 stores every object in a single storage class"),
+    ("class-rules", "B2 stores every object in a single storage class"),
+    ("canned-acl", "B2 has no object ACLs, only private or public buckets"),
+    ("grant-read", "B2 has no object ACLs, only private or public buckets"),
+    ("grant-full", "B2 has no object ACLs, only private or public buckets"),
+    ("grant-read-acp", "B2 has no object ACLs, only private or public buckets"),
+    ("grant-write-acp", "B2 has no object ACLs, only private or public buckets"),
+    ("checksum-algorithm", "B2 doesn't accept additional checksum headers"),
+];
+
+/// S3-compatible providers whose endpoint is derived from an account or region, with their quirks applied
+#[derive(clap::Args, Debug, Clone, Default)]
+#[clap(next_help_heading = "Providers")]
+pub struct OptionsProvider {
+    /// Use this provider's endpoint for --region, or its default region, instead of giving its endpoint.
+    /// Also read from `provider = "NAME"` in the config file
+    #[clap(long, global=true, value_enum, conflicts_with_all=["endpoint", "r2_account_id", "b2_region"])]
+    pub provider: Option<Preset>,
+    /// Use Cloudflare R2 with this account ID, instead of giving its endpoint
+    #[clap(long, global=true, value_name="ID", value_parser=parse_account_id, conflicts_with="endpoint")]
+    pub r2_account_id: Option<String>,
+    /// R2 jurisdiction the account's buckets are restricted to
+    #[clap(long, global=true, requires="r2_account_id", value_parser=["eu", "fedramp"])]
+    pub jurisdiction: Option<String>,
+    /// Use Backblaze B2 in this region, e.g. us-west-004, instead of giving its endpoint
+    #[clap(long, global=true, value_name="REGION", value_parser=parse_region, conflicts_with_all=["endpoint", "r2_account_id", "region"])]
+    pub b2_region: Option<String>,
+}
+
+pub fn parse_region(s: &str) -> Result<String, String> {
+    match !s.is_empty() && s.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        true => Ok(s.to_owned()),
+        false => Err(format!("invalid region '{s}', expected lowercase letters, digits and '-'")),
+    }
+}
+
+fn parse_account_id(s: &str) -> Result<String, String> {
+    match !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric()) {
+        true => Ok(s.to_ascii_lowercase()),
+        false => Err(format!("invalid account ID '{s}', expected letters and digits")),
+    }
+}
+
+impl OptionsProvider {
+    /// Whether the endpoint comes from any of these options
+    pub fn is_enabled(&self) -> bool {
+        self.provider.is_some() || self.r2_account_id.is_some() || self.b2_region.is_some()
+    }
+
+    /// Endpoint to use instead of any given with --endpoint, for the region given with --region if any
+    pub fn endpoint(&self, region: Option<&str>) -> Option<http::uri::Uri> {
+        if let Some(preset) = self.provider {
+            let host = preset.host(region.unwrap_or(preset.default_region()));
+            return Some(format!("https://{host}").parse().expect("valid region is a valid host"));
+        }
+        if let Some(region) = &self.b2_region {
+            return Some(format!("https://s3.{region}.backblazeb2.com").parse().expect("valid region is a valid host"));
+        }
+        let account_id = self.r2_account_id.as_ref()?;
+        let host = match &self.jurisdiction {
+            Some(jurisdiction) => format!("{account_id}.{jurisdiction}.r2.cloudflarestorage.com"),
+            None => format!("{account_id}.r2.cloudflarestorage.com"),
+        };
+        Some(format!("https://{host}").parse().expect("valid account id is a valid host"))
+    }
+
+    /// Region to sign for, unless given with --region
+    pub fn region(&self) -> Option<&str> {
+        match (self.provider, &self.b2_region, &self.r2_account_id) {
+            (Some(preset), _, _) => Some(preset.default_region()),
+            (None, Some(region), _) => Some(region),
+            (None, None, Some(_)) => Some("auto"),
+            (None, None, None) => None,
+        }
+    }
+
+    /// Reject the first of `flags` (long names) asking for what the provider doesn't implement,
+    /// before requests fail one by one
+    pub fn check_supported<'a>(&self, flags: impl IntoIterator<Item = &'a str>) -> Result<(), String> {
+        if self.b2_region.is_none() {
+            return Ok(());
+        }
+        for flag in flags {
+            if let Some((_, why)) = B2_UNSUPPORTED.iter().find(|(unsupported, _)| *unsupported == flag) {
+                return Err(format!("--{flag} isn't supported with --b2-region: {why}"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Work around requests the provider rejects or mishandles
+    pub fn apply(&self, builder: aws_sdk_s3::config::Builder) -> aws_sdk_s3::config::Builder {
+        match self.is_enabled() {
+            // Not every checksum header the SDK sends by default is accepted
+            true => builder
+                .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+                .response_checksum_validation(ResponseChecksumValidation::WhenRequired),
+            false => builder,
+        }
+    }
+}
+
+#[test]
+fn test_r2_endpoint() {
+    let r2 = |account: &str, jurisdiction: Option<&str>| OptionsProvider {
+        r2_account_id: Some(parse_account_id(account).unwrap()),
+        jurisdiction: jurisdiction.map(str::to_owned),
+        b2_region: None,
+        provider: None,
+    };
+    assert_eq!(r2("0123ABCD", None).endpoint(None).unwrap().to_string(), "https://0123abcd.r2.cloudflarestorage.com/");
+    assert_eq!(r2("0123abcd", Some("eu")).endpoint(None).unwrap().to_string(), "https://0123abcd.eu.r2.cloudflarestorage.com/");
+    assert_eq!(r2("0123abcd", None).region(), Some("auto"));
+    assert!(OptionsProvider::default().endpoint(None).is_none());
+    assert!(parse_account_id("evil.example.com/").is_err());
+}
+
+#[test]
+fn test_b2_endpoint() {
+    let b2 = OptionsProvider { b2_region: Some(parse_region("us-west-004").unwrap()), ..Default::default() };
+    assert_eq!(b2.endpoint(None).unwrap().to_string(), "https://s3.us-west-004.backblazeb2.com/");
+    assert_eq!(b2.region(), Some("us-west-004"));
+    assert!(parse_region("us-west-004.evil.com/").is_err());
+    assert!(parse_region("").is_err());
+
+    assert!(b2.check_supported(["recursive", "metadata", "cache-control"]).is_ok());
+    assert!(b2.check_supported(["recursive", "class"]).unwrap_err().contains("--class"));
+    assert!(b2.check_supported(["grant-read"]).is_err());
+    assert!(OptionsProvider::default().check_supported(["class", "accelerate"]).is_ok());
+}
+
+#[test]
+fn test_preset_endpoint() {
+    let preset = |name: &str| OptionsProvider { provider: Some(name.parse().unwrap()), ..Default::default() };
+    assert_eq!(preset("scaleway").endpoint(Some("nl-ams")).unwrap().to_string(), "https://s3.nl-ams.scw.cloud/");
+    assert_eq!(preset("Hetzner").endpoint(None).unwrap().to_string(), "https://fsn1.your-objectstorage.com/");
+    assert_eq!(preset("ovh").region(), Some("gra"));
+    assert!("minio".parse::<Preset>().is_err());
+}
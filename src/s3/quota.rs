@@ -0,0 +1,69 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::Error;
+
+/// Error codes of providers refusing writes once an account or bucket is out of storage
+const QUOTA_ERROR_CODES: &[&str] = &[
+    "QuotaExceeded",
+    "QuotaExceededException",
+    "StorageQuotaExceeded",
+    "XMinioAdminBucketQuotaExceeded",
+    "XMinioStorageFull",
+    "InsufficientStorage",
+];
+
+impl Error {
+    /// Whether the provider refused the request for lack of storage quota, rather than transiently
+    pub fn is_quota_exceeded(&self) -> bool {
+        let Error::S3SdkErrorMeta(meta) = self else {
+            return false;
+        };
+        let code = meta.code().unwrap_or_default();
+        // Backblaze B2 reports its storage cap as access denied
+        QUOTA_ERROR_CODES.contains(&code)
+            || (code == "AccessDenied" && meta.message().is_some_and(|message| message.to_lowercase().contains("cap exceeded")))
+    }
+}
+
+/// Whether a quota error has been seen, and the transfers not attempted since, shared by clones of a Client
+#[derive(Clone, Default)]
+pub struct Quota(Arc<(AtomicBool, Mutex<Vec<String>>)>);
+
+impl Quota {
+    pub fn is_exceeded(&self) -> bool {
+        self.0.0.load(Ordering::Relaxed)
+    }
+    /// Note `error`, returning whether it exceeded the quota
+    pub fn check(&self, error: &Error) -> bool {
+        let exceeded = error.is_quota_exceeded();
+        if exceeded {
+            self.0.0.store(true, Ordering::Relaxed);
+        }
+        exceeded
+    }
+    pub fn not_attempted(&self, name: String) {
+        self.0.1.lock().unwrap().push(name);
+    }
+    /// Names given to `not_attempted`, in order
+    pub fn take_not_attempted(&self) -> Vec<String> {
+        std::mem::take(&mut *self.0.1.lock().unwrap())
+    }
+}
+
+#[test]
+fn test_quota_exceeded() {
+    let error = |code: &str, message: &str| Error::S3SdkErrorMeta(aws_sdk_s3::error::ErrorMetadata::builder().code(code).message(message).build());
+    assert!(error("QuotaExceeded", "bucket quota exceeded").is_quota_exceeded());
+    assert!(error("XMinioStorageFull", "Storage backend has reached its minimum free drive threshold").is_quota_exceeded());
+    assert!(error("AccessDenied", "Storage cap exceeded.").is_quota_exceeded());
+    assert!(!error("AccessDenied", "Access Denied").is_quota_exceeded());
+    assert!(!error("SlowDown", "Please reduce your request rate").is_quota_exceeded());
+    assert!(!Error::NoFilename.is_quota_exceeded());
+
+    let quota = Quota::default();
+    assert!(!quota.check(&error("SlowDown", "")));
+    assert!(!quota.clone().is_exceeded());
+    assert!(quota.check(&error("QuotaExceeded", "")));
+    assert!(quota.clone().is_exceeded());
+}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use futures::StreamExt;
+
+use super::{Client, Error, Uri, Target};
+use crate::cli;
+
+/// Fetch objects larger than one part as concurrent ranged GETs
+#[derive(Debug, Clone, Copy)]
+pub struct RangedDownload {
+    pub part_size: u64,
+    pub concurrency: usize,
+}
+
+/// Total object size from a Content-Range header, e.g. "bytes 0-99/1234"
+fn content_range_total(content_range: &str) -> Option<u64> {
+    content_range.rsplit_once('/')?.1.parse().ok()
+}
+
+/// Inclusive byte ranges after the first part
+fn remaining_ranges(part_size: u64, total: u64) -> impl Iterator<Item = (u64, u64)> {
+    (part_size..total).step_by(part_size as usize)
+        .map(move |start| (start, (start + part_size).min(total) - 1))
+}
+
+/// Write fetched parts as they arrive, stopping at the first that failed to be fetched or written.
+/// Parts are paced as they're received, not here
+pub(super) async fn write_in_order(local_file: &mut super::partial_file::PartialFile, parts: impl futures::Stream<Item = Result<bytes::Bytes, Error>>, progress_fn: &cli::ProgressFn) -> Result<(), Error> {
+    let mut parts = std::pin::pin!(parts);
+    while let Some(bytes) = parts.next().await {
+        let bytes = bytes?;
+        local_file.write_all(&bytes).await?;
+        progress_fn(cli::Update::StateProgress(bytes.len()));
+    }
+    Ok(())
+}
+
+impl Client {
+    /// Download with the first part streamed while the rest are fetched concurrently,
+    /// written in order as they complete. None for empty objects, which have no first part
+    #[allow(clippy::too_many_arguments)]
+    pub(super) async fn get_ranged(&self, verbose: bool, ranged: RangedDownload, from: &Uri, etag: Option<&str>, to: &Target, limiter: &super::rate_limit::Limiter, progress_fn: cli::ProgressFn) -> Result<Option<PathBuf>, Error> {
+        let first = self.client.get_object()
+            .bucket(from.bucket.clone())
+            .key(from.key.to_string())
+            .range(format!("bytes=0-{}", ranged.part_size - 1))
+            .set_if_match(etag.map(str::to_owned))
+            .send()
+            .await;
+        let first = match first {
+            Err(e) if e.code() == Some("InvalidRange") => return Ok(None),
+            result => result.map_err(|e| super::error_from_get(from, e))?,
+        };
+        let length = first.content_length().unwrap_or(0) as u64;
+        // Servers ignoring Range send the whole object
+        let total = first.content_range().and_then(content_range_total).unwrap_or(length);
+        // Later parts must come from the same version as the first
+        let etag = first.e_tag().map(str::to_owned).or_else(|| etag.map(str::to_owned));
+
+        progress_fn(cli::Update::State("opening"));
+        let local_path = to.local_path(from)?;
+        let mut local_file = super::partial_file::PartialFile::new(local_path, self.disk_jobs.clone()).await?;
+
+        progress_fn(cli::Update::State("downloading"));
+        progress_fn(cli::Update::StateLength(total as usize));
+        if verbose {
+            let parts = total.div_ceil(ranged.part_size);
+            println!("🏁 downloading '{from}' [{total} bytes, {parts} parts, {concurrency} concurrent] to {path_printable}", concurrency = ranged.concurrency, path_printable = local_file.path_printable());
+        }
+
+        let mut result = super::get_write_loop(&mut local_file, first.body, limiter, &progress_fn).await;
+        if result.is_ok() && length < total {
+            let parts = futures::stream::iter(remaining_ranges(ranged.part_size, total))
+                .map(|(start, end)| self.get_range(from, start, end, etag.as_deref(), limiter))
+                .buffered(ranged.concurrency);
+            result = write_in_order(&mut local_file, parts, &progress_fn).await;
+        }
+        let local_path = match result {
+            Ok(_) => local_file.finished().await?,
+            Err(err) => {
+                local_file.cancelled().await?;
+                return Err(err);
+            }
+        };
+        self.audit_read(from, total, etag.as_deref());
+        progress_fn(cli::Update::Finished());
+        Ok(Some(local_path))
+    }
+}
+
+#[test]
+fn test_ranges() {
+    assert_eq!(content_range_total("bytes 0-99/1234"), Some(1234));
+    assert_eq!(content_range_total("bytes 0-99/*"), None);
+    assert_eq!(remaining_ranges(100, 250).collect::<Vec<_>>(), [(100, 199), (200, 249)]);
+    assert_eq!(remaining_ranges(100, 200).collect::<Vec<_>>(), [(100, 199)]);
+    assert_eq!(remaining_ranges(100, 50).count(), 0);
+}
@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aws_sdk_s3::primitives::{ByteStream, SdkBody};
+use bytes::{Bytes, BytesMut};
+use tokio::time::Instant;
+
+/// Paces bytes to a rate, shared by clones: each acquisition waits for the time the bytes
+/// before it take at that rate
+#[derive(Clone, Debug)]
+struct Pace {
+    bytes_per_second: u64,
+    next: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Pace {
+    fn new(bytes_per_second: u64) -> Pace {
+        Pace { bytes_per_second, next: Default::default() }
+    }
+
+    /// Reserve the time `bytes` take, returning when they may be sent
+    fn reserve(&self, bytes: usize) -> Instant {
+        let now = Instant::now();
+        let mut next = self.next.lock().unwrap();
+        // Idle time isn't saved up for a burst
+        let start = next.map_or(now, |next| next.max(now));
+        *next = Some(start + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64));
+        start
+    }
+}
+
+/// Rate limits of a Client: one across its transfers, and one each
+#[derive(Clone, Debug, Default)]
+pub struct RateLimits {
+    global: Option<Pace>,
+    per_file: Option<u64>,
+}
+
+impl RateLimits {
+    pub fn new(global: Option<u64>, per_file: Option<u64>) -> RateLimits {
+        RateLimits { global: global.map(Pace::new), per_file }
+    }
+
+    /// Limiter for a single file's transfer
+    pub fn transfer(&self) -> Limiter {
+        Limiter { global: self.global.clone(), file: self.per_file.map(Pace::new) }
+    }
+}
+
+/// Paces one transfer to both its own and the shared limit
+#[derive(Clone, Debug, Default)]
+pub struct Limiter {
+    global: Option<Pace>,
+    file: Option<Pace>,
+}
+
+impl Limiter {
+    /// Wait until `bytes` may be sent or received
+    pub async fn acquire(&self, bytes: usize) {
+        if let Some(file) = &self.file {
+            tokio::time::sleep_until(file.reserve(bytes)).await;
+        }
+        if let Some(global) = &self.global {
+            tokio::time::sleep_until(global.reserve(bytes)).await;
+        }
+    }
+
+    fn is_limited(&self) -> bool {
+        self.file.is_some() || self.global.is_some()
+    }
+
+    /// `data` as a request body sent a block at a time as allowed, paced afresh on each retry.
+    /// Its length isn't known to the SDK, so the request must set it
+    pub fn body(&self, data: Bytes) -> ByteStream {
+        if !self.is_limited() {
+            return ByteStream::from(data);
+        }
+        let limiter = self.clone();
+        ByteStream::from(SdkBody::retryable(move || {
+            let limiter = limiter.clone();
+            let blocks = futures::stream::unfold(data.clone(), move |mut remaining| {
+                let limiter = limiter.clone();
+                async move {
+                    if remaining.is_empty() {
+                        return None;
+                    }
+                    let block = remaining.split_to(remaining.len().min(super::READ_BLOCK_SIZE));
+                    limiter.acquire(block.len()).await;
+                    Some((Ok::<_, std::io::Error>(hyper::body::Frame::data(block)), remaining))
+                }
+            });
+            // Polled again for trailers after the last block
+            let blocks = futures::StreamExt::fuse(blocks);
+            SdkBody::from_body_1_x(http_body_util::StreamBody::new(blocks))
+        }))
+    }
+
+    /// Whole `body` of a response, received a block at a time as allowed
+    pub async fn collect(&self, mut body: ByteStream) -> Result<Bytes, aws_sdk_s3::primitives::ByteStreamError> {
+        let mut data = BytesMut::new();
+        while let Some(block) = body.try_next().await? {
+            self.acquire(block.len()).await;
+            data.extend_from_slice(&block);
+        }
+        Ok(data.freeze())
+    }
+}
+
+#[test]
+fn test_pace_reserve() {
+    let pace = Pace::new(1000);
+    let first = pace.reserve(500);
+    assert_eq!(pace.reserve(250), first + Duration::from_millis(500));
+    assert_eq!(pace.reserve(250), first + Duration::from_millis(750));
+    let other = pace.clone();
+    assert_eq!(other.reserve(1), first + Duration::from_millis(1000));
+}
@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart};
+use futures::{StreamExt, TryStreamExt};
+use md5::Digest;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::rate_limit::Limiter;
+use super::{checksum, ChangedFile, Client, CustomerKey, Error, OptionsUpload, Uri};
+use crate::cli;
+
+/// Most parts S3 accepts in one multipart upload
+const MAX_PARTS: u64 = 10_000;
+/// Parts uploaded at once for a single file
+const UPLOAD_PART_CONCURRENCY: usize = 4;
+
+#[derive(clap::Args, Debug, Clone)]
+#[clap(next_help_heading = "Multipart Uploads")]
+pub struct OptionsMultipart {
+    /// Upload files larger than this in parts. An interrupted upload is kept, and uploading the
+    /// same file to the same key again only sends the parts it's missing
+    #[clap(long, default_value = "64M", value_name = "SIZE", value_parser = crate::cli::parse_size)]
+    pub multipart_threshold: u64,
+    /// Size of each part, grown for files that would need more than 10000
+    #[clap(long, default_value = "16M", value_name = "SIZE", value_parser = parse_multipart_part_size)]
+    pub multipart_part_size: u64,
+    /// Most attempts at sending each part, only the failed part being sent again
+    #[clap(long, default_value = "5", value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub part_attempts: u32,
+    /// Print how many times parts of multipart uploads were retried once done
+    #[clap(long)]
+    pub stats: bool,
+    /// On ctrl-c, leave multipart uploads in progress to be resumed, rather than aborting them.
+    /// Their parts are stored, and billed, until resumed or aborted
+    #[clap(long)]
+    pub keep_cancelled_uploads: bool,
+}
+
+/// Retries of each multipart upload part that needed them, shared by clones of a Client
+#[derive(Clone, Default)]
+pub struct PartRetries(Arc<Mutex<Vec<(Uri, i32, u32)>>>);
+
+impl PartRetries {
+    fn record(&self, uri: &Uri, part_number: i32, retries: u32) {
+        self.0.lock().unwrap().push((uri.clone(), part_number, retries));
+    }
+    /// Destination, part number and retries of each part retried, in the order they finished
+    pub fn retried(&self) -> Vec<(Uri, i32, u32)> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Multipart uploads started and not yet completed, shared by clones of a Client.
+///
+/// An upload whose future is dropped on cancellation stays here to be aborted
+#[derive(Clone, Default)]
+pub struct InFlight(Arc<Mutex<Vec<(Uri, String)>>>);
+
+impl InFlight {
+    pub(super) fn add(&self, uri: &Uri, upload_id: &str) {
+        self.0.lock().unwrap().push((uri.clone(), upload_id.to_owned()));
+    }
+    pub(super) fn remove(&self, upload_id: &str) {
+        self.0.lock().unwrap().retain(|(_, id)| id != upload_id);
+    }
+    fn take(&self) -> Vec<(Uri, String)> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+fn parse_multipart_part_size(s: &str) -> Result<u64, String> {
+    match crate::cli::parse_size(s)? {
+        size if size < 5 * 1024 * 1024 => Err("part size must be at least 5MiB".into()),
+        size => Ok(size),
+    }
+}
+
+/// Offset and length of each part of a file of `length` bytes
+fn part_ranges(length: u64, part_size: u64) -> Vec<(u64, u64)> {
+    let part_size = part_size.max(length.div_ceil(MAX_PARTS));
+    (0..length).step_by(part_size as usize)
+        .map(|offset| (offset, part_size.min(length - offset)))
+        .collect()
+}
+
+/// Parts already uploaded, by number, with their size, ETag and checksum for --checksum-algorithm
+type UploadedParts = HashMap<i32, (i64, String, Option<String>)>;
+
+/// Where multipart uploads started from this machine are recorded, within the platform's
+/// state directory. Only recorded uploads are resumed, never ones started elsewhere
+fn records_dir() -> Option<PathBuf> {
+    let state_dir = match (std::env::var_os("XDG_STATE_HOME"), std::env::var_os("LOCALAPPDATA"), std::env::var_os("HOME")) {
+        (Some(xdg), _, _) => PathBuf::from(xdg),
+        (None, Some(local), _) if cfg!(windows) => PathBuf::from(local),
+        (None, _, Some(home)) => Path::new(&home).join(".local").join("state"),
+        _ => return None,
+    };
+    Some(state_dir.join("sup3").join("uploads"))
+}
+
+fn md5_hex(data: &[u8]) -> String {
+    md5::Md5::digest(data).iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// File recording the upload in progress to `to`
+fn record_path(to: &Uri) -> Option<PathBuf> {
+    Some(records_dir()?.join(md5_hex(format!("{}/{}", to.bucket, to.key).as_bytes())))
+}
+
+/// Upload ID and fingerprint recorded at `path`
+async fn read_record(path: &Path) -> Option<(String, String)> {
+    let record = tokio::fs::read_to_string(path).await.ok()?;
+    let (upload_id, fingerprint) = record.trim_end().split_once('\n')?;
+    Some((upload_id.to_owned(), fingerprint.to_owned()))
+}
+
+async fn write_record(path: &Path, upload_id: &str, fingerprint: &str) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(path.parent().expect("record within a directory")).await?;
+    tokio::fs::write(path, format!("{upload_id}\n{fingerprint}\n")).await
+}
+
+/// What a multipart upload depends on: the local file as it is now, its part size, and the
+/// options the upload is started with, which resuming it keeps whatever options are given later
+fn upload_fingerprint(options_upload: &OptionsUpload, path: &Path, length: u64, modified: Option<std::time::SystemTime>, to: &Uri) -> String {
+    let mut metadata = options_upload.metadata.clone();
+    metadata.sort();
+    let settings = format!("{:?}", (
+        (path, length, modified, options_upload.multipart.multipart_part_size),
+        &options_upload.canned_acl, &options_upload.access_control, options_upload.class_for(path, length),
+        options_upload.cache_control_for(&to.key), metadata, options_upload.tagging(),
+        &options_upload.headers.content_encoding, &options_upload.headers.content_disposition, options_upload.headers.expires,
+        &options_upload.checksum_algorithm,
+    ));
+    md5_hex(settings.as_bytes())
+}
+
+/// Whether an upload failing with `e` might succeed sent again, so is worth keeping to resume
+fn is_resumable(e: &Error) -> bool {
+    match e {
+        Error::S3SdkError(..) | Error::S3SdkErrorDebug(..) | Error::Streaming(_) => true,
+        Error::S3SdkErrorMeta(meta) => matches!(meta.code(), Some("InternalError" | "ServiceUnavailable" | "SlowDown" | "RequestTimeout" | "Throttling" | "TooManyRequests")),
+        _ => false,
+    }
+}
+
+impl Client {
+    /// Whether the upload `upload_id` to `to` is still in progress with the storage class and
+    /// checksum algorithm requested
+    async fn is_pending(&self, to: &Uri, upload_id: &str, class: Option<&aws_sdk_s3::types::StorageClass>, algorithm: Option<&ChecksumAlgorithm>) -> Result<bool, Error> {
+        let response = self.client.list_multipart_uploads()
+            .bucket(to.bucket.clone())
+            .prefix(to.key.to_string())
+            .send()
+            .await?;
+        Ok(response.uploads().iter().any(|upload| {
+            upload.key() == Some(to.key.as_str())
+                && upload.upload_id() == Some(upload_id)
+                && upload.checksum_algorithm() == algorithm
+                && class.is_none_or(|class| upload.storage_class().is_none_or(|listed| listed.as_str() == class.as_str()))
+        }))
+    }
+
+    async fn abort_upload(&self, to: &Uri, upload_id: &str) {
+        let _ = self.client.abort_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .upload_id(upload_id)
+            .send()
+            .await;
+    }
+
+    pub(super) async fn uploaded_parts(&self, to: &Uri, upload_id: &str, algorithm: Option<&ChecksumAlgorithm>) -> Result<UploadedParts, Error> {
+        let mut parts = HashMap::new();
+        let mut marker: Option<String> = None;
+        loop {
+            let response = self.client.list_parts()
+                .bucket(to.bucket.clone())
+                .key(to.key.to_string())
+                .upload_id(upload_id)
+                .set_part_number_marker(marker.take())
+                .send()
+                .await?;
+            for part in response.parts() {
+                if let (Some(number), Some(etag)) = (part.part_number(), part.e_tag()) {
+                    let checksum = algorithm.and_then(|algorithm| checksum::of_part(part, algorithm));
+                    parts.insert(number, (part.size().unwrap_or(0), etag.trim_matches('"').to_owned(), checksum));
+                }
+            }
+            match (response.is_truncated(), response.next_part_number_marker()) {
+                (Some(true), Some(next)) => marker = Some(next.to_owned()),
+                _ => return Ok(parts),
+            }
+        }
+    }
+
+    /// Resume the upload to `to` recorded with the same fingerprint, or start one, with the parts
+    /// it already has. A recorded upload of an older file or with other options is aborted
+    #[allow(clippy::too_many_arguments)]
+    async fn start_or_resume(&self, verbose: bool, options_upload: &OptionsUpload, path: &Path, length: u64, to: &Uri, recorded: Option<(String, String)>, fingerprint: &str) -> Result<(String, UploadedParts), Error> {
+        let algorithm = options_upload.checksum_algorithm.as_ref();
+        match recorded {
+            Some((upload_id, recorded_fingerprint)) if recorded_fingerprint == fingerprint => {
+                let class = options_upload.class_for(path, length);
+                if self.is_pending(to, &upload_id, class.as_ref(), algorithm).await? {
+                    match self.uploaded_parts(to, &upload_id, algorithm).await {
+                        Ok(parts) => {
+                            if verbose {
+                                println!("🏁 resuming upload to {to}, {} part(s) already uploaded", parts.len());
+                            }
+                            return Ok((upload_id, parts));
+                        },
+                        Err(Error::S3SdkErrorMeta(meta)) if meta.code() == Some("NoSuchUpload") => {},
+                        Err(e) => return Err(e),
+                    }
+                }
+            },
+            Some((upload_id, _)) => {
+                if verbose {
+                    println!("🏁 aborting earlier upload to {to}, of a changed file or with other options");
+                }
+                self.abort_upload(to, &upload_id).await;
+            },
+            None => {},
+        }
+        let upload = self.client.create_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .set_acl(options_upload.canned_acl.to_owned())
+            .set_grant_read(options_upload.access_control.grant_read.to_owned())
+            .set_grant_full_control(options_upload.access_control.grant_full.to_owned())
+            .set_grant_read_acp(options_upload.access_control.grant_read_acp.to_owned())
+            .set_grant_write_acp(options_upload.access_control.grant_write_acp.to_owned())
+            .set_storage_class(options_upload.class_for(path, length))
+            .set_cache_control(options_upload.cache_control_for(&to.key))
+            .set_metadata(options_upload.user_metadata())
+            .set_tagging(options_upload.tagging())
+            .set_content_encoding(options_upload.headers.content_encoding.to_owned())
+            .set_content_disposition(options_upload.headers.content_disposition.to_owned())
+            .set_expires(options_upload.headers.expires)
+            .set_checksum_algorithm(options_upload.checksum_algorithm.clone())
+            .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+            .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+            .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
+            .send()
+            .await?;
+        Ok((upload.upload_id().expect("upload id in response").to_owned(), HashMap::new()))
+    }
+
+    async fn read_part(&self, path: &Path, offset: u64, length: u64) -> Result<Vec<u8>, Error> {
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+        let mut data = vec![0; length as usize];
+        let _permit = self.disk_jobs.acquire().await;
+        match file.read_exact(&mut data).await {
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Err(Error::LocalFileChanged(path.to_owned())),
+            result => result.map(|_| data).map_err(Error::from),
+        }
+    }
+
+    /// Multipart upload of a local file, resuming an earlier upload of it to the same key
+    /// started from this machine with the same options. Parts found uploaded are only reused
+    /// if their MD5 ETag matches the local data. Returns the ETag of the completed object
+    #[allow(clippy::too_many_arguments)]
+    pub async fn put_resumable(&self, verbose: bool, options_upload: &OptionsUpload, path: &Path, length: u64, to: &Uri, limiter: &Limiter, progress_fn: &cli::ProgressFn) -> Result<Option<String>, Error> {
+        let modified = tokio::fs::metadata(path).await?.modified().ok();
+        let canonical = tokio::fs::canonicalize(path).await?;
+        let fingerprint = upload_fingerprint(options_upload, &canonical, length, modified, to);
+        let record = record_path(to);
+        let recorded = match &record {
+            Some(record) => read_record(record).await,
+            None => None,
+        };
+        let (upload_id, uploaded) = self.start_or_resume(verbose, options_upload, path, length, to, recorded, &fingerprint).await?;
+        let resumable = match &record {
+            Some(record) => write_record(record, &upload_id, &fingerprint).await.is_ok(),
+            None => false,
+        };
+        self.in_flight.add(to, &upload_id);
+        let result = self.put_parts(options_upload, path, length, to, &upload_id, &uploaded, limiter, progress_fn).await;
+        // Cancelled uploads never get here, they're left for abort_in_flight
+        self.in_flight.remove(&upload_id);
+        match &result {
+            Err(e) if resumable && is_resumable(e) => {},
+            Ok(_) => if let Some(record) = &record {
+                let _ = tokio::fs::remove_file(record).await;
+            },
+            Err(_) => {
+                self.abort_upload(to, &upload_id).await;
+                if let Some(record) = &record {
+                    let _ = tokio::fs::remove_file(record).await;
+                }
+            },
+        }
+        result
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn put_parts(&self, options_upload: &OptionsUpload, path: &Path, length: u64, to: &Uri, upload_id: &str, uploaded: &UploadedParts, limiter: &Limiter, progress_fn: &cli::ProgressFn) -> Result<Option<String>, Error> {
+        let ranges = part_ranges(length, options_upload.multipart.multipart_part_size);
+        let parts = futures::stream::iter(ranges.into_iter().enumerate())
+            .map(|(index, (offset, part_length))| {
+                Ok(async move {
+                    let part_number = index as i32 + 1;
+                    let data = self.read_part(path, offset, part_length).await?;
+                    let digest: String = md5::Md5::digest(&data).iter().map(|b| format!("{b:02x}")).collect();
+                    let algorithm = options_upload.checksum_algorithm.as_ref();
+                    let (e_tag, checksum) = match uploaded.get(&part_number) {
+                        Some((size, e_tag, checksum)) if *size as u64 == part_length && *e_tag == digest && (algorithm.is_none() || checksum.is_some()) => (e_tag.clone(), checksum.clone()),
+                        _ => {
+                            progress_fn(cli::Update::StateBuffered(part_length as usize));
+                            // Each attempt is signed afresh, count them to report retries
+                            let attempts = Arc::new(AtomicU32::new(0));
+                            let counter = attempts.clone();
+                            let retry_config = aws_sdk_s3::config::retry::RetryConfig::standard()
+                                .with_max_attempts(options_upload.multipart.part_attempts);
+                            let response = self.client.upload_part()
+                                .bucket(to.bucket.clone())
+                                .key(to.key.to_string())
+                                .upload_id(upload_id)
+                                .part_number(part_number)
+                                .set_checksum_algorithm(algorithm.cloned())
+                                .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+                                .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+                                .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
+                                .content_length(part_length as i64)
+                                .body(limiter.body(data.into()))
+                                .customize()
+                                .config_override(aws_sdk_s3::config::Builder::default().retry_config(retry_config))
+                                .mutate_request(move |_| { counter.fetch_add(1, Ordering::Relaxed); })
+                                .send()
+                                .await;
+                            let retries = attempts.load(Ordering::Relaxed).saturating_sub(1);
+                            if retries > 0 {
+                                self.part_retries.record(to, part_number, retries);
+                            }
+                            let response = response?;
+                            let checksum = algorithm.and_then(|algorithm| checksum::of_upload(&response, algorithm));
+                            (response.e_tag().unwrap_or_default().to_owned(), checksum)
+                        },
+                    };
+                    progress_fn(cli::Update::StateProgress(part_length as usize));
+                    let part = CompletedPart::builder().part_number(part_number).e_tag(e_tag);
+                    let part = match algorithm {
+                        Some(algorithm) => checksum::with_checksum(part, algorithm, checksum),
+                        None => part,
+                    };
+                    Ok::<_, Error>(part.build())
+                })
+            })
+            .try_buffered(UPLOAD_PART_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        // Parts were read at offsets fixed by the length at start, growth would go unnoticed
+        if options_upload.changed_file != ChangedFile::Ignore && tokio::fs::metadata(path).await?.len() != length {
+            return Err(Error::LocalFileChanged(path.to_owned()));
+        }
+        let extra_headers = options_upload.conditional.extra_headers();
+        let completed = self.client.complete_multipart_upload()
+            .bucket(to.bucket.clone())
+            .key(to.key.to_string())
+            .upload_id(upload_id)
+            .set_if_match(options_upload.conditional.if_match.as_deref().map(super::quoted_etag))
+            .set_sse_customer_algorithm(options_upload.sse_c_key.as_ref().map(CustomerKey::algorithm))
+            .set_sse_customer_key(options_upload.sse_c_key.as_ref().map(CustomerKey::key))
+            .set_sse_customer_key_md5(options_upload.sse_c_key.as_ref().map(CustomerKey::key_md5))
+            .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+            .customize()
+            .mutate_request(move |request| {
+                for (name, value) in &extra_headers {
+                    request.headers_mut().insert(*name, value.clone());
+                }
+            })
+            .send()
+            .await?;
+        Ok(completed.e_tag().map(str::to_owned))
+    }
+
+    /// Abort multipart uploads left in progress by cancelled transfers, giving up after `timeout`.
+    /// Returns how many were aborted, and how many there were
+    pub async fn abort_in_flight(&self, timeout: std::time::Duration) -> (usize, usize) {
+        let uploads = self.in_flight.take();
+        let aborted = Arc::new(AtomicU32::new(0));
+        let aborts = uploads.iter().map(|(uri, upload_id)| {
+            let aborted = aborted.clone();
+            async move {
+                let result = self.client.abort_multipart_upload()
+                    .bucket(uri.bucket.clone())
+                    .key(uri.key.to_string())
+                    .upload_id(upload_id)
+                    .send()
+                    .await;
+                if result.is_ok() {
+                    aborted.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+        let _ = tokio::time::timeout(timeout, futures::future::join_all(aborts)).await;
+        (aborted.load(Ordering::Relaxed) as usize, uploads.len())
+    }
+}
+
+#[test]
+fn test_upload_fingerprint() {
+    use clap::Parser;
+    #[derive(clap::Parser)]
+    struct Options {
+        #[clap(flatten)]
+        upload: OptionsUpload,
+    }
+    let options = |args: &[&str]| Options::parse_from(std::iter::once("sup3").chain(args.iter().copied())).upload;
+    let to: Uri = "s3://bucket/big.bin".parse().unwrap();
+    let path = Path::new("/data/big.bin");
+    let modified = Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000));
+    let fingerprint = |options: &OptionsUpload, length, modified| upload_fingerprint(options, path, length, modified, &to);
+    let plain = fingerprint(&options(&[]), 100 << 20, modified);
+    assert_eq!(plain, fingerprint(&options(&[]), 100 << 20, modified));
+    assert_ne!(plain, fingerprint(&options(&[]), 101 << 20, modified));
+    assert_ne!(plain, fingerprint(&options(&[]), 100 << 20, None));
+    assert_ne!(plain, fingerprint(&options(&["--class", "GLACIER"]), 100 << 20, modified));
+    assert_ne!(plain, fingerprint(&options(&["--metadata", "a=1"]), 100 << 20, modified));
+    assert_ne!(plain, fingerprint(&options(&["--canned-acl", "public-read"]), 100 << 20, modified));
+    assert_eq!(
+        fingerprint(&options(&["--metadata", "a=1", "--metadata", "b=2"]), 100 << 20, modified),
+        fingerprint(&options(&["--metadata", "b=2", "--metadata", "a=1"]), 100 << 20, modified),
+    );
+
+    assert!(is_resumable(&Error::S3SdkError("", "connection reset".into())));
+    assert!(is_resumable(&Error::S3SdkErrorMeta(aws_sdk_s3::error::ErrorMetadata::builder().code("SlowDown").build())));
+    assert!(!is_resumable(&Error::S3SdkErrorMeta(aws_sdk_s3::error::ErrorMetadata::builder().code("PreconditionFailed").build())));
+    assert!(!is_resumable(&Error::LocalFileChanged(path.to_owned())));
+}
+
+#[test]
+fn test_part_ranges() {
+    assert_eq!(part_ranges(25, 10), [(0, 10), (10, 10), (20, 5)]);
+    assert_eq!(part_ranges(20, 10), [(0, 10), (10, 10)]);
+    let many = part_ranges(MAX_PARTS * 10 + 1, 5);
+    assert!(many.len() as u64 <= MAX_PARTS);
+    assert_eq!(many.iter().map(|(_, length)| length).sum::<u64>(), MAX_PARTS * 10 + 1);
+}
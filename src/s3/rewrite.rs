@@ -0,0 +1,130 @@
+use super::{Error, Key, Uri};
+
+/// A sed-like substitution `sDREGEXDREPLACEMENTD[g]`, D being any delimiter, with `\1` and `&`
+/// in the replacement for the groups and the whole match
+#[derive(Debug, Clone)]
+pub struct Rewrite {
+    regex: regex::Regex,
+    replacement: String,
+    global: bool,
+}
+
+/// `s` split at each `delimiter` not escaped with a backslash, unescaping them
+fn split_unescaped(s: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == delimiter => parts.last_mut().unwrap().push(next),
+                Some(next) => parts.last_mut().unwrap().extend(['\\', next]),
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            c if c == delimiter => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+/// sed replacement syntax as the regex crate's
+fn regex_replacement(sed: &str) -> String {
+    let mut replacement = String::new();
+    let mut chars = sed.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => replacement.push_str(&format!("${{{digit}}}")),
+                Some('$') => replacement.push_str("$$"),
+                Some(literal) => replacement.push(literal),
+                None => replacement.push('\\'),
+            },
+            '&' => replacement.push_str("${0}"),
+            '$' => replacement.push_str("$$"),
+            c => replacement.push(c),
+        }
+    }
+    replacement
+}
+
+impl std::str::FromStr for Rewrite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let (Some('s'), Some(delimiter)) = (chars.next(), chars.next()) else {
+            return Err(format!("invalid rewrite '{s}', expected e.g. 's#^raw/#processed/#'"));
+        };
+        let [pattern, replacement, flags] = &split_unescaped(chars.as_str(), delimiter)[..] else {
+            return Err(format!("invalid rewrite '{s}', expected three '{delimiter}' delimiters"));
+        };
+        let global = match flags.as_str() {
+            "" => false,
+            "g" => true,
+            flags => return Err(format!("unsupported rewrite flags '{flags}', expected none or g")),
+        };
+        let regex = regex::Regex::new(pattern).map_err(|e| format!("invalid regex '{pattern}': {e}"))?;
+        Ok(Rewrite { regex, replacement: regex_replacement(replacement), global })
+    }
+}
+
+impl Rewrite {
+    fn apply<'a>(&self, key: &'a str) -> std::borrow::Cow<'a, str> {
+        match self.global {
+            true => self.regex.replace_all(key, self.replacement.as_str()),
+            false => self.regex.replace(key, self.replacement.as_str()),
+        }
+    }
+}
+
+/// Destination key transformations for server-side copies and moves
+#[derive(clap::Args, Debug, Clone, Default)]
+pub struct OptionsRewrite {
+    /// Rewrite destination keys with a sed-like substitution, e.g. 's#^raw/#processed/#'.
+    /// Repeatable, applied in order
+    #[clap(long, value_name="s/REGEX/REPLACEMENT/", help_heading="Key Rewriting")]
+    pub rewrite: Vec<Rewrite>,
+    /// Lowercase destination keys, after any --rewrite
+    #[clap(long, help_heading="Key Rewriting")]
+    pub lowercase_keys: bool,
+}
+
+impl OptionsRewrite {
+    /// `key` after every rewrite
+    pub fn key(&self, key: &Key) -> Key {
+        let mut rewritten = key.as_str().to_owned();
+        for rewrite in &self.rewrite {
+            rewritten = rewrite.apply(&rewritten).into_owned();
+        }
+        if self.lowercase_keys {
+            rewritten = rewritten.to_lowercase();
+        }
+        Key::new(rewritten)
+    }
+
+    /// Destination of copying the single object `from` to `to`, rewritten
+    pub fn object_destination(&self, from: &Uri, to: &Uri) -> Result<Uri, Error> {
+        let destination = super::copy::object_destination(from, to)?;
+        Ok(Uri::new(destination.bucket, self.key(&destination.key)))
+    }
+}
+
+#[test]
+fn test_rewrite() {
+    let rewrite = |rules: &[&str], lowercase_keys, key: &str| {
+        let options = OptionsRewrite { rewrite: rules.iter().map(|rule| rule.parse().unwrap()).collect(), lowercase_keys };
+        options.key(&Key::new(key.to_owned())).as_str().to_owned()
+    };
+    assert_eq!(rewrite(&["s#^raw/#processed/#"], false, "raw/2024/a.csv"), "processed/2024/a.csv");
+    assert_eq!(rewrite(&["s#^raw/#processed/#"], false, "other/raw/a.csv"), "other/raw/a.csv");
+    assert_eq!(rewrite(&["s/-/_/g"], false, "a-b-c"), "a_b_c");
+    assert_eq!(rewrite(&["s/-/_/"], false, "a-b-c"), "a_b-c");
+    assert_eq!(rewrite(&[r"s|(\d{4})-(\d{2})|\1/\2|"], false, "logs/2024-01.log"), "logs/2024/01.log");
+    assert_eq!(rewrite(&["s/[.]CSV$/&.bak/", "s/^/$HOME\\//"], true, "Data/A.CSV"), "$home/data/a.csv.bak");
+    assert_eq!(rewrite(&[r"s/a\/b/c/"], false, "a/b"), "c");
+
+    assert!("s#a#b".parse::<Rewrite>().is_err());
+    assert!("y/a/b/".parse::<Rewrite>().is_err());
+    assert!("s/a/b/x".parse::<Rewrite>().is_err());
+    assert!("s/(/b/".parse::<Rewrite>().is_err());
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+use aws_sdk_s3::types::StorageClass;
+
+use super::{OptionsUpload, Uri};
+
+/// Objects smaller than this are billed as this size in infrequent access classes
+const MINIMUM_BILLABLE_SIZE: u64 = 128 * 1024;
+
+/// Days an object is billed for in `class`, even if deleted or overwritten sooner
+fn minimum_days(class: &StorageClass) -> Option<u32> {
+    match class {
+        StorageClass::StandardIa | StorageClass::OnezoneIa => Some(30),
+        StorageClass::GlacierIr | StorageClass::Glacier => Some(90),
+        StorageClass::DeepArchive => Some(180),
+        _ => None,
+    }
+}
+
+fn has_minimum_billable_size(class: &StorageClass) -> bool {
+    matches!(class, StorageClass::StandardIa | StorageClass::OnezoneIa | StorageClass::GlacierIr)
+}
+
+/// Directory buckets only hold S3 Express One Zone objects
+fn is_directory_bucket(bucket: &str) -> bool {
+    bucket.ends_with("--x-s3")
+}
+
+fn is_outposts_bucket(bucket: &str) -> bool {
+    bucket.starts_with("arn:aws:s3-outposts:")
+}
+
+impl OptionsUpload {
+    /// Storage class to upload the file at `path` of `size` bytes as, if not the bucket default
+    pub fn class_for(&self, path: &Path, size: u64) -> Option<StorageClass> {
+        match &self.class_rules {
+            Some(rules) => rules.class_for(path, size).cloned(),
+            None => self.class.clone(),
+        }
+    }
+
+    /// Reject a storage class the destination bucket can't hold, or encryption it can't be
+    /// combined with, before transferring anything
+    pub fn validate_destination(&self, to: &Uri) -> Result<(), String> {
+        let directory = is_directory_bucket(&to.bucket);
+        if self.sse_c_key.is_some() && directory {
+            return Err(format!("directory bucket {} doesn't support --sse-c-key", to.bucket));
+        }
+        let rule_classes = self.class_rules.iter().flat_map(|rules| rules.classes());
+        self.class.iter().chain(rule_classes).try_for_each(|class| match class {
+            StorageClass::ExpressOnezone if !directory => Err(format!("storage class EXPRESS_ONEZONE requires a directory bucket (name ending in --x-s3), not {}", to.bucket)),
+            class if directory && *class != StorageClass::ExpressOnezone => Err(format!("directory bucket {} only supports storage class EXPRESS_ONEZONE, not {}", to.bucket, class.as_str())),
+            StorageClass::Glacier | StorageClass::DeepArchive if self.sse_c_key.is_some() => Err(format!("storage class {} can't be combined with --sse-c-key", class.as_str())),
+            StorageClass::Outposts if !is_outposts_bucket(&to.bucket) => Err(format!("storage class OUTPOSTS requires an S3 on Outposts bucket ARN, not {}", to.bucket)),
+            _ => Ok(()),
+        })
+    }
+
+    /// Warn about `files` being billed as larger than they are in the class each would be stored as, if it applies
+    pub fn small_files_warning(&self, files: &[(PathBuf, u64)]) -> Option<String> {
+        let mut classes: Vec<StorageClass> = Vec::new();
+        let mut small_count = 0;
+        for (path, size) in files {
+            let Some(class) = self.class_for(path, *size).filter(|class| has_minimum_billable_size(class) && *size < MINIMUM_BILLABLE_SIZE) else { continue };
+            small_count += 1;
+            if !classes.contains(&class) {
+                classes.push(class);
+            }
+        }
+        let classes: Vec<&str> = classes.iter().map(StorageClass::as_str).collect();
+        (small_count > 0).then(|| format!(
+            "{small_count} file(s) smaller than 128KiB will each be billed as 128KiB in {}", classes.join(", ")))
+    }
+
+    /// Describe what an upload of `count` files totalling `bytes` (if known) will create
+    pub fn preflight_summary(&self, to: &Uri, count: usize, bytes: Option<u64>) -> String {
+        let class = self.class.as_ref().unwrap_or(&StorageClass::Standard);
+        let mut summary = format!("uploading {count} file(s)");
+        if let Some(bytes) = bytes {
+            summary += &format!(" ({bytes} bytes)");
+        }
+        if let Some(rules) = &self.class_rules {
+            let classes: Vec<&str> = rules.classes().map(|class| class.as_str()).collect();
+            return summary + &format!(" to {to} as {} by rule", classes.join(", "));
+        }
+        summary += &format!(" to {to} as {}", class.as_str());
+        if let Some(days) = minimum_days(class) {
+            summary += &format!(", billed for at least {days} days");
+        }
+        if matches!(class, StorageClass::Glacier | StorageClass::DeepArchive) {
+            summary += ", must be restored before download";
+        }
+        summary
+    }
+}
+
+#[test]
+fn test_validate_destination() {
+    use clap::Parser;
+    #[derive(clap::Parser)]
+    struct Upload {
+        #[clap(flatten)]
+        upload: OptionsUpload,
+    }
+    let options = |class: Option<StorageClass>| OptionsUpload { class, ..Upload::parse_from(["upload"]).upload };
+    let regular: Uri = "s3://bucket/key".parse().unwrap();
+    let directory: Uri = "s3://data--usw2-az1--x-s3/key".parse().unwrap();
+
+    assert!(options(None).validate_destination(&regular).is_ok());
+    assert!(options(Some(StorageClass::Glacier)).validate_destination(&regular).is_ok());
+    assert!(options(Some(StorageClass::ExpressOnezone)).validate_destination(&regular).is_err());
+    assert!(options(Some(StorageClass::ExpressOnezone)).validate_destination(&directory).is_ok());
+    assert!(options(Some(StorageClass::StandardIa)).validate_destination(&directory).is_err());
+    assert!(options(Some(StorageClass::Outposts)).validate_destination(&regular).is_err());
+    let encrypted = |class| OptionsUpload { sse_c_key: Some(super::customer_key::parse_customer_key("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=").unwrap()), ..options(class) };
+    assert!(encrypted(Some(StorageClass::StandardIa)).validate_destination(&regular).is_ok());
+    assert!(encrypted(Some(StorageClass::Glacier)).validate_destination(&regular).unwrap_err().contains("--sse-c-key"));
+    assert!(encrypted(Some(StorageClass::ExpressOnezone)).validate_destination(&directory).is_err());
+
+    let files = |sizes: &[u64]| sizes.iter().map(|size| (PathBuf::from("file"), *size)).collect::<Vec<_>>();
+    assert_eq!(options(Some(StorageClass::Standard)).small_files_warning(&files(&[1, 2])), None);
+    assert!(options(Some(StorageClass::OnezoneIa)).small_files_warning(&files(&[1, 1 << 20])).is_some());
+    assert_eq!(options(Some(StorageClass::OnezoneIa)).small_files_warning(&files(&[1 << 20])), None);
+    let ruled = OptionsUpload { class_rules: Some("size<1M:STANDARD_IA;default:STANDARD".parse().unwrap()), ..options(None) };
+    assert_eq!(ruled.small_files_warning(&files(&[1, 2 << 20])).as_deref(), Some("1 file(s) smaller than 128KiB will each be billed as 128KiB in STANDARD_IA"));
+    assert_eq!(
+        options(Some(StorageClass::DeepArchive)).preflight_summary(&regular, 2, Some(10)),
+        "uploading 2 file(s) (10 bytes) to s3://bucket/key as DEEP_ARCHIVE, billed for at least 180 days, must be restored before download");
+}
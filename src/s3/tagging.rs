@@ -0,0 +1,88 @@
+use aws_sdk_s3::types::{Tag, Tagging};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use super::{Client, Error, Uri};
+
+/// A `key=value` object tag, within S3's length limits
+pub fn parse_tag(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s.split_once('=').ok_or_else(|| format!("expected KEY=VALUE, got '{s}'"))?;
+    if key.is_empty() || key.chars().count() > 128 {
+        return Err(format!("invalid tag key '{key}', expected 1 to 128 characters"));
+    }
+    if value.chars().count() > 256 {
+        return Err(format!("invalid tag value for '{key}', expected at most 256 characters"));
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Tags as the URL query encoded x-amz-tagging header, None if there are none
+pub fn tagging_header(tags: &[(String, String)]) -> Option<String> {
+    const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+    let encoded: Vec<String> = tags.iter()
+        .map(|(key, value)| format!("{}={}", utf8_percent_encode(key, COMPONENT), utf8_percent_encode(value, COMPONENT)))
+        .collect();
+    (!encoded.is_empty()).then(|| encoded.join("&"))
+}
+
+impl super::OptionsUpload {
+    /// --tag as the tagging header
+    pub fn tagging(&self) -> Option<String> {
+        tagging_header(&self.tag)
+    }
+}
+
+impl Client {
+    /// Tags of the object at `uri`, in the order stored
+    pub async fn get_tags(&self, uri: &Uri) -> Result<Vec<(String, String)>, Error> {
+        let output = self.client.get_object_tagging()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .send()
+            .await?;
+        Ok(output.tag_set().iter().map(|tag| (tag.key().to_owned(), tag.value().to_owned())).collect())
+    }
+
+    /// Replace every tag of the object at `uri`
+    pub async fn put_tags(&self, uri: &Uri, tags: &[(String, String)]) -> Result<(), Error> {
+        let tag_set = tags.iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::S3SdkError("invalid tag: ", Box::new(e)))?;
+        let tagging = Tagging::builder()
+            .set_tag_set(Some(tag_set))
+            .build()
+            .map_err(|e| Error::S3SdkError("invalid tag: ", Box::new(e)))?;
+        self.client.put_object_tagging()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .tagging(tagging)
+            .send()
+            .await?;
+        self.head_cache.invalidate(uri);
+        Ok(())
+    }
+
+    /// Remove every tag of the object at `uri`
+    pub async fn delete_tags(&self, uri: &Uri) -> Result<(), Error> {
+        self.client.delete_object_tagging()
+            .bucket(uri.bucket.clone())
+            .key(uri.key.to_string())
+            .send()
+            .await?;
+        self.head_cache.invalidate(uri);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_tagging_header() {
+    assert_eq!(parse_tag("team=data"), Ok(("team".into(), "data".into())));
+    assert_eq!(parse_tag("empty="), Ok(("empty".into(), "".into())));
+    assert_eq!(parse_tag("a=b=c"), Ok(("a".into(), "b=c".into())));
+    assert!(parse_tag("novalue").is_err());
+    assert!(parse_tag("=value").is_err());
+
+    assert_eq!(tagging_header(&[]), None);
+    let tags = [("team".to_owned(), "data eng".to_owned()), ("cost/centre".to_owned(), "a&b=c".to_owned())];
+    assert_eq!(tagging_header(&tags).as_deref(), Some("team=data%20eng&cost%2Fcentre=a%26b%3Dc"));
+}
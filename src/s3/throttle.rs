@@ -0,0 +1,103 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use aws_sdk_s3::config::interceptors::FinalizerInterceptorContextRef;
+use aws_sdk_s3::config::retry::{ClassifyRetry, RetryAction};
+use aws_sdk_s3::config::{ConfigBag, Intercept, RuntimeComponents};
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+use aws_smithy_runtime_api::client::interceptors::context::InterceptorContext;
+use aws_smithy_runtime_api::client::retries::classifiers::{RetryClassifierPriority, RetryReason};
+use aws_smithy_types::retry::ErrorKind;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Requests the server throttled, shared by clones of a Client.
+///
+/// Counts attempts after the fact, as classifiers run more than once for each
+#[derive(Clone, Debug, Default)]
+pub struct Throttles(Arc<AtomicU64>);
+
+impl Throttles {
+    pub fn count(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Intercept for Throttles {
+    fn name(&self) -> &'static str {
+        "Throttles"
+    }
+
+    fn read_after_attempt(&self, context: &FinalizerInterceptorContextRef<'_>, runtime_components: &RuntimeComponents, _cfg: &mut ConfigBag) -> Result<(), BoxError> {
+        // The verdict the retry strategy reaches, classifiers being sorted by priority
+        let action = runtime_components.retry_classifiers().fold(RetryAction::NoActionIndicated, |previous, classifier| {
+            match classifier.classify_retry_v2(context.inner(), &previous) {
+                RetryAction::NoActionIndicated => previous,
+                action => action,
+            }
+        });
+        if matches!(action, RetryAction::RetryIndicated(RetryReason::RetryableError { kind: ErrorKind::ThrottlingError, .. })) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+/// Delay asked for by a Retry-After header, in seconds or as an HTTP date
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::from_str(value, DateTimeFormat::HttpDate).ok()?;
+    let seconds = at.secs() - DateTime::from(now).secs();
+    Some(Duration::from_secs(seconds.max(0) as u64))
+}
+
+/// Retries 429 Too Many Requests as a throttle, which S3-compatible gateways send instead of
+/// 503 SlowDown, and waits as long as any Retry-After header asks.
+///
+/// Runs after the SDK's own classifiers, adding the delay to throttles and errors they find
+#[derive(Debug)]
+pub struct RetryAfter;
+
+impl ClassifyRetry for RetryAfter {
+    fn classify_retry(&self, ctx: &InterceptorContext) -> RetryAction {
+        self.classify_retry_v2(ctx, &RetryAction::NoActionIndicated)
+    }
+
+    fn classify_retry_v2(&self, ctx: &InterceptorContext, previous: &RetryAction) -> RetryAction {
+        let Some(response) = ctx.response() else {
+            return RetryAction::NoActionIndicated;
+        };
+        let retry_after = response.headers().get("retry-after").and_then(|value| parse_retry_after(value, SystemTime::now()));
+        match (previous, retry_after) {
+            (RetryAction::RetryIndicated(RetryReason::RetryableError { kind, retry_after: None }), Some(delay)) =>
+                RetryAction::retryable_error_with_explicit_delay(*kind, delay),
+            (RetryAction::NoActionIndicated, Some(delay)) if response.status().as_u16() == 429 =>
+                RetryAction::retryable_error_with_explicit_delay(ErrorKind::ThrottlingError, delay),
+            (RetryAction::NoActionIndicated, None) if response.status().as_u16() == 429 => RetryAction::throttling_error(),
+            _ => RetryAction::NoActionIndicated,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "RetryAfter"
+    }
+
+    fn priority(&self) -> RetryClassifierPriority {
+        RetryClassifierPriority::run_after(RetryClassifierPriority::transient_error_classifier())
+    }
+}
+
+#[test]
+fn test_parse_retry_after() {
+    let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(parse_retry_after("7", now), Some(Duration::from_secs(7)));
+    assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+    assert_eq!(parse_retry_after("Tue, 14 Nov 2023 22:13:50 GMT", now), Some(Duration::from_secs(30)));
+    assert_eq!(parse_retry_after("Tue, 14 Nov 2023 22:00:00 GMT", now), Some(Duration::ZERO));
+    assert_eq!(parse_retry_after("-1", now), None);
+    assert_eq!(parse_retry_after("soon", now), None);
+}
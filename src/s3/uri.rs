@@ -22,11 +22,44 @@ pub enum UriError {
     InvalidUrlComponents(&'static str),
     #[error("invalid bucket name: {0}")]
     InvalidBucketName(&'static str),
+    #[error("'..' in key '{0}', which S3 would store literally rather than as a parent directory (--raw-keys to address it)")]
+    ParentSegment(String),
+    #[error("key not UTF-8 once percent-decoded")]
+    KeyNotUtf8,
+}
+
+/// Key with duplicate slashes, leading slashes and '.' segments collapsed, keeping any trailing
+/// slash. Keys with '..' segments are rejected rather than resolved, S3 having no directories to
+/// resolve them in
+pub fn normalize_key(key: &str) -> Result<String, UriError> {
+    let segments: Vec<&str> = key.split('/').collect();
+    if segments.contains(&"..") {
+        return Err(UriError::ParentSegment(key.to_owned()));
+    }
+    let mut normalized = segments.iter()
+        .filter(|segment| !matches!(**segment, "" | "."))
+        .copied()
+        .collect::<Vec<_>>()
+        .join("/");
+    let directory = segments.len() > 1 && matches!(segments.last(), Some(&("" | ".")));
+    if directory && !normalized.is_empty() {
+        normalized.push('/');
+    }
+    Ok(normalized)
 }
 
 impl std::str::FromStr for Uri {
     type Err = UriError;
+    /// The key is everything after the bucket and its '/', '?', '#' and spaces included, as
+    /// S3 keys have no query or fragment. It's taken as written, see Uri::normalized
+    #[allow(clippy::unnecessary_lazy_evaluations, clippy::redundant_closure)]
     fn from_str(s: &str) -> Result<Uri, Self::Err> {
+        let key_start = s.find("://")
+            .and_then(|authority| s[authority + 3..].find('/').map(|slash| authority + 3 + slash));
+        let (s, key) = match key_start {
+            Some(slash) => (&s[..slash], &s[slash + 1..]),
+            None => (s, ""),
+        };
         let parsed = url::Url::parse(s)?;
         if parsed.scheme() != "s3" {
             return Err(UriError::InvalidScheme);
@@ -44,15 +77,37 @@ impl std::str::FromStr for Uri {
 
         validate_bucket_name(&bucket)
             .map_err(|e| UriError::InvalidBucketName(e))?;
-        let path = parsed.path();
-        let key = if path.is_empty() { "".to_owned() } else { path.strip_prefix('/').expect("separator must be /").to_owned() };
         Ok(Uri {
             bucket,
-            key: Key(key),
+            key: Key(key.to_owned()),
         })
     }
 }
 
+impl Uri {
+    /// The URI with its key as written in it percent-decoded, e.g. '%25' for a '%', and
+    /// normalized, unlike the key taken as written for --raw-keys
+    pub fn normalized(&self) -> Result<Uri, UriError> {
+        let decoded = percent_encoding::percent_decode_str(self.key.as_str()).decode_utf8().map_err(|_| UriError::KeyNotUtf8)?;
+        Ok(Uri::new(self.bucket.clone(), Key(normalize_key(&decoded)?)))
+    }
+}
+
+/// Key as written in an S3 URI, escaping only the '%' that parsing would decode
+pub fn encode_key_uri(key: &str) -> std::borrow::Cow<'_, str> {
+    match key.contains('%') {
+        true => key.replace('%', "%25").into(),
+        false => key.into(),
+    }
+}
+
+/// Percent-encode a key for use in a URL path, keeping '/' separators
+pub fn encode_key_path(key: &str) -> String {
+    use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+    const KEY_PATH: &AsciiSet = &NON_ALPHANUMERIC.remove(b'/').remove(b'-').remove(b'_').remove(b'.').remove(b'~');
+    utf8_percent_encode(key, KEY_PATH).to_string()
+}
+
 pub fn filename(key: &str) -> Option<&str> {
     match key.rsplit_once('/') {
         None if !key.is_empty() => Some(key),
@@ -91,6 +146,7 @@ impl Key {
     pub fn as_str(&self) -> &str {
         &self.0
     }
+    #[allow(unused)]
     pub fn as_directory_component(&self) -> &str {
         let without_slash = self.0.strip_suffix('/').unwrap_or(&self.0);
         without_slash.rsplit_once('/').map(|(_, component)| component).unwrap_or("")
@@ -129,10 +185,11 @@ impl Uri {
 
 impl std::fmt::Display for Uri {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(f, "s3://{}/{}", self.bucket, self.key)
+        write!(f, "s3://{}/{}", self.bucket, encode_key_uri(&self.key))
     }
 }
 
+#[allow(clippy::match_like_matches_macro)]
 pub fn bucket_valid_starting_char(c: char) -> bool {
     match c {
         'a'..='z' => true,
@@ -141,6 +198,7 @@ pub fn bucket_valid_starting_char(c: char) -> bool {
     }
 }
 
+#[allow(clippy::match_like_matches_macro)]
 pub fn bucket_valid_char(c: char) -> bool {
     match c {
         'a'..='z' => true,
@@ -170,3 +228,49 @@ pub fn validate_bucket_name(bucket: &str) -> Result<(), &'static str> {
     Ok(())
 }
 
+
+#[test]
+fn test_normalize_key() {
+    assert_eq!(normalize_key("").unwrap(), "");
+    assert_eq!(normalize_key("a/b.txt").unwrap(), "a/b.txt");
+    assert_eq!(normalize_key("a//b/").unwrap(), "a/b/");
+    assert_eq!(normalize_key("a/./b//./c").unwrap(), "a/b/c");
+    assert_eq!(normalize_key("/a").unwrap(), "a");
+    assert_eq!(normalize_key("//a/").unwrap(), "a/");
+    assert_eq!(normalize_key("a/.").unwrap(), "a/");
+    assert_eq!(normalize_key("./").unwrap(), "");
+    assert_eq!(normalize_key("a/.hidden/..b").unwrap(), "a/.hidden/..b");
+    assert!(matches!(normalize_key("a/../b"), Err(UriError::ParentSegment(_))));
+    assert!(matches!(normalize_key(".."), Err(UriError::ParentSegment(_))));
+
+    let normalized = |uri: &str| uri.parse::<Uri>().unwrap().normalized();
+    assert_eq!(normalized("s3://bucket/logs/./2024//").unwrap().to_string(), "s3://bucket/logs/2024/");
+    assert_eq!(normalized("s3://bucket").unwrap().key.as_str(), "");
+    assert!(normalized("s3://bucket/logs/../etc").is_err());
+    assert_eq!(normalized("s3://bucket//logs").unwrap().to_string(), "s3://bucket/logs");
+    assert_eq!("s3://bucket//logs/../%41".parse::<Uri>().unwrap().key.as_str(), "/logs/../%41");
+}
+
+#[test]
+fn test_uri_special_characters() {
+    let key = |uri: &str| uri.parse::<Uri>().and_then(|uri| uri.normalized()).map(|uri| uri.key.as_str().to_owned());
+    assert_eq!(key("s3://bucket/a b+c.txt").unwrap(), "a b+c.txt");
+    assert_eq!(key("s3://bucket/notes#1?draft=yes").unwrap(), "notes#1?draft=yes");
+    assert_eq!(key("s3://bucket/a%20b%23c%3F").unwrap(), "a b#c?");
+    assert_eq!(key("s3://bucket/100%25/50%").unwrap(), "100%/50%");
+    assert_eq!(key("s3://bucket/caf%C3%A9/café").unwrap(), "café/café");
+    assert!(matches!(key("s3://bucket/%FF"), Err(UriError::KeyNotUtf8)));
+    assert!(matches!(key("s3://bucket/a/%2E%2E/b"), Err(UriError::ParentSegment(_))));
+    assert!(matches!(key("s3://bucket?query"), Err(UriError::InvalidUrlComponents("query string"))));
+    assert!(matches!(key("s3://bucket#fragment/key"), Err(UriError::InvalidUrlComponents("fragment"))));
+    assert!(matches!(key("relative/path"), Err(UriError::ParseError { .. })));
+
+    for written in ["s3://bucket/a b+c#d?e.txt", "s3://bucket/100%25 done/", "s3://bucket/%2525", "s3://bucket/"] {
+        let uri = written.parse::<Uri>().unwrap().normalized().unwrap();
+        assert_eq!(uri.to_string(), written);
+        assert_eq!(uri.to_string().parse::<Uri>().unwrap().normalized().unwrap().key, uri.key);
+    }
+    let uri = Uri::new("bucket".into(), Key::new("50% off #1?.txt".into()));
+    assert_eq!(uri.to_string(), "s3://bucket/50%25 off #1?.txt");
+    assert_eq!(uri.to_string().parse::<Uri>().unwrap().normalized().unwrap().key, uri.key);
+}
@@ -0,0 +1,73 @@
+use super::uri;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Default)]
+pub enum UrlStyle {
+    /// s3://bucket/key
+    #[default]
+    S3,
+    /// Path style for custom endpoints and buckets with '.' in their name, virtual-hosted otherwise
+    Auto,
+    /// https://endpoint/bucket/key
+    Path,
+    /// https://bucket.endpoint/key
+    Virtual,
+}
+
+/// Where requests are actually sent, for printing reachable URLs
+#[derive(Clone, Debug, Default)]
+pub struct Endpoint {
+    pub custom: Option<http::uri::Uri>,
+    pub region: Option<String>,
+    pub accelerate: bool,
+}
+
+impl Endpoint {
+    pub fn url(&self, style: UrlStyle, bucket: &str, key: &str) -> String {
+        let virtual_hosted = match style {
+            UrlStyle::S3 => return format!("s3://{bucket}/{}", uri::encode_key_uri(key)),
+            // Acceleration is only available virtual-hosted
+            _ if self.accelerate => true,
+            // A '.' would add a level to the host name, which the wildcard certificate doesn't cover
+            UrlStyle::Auto => self.custom.is_none() && !bucket.contains('.'),
+            UrlStyle::Path => false,
+            UrlStyle::Virtual => true,
+        };
+        let (scheme, host) = match (&self.custom, self.accelerate) {
+            (_, true) => ("https", "s3-accelerate.amazonaws.com".to_owned()),
+            (Some(custom), false) => {
+                let scheme = custom.scheme_str().unwrap_or("https");
+                let host = custom.authority().map(|a| a.as_str()).unwrap_or_default();
+                let base_path = custom.path().trim_end_matches('/');
+                (scheme, format!("{host}{base_path}"))
+            },
+            (None, false) => {
+                let region = self.region.as_deref().unwrap_or("us-east-1");
+                ("https", format!("s3.{region}.amazonaws.com"))
+            },
+        };
+        let key = uri::encode_key_path(key);
+        if virtual_hosted {
+            format!("{scheme}://{bucket}.{host}/{key}")
+        } else {
+            format!("{scheme}://{host}/{bucket}/{key}")
+        }
+    }
+}
+
+#[test]
+fn test_url() {
+    let aws = Endpoint { custom: None, region: Some("eu-west-1".into()), accelerate: false };
+    assert_eq!(aws.url(UrlStyle::S3, "bucket", "a b/c"), "s3://bucket/a b/c");
+    assert_eq!(aws.url(UrlStyle::S3, "bucket", "a%41"), "s3://bucket/a%2541");
+    assert_eq!(aws.url(UrlStyle::Auto, "bucket", "dir/f"), "https://bucket.s3.eu-west-1.amazonaws.com/dir/f");
+    assert_eq!(aws.url(UrlStyle::Auto, "my.bucket", "f"), "https://s3.eu-west-1.amazonaws.com/my.bucket/f");
+    assert_eq!(aws.url(UrlStyle::Path, "bucket", "dir/f"), "https://s3.eu-west-1.amazonaws.com/bucket/dir/f");
+    assert_eq!(aws.url(UrlStyle::Virtual, "bucket", "a b+c"), "https://bucket.s3.eu-west-1.amazonaws.com/a%20b%2Bc");
+
+    let accelerated = Endpoint { accelerate: true, ..aws };
+    assert_eq!(accelerated.url(UrlStyle::Path, "bucket", "f"), "https://bucket.s3-accelerate.amazonaws.com/f");
+
+    let custom = Endpoint { custom: Some("http://127.0.0.1:9000".parse().unwrap()), region: None, accelerate: false };
+    assert_eq!(custom.url(UrlStyle::Auto, "bucket", "f"), "http://127.0.0.1:9000/bucket/f");
+    assert_eq!(custom.url(UrlStyle::Virtual, "bucket", "f"), "http://bucket.127.0.0.1:9000/f");
+}
@@ -0,0 +1,209 @@
+use aws_sdk_s3::operation::list_object_versions::ListObjectVersionsOutput;
+use aws_sdk_s3::error::ProvideErrorMetadata;
+use aws_sdk_s3::primitives::{DateTime, DateTimeFormat};
+
+use super::{printable_filename, Client, Error, Key, ListArguments, OptionsAge, OptionsSize, Uri, DATE_LEN, MAX_DELETE_BATCH};
+use crate::cli;
+use crate::shared_options::SharedOptions;
+
+/// A version of an object, or a delete marker
+#[derive(Debug)]
+struct Version<'a> {
+    key: &'a str,
+    version_id: &'a str,
+    latest: bool,
+    delete_marker: bool,
+    size: i64,
+    last_modified: Option<&'a DateTime>,
+    etag: Option<&'a str>,
+}
+
+impl Version<'_> {
+    fn state(&self) -> &'static str {
+        match (self.latest, self.delete_marker) {
+            (true, false) => "latest",
+            (true, true) => "latest,delete-marker",
+            (false, true) => "delete-marker",
+            (false, false) => "-",
+        }
+    }
+}
+
+/// Versions and delete markers of a page together, by key then newest first
+fn versions(response: &ListObjectVersionsOutput) -> Vec<Version<'_>> {
+    let objects = response.versions().iter().map(|version| Version {
+        key: version.key().unwrap_or_default(),
+        version_id: version.version_id().unwrap_or("null"),
+        latest: version.is_latest().unwrap_or(false),
+        delete_marker: false,
+        size: version.size().unwrap_or(0),
+        last_modified: version.last_modified(),
+        etag: version.e_tag(),
+    });
+    let markers = response.delete_markers().iter().map(|marker| Version {
+        key: marker.key().unwrap_or_default(),
+        version_id: marker.version_id().unwrap_or("null"),
+        latest: marker.is_latest().unwrap_or(false),
+        delete_marker: true,
+        size: 0,
+        last_modified: marker.last_modified(),
+        etag: None,
+    });
+    let mut versions: Vec<Version> = objects.chain(markers).collect();
+    let newest_first = |version: &Version| std::cmp::Reverse(version.last_modified.map(|t| (t.secs(), t.subsec_nanos())));
+    versions.sort_by(|a, b| a.key.cmp(b.key).then(b.latest.cmp(&a.latest)).then(newest_first(a).cmp(&newest_first(b))));
+    versions
+}
+
+impl Client {
+    /// List every version and delete marker below `s3_uri` with ListObjectVersions, returning how many were output
+    pub(super) async fn ls_versions(&self, opts: &SharedOptions, args: &ListArguments, s3_uri: &Uri) -> Result<usize, Error> {
+        let directory_prefix = s3_uri.key.basename_key();
+        let delimiter = (!args.recurse).then(|| "/".to_owned());
+        let mut count = 0;
+        let mut markers: (Option<String>, Option<String>) = (None, None);
+        for page in 1.. {
+            if opts.verbose {
+                println!("🏁 listing versions of s3://{}/{} (page {page})... ", s3_uri.bucket, s3_uri.key);
+            }
+            let response = self.client.list_object_versions()
+                .bucket(s3_uri.bucket.clone())
+                .prefix(s3_uri.key.to_string())
+                .set_delimiter(delimiter.clone())
+                .set_key_marker(markers.0.take())
+                .set_version_id_marker(markers.1.take())
+                .send()
+                .await?;
+
+            if !args.only_files {
+                for directory in response.common_prefixes().iter().filter_map(|prefix| prefix.prefix()) {
+                    let name = printable_filename(directory, true, &s3_uri.bucket, args, &self.endpoint, &directory_prefix);
+                    match args.output {
+                        cli::OutputFormat::Csv => cli::println_csv(&[&name, "", "directory", "", "", ""]),
+                        _ => println!("{name}"),
+                    }
+                    count += 1;
+                }
+            }
+
+            let versions = versions(&response);
+            let id_width = versions.iter().map(|version| version.version_id.len()).max().unwrap_or(0);
+            let size_width = cli::digit_count(versions.iter().map(|version| version.size).max().unwrap_or(0) as u64);
+            for version in versions.iter().filter(|_| !args.only_directories) {
+                let relative = version.key.strip_prefix(directory_prefix.as_str()).unwrap_or(version.key);
+                if !args.filter.matches(relative) || !args.size.matches(version.size as u64) || !args.age.matches(version.last_modified) {
+                    continue;
+                }
+                count += 1;
+                let name = printable_filename(version.key, false, &s3_uri.bucket, args, &self.endpoint, &directory_prefix);
+                let date = version.last_modified
+                    .and_then(|d| d.fmt(DateTimeFormat::DateTime).ok())
+                    .unwrap_or_default();
+                if args.output == cli::OutputFormat::Csv {
+                    let etag = version.etag.unwrap_or_default().trim_matches('"');
+                    cli::println_csv(&[&name, version.version_id, version.state(), &version.size.to_string(), &date, etag]);
+                } else if args.long {
+                    println!("{:size_width$} {date:DATE_LEN$} {:id_width$} {:20} {name}", version.size, version.version_id, version.state());
+                } else {
+                    println!("{:id_width$} {:20} {name}", version.version_id, version.state());
+                }
+            }
+
+            match response.is_truncated() {
+                Some(true) => markers = (response.next_key_marker().map(str::to_owned), response.next_version_id_marker().map(str::to_owned)),
+                _ => break,
+            }
+        }
+        Ok(count)
+    }
+
+    /// Key and version ID of every version and delete marker within `size` and `age`, of every
+    /// key starting with `prefix`, or with `exact` of the key `prefix` only
+    pub async fn list_versions(&self, bucket: &str, prefix: &Key, exact: bool, size: &OptionsSize, age: &OptionsAge) -> Result<Vec<(Key, String)>, Error> {
+        let mut found = Vec::new();
+        let mut markers: (Option<String>, Option<String>) = (None, None);
+        loop {
+            let response = self.client.list_object_versions()
+                .bucket(bucket)
+                .prefix(prefix.to_string())
+                .set_key_marker(markers.0.take())
+                .set_version_id_marker(markers.1.take())
+                .send()
+                .await?;
+            let versions = versions(&response);
+            // Listed in key order, so once past the exact key there's nothing more of it
+            let past = exact && versions.iter().any(|version| version.key != prefix.as_str());
+            found.extend(versions.into_iter()
+                .filter(|version| !exact || version.key == prefix.as_str())
+                .filter(|version| size.matches(version.size as u64) && age.matches(version.last_modified))
+                .map(|version| (Key::new(version.key.to_owned()), version.version_id.to_owned())));
+            match response.is_truncated() {
+                Some(true) if !past => markers = (response.next_key_marker().map(str::to_owned), response.next_version_id_marker().map(str::to_owned)),
+                _ => break,
+            }
+        }
+        Ok(found)
+    }
+
+    /// Permanently remove versions of keys of one bucket with DeleteObjects, returning those that
+    /// failed and why. Falls back to removing one at a time where DeleteObjects isn't implemented
+    pub async fn remove_versions(&self, opts: &SharedOptions, bucket: &str, versions: &[(Key, String)]) -> Result<Vec<(Key, String, String)>, Error> {
+        use aws_sdk_s3::types::{Delete, ObjectIdentifier};
+        let mut failed = Vec::new();
+        for batch in versions.chunks(MAX_DELETE_BATCH) {
+            if opts.verbose {
+                println!("🏁 removing {} version(s) from s3://{bucket}/... ", batch.len());
+            }
+            let objects = batch.iter()
+                .map(|(key, version_id)| ObjectIdentifier::builder().key(key.to_string()).version_id(version_id).build().expect("key set"))
+                .collect();
+            let result = self.client.delete_objects()
+                .bucket(bucket)
+                .delete(Delete::builder().set_objects(Some(objects)).quiet(true).build().expect("objects set"))
+                .send()
+                .await;
+            let response = match result {
+                Ok(response) => response,
+                Err(e) if e.code() == Some("NotImplemented") => {
+                    for (key, version_id) in batch {
+                        let removed = self.client.delete_object()
+                            .bucket(bucket)
+                            .key(key.to_string())
+                            .version_id(version_id)
+                            .send()
+                            .await;
+                        if let Err(e) = removed {
+                            failed.push((key.clone(), version_id.clone(), Error::from(e).to_string()));
+                        }
+                        self.head_cache.invalidate(&Uri::new(bucket.to_owned(), key.clone()));
+                    }
+                    continue;
+                },
+                Err(e) => return Err(e.into()),
+            };
+            for (key, _) in batch {
+                self.head_cache.invalidate(&Uri::new(bucket.to_owned(), key.clone()));
+            }
+            for error in response.errors() {
+                let reason = error.message().or(error.code()).unwrap_or("unknown error");
+                failed.push((Key::new(error.key().unwrap_or_default().to_owned()), error.version_id().unwrap_or("null").to_owned(), reason.to_owned()));
+            }
+        }
+        Ok(failed)
+    }
+}
+
+#[test]
+fn test_versions_order() {
+    use aws_sdk_s3::types::{DeleteMarkerEntry, ObjectVersion};
+    let at = |secs| DateTime::from_secs(secs);
+    let response = ListObjectVersionsOutput::builder()
+        .versions(ObjectVersion::builder().key("b").version_id("b1").is_latest(true).last_modified(at(10)).build())
+        .versions(ObjectVersion::builder().key("a").version_id("a1").is_latest(false).last_modified(at(10)).build())
+        .versions(ObjectVersion::builder().key("a").version_id("a2").is_latest(false).last_modified(at(20)).build())
+        .delete_markers(DeleteMarkerEntry::builder().key("a").version_id("a3").is_latest(true).last_modified(at(30)).build())
+        .build();
+    let versions = versions(&response);
+    let listed: Vec<(&str, &str)> = versions.iter().map(|version| (version.version_id, version.state())).collect();
+    assert_eq!(listed, [("a3", "latest,delete-marker"), ("a2", "-"), ("a1", "-"), ("b1", "latest")]);
+}
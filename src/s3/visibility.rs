@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+use super::{Client, Error, Uri};
+use crate::cli;
+
+#[derive(clap::Args, Debug, Clone)]
+#[clap(next_help_heading = "Consistency")]
+pub struct OptionsVisibility {
+    /// After uploading to a custom endpoint, wait until each object is visible with its full size,
+    /// for eventually consistent providers
+    #[clap(long)]
+    pub verify_visibility: bool,
+    /// Give up waiting for an upload to become visible after this many seconds
+    #[clap(long, default_value = "30", value_name = "SECONDS")]
+    pub visibility_timeout: u64,
+}
+
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+impl Client {
+    /// Poll HeadObject until `uri` has `size` bytes and the `etag` the upload returned, if requested,
+    /// so an overwritten object of the same size isn't taken for the new one. AWS itself is
+    /// strongly consistent, so only custom endpoints are checked
+    pub(super) async fn await_visible(&self, verbose: bool, options: &OptionsVisibility, uri: &Uri, size: u64, etag: Option<&str>, progress_fn: &cli::ProgressFn) -> Result<(), Error> {
+        if !options.verify_visibility || self.endpoint.custom.is_none() {
+            return Ok(());
+        }
+        progress_fn(cli::Update::State("verifying"));
+        let started = Instant::now();
+        let timeout = Duration::from_secs(options.visibility_timeout);
+        let mut interval = Duration::from_millis(100);
+        loop {
+            let head = self.client.head_object()
+                .bucket(uri.bucket.clone())
+                .key(uri.key.to_string())
+                .send()
+                .await;
+            match head {
+                Ok(head) if head.content_length() == Some(size as i64) && same_etag(head.e_tag(), etag) => break,
+                Ok(_) => {},
+                Err(e) if e.as_service_error().map(|e| e.is_not_found()).unwrap_or(false) => {},
+                Err(e) => return Err(e.into()),
+            }
+            if started.elapsed() + interval > timeout {
+                return Err(Error::NotVisible(uri.clone(), options.visibility_timeout));
+            }
+            tokio::time::sleep(interval).await;
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+        if verbose {
+            println!("🏁 {uri} visible after {:.1}s", started.elapsed().as_secs_f32());
+        }
+        Ok(())
+    }
+}
+
+/// Whether `visible` is the `expected` ETag, ignoring quoting. Any matches if none was returned
+fn same_etag(visible: Option<&str>, expected: Option<&str>) -> bool {
+    let unquoted = |etag: &str| etag.trim_matches('"').to_owned();
+    match expected {
+        Some(expected) => visible.map(unquoted) == Some(unquoted(expected)),
+        None => true,
+    }
+}
+
+#[test]
+fn test_same_etag() {
+    assert!(same_etag(Some("\"abc\""), Some("abc")));
+    assert!(same_etag(Some("abc"), None));
+    assert!(!same_etag(Some("\"abc\""), Some("\"abd\"")));
+    assert!(!same_etag(None, Some("abc")));
+}
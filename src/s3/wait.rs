@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use super::{Client, Error, Uri};
+
+/// Longest pause between polls, however long the wait
+const MAX_INTERVAL: Duration = Duration::from_secs(30);
+
+/// What `Client::wait_for` polls for
+#[derive(Debug, Clone)]
+pub enum Condition {
+    ObjectExists(Uri),
+    BucketExists(String),
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::ObjectExists(uri) => write!(f, "object {uri} to exist"),
+            Condition::BucketExists(bucket) => write!(f, "bucket s3://{bucket}/ to exist"),
+        }
+    }
+}
+
+/// Pause after `interval`: doubled, at least a second and at most MAX_INTERVAL
+fn next_interval(interval: Duration) -> Duration {
+    (interval * 2).clamp(Duration::from_secs(1), MAX_INTERVAL)
+}
+
+impl Client {
+    /// Whether `condition` holds now, bypassing the HeadObject cache
+    async fn holds(&self, condition: &Condition) -> Result<bool, Error> {
+        match condition {
+            Condition::ObjectExists(uri) => {
+                self.head_cache.invalidate(uri);
+                match self.head(uri).await {
+                    Ok(_) => Ok(true),
+                    Err(Error::NoSuchKey(_)) => Ok(false),
+                    Err(e) => Err(e),
+                }
+            },
+            Condition::BucketExists(bucket) => match self.client.head_bucket().bucket(bucket).send().await {
+                Ok(_) => Ok(true),
+                Err(e) if e.as_service_error().map(|e| e.is_not_found()).unwrap_or(false) => Ok(false),
+                Err(e) => Err(e.into()),
+            },
+        }
+    }
+
+    /// Poll until `condition` holds, pausing `interval` then backing off, until `deadline`.
+    /// Returns whether it held, failing on any error other than not found
+    pub async fn wait_for(&self, verbose: bool, condition: &Condition, interval: Duration, deadline: Instant) -> Result<bool, Error> {
+        let mut interval = interval;
+        for attempt in 1.. {
+            if verbose {
+                eprintln!("🏁 waiting for {condition} (attempt {attempt})");
+            }
+            if self.holds(condition).await? {
+                return Ok(true);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            tokio::time::sleep(interval.min(remaining)).await;
+            interval = next_interval(interval);
+        }
+        Ok(false)
+    }
+}
+
+#[test]
+fn test_next_interval() {
+    assert_eq!(next_interval(Duration::ZERO), Duration::from_secs(1));
+    assert_eq!(next_interval(Duration::from_secs(5)), Duration::from_secs(10));
+    assert_eq!(next_interval(Duration::from_secs(20)), MAX_INTERVAL);
+}
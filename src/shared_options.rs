@@ -4,4 +4,69 @@ use clap::Args;
 pub struct SharedOptions {
     #[clap(long, short='v', global = true)]
     pub verbose: bool,
+    /// Ask before removing objects or buckets. Without a terminal to ask on, e.g. from cron,
+    /// the answer is no
+    #[clap(long, short='i', global = true)]
+    pub interactive: bool,
+    /// Answer yes to every confirmation, taking precedence over --interactive and the
+    /// missing terminal check
+    #[clap(long, global = true)]
+    pub assume_yes: bool,
+    /// Take keys in S3 URIs as written, e.g. to reach objects uploaded with '..' or '//' in their
+    /// keys, rather than collapsing '//', '/./' and a leading '/' and rejecting '..'
+    #[clap(long, global = true, env = "SUP3_RAW_KEYS", value_parser = clap::builder::FalseyValueParser::new())]
+    pub raw_keys: bool,
+}
+
+/// How a confirmation is answered, in order of precedence
+#[derive(Debug, PartialEq)]
+enum Answer {
+    /// --assume-yes, or nothing asked without --interactive
+    Yes,
+    /// --interactive, but stdin isn't a terminal
+    NoTerminal,
+    Ask,
+}
+
+impl SharedOptions {
+    fn answer(&self, terminal: bool) -> Answer {
+        match (self.assume_yes, self.interactive, terminal) {
+            (true, _, _) | (false, false, _) => Answer::Yes,
+            (false, true, false) => Answer::NoTerminal,
+            (false, true, true) => Answer::Ask,
+        }
+    }
+
+    /// Whether to go ahead with `action`, asking on the terminal if --interactive
+    pub fn confirm(&self, action: std::fmt::Arguments) -> bool {
+        use std::io::{BufRead, IsTerminal, Write};
+        match self.answer(std::io::stdin().is_terminal()) {
+            Answer::Yes => true,
+            Answer::NoTerminal => {
+                eprintln!("⚠️: not confirmed, no terminal to ask on: {action}");
+                false
+            },
+            Answer::Ask => {
+                eprint!("{action}? [y/N] ");
+                let _ = std::io::stderr().flush();
+                let mut reply = String::new();
+                if std::io::stdin().lock().read_line(&mut reply).is_err() {
+                    return false;
+                }
+                matches!(reply.trim(), "y" | "Y" | "yes" | "Yes")
+            },
+        }
+    }
+}
+
+#[test]
+fn test_confirm_precedence() {
+    let opts = |interactive, assume_yes| SharedOptions { verbose: false, interactive, assume_yes, raw_keys: false };
+    assert_eq!(opts(false, false).answer(false), Answer::Yes);
+    assert_eq!(opts(false, false).answer(true), Answer::Yes);
+    assert_eq!(opts(true, false).answer(true), Answer::Ask);
+    assert_eq!(opts(true, false).answer(false), Answer::NoTerminal);
+    assert_eq!(opts(true, true).answer(true), Answer::Yes);
+    assert_eq!(opts(true, true).answer(false), Answer::Yes);
+    assert_eq!(opts(false, true).answer(false), Answer::Yes);
 }
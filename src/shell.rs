@@ -0,0 +1,271 @@
+use clap::{CommandFactory, FromArgMatches};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+use crate::arguments::{self, MainResult};
+use crate::policy;
+use crate::shared_options::SharedOptions;
+use crate::{cli, s3, transfer};
+
+#[derive(clap::Parser, Debug)]
+#[clap(no_binary_name = true, name = "sup3 shell", bin_name = "", disable_version_flag = true, styles = arguments::clap3_help_style())]
+struct Line {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+/// Paths are relative to the current location unless given as s3://bucket/key, or /key for the current bucket
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Change the current bucket and prefix
+    Cd { path: Option<String> },
+    /// Print the current location
+    Pwd,
+    /// List paths, or the current location; lists buckets when outside of one
+    Ls {
+        paths: Vec<String>,
+        #[clap(flatten)]
+        args: s3::ListArguments,
+    },
+    /// Download to a local path, the current directory by default
+    Get {
+        #[clap(required = true)]
+        paths: Vec<String>,
+        #[clap(long, short = 'o', default_value = ".", value_hint = clap::ValueHint::AnyPath)]
+        to: std::path::PathBuf,
+        #[clap(long, short = 'r')]
+        recursive: bool,
+        #[clap(flatten)]
+        transfer: transfer::OptionsTransfer,
+        #[clap(flatten)]
+        download: s3::OptionsDownload,
+    },
+    /// Upload local paths to the current location
+    Put {
+        #[clap(required = true, value_hint = clap::ValueHint::AnyPath)]
+        local_paths: Vec<std::path::PathBuf>,
+        #[clap(long, short = 'r')]
+        recursive: bool,
+        #[clap(flatten)]
+        transfer: transfer::OptionsTransfer,
+        #[clap(flatten)]
+        upload: Box<s3::OptionsUpload>,
+    },
+    /// Remove objects
+    Rm {
+        #[clap(required = true)]
+        paths: Vec<String>,
+    },
+    /// Print object contents
+    Cat {
+        #[clap(required = true)]
+        paths: Vec<String>,
+        #[clap(flatten)]
+        cache: s3::OptionsCache,
+    },
+    /// Leave the shell
+    #[clap(alias = "quit")]
+    Exit,
+}
+
+/// Resolve a shell path against the current location, an S3 URI's key normalized unless
+/// `raw_keys`
+fn resolve(current: Option<&s3::Uri>, raw_keys: bool, path: &str) -> Result<s3::Uri, String> {
+    if path.starts_with("s3://") {
+        let uri: s3::Uri = path.parse().map_err(|e: s3::UriError| e.to_string())?;
+        return match raw_keys {
+            true => Ok(uri),
+            false => uri.normalized().map_err(|e| e.to_string()),
+        };
+    }
+    let current = current.ok_or("no current bucket, cd s3://bucket first")?;
+    let (base, relative) = match path.strip_prefix('/') {
+        Some(absolute) => ("", absolute),
+        None => (current.key.as_str(), path),
+    };
+    let mut components: Vec<&str> = base.split('/').filter(|c| !c.is_empty()).collect();
+    for component in relative.split('/') {
+        match component {
+            "" | "." => {},
+            ".." => { components.pop(); },
+            other => components.push(other),
+        }
+    }
+    let mut key = components.join("/");
+    let directory = relative.is_empty() || relative.ends_with('/') || matches!(relative.rsplit('/').next(), Some("." | ".."));
+    if directory && !key.is_empty() {
+        key.push('/');
+    }
+    Ok(s3::Uri::new(current.bucket.clone(), s3::Key::new(key)))
+}
+
+fn resolve_all(current: Option<&s3::Uri>, raw_keys: bool, paths: &[String]) -> Result<Vec<s3::Uri>, String> {
+    paths.iter().map(|path| resolve(current, raw_keys, path)).collect()
+}
+
+fn prompt(current: Option<&s3::Uri>) -> String {
+    match current {
+        Some(uri) => format!("{uri}> "),
+        None => "sup3> ".to_owned(),
+    }
+}
+
+/// Interactive session reusing one client, with a current bucket and prefix, each line checked
+/// against `policy`
+pub async fn run(client: &s3::Client, opts: &SharedOptions, policy: &policy::Policy, start: Option<s3::Uri>) -> MainResult {
+    let mut current = start.map(|uri| s3::Uri::new(uri.bucket.clone(), uri.key.to_explicit_directory()));
+    let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    let mut result = MainResult::Success;
+    loop {
+        let _ = stdout.write_all(prompt(current.as_ref()).as_bytes()).await;
+        let _ = stdout.flush().await;
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => {
+                println!();
+                break;
+            },
+            Err(e) => {
+                cli::println_error(format_args!("reading input: {e}"));
+                return MainResult::ErrorSomeOperationsFailed;
+            },
+        };
+        let Some(words) = shlex::split(&line) else {
+            cli::println_error(format_args!("unbalanced quotes"));
+            continue;
+        };
+        if words.is_empty() {
+            continue;
+        }
+        let line_command = Line::command();
+        let parsed = line_command.clone().try_get_matches_from(words)
+            .and_then(|matches| Ok((Line::from_arg_matches(&matches)?, matches)));
+        let (command, matches) = match parsed {
+            Ok((parsed, matches)) => (parsed.command, matches),
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            },
+        };
+        let invocation = policy::Invocation::from_shell_matches(&line_command, &matches, current.as_ref().map(|uri| uri.bucket.as_str()));
+        if let Err(e) = policy.check(&invocation) {
+            cli::println_error(format_args!("{e}"));
+            result = MainResult::ErrorDenied;
+            continue;
+        }
+        let outcome = match execute(client, opts, &mut current, command).await {
+            Ok(None) => break,
+            Ok(Some(outcome)) => outcome,
+            Err(e) => {
+                cli::println_error(format_args!("{e}"));
+                MainResult::ErrorArguments
+            },
+        };
+        // Exit status reflects the last command, as in other shells
+        result = outcome;
+    }
+    result
+}
+
+/// Run one command, None to leave the shell
+async fn execute(client: &s3::Client, opts: &SharedOptions, current: &mut Option<s3::Uri>, command: Command) -> Result<Option<MainResult>, String> {
+    let outcome = match command {
+        Command::Cd { path } => {
+            let target = match path {
+                Some(path) => resolve(current.as_ref(), opts.raw_keys, &path)?,
+                None => resolve(current.as_ref(), opts.raw_keys, "/")?,
+            };
+            *current = Some(s3::Uri::new(target.bucket, target.key.to_explicit_directory()));
+            MainResult::Success
+        },
+        Command::Pwd => {
+            match current {
+                Some(uri) => println!("{uri}"),
+                None => println!("(no bucket)"),
+            }
+            MainResult::Success
+        },
+        Command::Ls { paths, .. } if paths.is_empty() && current.is_none() => {
+            match client.list_buckets(opts).await {
+                Ok(()) => MainResult::Success,
+                Err(e) => {
+                    cli::println_error(format_args!("failed to list buckets: {e}"));
+                    MainResult::ErrorSomeOperationsFailed
+                },
+            }
+        },
+        Command::Ls { paths, args } => {
+            let uris = match paths.is_empty() {
+                true => vec![resolve(current.as_ref(), opts.raw_keys, "")?],
+                false => resolve_all(current.as_ref(), opts.raw_keys, &paths)?,
+            };
+            arguments::list(client, opts, &args, &uris).await
+        },
+        Command::Get { paths, to, recursive, transfer, download } => {
+            let uris = resolve_all(current.as_ref(), opts.raw_keys, &paths)?;
+            transfer::download(&uris, &to, client, opts, &transfer, &download, &Default::default(), recursive).await
+        },
+        Command::Put { local_paths, recursive, transfer, upload } => {
+            let to = resolve(current.as_ref(), opts.raw_keys, "")?;
+            transfer::upload(&local_paths, &to, client, opts, &transfer, &upload, recursive, None).await
+        },
+        Command::Rm { paths } => {
+            let uris = resolve_all(current.as_ref(), opts.raw_keys, &paths)?;
+            let mut outcome = MainResult::Success;
+            for uri in &uris {
+                if let Err(e) = client.remove(opts, uri).await {
+                    cli::println_error(format_args!("failed to remove {uri}: {e}"));
+                    outcome = MainResult::ErrorSomeOperationsFailed;
+                }
+            }
+            outcome
+        },
+        Command::Cat { paths, cache } => {
+            let uris = resolve_all(current.as_ref(), opts.raw_keys, &paths)?;
+            let mut outcome = MainResult::Success;
+            for uri in &uris {
+                if let Err(e) = client.cat(&cache, uri).await {
+                    cli::println_error(format_args!("failed to cat {uri}: {e}"));
+                    outcome = MainResult::ErrorSomeOperationsFailed;
+                }
+            }
+            outcome
+        },
+        Command::Exit => return Ok(None),
+    };
+    Ok(Some(outcome))
+}
+
+#[test]
+fn test_resolve() {
+    let current: s3::Uri = "s3://bucket/data/2024/".parse().unwrap();
+    let resolved = |path| resolve(Some(&current), false, path).map(|uri| uri.to_string());
+    assert_eq!(resolved("file.csv"), Ok("s3://bucket/data/2024/file.csv".into()));
+    assert_eq!(resolved("../2023/"), Ok("s3://bucket/data/2023/".into()));
+    assert_eq!(resolved(".."), Ok("s3://bucket/data/".into()));
+    assert_eq!(resolved("../.."), Ok("s3://bucket/".into()));
+    assert_eq!(resolved("/other/x"), Ok("s3://bucket/other/x".into()));
+    assert_eq!(resolved(""), Ok("s3://bucket/data/2024/".into()));
+    assert_eq!(resolved("s3://elsewhere/k"), Ok("s3://elsewhere/k".into()));
+    assert_eq!(resolved("s3://elsewhere/a/./%41"), Ok("s3://elsewhere/a/A".into()));
+    assert_eq!(resolve(Some(&current), true, "s3://elsewhere/a/./%41").map(|uri| uri.key.as_str().to_owned()), Ok("a/./%41".into()));
+    assert!(resolve(None, false, "file.csv").is_err());
+}
+
+#[test]
+fn test_policy_lines() {
+    let config = crate::config::Config::parse(concat!(
+        "[policy.prod-upload]\naction = \"deny\"\ncommand = \"upload\"\nbuckets = \"prod-*\"\n",
+        "[policy.no-rm]\naction = \"deny\"\ncommand = \"rm\"\n",
+    )).unwrap();
+    let policy = policy::Policy::from_config(&config, &arguments::Arguments::command()).unwrap();
+    let check = |line: &str, current: Option<&str>| {
+        let matches = Line::command().try_get_matches_from(shlex::split(line).unwrap()).unwrap();
+        policy.check(&policy::Invocation::from_shell_matches(&Line::command(), &matches, current))
+    };
+    assert!(check("put report.csv", Some("prod-data")).is_err());
+    assert!(check("put report.csv", Some("dev-data")).is_ok());
+    assert!(check("get report.csv", Some("prod-data")).is_ok());
+    assert!(check("rm s3://dev-data/a", None).is_err());
+    assert!(check("ls", Some("prod-data")).is_ok());
+}
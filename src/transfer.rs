@@ -1,4 +1,3 @@
-use futures::FutureExt;
 use std::sync::Arc;
 use futures::StreamExt;
 use futures::stream::FuturesUnordered;
@@ -6,41 +5,355 @@ use std::num::NonZeroU16;
 
 use crate::s3;
 use crate::cli;
+use crate::manifest;
+use crate::clean;
 use super::MainResult;
 use crate::shared_options::SharedOptions;
 
+mod sync;
+mod remove;
+mod report;
+mod partition;
+pub use sync::{sync, diff, Compare, Direction, OptionsSync};
+pub use partition::Partition;
+pub use remove::remove;
+
+/// How long to wait on aborting multipart uploads after ctrl-c
+const ABORT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Cancelled on ctrl-c until dropped, stopping its listener so that commands run one after
+/// another, e.g. from the shell, don't each leave one behind
+pub(crate) struct CtrlC {
+    cancellation: tokio_util::sync::CancellationToken,
+    listener: tokio::task::AbortHandle,
+}
+
+impl CtrlC {
+    pub(crate) fn listen() -> CtrlC {
+        let cancellation = tokio_util::sync::CancellationToken::new();
+        let ctrlc_cancel = cancellation.clone();
+        let listener = tokio::spawn(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            ctrlc_cancel.cancel();
+        }).abort_handle();
+        CtrlC { cancellation, listener }
+    }
+
+    pub(crate) fn token(&self) -> &tokio_util::sync::CancellationToken {
+        &self.cancellation
+    }
+}
+
+impl Drop for CtrlC {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
 #[derive(clap::Args, Debug, Clone)]
 pub struct OptionsTransfer {
-    /// Perform multiple uploads concurrently
-    #[clap(long, short='j', default_value="1")]
+    /// Perform multiple transfers concurrently
+    #[clap(long, short='j', visible_alias="net-jobs", default_value="1")]
     concurrency: NonZeroU16,
+    /// Most local file reads and writes in progress at once across concurrent transfers,
+    /// e.g. to keep a spinning disk from seeking between many, unbounded by default
+    #[clap(long, value_name="N")]
+    disk_jobs: Option<NonZeroU16>,
+    /// Most bytes per second across all transfers, e.g. 10M
+    #[clap(long, value_name="RATE", value_parser=parse_rate)]
+    limit_rate: Option<u64>,
+    /// Most bytes per second of each transfer, e.g. 2M, so a large file can't take the whole of
+    /// --limit-rate from many small ones
+    #[clap(long, value_name="RATE", value_parser=parse_rate)]
+    limit_rate_per_file: Option<u64>,
     /// Continue to next file on error
     #[clap(long, short='y')]
     continue_on_error: bool,
+    /// Download each object larger than --part-size as this many concurrent ranged requests
+    #[clap(long, default_value="1")]
+    part_concurrency: NonZeroU16,
+    /// Size of each ranged request when downloading with --part-concurrency
+    #[clap(long, default_value="8M", value_parser=parse_part_size)]
+    part_size: u64,
+    /// Write a JSON report of each file uploaded or downloaded to FILE, with its destination,
+    /// bytes transferred, duration and error if any
+    #[clap(long, value_name="FILE", value_hint=clap::ValueHint::FilePath)]
+    report: Option<std::path::PathBuf>,
+    /// Upload the report as JSON to this S3 object once done, with the run's status ('succeeded',
+    /// 'failed', 'cancelled' or 'quota exceeded'), start and finish times and totals, e.g. as a
+    /// _SUCCESS-style marker for pipelines to trigger on
+    #[clap(long, value_name="S3_URI", value_hint=clap::ValueHint::Url, value_parser=parse_summary_uri)]
+    summary_to: Option<s3::Uri>,
+
+    #[clap(flatten)]
+    filter: s3::OptionsFilter,
 
     #[clap(flatten)]
     progress: cli::ArgProgress,
 }
 
-pub async fn upload(local_paths: &[std::path::PathBuf], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload, recursive: bool) -> MainResult {
+fn parse_rate(s: &str) -> Result<u64, String> {
+    match cli::parse_size(s)? {
+        0 => Err("rate must be greater than zero".into()),
+        rate => Ok(rate),
+    }
+}
+
+fn parse_summary_uri(s: &str) -> Result<s3::Uri, String> {
+    let uri: s3::Uri = s.parse().map_err(|e: s3::UriError| e.to_string())?;
+    match uri.key.is_explicitly_directory() {
+        true => Err(format!("expected an object, not a directory: '{uri}'")),
+        false => Ok(uri),
+    }
+}
+
+fn parse_part_size(s: &str) -> Result<u64, String> {
+    match cli::parse_size(s)? {
+        0 => Err("part size must be greater than zero".into()),
+        size => Ok(size),
+    }
+}
+
+impl OptionsTransfer {
+    /// The --summary-to URI, e.g. to normalize its key
+    pub fn summary_to_mut(&mut self) -> Option<&mut s3::Uri> {
+        self.summary_to.as_mut()
+    }
+
+    /// Client sharing a bound of --disk-jobs and --limit-rate between its transfers
+    fn client(&self, client: &s3::Client) -> s3::Client {
+        client.with_disk_jobs(self.disk_jobs.map(|jobs| jobs.get() as usize))
+            .with_rate_limits(self.limit_rate, self.limit_rate_per_file)
+    }
+    /// Write the per-file results to --report and --summary-to, if given, failing `result` if that fails
+    async fn write_report(&self, client: &s3::Client, report: &report::Report, result: MainResult) -> MainResult {
+        let mut failed = false;
+        if let Some(path) = &self.report {
+            if let Err(e) = report.write(path) {
+                cli::println_error(format_args!("failed to write report {path:?}: {e}"));
+                failed = true;
+            }
+        }
+        if let Some(uri) = &self.summary_to {
+            let status = match result {
+                MainResult::Success => "succeeded",
+                MainResult::Cancelled => "cancelled",
+                MainResult::ErrorQuotaExceeded => "quota exceeded",
+                MainResult::ErrorArguments | MainResult::ErrorSomeOperationsFailed | MainResult::ErrorDenied => "failed",
+            };
+            let summary = report.summary_json(status, client.throttles().count(), chrono::Utc::now());
+            if let Err(e) = client.put_bytes(uri, "application/json", summary.into_bytes()).await {
+                cli::println_error(format_args!("failed to upload summary to {uri}: {e}"));
+                failed = true;
+            }
+        }
+        match (failed, result) {
+            (true, MainResult::Success) => MainResult::ErrorSomeOperationsFailed,
+            (_, result) => result,
+        }
+    }
+    fn ranged_download(&self) -> Option<s3::RangedDownload> {
+        (self.part_concurrency.get() > 1).then(|| s3::RangedDownload {
+            part_size: self.part_size,
+            concurrency: self.part_concurrency.get() as usize,
+        })
+    }
+}
+
+/// The files an upload would read with their sizes, skipping anything unreadable for the upload itself to report
+fn local_file_sizes(paths: &[std::path::PathBuf], recursive: bool, files: &mut Vec<(std::path::PathBuf, u64)>) {
+    for path in paths {
+        let Ok(metadata) = std::fs::metadata(path) else { continue };
+        if !metadata.is_dir() {
+            files.push((path.clone(), metadata.len()));
+        } else if recursive {
+            let Ok(children) = std::fs::read_dir(path) else { continue };
+            let children: Vec<_> = children.filter_map(|child| child.ok().map(|c| c.path())).collect();
+            local_file_sizes(&children, recursive, files);
+        }
+    }
+}
+
+/// Check the upload options against the destination, printing warnings and, in verbose mode, a summary
+fn preflight(to: &s3::Uri, verbose: bool, opts_upload: &s3::OptionsUpload, count: usize, files: Option<&[(std::path::PathBuf, u64)]>) -> Result<(), MainResult> {
+    if let Err(e) = opts_upload.validate_destination(to) {
+        cli::println_error(format_args!("{e}"));
+        return Err(MainResult::ErrorArguments);
+    }
+    if let Some(warning) = files.and_then(|files| opts_upload.small_files_warning(files)) {
+        eprintln!("⚠️: {warning}");
+    }
+    if verbose {
+        println!("🏁 {}", opts_upload.preflight_summary(to, count, files.map(|files| files.iter().map(|(_, size)| size).sum())));
+    }
+    Ok(())
+}
+
+/// Run `work` holding the destination lock, if requested, stopping it if the lock is lost
+async fn locked(to: &s3::Uri, client: &s3::Client, verbose: bool, opts_upload: &s3::OptionsUpload, work: impl std::future::Future<Output = MainResult>) -> MainResult {
+    let lock = match client.lock(verbose, &opts_upload.lock, to).await {
+        Ok(lock) => lock,
+        Err(e @ s3::Error::Locked(..)) => {
+            cli::println_error(format_args!("{e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        },
+        Err(e) => {
+            cli::println_error(format_args!("failed to lock {to}: {e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        },
+    };
+    // Writing on without the lock would race whoever holds it now
+    let result = match &lock {
+        Some(held) => tokio::select! {
+            result = work => result,
+            _ = held.lost() => {
+                cli::println_error(format_args!("stopped writing to {to} after losing its lock"));
+                abort_in_flight(client, opts_upload).await;
+                MainResult::ErrorSomeOperationsFailed
+            },
+        },
+        None => work.await,
+    };
+    if let Some(lock) = lock {
+        if let Err(e) = lock.release().await {
+            cli::println_error(format_args!("failed to release lock on {to}: {e}"));
+        }
+    }
+    result
+}
+
+/// Key of the empty object marking a prefix's output complete, as written by Hadoop and Spark
+const SUCCESS_MARKER: &str = "_SUCCESS";
+
+/// Write the empty _SUCCESS object into `prefix`, after every file of a run succeeded
+pub async fn write_success_marker(client: &s3::Client, verbose: bool, prefix: &s3::Uri) -> MainResult {
+    let mut key = prefix.key.clone();
+    key.push(SUCCESS_MARKER);
+    let marker = s3::Uri::new(prefix.bucket.clone(), key);
+    if verbose {
+        println!("🏁 writing {marker}");
+    }
+    match client.put_bytes(&marker, "application/octet-stream", Vec::new()).await {
+        Ok(()) => MainResult::Success,
+        Err(e) => {
+            cli::println_error(format_args!("failed to write {marker}: {e}"));
+            MainResult::ErrorSomeOperationsFailed
+        },
+    }
+}
+
+/// Prefixes an upload to `to` that wrote `written` marks complete: the destination's, unless `to`
+/// named the single object written, or with `partitioned` each partition's written into
+pub fn success_marker_prefixes(to: &s3::Uri, written: &[s3::Uri], partitioned: bool) -> Vec<s3::Uri> {
+    let keys: Vec<s3::Key> = match partitioned {
+        true => written.iter().map(|uri| uri.key.basename_key()).collect(),
+        false if to.key.is_empty() || to.key.is_explicitly_directory() => vec![to.key.clone()],
+        false if matches!(written, [single] if single.key == to.key) => vec![to.key.basename_key()],
+        false => vec![to.key.to_explicit_directory()],
+    };
+    let mut prefixes: Vec<s3::Uri> = Vec::new();
+    for key in keys {
+        if !prefixes.iter().any(|prefix| prefix.key == key) {
+            prefixes.push(s3::Uri::new(to.bucket.clone(), key));
+        }
+    }
+    prefixes
+}
+
+/// `to` with `format` rendered at `now` inserted after its directory, before any filename
+pub fn dated_destination(to: &s3::Uri, format: &str, now: &chrono::DateTime<chrono::Utc>) -> s3::Uri {
+    inserted_destination(to, &now.format(format).to_string())
+}
+
+/// `to` with `inserted` after its directory, before any filename
+fn inserted_destination(to: &s3::Uri, inserted: &str) -> s3::Uri {
+    let filename = to.filename().unwrap_or("");
+    let mut key = s3::Key::new(to.key[..to.key.len() - filename.len()].to_owned());
+    key.push(inserted);
+    key.push(filename);
+    s3::Uri::new(to.bucket.clone(), key)
+}
+
+/// `items` without repeats, and with `recursive` without any `within` another, warning about each skipped
+fn distinct<T: Clone, N: PartialEq>(items: &[T], recursive: bool, name: impl Fn(&T) -> N, within: impl Fn(&N, &N) -> bool, describe: impl Fn(&T) -> String) -> Vec<T> {
+    let names: Vec<N> = items.iter().map(name).collect();
+    let mut kept = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        let covering = (0..items.len()).find(|&j| match names[j] == names[i] {
+            true => j < i,
+            false => recursive && within(&names[i], &names[j]),
+        });
+        match covering {
+            Some(j) if names[j] == names[i] => eprintln!("⚠️: skipping repeated {}", describe(item)),
+            Some(j) => eprintln!("⚠️: skipping {}, already within {}", describe(item), describe(&items[j])),
+            None => kept.push(item.clone()),
+        }
+    }
+    kept
+}
+
+fn distinct_paths(paths: &[std::path::PathBuf], recursive: bool) -> Vec<std::path::PathBuf> {
+    let name = |path: &std::path::PathBuf| std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+    distinct(paths, recursive, name, |inner, outer| inner.starts_with(outer), |path| format!("{path:?}"))
+}
+
+fn distinct_uris<T: Clone>(items: &[T], recursive: bool, uri: impl Fn(&T) -> &s3::Uri) -> Vec<T> {
+    // Recursively, a prefix is the same with or without its trailing '/'
+    let name = |item: &T| match recursive {
+        true => format!("{}/{}", uri(item).bucket, uri(item).key.trim_end_matches('/')),
+        false => format!("{}/{}", uri(item).bucket, uri(item).key),
+    };
+    let within = |inner: &String, outer: &String| inner.starts_with(&format!("{}/", outer.trim_end_matches('/')));
+    distinct(items, recursive, name, within, |item| uri(item).to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn upload(local_paths: &[std::path::PathBuf], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload, recursive: bool, partition: Option<&Partition>) -> MainResult {
+    upload_written(local_paths, to, client, opts, transfer, opts_upload, recursive, partition).await.0
+}
+
+/// Upload, also returning the URIs of the objects written
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_written(local_paths: &[std::path::PathBuf], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload, recursive: bool, partition: Option<&Partition>) -> (MainResult, Vec<s3::Uri>) {
+    let report = report::Report::default();
+    let result = locked(to, client, opts.verbose, opts_upload, upload_unlocked(local_paths, to, client, opts, transfer, opts_upload, recursive, partition, &report)).await;
+    (transfer.write_report(client, &report, result).await, report.written())
+}
+
+#[allow(clippy::too_many_arguments, clippy::into_iter_on_ref)]
+async fn upload_unlocked(local_paths: &[std::path::PathBuf], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload, recursive: bool, partition: Option<&Partition>, report: &report::Report) -> MainResult {
+    let client = &transfer.client(client);
+    let local_paths = &distinct_paths(local_paths, recursive);
+    let files = match opts.verbose || opts_upload.class.is_some() || opts_upload.class_rules.is_some() {
+        // Walking a large tree blocks, so it's kept off the runtime's threads
+        true => {
+            let paths = local_paths.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut files = Vec::new();
+                local_file_sizes(&paths, recursive, &mut files);
+                files
+            }).await.unwrap_or_default()
+        },
+        false => Vec::new(),
+    };
+    if let Err(result) = preflight(to, opts.verbose, opts_upload, files.len(), Some(&files)) {
+        return result;
+    }
+
     let file_prefix = cli::longest_file_display_prefix(local_paths.iter().filter_map(|path| path.to_str()));
     let progress = Arc::new(cli::Output::new(&transfer.progress, opts.verbose, Some(file_prefix)));
     progress.add_incoming_tasks(local_paths.len());
     let semaphore = Arc::new(tokio::sync::Semaphore::new(transfer.concurrency.get() as usize));
 
-    let cancellation = tokio_util::sync::CancellationToken::new();
-    let ctrlc_cancel = cancellation.clone();
-    tokio::spawn(async move {
-        let _ = tokio::signal::ctrl_c().await;
-        ctrlc_cancel.cancel();
-    });
+    let ctrl_c = CtrlC::listen();
+    let cancellation = ctrl_c.token();
 
     let verbose = opts.verbose && !progress.progress_enabled();
 
     let mut futures = FuturesUnordered::new();
 
     for path in local_paths.into_iter() {
-        let fut = upload_recursive_one(path.to_owned(), to, recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), transfer.clone(), opts_upload);
+        let fut = upload_recursive_one(path.to_owned(), path, to, recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), transfer.clone(), opts_upload, partition, report);
         futures.push(fut);
 
         if cancellation.is_cancelled() {
@@ -53,41 +366,95 @@ pub async fn upload(local_paths: &[std::path::PathBuf], to: &s3::Uri, client: &s
         let result = tokio::select!{
             res = &mut futures.next() => res,
             _ = cancellation.cancelled() => {
-                progress.mark_cancelled();
-                return MainResult::Cancelled;
+                drop(futures);
+                return abort_cancelled(client, &progress, opts_upload).await;
             },
         };
         match result {
             Some(count) => error_count += count,
             None => break,
         }
-        if error_count > 0 && !transfer.continue_on_error {
+        // Carry on past a quota error, to list everything not attempted
+        if error_count > 0 && !transfer.continue_on_error && !client.quota().is_exceeded() {
             break;
         }
     }
+    if opts_upload.multipart.stats {
+        print_part_retries(client);
+    }
+    if client.quota().is_exceeded() {
+        let not_attempted = client.quota().take_not_attempted();
+        progress.println_error(format_args!("storage quota exceeded, {} upload(s) not attempted", not_attempted.len()));
+        for path in not_attempted {
+            eprintln!("  {path}");
+        }
+        return MainResult::ErrorQuotaExceeded;
+    }
     MainResult::from_error_count(error_count)
 }
 
-async fn upload_single(path: &std::path::PathBuf, to: &s3::Uri, progress: Arc<cli::Output>, update_fn: cli::ProgressFn, client: s3::Client, verbose: bool, opts_upload: &s3::OptionsUpload, _permit: tokio::sync::OwnedSemaphorePermit) -> u32 {
+/// Abort multipart uploads cut short by ctrl-c, so their parts aren't left stored, unless
+/// --keep-cancelled-uploads
+async fn abort_cancelled(client: &s3::Client, progress: &cli::Output, opts_upload: &s3::OptionsUpload) -> MainResult {
+    progress.mark_cancelled();
+    abort_in_flight(client, opts_upload).await
+}
+
+/// Abort multipart uploads cut short by ctrl-c without progress output, see abort_cancelled
+async fn abort_in_flight(client: &s3::Client, opts_upload: &s3::OptionsUpload) -> MainResult {
+    if !opts_upload.multipart.keep_cancelled_uploads {
+        let (aborted, total) = client.abort_in_flight(ABORT_TIMEOUT).await;
+        if total > 0 {
+            eprintln!("⚠️: aborted {aborted} of {total} multipart upload(s) in progress");
+        }
+    }
+    MainResult::Cancelled
+}
+
+/// Summary of multipart upload parts that needed retrying and of throttled requests, for --stats
+fn print_part_retries(client: &s3::Client) {
+    let retried = client.part_retries().retried();
+    let total: u32 = retried.iter().map(|(_, _, retries)| retries).sum();
+    eprintln!("📊 {total} part retries across {} part(s)", retried.len());
+    for (uri, part_number, retries) in retried {
+        eprintln!("  {uri} part {part_number}: {retries} retries");
+    }
+    eprintln!("📊 {} request(s) throttled by the server", client.throttles().count());
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn upload_single(path: &std::path::PathBuf, length: u64, to: &s3::Uri, progress: Arc<cli::Output>, update_fn: cli::ProgressFn, client: s3::Client, verbose: bool, opts_upload: &s3::OptionsUpload, report: &report::Report, _permit: tokio::sync::OwnedSemaphorePermit) -> u32 {
     let update_fn_for_error = update_fn.clone();
+    let started = std::time::Instant::now();
     match client.put(verbose, opts_upload, path, to, update_fn).await {
         Ok(uri) => {
             progress.println_done_verbose(format_args!("uploaded {uri}"));
+            report.record(path.to_string_lossy(), &uri, started, Ok(length));
+            report.record_written(uri);
             0
         },
         Err(e) => {
+            client.quota().check(&e);
             progress.println_error_noprogress(format_args!("failed to upload {path:?} to {to}: {e}"));
+            report.record(path.to_string_lossy(), to, started, Err(e.to_string()));
             update_fn_for_error(cli::Update::Error(e.to_string()));
             1
         }
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 #[async_recursion::async_recursion]
-async fn upload_recursive_one(path: std::path::PathBuf, to: &s3::Uri, recursive: bool, progress: Arc<cli::Output>, client: s3::Client, verbose: bool, semaphore: Arc<tokio::sync::Semaphore>, options: OptionsTransfer, opts_upload: &s3::OptionsUpload) -> u32 {
+async fn upload_recursive_one(path: std::path::PathBuf, root: &std::path::Path, to: &s3::Uri, recursive: bool, progress: Arc<cli::Output>, client: s3::Client, verbose: bool, semaphore: Arc<tokio::sync::Semaphore>, options: OptionsTransfer, opts_upload: &s3::OptionsUpload, partition: Option<&Partition>, report: &report::Report) -> u32 {
     let token = semaphore.clone().acquire_owned().await.unwrap();
 
     let filename = path.to_string_lossy().to_string();
+    // Directories are still walked, so that only their files are listed as not attempted
+    if client.quota().is_exceeded() && !tokio::fs::metadata(&path).await.is_ok_and(|m| m.is_dir()) {
+        report.record(&filename, to, std::time::Instant::now(), Err("not attempted, storage quota exceeded".into()));
+        client.quota().not_attempted(filename);
+        return 1;
+    }
     let update_fn = progress.add("statting", filename);
 
     let metadata = match tokio::fs::metadata(&path).await {
@@ -100,7 +467,21 @@ async fn upload_recursive_one(path: std::path::PathBuf, to: &s3::Uri, recursive:
     };
 
     if !metadata.is_dir() {
-        return upload_single(&path, to, progress, update_fn, client, verbose, opts_upload, token).await;
+        let partitioned;
+        let to = match partition.map(|partition| partition.render(metadata.modified().ok(), |name| std::env::var(name).ok())) {
+            None => to,
+            Some(Ok(directories)) => {
+                partitioned = inserted_destination(to, &directories);
+                &partitioned
+            },
+            Some(Err(e)) => {
+                progress.println_error_noprogress(format_args!("failed to partition {path:?}: {e}"));
+                report.record(path.to_string_lossy(), to, std::time::Instant::now(), Err(e.clone()));
+                update_fn(cli::Update::Error(e));
+                return 1;
+            },
+        };
+        return upload_single(&path, metadata.len(), to, progress, update_fn, client, verbose, opts_upload, report, token).await;
     }
     if !recursive {
         progress.println_error_noprogress(format_args!("given directory {path:?} in non-recursive mode"));
@@ -144,33 +525,127 @@ async fn upload_recursive_one(path: std::path::PathBuf, to: &s3::Uri, recursive:
             Ok(Some(file)) => file,
             Ok(None) => break,
         };
+        let child_path = child_file.path();
+        let is_dir = child_file.file_type().await.is_ok_and(|t| t.is_dir());
+        let relative = child_path.strip_prefix(root).unwrap_or(&child_path).to_string_lossy();
+        if !is_dir && !options.filter.matches(&relative) {
+            continue;
+        }
         progress.add_incoming_tasks(1);
 
-        futures.push(upload_recursive_one(child_file.path(), &to_child, recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), options.clone(), opts_upload));
+        futures.push(upload_recursive_one(child_path, root, &to_child, recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), options.clone(), opts_upload, partition, report));
     }
 
     update_fn(cli::Update::FinishedHide());
     while let Some(res) = futures.next().await {
         error_count += res;
-        if error_count > 0 && !options.continue_on_error {
+        if error_count > 0 && !options.continue_on_error && !client.quota().is_exceeded() {
             return error_count;
         }
     }
     error_count
 }
 
+pub async fn upload_urls(entries: &[manifest::Entry], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload) -> MainResult {
+    upload_urls_written(entries, to, client, opts, transfer, opts_upload).await.0
+}
+
+/// Upload from URLs, also returning the URIs of the objects written
+pub async fn upload_urls_written(entries: &[manifest::Entry], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload) -> (MainResult, Vec<s3::Uri>) {
+    let report = report::Report::default();
+    let result = locked(to, client, opts.verbose, opts_upload, upload_urls_unlocked(entries, to, client, opts, transfer, opts_upload, &report)).await;
+    (transfer.write_report(client, &report, result).await, report.written())
+}
+
+async fn upload_urls_unlocked(entries: &[manifest::Entry], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload, report: &report::Report) -> MainResult {
+    if let Err(result) = preflight(to, opts.verbose, opts_upload, entries.len(), None) {
+        return result;
+    }
+    let client = &transfer.client(client);
+    let url_prefix = cli::longest_file_display_prefix(entries.iter().map(|entry| entry.url.as_str()));
+    let progress = Arc::new(cli::Output::new(&transfer.progress, opts.verbose, Some(url_prefix)));
+    progress.add_incoming_tasks(entries.len());
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(transfer.concurrency.get() as usize));
+
+    let ctrl_c = CtrlC::listen();
+    let cancellation = ctrl_c.token();
+
+    let verbose = opts.verbose && !progress.progress_enabled();
+
+    let mut futures = FuturesUnordered::new();
+
+    for entry in entries.iter() {
+        futures.push(upload_url_one(entry, to, progress.clone(), client.clone(), verbose, semaphore.clone(), opts_upload, report));
+    }
+
+    let mut error_count = 0;
+    loop {
+        let result = tokio::select!{
+            res = &mut futures.next() => res,
+            _ = cancellation.cancelled() => {
+                drop(futures);
+                return abort_cancelled(client, &progress, opts_upload).await;
+            },
+        };
+        match result {
+            Some(count) => error_count += count,
+            None => break,
+        }
+        if error_count > 0 && !transfer.continue_on_error {
+            break;
+        }
+    }
+    MainResult::from_error_count(error_count)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn upload_url_one(entry: &manifest::Entry, to: &s3::Uri, progress: Arc<cli::Output>, client: s3::Client, verbose: bool, semaphore: Arc<tokio::sync::Semaphore>, opts_upload: &s3::OptionsUpload, report: &report::Report) -> u32 {
+    let _permit = semaphore.acquire_owned().await.unwrap();
+    let url = &entry.url;
+    let update_fn = progress.add("connecting", url.to_string());
+    let started = std::time::Instant::now();
+    match client.put_url(verbose, opts_upload, entry, to, update_fn.clone()).await {
+        Ok((uri, length)) => {
+            progress.println_done_verbose(format_args!("uploaded {uri}"));
+            report.record(url, &uri, started, Ok(length));
+            report.record_written(uri);
+            0
+        },
+        Err(e) => {
+            progress.println_error_noprogress(format_args!("failed to upload {url} to {to}: {e}"));
+            report.record(url, to, started, Err(e.to_string()));
+            update_fn(cli::Update::Error(e.to_string()));
+            1
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 #[async_recursion::async_recursion]
-async fn download_recursive_one(uri: s3::Uri, target: s3::Target, recursive: bool, progress: Arc<cli::Output>, client: s3::Client, verbose: bool, semaphore: Arc<tokio::sync::Semaphore>, options: OptionsTransfer) -> u32 {
+#[allow(clippy::len_zero)]
+async fn download_recursive_one(uri: s3::Uri, listed_etag: Option<String>, target: s3::Target, recursive: bool, progress: Arc<cli::Output>, client: s3::Client, verbose: bool, semaphore: Arc<tokio::sync::Semaphore>, options: OptionsTransfer, opts_download: &s3::OptionsDownload, report: &report::Report) -> u32 {
     let token = semaphore.clone().acquire_owned().await.unwrap();
+    let started = std::time::Instant::now();
     let update_fn = progress.add("initialising", uri.to_string());
     let update_fn_for_error = update_fn.clone();
     let mut error_count = 0;
-    let (res, ..) = client.get_recursive_stream(verbose, recursive, uri.clone(), target.clone(), update_fn)
-        .map(|res| (res, token))
-        .await;
+    let res = client.get_recursive_stream(verbose, recursive, opts_download, options.ranged_download(), uri.clone(), listed_etag.as_deref(), target.clone(), update_fn.clone()).await;
+    // Rewritten since listed, so fetched again whole rather than mixing versions
+    let res = match res {
+        Err(s3::Error::Modified(_)) => {
+            report.record_refetched(&uri);
+            client.get_recursive_stream(verbose, false, opts_download, options.ranged_download(), uri.clone(), None, target.clone(), update_fn).await
+        },
+        res => res,
+    };
+    drop(token);
     match res {
-        Ok(s3::GetRecursiveResultStream::One(path)) => if verbose && options.concurrency.get() > 1 && !progress.progress_enabled() {
-            progress.println_done_verbose(format_args!("downloaded {path:?}"));
+        Ok(s3::GetRecursiveResultStream::One(path)) => {
+            if verbose && options.concurrency.get() > 1 && !progress.progress_enabled() {
+                progress.println_done_verbose(format_args!("downloaded {path:?}"));
+            }
+            let length = tokio::fs::metadata(&path).await.map(|m| m.len()).unwrap_or(0);
+            report.record(&uri, path.to_string_lossy(), started, Ok(length));
         },
         Ok(s3::GetRecursiveResultStream::Many(mut list_stream)) => {
             let stream = list_stream.stream();
@@ -186,7 +661,14 @@ async fn download_recursive_one(uri: s3::Uri, target: s3::Target, recursive: boo
                     },
                 };
                 let mut futures = FuturesUnordered::new();
-                let file_count = page.iter().filter(|e| matches!(e, s3::RecursiveStreamItem::File(_))).count();
+                let relative_path = |key: &str| key[uri.key.len()..].trim_start_matches('/').to_owned();
+                let page: Vec<_> = page.into_iter()
+                    .filter(|entry| match entry {
+                        s3::RecursiveStreamItem::File(key, summary) => options.filter.matches(&relative_path(key)) && opts_download.matches_age(summary.last_modified.as_ref()),
+                        s3::RecursiveStreamItem::Directory(_) => true,
+                    })
+                    .collect();
+                let file_count = page.iter().filter(|e| matches!(e, s3::RecursiveStreamItem::File(..))).count();
                 progress.add_incoming_tasks(file_count);
                 for entry in page {
                     match entry {
@@ -210,7 +692,7 @@ async fn download_recursive_one(uri: s3::Uri, target: s3::Target, recursive: boo
                                 }
                             }
                         },
-                        s3::RecursiveStreamItem::File(key) => {
+                        s3::RecursiveStreamItem::File(key, summary) => {
                             let mut additional_path: &str = &key[uri.key.len()..];
                             if let Some(path) = additional_path.strip_prefix('/') {
                                 additional_path = path;
@@ -220,7 +702,7 @@ async fn download_recursive_one(uri: s3::Uri, target: s3::Target, recursive: boo
                                 Some(dir) => target.child(dir),
                                 None => target.clone(),
                             };
-                            let fut = download_recursive_one(s3::Uri::new(uri.bucket.clone(), key), target.clone(), recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), options.clone());
+                            let fut = download_recursive_one(s3::Uri::new(uri.bucket.clone(), key), summary.etag, target.clone(), recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), options.clone(), opts_download, report);
                             futures.push(fut);
                         },
                     };
@@ -236,39 +718,166 @@ async fn download_recursive_one(uri: s3::Uri, target: s3::Target, recursive: boo
         Err(err) => {
             update_fn_for_error(cli::Update::Error(err.to_string()));
             progress.println_error_noprogress(format_args!("failed to download {uri}: {err}"));
+            report.record(&uri, target.path().to_string_lossy(), started, Err(err.to_string()));
             error_count += 1;
         }
     }
     error_count
 }
 
-pub async fn download(uris: &[s3::Uri], to: &std::path::PathBuf, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, recursive: bool) -> MainResult {
+/// Each of `uris`, or with globbing enabled the objects a glob matches, with their path below its
+/// prefix to keep. Objects `include` rejects are skipped
+async fn expand_globs(uris: &[s3::Uri], client: &s3::Client, glob_options: &s3::GlobOptions, include: impl Fn(&str, &s3::ObjectSummary) -> bool) -> Result<Vec<(s3::Uri, Option<String>)>, String> {
+    let mut expanded = Vec::new();
+    for uri in uris {
+        let Some(glob) = s3::Glob::new(&uri.key, glob_options) else {
+            expanded.push((uri.clone(), None));
+            continue;
+        };
+        let objects = client.glob_objects(&uri.bucket, &glob).await.map_err(|e| format!("failed to list {uri}: {e}"))?;
+        if objects.is_empty() {
+            return Err(format!("no objects match {uri}"));
+        }
+        expanded.extend(objects.into_iter()
+            .filter(|(key, summary)| include(glob.relative(key), summary))
+            .map(|(key, _)| {
+                let relative = glob.relative(&key).to_owned();
+                (s3::Uri::new(uri.bucket.clone(), key), Some(relative))
+            }));
+    }
+    Ok(expanded)
+}
+
+/// Server-side copy of objects, or with `recursive` of whole prefixes
+#[allow(clippy::too_many_arguments)]
+pub async fn copy(uris: &[s3::Uri], to: &s3::Uri, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_upload: &s3::OptionsUpload, glob_options: &s3::GlobOptions, rewrite: &s3::OptionsRewrite, recursive: bool) -> MainResult {
+    if let Err(e) = opts_upload.validate_destination(to) {
+        cli::println_error(format_args!("{e}"));
+        return MainResult::ErrorArguments;
+    }
+    let sources = match expand_globs(uris, client, glob_options, |relative, _| transfer.filter.matches(relative)).await {
+        Ok(sources) => distinct_uris(&sources, recursive, |(uri, _)| uri),
+        Err(e) => {
+            cli::println_error(format_args!("{e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        },
+    };
+    let copied = async {
+        let mut error_count = 0;
+        for (from, relative) in &sources {
+            // Glob matches keep their path below the glob's prefix
+            let to = &match relative {
+                Some(relative) => s3::Uri::new(to.bucket.clone(), s3::Key::new(format!("{}{relative}", to.key.to_explicit_directory()))),
+                None => to.clone(),
+            };
+            let result = match recursive {
+                false => match rewrite.object_destination(from, to) {
+                    Ok(to) => client.copy_object(opts.verbose, Some(opts_upload), from, &to).await.map(|_| 0),
+                    Err(e) => Err(e),
+                },
+                true => client.copy_recursive(opts.verbose, opts_upload, from, to, transfer.concurrency.get() as usize, transfer.continue_on_error, &transfer.filter, rewrite).await,
+            };
+            match result {
+                Ok(failed) => error_count += failed,
+                Err(e) => {
+                    cli::println_error(format_args!("failed to copy {from}: {e}"));
+                    error_count += 1;
+                },
+            }
+            if error_count > 0 && !transfer.continue_on_error {
+                break;
+            }
+        }
+        MainResult::from_error_count(error_count)
+    };
+    // Cancelled within the lock, so it's still released after ctrl-c
+    let ctrl_c = CtrlC::listen();
+    locked(to, client, opts.verbose, opts_upload, async {
+        tokio::select! {
+            result = copied => result,
+            _ = ctrl_c.token().cancelled() => abort_in_flight(client, opts_upload).await,
+        }
+    }).await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn download(uris: &[s3::Uri], to: &std::path::PathBuf, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_download: &s3::OptionsDownload, glob_options: &s3::GlobOptions, recursive: bool) -> MainResult {
+    let include = |relative: &str, summary: &s3::ObjectSummary| transfer.filter.matches(relative) && opts_download.matches_age(summary.last_modified.as_ref());
+    let sources = match expand_globs(uris, client, glob_options, include).await {
+        Ok(sources) => distinct_uris(&sources, recursive, |(uri, _)| uri),
+        Err(e) => {
+            cli::println_error(format_args!("{e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        },
+    };
+    let sources = named_within_age(sources, client, opts_download).await;
+    let report = report::Report::default();
+    let result = download_reported(&sources, to, client, opts, transfer, opts_download, recursive, &report).await;
+    transfer.write_report(client, &report, result).await
+}
+
+/// `sources` without the explicitly named objects outside --newer-than, --older-than or
+/// --settle-time; listed objects are already filtered and prefixes are left for the listing
+async fn named_within_age(sources: Vec<(s3::Uri, Option<String>)>, client: &s3::Client, opts_download: &s3::OptionsDownload) -> Vec<(s3::Uri, Option<String>)> {
+    if !opts_download.age.is_enabled() && opts_download.settle_time.is_none() {
+        return sources;
+    }
+    let mut kept = Vec::with_capacity(sources.len());
+    for (uri, relative) in sources {
+        if relative.is_none() && !uri.key.is_empty() && !uri.key.ends_with('/') {
+            if let Ok(head) = client.head(&uri).await {
+                if !opts_download.matches_age(head.last_modified()) {
+                    eprintln!("⚠️: skipping {uri}, last modified outside --newer-than, --older-than or --settle-time");
+                    continue;
+                }
+            }
+        }
+        kept.push((uri, relative));
+    }
+    kept
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download_reported(sources: &[(s3::Uri, Option<String>)], to: &std::path::PathBuf, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, opts_download: &s3::OptionsDownload, recursive: bool, report: &report::Report) -> MainResult {
+    let client = &transfer.client(client);
+    let uris: Vec<s3::Uri> = sources.iter().map(|(uri, _)| uri.clone()).collect();
     let uri_prefix = cli::longest_file_display_prefix(uris.iter().map(|uri| uri.to_string()));
     let progress = Arc::new(cli::Output::new(&transfer.progress, opts.verbose, Some(uri_prefix.clone())));
     progress.add_incoming_tasks(uris.len());
     let verbose = opts.verbose && !progress.progress_enabled();
 
     let semaphore = Arc::new(tokio::sync::Semaphore::new(transfer.concurrency.get() as usize));
-    let cancellation = tokio_util::sync::CancellationToken::new();
+    let ctrl_c = CtrlC::listen();
+    let cancellation = ctrl_c.token();
 
-    let ctrlc_cancel = cancellation.clone();
-    tokio::spawn(async move {
-        let _ = tokio::signal::ctrl_c().await;
-        ctrlc_cancel.cancel();
-    });
-
-    let target = match s3::Target::new_create(uris, to, true) {
+    let target = match s3::Target::new_create(&uris, to, true) {
         Ok(i) => i,
         Err(err) => {
             progress.println_error(format_args!("local path {to:?}: {err}"));
             return MainResult::ErrorArguments;
         },
     };
+    match &target {
+        s3::Target::Directory(dir) => clean::warn_stale(dir),
+        s3::Target::File(path) => clean::warn_stale(path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."))),
+    }
 
     let mut futures = FuturesUnordered::new();
 
-    for uri in uris.iter() {
-        let fut = download_recursive_one(uri.clone(), target.clone(), recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), transfer.clone());
+    for (uri, relative) in sources {
+        // Glob matches keep their directories below the glob's prefix
+        let target = match (&target, relative.as_deref().and_then(|relative| relative.rsplit_once('/'))) {
+            (s3::Target::Directory(_), Some((directory, _))) => {
+                let child = target.child(directory);
+                if let Err(e) = tokio::fs::create_dir_all(child.path()).await {
+                    progress.println_error(format_args!("creating directory {:?}: {e}", child.path()));
+                    return MainResult::ErrorSomeOperationsFailed;
+                }
+                child
+            },
+            _ => target.clone(),
+        };
+        let fut = download_recursive_one(uri.clone(), None, target, recursive, progress.clone(), client.clone(), verbose, semaphore.clone(), transfer.clone(), opts_download, report);
         futures.push(fut);
 
         if cancellation.is_cancelled() {
@@ -293,5 +902,50 @@ pub async fn download(uris: &[s3::Uri], to: &std::path::PathBuf, client: &s3::Cl
             break;
         }
     }
+    let refetched = report.refetched();
+    if !refetched.is_empty() {
+        eprintln!("⚠️: {} object(s) changed while downloading, fetched again at their latest version", refetched.len());
+        for uri in refetched {
+            eprintln!("  {uri}");
+        }
+    }
     MainResult::from_error_count(error_count)
 }
+
+#[test]
+fn test_dated_destination() {
+    use chrono::TimeZone;
+    let now = chrono::Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+    let dated = |to: &str, format| dated_destination(&to.parse().unwrap(), format, &now).to_string();
+    assert_eq!(dated("s3://bucket/backups/", "%Y/%m/%d/"), "s3://bucket/backups/2024/01/02/");
+    assert_eq!(dated("s3://bucket/backups/db.tar", "%Y/%m/%d/"), "s3://bucket/backups/2024/01/02/db.tar");
+    assert_eq!(dated("s3://bucket/", "%Y%m%dT%H%M/"), "s3://bucket/20240102T0304/");
+}
+
+#[test]
+fn test_success_marker_prefixes() {
+    let uri = |uri: &str| uri.parse::<s3::Uri>().unwrap();
+    let prefixes = |to: &str, written: &[&str], partitioned| success_marker_prefixes(&uri(to), &written.iter().map(|written| uri(written)).collect::<Vec<_>>(), partitioned)
+        .iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(prefixes("s3://bkt/out/", &["s3://bkt/out/a", "s3://bkt/out/d/b"], false), ["s3://bkt/out/"]);
+    assert_eq!(prefixes("s3://bkt/out", &["s3://bkt/out/a", "s3://bkt/out/b"], false), ["s3://bkt/out/"]);
+    assert_eq!(prefixes("s3://bkt/out/a.csv", &["s3://bkt/out/a.csv"], false), ["s3://bkt/out/"]);
+    assert_eq!(prefixes("s3://bkt/out/", &["s3://bkt/out/date=1/a", "s3://bkt/out/date=2/b", "s3://bkt/out/date=1/c"], true), ["s3://bkt/out/date=1/", "s3://bkt/out/date=2/"]);
+}
+
+#[test]
+fn test_distinct_uris() {
+    let uris: Vec<s3::Uri> = ["s3://bkt/dir/", "s3://bkt/dir/x", "s3://bkt/dir", "s3://bkt/dirx", "s3://cat/dir/x"].iter().map(|uri| uri.parse().unwrap()).collect();
+    let distinct = |recursive| distinct_uris(&uris, recursive, |uri| uri).iter().map(|uri| uri.to_string()).collect::<Vec<_>>();
+    assert_eq!(distinct(false), ["s3://bkt/dir/", "s3://bkt/dir/x", "s3://bkt/dir", "s3://bkt/dirx", "s3://cat/dir/x"]);
+    assert_eq!(distinct(true), ["s3://bkt/dir/", "s3://bkt/dirx", "s3://cat/dir/x"]);
+    let repeated: Vec<s3::Uri> = ["s3://bkt/x", "s3://bkt/x"].iter().map(|uri| uri.parse().unwrap()).collect();
+    assert_eq!(distinct_uris(&repeated, false, |uri| uri).len(), 1);
+}
+
+#[test]
+fn test_distinct_paths() {
+    let paths: Vec<std::path::PathBuf> = ["nonexistent/a", "nonexistent/a", "nonexistent/a/b", "nonexistent/ab"].iter().map(Into::into).collect();
+    assert_eq!(distinct_paths(&paths, false), [&paths[0], &paths[2], &paths[3]].map(Clone::clone));
+    assert_eq!(distinct_paths(&paths, true), [&paths[0], &paths[3]].map(Clone::clone));
+}
@@ -0,0 +1,99 @@
+use std::time::SystemTime;
+
+/// One piece of a --partition-by template
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// strftime format of the file's modification time in UTC
+    Mtime(String),
+    /// Name of an environment variable
+    Env(String),
+}
+
+/// Hive-style partition directories rendered per uploaded file, e.g.
+/// 'date={mtime:%Y-%m-%d}/host={env:HOSTNAME}'
+#[derive(Debug, Clone, PartialEq)]
+pub struct Partition {
+    segments: Vec<Segment>,
+}
+
+impl std::str::FromStr for Partition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use chrono::format::{Item, StrftimeItems};
+        let mut segments = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_owned()));
+            }
+            let end = rest[start..].find('}').ok_or_else(|| format!("unterminated '{{' in '{s}'"))? + start;
+            let placeholder = &rest[start + 1..end];
+            segments.push(match placeholder.split_once(':') {
+                Some(("mtime", format)) if !format.is_empty() => {
+                    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                        return Err(format!("invalid strftime format '{format}'"));
+                    }
+                    Segment::Mtime(format.to_owned())
+                },
+                Some(("env", name)) if !name.is_empty() => Segment::Env(name.to_owned()),
+                _ => return Err(format!("unknown placeholder '{{{placeholder}}}', expected {{mtime:FORMAT}} or {{env:NAME}}")),
+            });
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("unmatched '}}' in '{s}'"));
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_owned()));
+        }
+        Ok(Partition { segments })
+    }
+}
+
+impl Partition {
+    /// Directories for a file last modified at `mtime`, ending in '/'
+    pub fn render(&self, mtime: Option<SystemTime>, env: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => rendered.push_str(literal),
+                Segment::Mtime(format) => {
+                    let mtime = mtime.ok_or("modification time unavailable")?;
+                    rendered += &chrono::DateTime::<chrono::Utc>::from(mtime).format(format).to_string();
+                },
+                Segment::Env(name) => {
+                    let value = env(name).ok_or_else(|| format!("environment variable {name} is unset"))?;
+                    // Only the template may add directories, or climb out of the destination
+                    if value.contains('/') || value.contains("..") {
+                        return Err(format!("environment variable {name} contains '/' or '..'"));
+                    }
+                    rendered += &value;
+                },
+            }
+        }
+        if !rendered.ends_with('/') {
+            rendered.push('/');
+        }
+        Ok(rendered)
+    }
+}
+
+#[test]
+fn test_partition() {
+    let partition: Partition = "date={mtime:%Y-%m-%d}/host={env:HOST}".parse().unwrap();
+    let mtime = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    let env = |name: &str| (name == "HOST").then(|| "web1".to_owned());
+    assert_eq!(partition.render(Some(mtime), env), Ok("date=2023-11-14/host=web1/".into()));
+    assert!(partition.render(None, env).is_err());
+    assert!(partition.render(Some(mtime), |_| None).is_err());
+    assert_eq!("raw/".parse::<Partition>().unwrap().render(None, |_| None), Ok("raw/".into()));
+    assert!(partition.render(Some(mtime), |_| Some("web/1".into())).is_err());
+    assert!(partition.render(Some(mtime), |_| Some("..".into())).is_err());
+
+    assert!("date={mtime}".parse::<Partition>().is_err());
+    assert!("date={size:x}".parse::<Partition>().is_err());
+    assert!("date={mtime:%Y".parse::<Partition>().is_err());
+    assert!("date=}".parse::<Partition>().is_err());
+}
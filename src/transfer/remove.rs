@@ -0,0 +1,219 @@
+use futures::StreamExt;
+
+use crate::cli;
+use crate::s3;
+use crate::shared_options::SharedOptions;
+use super::MainResult;
+
+/// Remove keys of one bucket, batched when there's more than one. Returns the number of failures
+async fn remove_keys(bucket: &str, keys: &[s3::Key], client: &s3::Client, opts: &SharedOptions, progress: &cli::Output, update_fn: &cli::ProgressFn) -> u32 {
+    let uri = |key: &s3::Key| s3::Uri::new(bucket.to_owned(), key.clone());
+    let failed = match keys {
+        [key] => match client.remove(opts, &uri(key)).await {
+            Ok(()) => vec![],
+            Err(e) => vec![(key.clone(), e.to_string())],
+        },
+        keys => match client.remove_batch(opts, bucket, keys).await {
+            Ok(failed) => failed,
+            Err(e) => keys.iter().map(|key| (key.clone(), e.to_string())).collect(),
+        },
+    };
+    for (key, e) in &failed {
+        progress.println_error(format_args!("failed to remove {}: {e}", uri(key)));
+    }
+    update_fn(cli::Update::StateProgress(keys.len() - failed.len()));
+    failed.len() as u32
+}
+
+/// Those of `keys` whose objects are within `size` and `age`, and the number that couldn't be checked
+async fn keys_within(bucket: &str, keys: Vec<s3::Key>, client: &s3::Client, size: &s3::OptionsSize, age: &s3::OptionsAge, progress: &cli::Output) -> (Vec<s3::Key>, u32) {
+    if !size.is_enabled() && !age.is_enabled() {
+        return (keys, 0);
+    }
+    let mut within = Vec::new();
+    let mut error_count = 0;
+    for key in keys {
+        let uri = s3::Uri::new(bucket.to_owned(), key);
+        match client.head(&uri).await {
+            Ok(head) if size.matches(head.content_length().unwrap_or(0) as u64) && age.matches(head.last_modified()) => within.push(uri.key),
+            Ok(_) => {},
+            Err(e) => {
+                progress.println_error(format_args!("failed to check {uri}: {e}"));
+                error_count += 1;
+            },
+        }
+    }
+    (within, error_count)
+}
+
+/// Remove every object below `uri`, or `uri` itself if nothing is below it. Returns the number of failures
+async fn remove_prefix(uri: &s3::Uri, client: &s3::Client, opts: &SharedOptions, progress: &cli::Output, size: &s3::OptionsSize, age: &s3::OptionsAge, continue_on_error: bool) -> u32 {
+    let list_update_fn = progress.add("listing", uri.to_string());
+    let mut list_stream = match client.get_recursive_list_stream(uri, list_update_fn.clone()).await {
+        Ok(list_stream) => list_stream,
+        Err(e) => {
+            progress.println_error(format_args!("failed to list {uri}: {e}"));
+            return 1;
+        },
+    };
+    let update_fn = progress.add("removing", uri.to_string());
+    let stream = list_stream.stream();
+    futures::pin_mut!(stream);
+    let mut listed = 0;
+    let mut error_count = 0;
+    while let Some(page) = stream.next().await {
+        let keys: Vec<s3::Key> = match page {
+            Ok(page) => page.into_iter()
+                .filter_map(|item| match item {
+                    s3::RecursiveStreamItem::File(key, summary) if size.matches(summary.size) && age.matches(summary.last_modified.as_ref()) => Some(key),
+                    s3::RecursiveStreamItem::File(..) | s3::RecursiveStreamItem::Directory(_) => None,
+                })
+                .collect(),
+            // Not a prefix, the key itself may still be an object unless it's the whole bucket
+            Err(s3::Error::NoSuchKey(_)) if listed == 0 => {
+                list_update_fn(cli::Update::FinishedHide());
+                if uri.key.is_empty() {
+                    vec![]
+                } else {
+                    let (keys, failed) = keys_within(&uri.bucket, vec![uri.key.clone()], client, size, age, progress).await;
+                    error_count += failed;
+                    keys
+                }
+            },
+            Err(e) => {
+                list_update_fn(cli::Update::Error(e.to_string()));
+                progress.println_error_noprogress(format_args!("failed to list {uri}: {e}"));
+                return error_count + 1;
+            },
+        };
+        listed += keys.len();
+        update_fn(cli::Update::StateLength(listed));
+        error_count += remove_keys(&uri.bucket, &keys, client, opts, progress, &update_fn).await;
+        if error_count > 0 && !continue_on_error {
+            update_fn(cli::Update::Error(format!("{error_count} failed")));
+            return error_count;
+        }
+    }
+    match error_count {
+        0 => update_fn(cli::Update::Finished()),
+        count => update_fn(cli::Update::Error(format!("{count} failed"))),
+    }
+    progress.println_done_verbose(format_args!("removed {listed} object(s) below {uri}"));
+    error_count
+}
+
+/// Permanently remove every version and delete marker within `size` and `age` of `uri`, or with
+/// `recursive` of everything below it, or `uri` itself if nothing is below it. Returns the number of failures
+async fn remove_all_versions(uri: &s3::Uri, client: &s3::Client, opts: &SharedOptions, progress: &cli::Output, size: &s3::OptionsSize, age: &s3::OptionsAge, recursive: bool) -> u32 {
+    let update_fn = progress.add("removing versions", uri.to_string());
+    let listed = async {
+        if recursive {
+            let below = client.list_versions(&uri.bucket, &uri.key.to_explicit_directory(), false, size, age).await?;
+            if !below.is_empty() || uri.key.is_empty() {
+                return Ok(below);
+            }
+        }
+        client.list_versions(&uri.bucket, &uri.key, true, size, age).await
+    }.await;
+    let versions = match listed {
+        Ok(versions) => versions,
+        Err(e) => {
+            update_fn(cli::Update::Error(e.to_string()));
+            progress.println_error_noprogress(format_args!("failed to list versions of {uri}: {e}"));
+            return 1;
+        },
+    };
+    update_fn(cli::Update::StateLength(versions.len()));
+    let failed = match client.remove_versions(opts, &uri.bucket, &versions).await {
+        Ok(failed) => failed,
+        Err(e) => versions.iter().map(|(key, version_id)| (key.clone(), version_id.clone(), e.to_string())).collect(),
+    };
+    for (key, version_id, e) in &failed {
+        progress.println_error(format_args!("failed to remove version {version_id} of {}: {e}", s3::Uri::new(uri.bucket.clone(), key.clone())));
+    }
+    update_fn(cli::Update::StateProgress(versions.len() - failed.len()));
+    match failed.len() {
+        0 => update_fn(cli::Update::Finished()),
+        count => update_fn(cli::Update::Error(format!("{count} failed"))),
+    }
+    progress.println_done_verbose(format_args!("removed {} version(s) of {uri}", versions.len() - failed.len()));
+    failed.len() as u32
+}
+
+/// Exact keys grouped by bucket, in order of first appearance
+fn keys_by_bucket(uris: &[s3::Uri]) -> Vec<(&str, Vec<s3::Key>)> {
+    let mut buckets: Vec<(&str, Vec<s3::Key>)> = Vec::new();
+    for uri in uris {
+        match buckets.iter_mut().find(|(bucket, _)| *bucket == uri.bucket) {
+            Some((_, keys)) => keys.push(uri.key.clone()),
+            None => buckets.push((&uri.bucket, vec![uri.key.clone()])),
+        }
+    }
+    buckets
+}
+
+/// Remove objects within `size` and `age`, and with `recursive` everything below prefixes. With
+/// `all_versions` every version and delete marker is removed, rather than adding delete markers
+#[allow(clippy::too_many_arguments)]
+pub async fn remove(uris: &[s3::Uri], client: &s3::Client, opts: &SharedOptions, progress: &cli::ArgProgress, size: &s3::OptionsSize, age: &s3::OptionsAge, recursive: bool, all_versions: bool, continue_on_error: bool) -> MainResult {
+    let progress = cli::Output::new(progress, opts.verbose, None);
+
+    let ctrl_c = super::CtrlC::listen();
+    let cancellation = ctrl_c.token();
+
+    let removal = async {
+        let mut error_count = 0;
+        if all_versions {
+            for uri in uris {
+                error_count += remove_all_versions(uri, client, opts, &progress, size, age, recursive).await;
+                if error_count > 0 && !continue_on_error {
+                    break;
+                }
+            }
+            return error_count;
+        }
+        if recursive {
+            for uri in uris {
+                error_count += remove_prefix(uri, client, opts, &progress, size, age, continue_on_error).await;
+                if error_count > 0 && !continue_on_error {
+                    break;
+                }
+            }
+            return error_count;
+        }
+        for (bucket, keys) in keys_by_bucket(uris) {
+            let (keys, failed) = keys_within(bucket, keys, client, size, age, &progress).await;
+            error_count += failed;
+            if error_count > 0 && !continue_on_error {
+                break;
+            }
+            if keys.is_empty() {
+                continue;
+            }
+            let update_fn = progress.add("removing", format!("s3://{bucket}/"));
+            update_fn(cli::Update::StateLength(keys.len()));
+            error_count += remove_keys(bucket, &keys, client, opts, &progress, &update_fn).await;
+            update_fn(cli::Update::FinishedHide());
+            if error_count > 0 && !continue_on_error {
+                break;
+            }
+        }
+        error_count
+    };
+    tokio::select!{
+        error_count = removal => MainResult::from_error_count(error_count),
+        _ = cancellation.cancelled() => {
+            progress.mark_cancelled();
+            MainResult::Cancelled
+        },
+    }
+}
+
+#[test]
+fn test_keys_by_bucket() {
+    let uris: Vec<s3::Uri> = ["s3://one/a", "s3://two/b", "s3://one/c"].iter().map(|uri| uri.parse().unwrap()).collect();
+    let grouped: Vec<String> = keys_by_bucket(&uris).into_iter()
+        .map(|(bucket, keys)| format!("{bucket}: {}", keys.iter().map(|key| key.as_str()).collect::<Vec<_>>().join(",")))
+        .collect();
+    assert_eq!(grouped, ["one: a,c", "two: b"]);
+}
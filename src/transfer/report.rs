@@ -0,0 +1,135 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::cli::json_string;
+
+/// Outcome of one file transfer
+struct Entry {
+    source: String,
+    destination: String,
+    bytes: u64,
+    duration: Duration,
+    error: Option<String>,
+}
+
+/// Per-file results of a transfer, written as JSON with --report
+pub struct Report {
+    started: chrono::DateTime<chrono::Utc>,
+    entries: Mutex<Vec<Entry>>,
+    /// Sources that changed mid-transfer and were transferred again
+    refetched: Mutex<Vec<String>>,
+    /// Objects uploaded
+    written: Mutex<Vec<crate::s3::Uri>>,
+}
+
+impl Default for Report {
+    fn default() -> Report {
+        Report { started: chrono::Utc::now(), entries: Default::default(), refetched: Default::default(), written: Default::default() }
+    }
+}
+
+impl Report {
+    /// Record a transfer begun at `started`, with the bytes it transferred or the error it failed with
+    pub fn record(&self, source: impl ToString, destination: impl ToString, started: Instant, result: Result<u64, String>) {
+        let (bytes, error) = match result {
+            Ok(bytes) => (bytes, None),
+            Err(e) => (0, Some(e)),
+        };
+        self.entries.lock().unwrap().push(Entry {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            bytes,
+            duration: started.elapsed(),
+            error,
+        });
+    }
+
+    /// Note `source` changed after it was listed, so was transferred again from scratch
+    pub fn record_refetched(&self, source: impl ToString) {
+        self.refetched.lock().unwrap().push(source.to_string());
+    }
+
+    pub fn refetched(&self) -> Vec<String> {
+        self.refetched.lock().unwrap().clone()
+    }
+
+    pub fn record_written(&self, uri: crate::s3::Uri) {
+        self.written.lock().unwrap().push(uri);
+    }
+
+    pub fn written(&self) -> Vec<crate::s3::Uri> {
+        self.written.lock().unwrap().clone()
+    }
+
+    fn files_json(&self) -> String {
+        let entries = self.entries.lock().unwrap();
+        let files: Vec<String> = entries.iter()
+            .map(|entry| format!(
+                "    {{\"source\": {}, \"destination\": {}, \"bytes\": {}, \"seconds\": {:.3}, \"error\": {}}}",
+                json_string(&entry.source),
+                json_string(&entry.destination),
+                entry.bytes,
+                entry.duration.as_secs_f64(),
+                entry.error.as_deref().map(json_string).unwrap_or("null".into())))
+            .collect();
+        match files.is_empty() {
+            true => "[]".into(),
+            false => format!("[\n{}\n]", files.join(",\n")),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        format!("{{\"files\": {}}}\n", self.files_json())
+    }
+
+    /// The report with the run's `status`, times and totals ahead of the files, for --summary-to.
+    /// Requests `throttled` and retried are counted apart from failures
+    pub fn summary_json(&self, status: &str, throttled: u64, finished: chrono::DateTime<chrono::Utc>) -> String {
+        let (succeeded, failed, bytes) = self.entries.lock().unwrap().iter()
+            .fold((0, 0, 0), |(succeeded, failed, bytes), entry| match entry.error {
+                None => (succeeded + 1, failed, bytes + entry.bytes),
+                Some(_) => (succeeded, failed + 1, bytes),
+            });
+        let time = |t: chrono::DateTime<chrono::Utc>| t.to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        format!(
+            "{{\"status\": {}, \"started\": {}, \"finished\": {}, \"succeeded\": {succeeded}, \"failed\": {failed}, \"bytes\": {bytes}, \"throttled\": {throttled}, \"refetched\": {}, \"files\": {}}}\n",
+            json_string(status),
+            json_string(&time(self.started)),
+            json_string(&time(finished)),
+            self.refetched.lock().unwrap().len(),
+            self.files_json())
+    }
+
+    pub fn write(&self, path: &std::path::Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
+
+#[test]
+fn test_report_json() {
+    let report = Report::default();
+    assert_eq!(report.to_json(), "{\"files\": []}\n");
+    let started = Instant::now();
+    report.record("dir/a \"1\".txt", "s3://bucket/a", started, Ok(12));
+    report.record("b\n", "s3://bucket/b", started, Err("denied\\".into()));
+    let json = report.to_json();
+    assert!(json.contains("{\"source\": \"dir/a \\\"1\\\".txt\", \"destination\": \"s3://bucket/a\", \"bytes\": 12, \"seconds\": "), "{json}");
+    assert!(json.contains("\"source\": \"b\\n\""), "{json}");
+    assert!(json.contains("\"bytes\": 0, \"seconds\": "), "{json}");
+    assert!(json.ends_with(", \"error\": \"denied\\\\\"}\n]}\n"), "{json}");
+}
+
+#[test]
+fn test_summary_json() {
+    let report = Report::default();
+    let started = Instant::now();
+    report.record("a", "s3://bucket/a", started, Ok(12));
+    report.record("b", "s3://bucket/b", started, Ok(30));
+    report.record("c", "s3://bucket/c", started, Err("denied".into()));
+    report.record_refetched("s3://bucket/b");
+    let finished = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+    let json = report.summary_json("failed", 3, finished);
+    assert!(json.starts_with("{\"status\": \"failed\", \"started\": \""), "{json}");
+    assert!(json.contains(", \"finished\": \"2023-11-14T22:13:20.000Z\", \"succeeded\": 2, \"failed\": 1, \"bytes\": 42, \"throttled\": 3, \"refetched\": 1, \"files\": [\n    {\"source\": \"a\""), "{json}");
+    assert!(json.ends_with("\"error\": \"denied\"}\n]}\n"), "{json}");
+}
@@ -0,0 +1,653 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use futures::StreamExt;
+use md5::Digest;
+use tokio::io::AsyncReadExt;
+
+use crate::cli;
+use crate::s3;
+use crate::shared_options::SharedOptions;
+use super::{MainResult, OptionsTransfer};
+use super::report::Report;
+
+/// How to decide whether a file present on both sides has changed
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    /// Sizes differ, or the source is newer than the destination
+    SizeMtime,
+    /// Sizes or content MD5s differ, using size-mtime for multipart objects
+    Etag,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct OptionsSync {
+    /// How to detect changed files
+    #[clap(long, value_enum, default_value = "size-mtime")]
+    compare: Compare,
+    /// Remove destination files not present in the source
+    #[clap(long)]
+    delete: bool,
+    /// Store files as content-defined chunks plus a manifest, uploading only chunks not
+    /// already stored. Suits large files changing in place, e.g. VM images or database dumps
+    #[clap(long, conflicts_with="sse_c_key")]
+    chunked: bool,
+}
+
+/// One file on either side of a sync
+#[derive(Debug, Clone, Default)]
+struct Entry {
+    size: u64,
+    /// Seconds since the epoch
+    mtime: i64,
+    /// Hex MD5 of the content, known for single part objects and hashed local files
+    md5: Option<String>,
+}
+
+/// Files keyed by their '/' separated path relative to the synced root
+type Tree = BTreeMap<String, Entry>;
+
+#[derive(Debug, PartialEq)]
+struct Plan {
+    transfer: Vec<String>,
+    delete: Vec<String>,
+}
+
+fn changed(source: &Entry, destination: &Entry, compare: Compare) -> bool {
+    if source.size != destination.size {
+        return true;
+    }
+    match (compare, &source.md5, &destination.md5) {
+        (Compare::Etag, Some(source), Some(destination)) => source != destination,
+        _ => source.mtime > destination.mtime,
+    }
+}
+
+fn plan(source: &Tree, destination: &Tree, options: &OptionsSync) -> Plan {
+    let transfer = source.iter()
+        .filter(|(path, entry)| destination.get(*path).map(|existing| changed(entry, existing, options.compare)).unwrap_or(true))
+        .map(|(path, _)| path.clone())
+        .collect();
+    let delete = match options.delete {
+        true => destination.keys().filter(|path| !source.contains_key(*path)).cloned().collect(),
+        false => Vec::new(),
+    };
+    Plan { transfer, delete }
+}
+
+/// How a file differs between a local directory and an S3 prefix
+#[derive(Debug, PartialEq)]
+enum Difference {
+    OnlyLocal,
+    OnlyRemote,
+    /// Local and remote sizes
+    Size(u64, u64),
+    /// Same size, different MD5
+    Content,
+    /// Same size, modified locally since uploaded
+    NewerLocally,
+}
+
+impl Difference {
+    fn kind(&self) -> &'static str {
+        match self {
+            Difference::OnlyLocal => "only-local",
+            Difference::OnlyRemote => "only-remote",
+            Difference::Size(..) | Difference::Content | Difference::NewerLocally => "differs",
+        }
+    }
+    fn reason(&self) -> Option<String> {
+        match self {
+            Difference::OnlyLocal | Difference::OnlyRemote => None,
+            Difference::Size(local, remote) => Some(format!("size {local} local, {remote} remote")),
+            Difference::Content => Some("content".into()),
+            Difference::NewerLocally => Some("newer locally".into()),
+        }
+    }
+}
+
+/// Paths differing between `local` and `remote` trees in path order, compared as a sync
+/// uploading `local` would
+fn differences<'a>(local: &'a Tree, remote: &'a Tree, compare: Compare) -> Vec<(&'a str, Difference)> {
+    let paths: std::collections::BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    paths.into_iter().filter_map(|path| {
+        let difference = match (local.get(path), remote.get(path)) {
+            (Some(_), None) => Difference::OnlyLocal,
+            (None, Some(_)) => Difference::OnlyRemote,
+            (Some(l), Some(r)) if l.size != r.size => Difference::Size(l.size, r.size),
+            (Some(l), Some(r)) if !changed(l, r, compare) => return None,
+            (Some(Entry { md5: Some(_), .. }), Some(Entry { md5: Some(_), .. })) if compare == Compare::Etag => Difference::Content,
+            (Some(_), Some(_)) => Difference::NewerLocally,
+            (None, None) => return None,
+        };
+        Some((path.as_str(), difference))
+    }).collect()
+}
+
+fn seconds_since_epoch(time: std::time::SystemTime) -> i64 {
+    time.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Regular files below `root`, following symlinks as uploads do. Empty if `root` doesn't exist yet
+async fn local_tree(root: &Path) -> Result<Tree, String> {
+    let mut tree = Tree::new();
+    let mut pending = vec![(root.to_path_buf(), String::new())];
+    while let Some((directory, relative)) = pending.pop() {
+        let mut children = match tokio::fs::read_dir(&directory).await {
+            Ok(children) => children,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && relative.is_empty() => return Ok(tree),
+            Err(e) => return Err(format!("failed to list directory {directory:?}: {e}")),
+        };
+        while let Some(child) = children.next_entry().await.map_err(|e| format!("failed to list directory {directory:?}: {e}"))? {
+            let path = child.path();
+            let Some(name) = child.file_name().to_str().map(str::to_owned) else {
+                return Err(format!("local path not unicode {path:?}"));
+            };
+            let metadata = tokio::fs::metadata(&path).await.map_err(|e| format!("failed to access local path {path:?}: {e}"))?;
+            if metadata.is_dir() {
+                pending.push((path, format!("{relative}{name}/")));
+            } else if metadata.is_file() {
+                let mtime = metadata.modified().map(seconds_since_epoch).unwrap_or(0);
+                tree.insert(format!("{relative}{name}"), Entry { size: metadata.len(), mtime, md5: None });
+            }
+        }
+    }
+    Ok(tree)
+}
+
+/// Whether `relative`, a listed key below the synced prefix, stays below a local directory it's
+/// joined to. Empty, '.' and '..' segments would make it absolute, alias or climb out of it
+fn is_contained(relative: &str) -> bool {
+    relative.split('/').all(|segment| !matches!(segment, "" | "." | "..") && !segment.contains('\\'))
+}
+
+/// Objects below `root`, skipping directory markers, lock objects and chunks.
+/// When `chunked`, only the files described by manifests. Keys that can't be safely joined to a
+/// local directory fail the listing, before anything is planned
+async fn remote_tree(client: &s3::Client, root: &s3::Uri, chunked: bool) -> Result<Tree, s3::Error> {
+    let mut tree = Tree::new();
+    let mut manifests = Vec::new();
+    for object in client.list_objects(root).await? {
+        let Some(relative) = object.key().and_then(|key| key.strip_prefix(root.key.as_str())) else { continue };
+        if relative.is_empty() || relative.ends_with('/') || relative.starts_with(s3::CHUNK_DIRECTORY) || (root.key.is_empty() && relative.starts_with(s3::LOCK_PREFIX)) {
+            continue;
+        }
+        if !is_contained(relative) {
+            return Err(s3::Error::UnsafeKey(remote_uri(root, relative)));
+        }
+        if chunked {
+            if let Some(path) = relative.strip_suffix(s3::MANIFEST_SUFFIX) {
+                manifests.push(path.to_owned());
+            }
+            continue;
+        }
+        let md5 = object.e_tag()
+            .map(|etag| etag.trim_matches('"').to_ascii_lowercase())
+            .filter(|etag| !etag.contains('-'));
+        tree.insert(relative.to_owned(), Entry {
+            size: object.size().unwrap_or(0) as u64,
+            mtime: object.last_modified().map(|t| t.secs()).unwrap_or(0),
+            md5,
+        });
+    }
+    let mut manifests = futures::stream::iter(manifests)
+        .map(|path| async move {
+            let manifest = client.manifest(&s3::manifest_uri(&remote_uri(root, &path))).await?;
+            Ok::<_, s3::Error>((path, Entry { size: manifest.size, mtime: manifest.mtime, md5: None }))
+        })
+        .buffer_unordered(16);
+    while let Some(manifest) = manifests.next().await {
+        let (path, entry) = manifest?;
+        tree.insert(path, entry);
+    }
+    Ok(tree)
+}
+
+async fn file_md5(path: &Path) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = md5::Md5::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buffer).await? {
+            0 => break,
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+    Ok(hasher.finalize().iter().map(|b| format!("{b:02x}")).collect())
+}
+
+/// Objects below `root` as `remote_tree` lists them, describing any failure
+async fn listed_tree(client: &s3::Client, root: &s3::Uri, chunked: bool) -> Result<Tree, String> {
+    remote_tree(client, root, chunked).await.map_err(|e| format!("failed to list {root}: {e}"))
+}
+
+/// Hash local files only where the remote side has a comparable MD5
+async fn hash_local(root: &Path, local: &mut Tree, remote: &Tree) -> Result<(), String> {
+    for (path, entry) in local.iter_mut() {
+        let comparable = remote.get(path).map(|r| r.size == entry.size && r.md5.is_some()).unwrap_or(false);
+        if comparable {
+            let local_path = root.join(path);
+            entry.md5 = Some(file_md5(&local_path).await.map_err(|e| format!("failed to read {local_path:?}: {e}"))?);
+        }
+    }
+    Ok(())
+}
+
+/// Print which files are only in `local`, only below `remote` or differ, changing nothing
+pub async fn diff(local: &Path, remote: &s3::Uri, client: &s3::Client, opts: &SharedOptions, compare: Compare, filter: &s3::OptionsFilter) -> MainResult {
+    let root = prefix_root(remote);
+    if opts.verbose {
+        println!("🏁 comparing {local:?} with {root}");
+    }
+    let (mut local_files, mut remote_files) = match tokio::join!(local_tree(local), listed_tree(client, &root, false)) {
+        (Ok(local_files), Ok(remote_files)) => (local_files, remote_files),
+        (Err(e), _) | (_, Err(e)) => {
+            cli::println_error(format_args!("{e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        },
+    };
+    local_files.retain(|path, _| filter.matches(path));
+    remote_files.retain(|path, _| filter.matches(path));
+    if compare == Compare::Etag {
+        if let Err(e) = hash_local(local, &mut local_files, &remote_files).await {
+            cli::println_error(format_args!("{e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        }
+    }
+    let differences = differences(&local_files, &remote_files, compare);
+    for (path, difference) in &differences {
+        match difference.reason() {
+            Some(reason) => println!("{}\t{path}\t{reason}", difference.kind()),
+            None => println!("{}\t{path}", difference.kind()),
+        }
+    }
+    if opts.verbose {
+        println!("🏁 {} difference(s) among {} local and {} remote file(s)", differences.len(), local_files.len(), remote_files.len());
+    }
+    MainResult::Success
+}
+
+/// A local directory and S3 prefix, or two S3 prefixes, and which way files go
+pub enum Direction<'a> {
+    Upload { local: &'a Path, remote: &'a s3::Uri, options: &'a s3::OptionsUpload },
+    Download { remote: &'a s3::Uri, local: &'a Path, options: &'a s3::OptionsDownload },
+    /// Between S3 prefixes in the same or different buckets, copying server-side
+    Copy { from: &'a s3::Uri, to: &'a s3::Uri, options: &'a s3::OptionsUpload },
+}
+
+impl Direction<'_> {
+    /// The S3 prefix synced with a local directory, or the destination prefix when copying
+    fn remote(&self) -> &s3::Uri {
+        match self {
+            Direction::Upload { remote, .. } | Direction::Download { remote, .. } => remote,
+            Direction::Copy { to, .. } => to,
+        }
+    }
+}
+
+/// The prefix below which `uri`'s objects are synced
+fn prefix_root(uri: &s3::Uri) -> s3::Uri {
+    match uri.key.is_empty() {
+        true => uri.clone(),
+        false => s3::Uri::new(uri.bucket.clone(), uri.key.to_explicit_directory()),
+    }
+}
+
+/// Whether either synced prefix holds the other, so copying would feed on its own output
+fn nested(from: &s3::Uri, to: &s3::Uri) -> bool {
+    from.bucket == to.bucket && (from.key.starts_with(to.key.as_str()) || to.key.starts_with(from.key.as_str()))
+}
+
+/// Where `path` lives below the synced prefix
+fn remote_uri(root: &s3::Uri, path: &str) -> s3::Uri {
+    let mut key = root.key.clone();
+    key.push(path);
+    s3::Uri::new(root.bucket.clone(), key)
+}
+
+/// Chunks stored below the synced prefix, when uploading chunked
+type StoredChunks = Mutex<HashSet<String>>;
+
+#[allow(clippy::too_many_arguments)]
+async fn transfer_one(direction: &Direction<'_>, root: &s3::Uri, path: &str, size: u64, progress: Arc<cli::Output>, client: &s3::Client, verbose: bool, transfer: &OptionsTransfer, chunked: bool, stored: Option<&StoredChunks>, report: &Report) -> u32 {
+    let uri = remote_uri(root, path);
+    let update_fn = progress.add("queued", path.to_owned());
+    let started = std::time::Instant::now();
+    let (source, destination, result) = match direction {
+        Direction::Upload { local, options, .. } => {
+            let local_path = local.join(path);
+            let result = match stored {
+                Some(stored) => client.put_chunked(verbose, options, &local_path, &uri, root, stored, update_fn.clone()).await,
+                None => client.put(verbose, options, &local_path, &uri, update_fn.clone()).await.map(|uploaded| uploaded.to_string()),
+            };
+            let length = tokio::fs::metadata(&local_path).await.map(|m| m.len()).unwrap_or(0);
+            (local_path.to_string_lossy().into_owned(), uri.to_string(), result.map(|uri| (format!("uploaded {uri}"), length)))
+        },
+        Direction::Download { local, options, .. } => {
+            let local_path = local.join(path);
+            let created = match local_path.parent() {
+                Some(parent) => tokio::fs::create_dir_all(parent).await.map_err(s3::Error::from),
+                None => Ok(()),
+            };
+            let result = match (created, chunked) {
+                (Ok(()), false) => client.get(verbose, options, transfer.ranged_download(), &uri, None, &s3::Target::File(local_path.clone()), update_fn.clone()).await,
+                (Ok(()), true) => client.get_chunked(verbose, &uri, root, &local_path, update_fn.clone()).await,
+                (Err(e), _) => Err(e),
+            };
+            let length = tokio::fs::metadata(&local_path).await.map(|m| m.len()).unwrap_or(0);
+            (uri.to_string(), local_path.to_string_lossy().into_owned(), result.map(|path| (format!("downloaded {path:?}"), length)))
+        },
+        Direction::Copy { from, options, .. } => {
+            let from = remote_uri(&prefix_root(from), path);
+            let result = client.copy_object(verbose, Some(options), &from, &uri).await;
+            (from.to_string(), uri.to_string(), result.map(|uri| (format!("copied {uri}"), size)))
+        },
+    };
+    match result {
+        Ok((done, length)) => {
+            progress.println_done_verbose(format_args!("{done}"));
+            report.record(source, destination, started, Ok(length));
+            0
+        },
+        Err(e) => {
+            progress.println_error_noprogress(format_args!("failed to sync {path}: {e}"));
+            report.record(source, destination, started, Err(e.to_string()));
+            update_fn(cli::Update::Error(e.to_string()));
+            1
+        },
+    }
+}
+
+async fn delete_one(direction: &Direction<'_>, root: &s3::Uri, path: &str, progress: Arc<cli::Output>, client: &s3::Client, opts: &SharedOptions, chunked: bool) -> u32 {
+    let result = match direction {
+        Direction::Upload { .. } | Direction::Copy { .. } => {
+            let uri = match chunked {
+                true => s3::manifest_uri(&remote_uri(root, path)),
+                false => remote_uri(root, path),
+            };
+            client.remove(opts, &uri).await.map(|_| uri.to_string())
+        },
+        Direction::Download { local, .. } => {
+            let local_path = local.join(path);
+            tokio::fs::remove_file(&local_path).await.map(|_| format!("{local_path:?}")).map_err(s3::Error::from)
+        },
+    };
+    match result {
+        Ok(removed) => {
+            progress.println_done_verbose(format_args!("deleted {removed}"));
+            0
+        },
+        Err(e) => {
+            progress.println_error(format_args!("failed to delete {path}: {e}"));
+            1
+        },
+    }
+}
+
+/// Transfer files missing or changed at the destination, optionally removing extraneous ones
+pub async fn sync(direction: Direction<'_>, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, options: &OptionsSync) -> MainResult {
+    let report = Report::default();
+    let result = match direction {
+        Direction::Upload { remote: to, options: opts_upload, .. } | Direction::Copy { to, options: opts_upload, .. } => {
+            super::locked(to, client, opts.verbose, opts_upload, sync_unlocked(direction, client, opts, transfer, options, &report)).await
+        },
+        Direction::Download { .. } => sync_unlocked(direction, client, opts, transfer, options, &report).await,
+    };
+    transfer.write_report(client, &report, result).await
+}
+
+async fn sync_unlocked(direction: Direction<'_>, client: &s3::Client, opts: &SharedOptions, transfer: &OptionsTransfer, options: &OptionsSync, report: &Report) -> MainResult {
+    let root = prefix_root(direction.remote());
+    // Buckets synced between may be in other regions than the client's, and each other's
+    let (client, source_client) = match &direction {
+        Direction::Copy { from, .. } => {
+            let (destination, source) = tokio::join!(client.for_bucket(&root.bucket), client.for_bucket(&from.bucket));
+            (destination, Some(source))
+        },
+        Direction::Upload { .. } | Direction::Download { .. } => (client.clone(), None),
+    };
+    let client = &transfer.client(&client);
+    let trees = match &direction {
+        Direction::Upload { local, .. } => {
+            if opts.verbose {
+                println!("🏁 comparing {local:?} with {root}");
+            }
+            tokio::join!(local_tree(local), listed_tree(client, &root, options.chunked))
+        },
+        Direction::Download { local, .. } => {
+            if opts.verbose {
+                println!("🏁 comparing {root} with {local:?}");
+            }
+            let (local, remote) = tokio::join!(local_tree(local), listed_tree(client, &root, options.chunked));
+            (remote, local)
+        },
+        Direction::Copy { from, .. } => {
+            let from = prefix_root(from);
+            if nested(&from, &root) {
+                cli::println_error(format_args!("cannot sync {from} with {root}, one is inside the other"));
+                return MainResult::ErrorArguments;
+            }
+            if options.chunked {
+                cli::println_error(format_args!("--chunked is only supported between a local directory and S3"));
+                return MainResult::ErrorArguments;
+            }
+            if opts.verbose {
+                println!("🏁 comparing {from} with {root}");
+            }
+            let source_client = source_client.as_ref().unwrap_or(client);
+            tokio::join!(listed_tree(source_client, &from, false), listed_tree(client, &root, false))
+        },
+    };
+    let (mut source, mut destination) = match trees {
+        (Ok(source), Ok(destination)) => (source, destination),
+        (Err(e), _) | (_, Err(e)) => {
+            cli::println_error(format_args!("{e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        },
+    };
+    // Excluded files are neither transferred nor deleted
+    source.retain(|path, _| transfer.filter.matches(path));
+    destination.retain(|path, _| transfer.filter.matches(path));
+    if let Direction::Download { options: opts_download, .. } = &direction {
+        let outside_age: Vec<String> = source.iter()
+            .filter(|(_, entry)| !opts_download.matches_age(Some(&aws_sdk_s3::primitives::DateTime::from_secs(entry.mtime))))
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in outside_age {
+            source.remove(&path);
+            destination.remove(&path);
+        }
+    }
+    if options.compare == Compare::Etag {
+        let hashed = match &direction {
+            Direction::Upload { local, .. } => hash_local(local, &mut source, &destination).await,
+            Direction::Download { local, .. } => hash_local(local, &mut destination, &source).await,
+            Direction::Copy { .. } => Ok(()),
+        };
+        if let Err(e) = hashed {
+            cli::println_error(format_args!("{e}"));
+            return MainResult::ErrorSomeOperationsFailed;
+        }
+    }
+    let plan = plan(&source, &destination, options);
+    if opts.verbose {
+        println!("🏁 {} file(s) to transfer, {} to delete", plan.transfer.len(), plan.delete.len());
+    }
+    if let (Direction::Upload { options: opts_upload, .. } | Direction::Copy { options: opts_upload, .. }, false) = (&direction, plan.transfer.is_empty()) {
+        let files: Vec<_> = plan.transfer.iter().map(|path| (path.into(), source[path].size)).collect();
+        if let Err(result) = super::preflight(&root, opts.verbose, opts_upload, files.len(), Some(&files)) {
+            return result;
+        }
+    }
+
+    let stored = match (&direction, options.chunked && !plan.transfer.is_empty()) {
+        (Direction::Upload { .. }, true) => match client.stored_chunks(&root).await {
+            Ok(stored) => Some(Mutex::new(stored)),
+            Err(e) => {
+                cli::println_error(format_args!("failed to list chunks below {root}: {e}"));
+                return MainResult::ErrorSomeOperationsFailed;
+            },
+        },
+        _ => None,
+    };
+
+    let progress = Arc::new(cli::Output::new(&transfer.progress, opts.verbose, None));
+    progress.add_incoming_tasks(plan.transfer.len());
+    let verbose = opts.verbose && !progress.progress_enabled();
+
+    let ctrl_c = super::CtrlC::listen();
+    let cancellation = ctrl_c.token();
+
+    let mut transfers = futures::stream::iter(&plan.transfer)
+        .map(|path| transfer_one(&direction, &root, path, source[path].size, progress.clone(), client, verbose, transfer, options.chunked, stored.as_ref(), report))
+        .buffer_unordered(transfer.concurrency.get() as usize);
+    let mut error_count = 0;
+    loop {
+        let result = tokio::select!{
+            res = transfers.next() => res,
+            _ = cancellation.cancelled() => {
+                return match &direction {
+                    Direction::Upload { options, .. } | Direction::Copy { options, .. } => super::abort_cancelled(client, &progress, options).await,
+                    Direction::Download { .. } => {
+                        progress.mark_cancelled();
+                        MainResult::Cancelled
+                    },
+                };
+            },
+        };
+        match result {
+            Some(count) => error_count += count,
+            None => break,
+        }
+        if error_count > 0 && !transfer.continue_on_error {
+            return MainResult::from_error_count(error_count);
+        }
+    }
+
+    // Extraneous files may be the old names of those that failed, keep them
+    if error_count > 0 {
+        if !plan.delete.is_empty() {
+            progress.println_error(format_args!("not deleting {} file(s) after failed transfers", plan.delete.len()));
+        }
+        return MainResult::from_error_count(error_count);
+    }
+    for path in &plan.delete {
+        if cancellation.is_cancelled() {
+            return MainResult::Cancelled;
+        }
+        error_count += delete_one(&direction, &root, path, progress.clone(), client, opts, options.chunked).await;
+        if error_count > 0 && !transfer.continue_on_error {
+            break;
+        }
+    }
+    if let (Direction::Upload { .. }, true, true, 0) = (&direction, options.chunked, options.delete, error_count) {
+        match client.remove_unreferenced_chunks(opts.verbose, &root).await {
+            Ok(removed) => progress.println_done_verbose(format_args!("removed {removed} unreferenced chunk(s)")),
+            Err(e) => {
+                progress.println_error(format_args!("failed to remove unreferenced chunks: {e}"));
+                error_count += 1;
+            },
+        }
+    }
+    MainResult::from_error_count(error_count)
+}
+
+#[test]
+fn test_plan() {
+    let entry = |size, mtime, md5: Option<&str>| Entry { size, mtime, md5: md5.map(str::to_owned) };
+    let source = Tree::from([
+        ("same".to_owned(), entry(1, 10, Some("aa"))),
+        ("newer".to_owned(), entry(1, 20, Some("aa"))),
+        ("resized".to_owned(), entry(2, 10, None)),
+        ("added".to_owned(), entry(1, 10, None)),
+    ]);
+    let destination = Tree::from([
+        ("same".to_owned(), entry(1, 15, Some("aa"))),
+        ("newer".to_owned(), entry(1, 15, Some("aa"))),
+        ("resized".to_owned(), entry(1, 15, None)),
+        ("extra".to_owned(), entry(1, 15, None)),
+    ]);
+
+    let options = OptionsSync { compare: Compare::SizeMtime, delete: false, chunked: false };
+    assert_eq!(plan(&source, &destination, &options), Plan {
+        transfer: vec!["added".into(), "newer".into(), "resized".into()],
+        delete: vec![],
+    });
+    let options = OptionsSync { compare: Compare::Etag, delete: true, chunked: false };
+    assert_eq!(plan(&source, &destination, &options), Plan {
+        transfer: vec!["added".into(), "resized".into()],
+        delete: vec!["extra".into()],
+    });
+}
+
+#[test]
+fn test_copy_plan() {
+    // Listed on both sides, so single part objects compare by ETag and copies are newer
+    let entry = |size, mtime, md5: Option<&str>| Entry { size, mtime, md5: md5.map(str::to_owned) };
+    let source = Tree::from([
+        ("copied".to_owned(), entry(1, 10, Some("aa"))),
+        ("edited".to_owned(), entry(1, 30, Some("bb"))),
+        ("multipart".to_owned(), entry(1, 30, None)),
+    ]);
+    let destination = Tree::from([
+        ("copied".to_owned(), entry(1, 20, Some("aa"))),
+        ("edited".to_owned(), entry(1, 20, Some("aa"))),
+        ("multipart".to_owned(), entry(1, 20, None)),
+        ("extra".to_owned(), entry(1, 20, Some("cc"))),
+    ]);
+    let options = OptionsSync { compare: Compare::Etag, delete: true, chunked: false };
+    assert_eq!(plan(&source, &destination, &options), Plan {
+        transfer: vec!["edited".into(), "multipart".into()],
+        delete: vec!["extra".into()],
+    });
+    let options = OptionsSync { compare: Compare::SizeMtime, delete: false, chunked: false };
+    assert_eq!(plan(&source, &destination, &options), Plan {
+        transfer: vec!["edited".into(), "multipart".into()],
+        delete: vec![],
+    });
+
+    let root = |uri: &str| prefix_root(&uri.parse().unwrap());
+    assert!(nested(&root("s3://bkt/a"), &root("s3://bkt/a/b")));
+    assert!(nested(&root("s3://bkt/a/b"), &root("s3://bkt/a")));
+    assert!(nested(&root("s3://bkt"), &root("s3://bkt/a")));
+    assert!(!nested(&root("s3://bkt/a"), &root("s3://bkt/ab")));
+    assert!(!nested(&root("s3://bkt/a"), &root("s3://other/a")));
+}
+
+#[test]
+fn test_differences() {
+    let entry = |size, mtime, md5: Option<&str>| Entry { size, mtime, md5: md5.map(str::to_owned) };
+    let local = Tree::from([
+        ("same".to_owned(), entry(1, 10, Some("aa"))),
+        ("edited".to_owned(), entry(1, 20, Some("bb"))),
+        ("resized".to_owned(), entry(2, 10, None)),
+        ("new".to_owned(), entry(1, 10, None)),
+    ]);
+    let remote = Tree::from([
+        ("same".to_owned(), entry(1, 15, Some("aa"))),
+        ("edited".to_owned(), entry(1, 15, Some("aa"))),
+        ("resized".to_owned(), entry(1, 15, None)),
+        ("old".to_owned(), entry(1, 15, None)),
+    ]);
+    assert_eq!(differences(&local, &remote, Compare::SizeMtime), [
+        ("edited", Difference::NewerLocally),
+        ("new", Difference::OnlyLocal),
+        ("old", Difference::OnlyRemote),
+        ("resized", Difference::Size(2, 1)),
+    ]);
+    assert_eq!(differences(&local, &remote, Compare::Etag)[0], ("edited", Difference::Content));
+    assert!(differences(&local, &local, Compare::Etag).is_empty());
+}
+
+#[test]
+fn test_hostile_keys() {
+    let local = Path::new("/sync/root");
+    for hostile in ["/etc/passwd", "a//b", "../outside", "a/../../outside", "a/./b", ".", "a\\..\\..\\x"] {
+        assert!(!is_contained(hostile), "{hostile}");
+    }
+    for benign in ["a", "a/b.txt", "..hidden", "a/.b/c..d"] {
+        assert!(is_contained(benign), "{benign}");
+        assert!(local.join(benign).starts_with(local));
+    }
+}
@@ -56,4 +56,46 @@ full_sup3=$(readlink --canonicalize $sup3)
    diff -urp $d/dir into
 )
 
+# More keys than one listing page holds, so recursive copies and removals follow continuation tokens
+many=$(mktemp -d)
+mkdir $many/many
+for i in $(seq 1 1001); do echo $i > $many/many/$i; done
+$sup3 cp -r $many/many $bucket/ --progress=off
+down_many=$(mktemp -d)
+$sup3 cp -r $bucket/many $down_many/ --progress=off
+diff -urp $many/many $down_many
+$sup3 rm -r $bucket/many
+if [ -n "$($sup3 ls -r $bucket/many/ 2>/dev/null)" ];then
+   echo "sup3-test: keys left after rm -r beyond the first listing page" >&2
+   exit 1
+fi
+echo "sup3-test: listing pages test: success"
+
+# Backblaze B2: flags for what it doesn't implement are refused before any request is made
+for flag in "--class GLACIER" "--canned-acl public-read" "--checksum-algorithm CRC32C"; do
+   if $sup3 --b2-region us-west-004 upload $flag $d/dir/a s3://b2-bucket/a 2>/dev/null; then
+      echo "sup3-test: B2 accepted unsupported $flag" >&2
+      exit 1
+   fi
+done
+echo "sup3-test: B2 unsupported flags test: success"
+
+# Against B2 itself, given a bucket and its region, with the key in AWS_ACCESS_KEY_ID and
+# AWS_SECRET_ACCESS_KEY
+if [ -n "$SUP3_TEST_B2_BUCKET" ]; then
+   b2() { env -u AWS_ENDPOINT_URL $sup3 --b2-region "$SUP3_TEST_B2_REGION" "$@"; }
+   b2bucket=s3://$SUP3_TEST_B2_BUCKET/sup3-test-$bucket_name
+   b2 cp -r $d/dir $b2bucket/ --progress=off --metadata origin=sup3-test
+   # Multipart, in parts of B2's 5MiB minimum
+   head -c 12582912 /dev/urandom > $d/large
+   b2 upload --multipart-threshold 5M --multipart-part-size 5M $d/large $b2bucket/large --progress=off
+   b2_down=$(mktemp -d)
+   b2 cp -r $b2bucket/dir $b2_down/ --progress=off
+   diff -urp $d/dir $b2_down
+   b2 download $b2bucket/large $b2_down/ --progress=off
+   cmp $d/large $b2_down/large
+   b2 rm -r $b2bucket/
+   echo "sup3-test: B2 test: success"
+fi
+
 echo "sup3-test: success"